@@ -31,7 +31,8 @@ impl CmdArgs {
         }
     }
 
-    fn from_args(raw: Vec<String>) -> CmdArgs {
+    /// Initialize with the given flags, instead of the real ones. Doesn't set up logging.
+    pub fn from_args(raw: Vec<String>) -> CmdArgs {
         let mut args = CmdArgs {
             kv: HashMap::new(),
             bits: HashSet::new(),
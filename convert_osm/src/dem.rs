@@ -0,0 +1,305 @@
+//! Readers for digital elevation models (DEMs). Only rasters in plain longitude and latitude
+//! (EPSG:4326) are supported, which covers SRTM, ASTER, and Copernicus GLO-30 downloads.
+
+use std::fs::File;
+use std::io::{BufReader, Read};
+
+use anyhow::Result;
+use tiff::decoder::{Decoder, DecodingResult};
+use tiff::tags::Tag;
+
+use geom::{Distance, GPSBounds, LonLat};
+
+/// A grid of elevation samples, in meters, evenly spaced in longitude and latitude. Rows run from
+/// north to south.
+pub struct ElevationGrid {
+    pub path: String,
+    /// The longitude and latitude of the center of the top-left sample
+    origin_lon: f64,
+    origin_lat: f64,
+    /// Degrees between samples, always positive
+    step_lon: f64,
+    step_lat: f64,
+    width: usize,
+    height: usize,
+    /// Missing data is NaN
+    values: Vec<f32>,
+}
+
+impl ElevationGrid {
+    /// Loads a DEM, based on the file extension.
+    pub fn load(path: &str) -> Result<ElevationGrid> {
+        let lower = path.to_lowercase();
+        if lower.ends_with(".hgt") {
+            ElevationGrid::load_hgt(path)
+        } else if lower.ends_with(".tif") || lower.ends_with(".tiff") {
+            ElevationGrid::load_geotiff(path)
+        } else {
+            bail!("Unknown elevation format for {}", path)
+        }
+    }
+
+    /// The area a DEM covers, without reading the whole file, or `None` if the file isn't
+    /// supported.
+    pub fn peek_bounds(path: &str) -> Option<GPSBounds> {
+        let lower = path.to_lowercase();
+        if lower.ends_with(".hgt") {
+            let (lon, lat) = parse_hgt_name(path).ok()?;
+            Some(GPSBounds::from(vec![
+                LonLat::new(lon, lat),
+                LonLat::new(lon + 1.0, lat + 1.0),
+            ]))
+        } else if lower.ends_with(".tif") || lower.ends_with(".tiff") {
+            let mut decoder = Decoder::new(BufReader::new(File::open(path).ok()?)).ok()?;
+            let (width, height) = decoder.dimensions().ok()?;
+            let georef = GeoReference::read(&mut decoder).ok()?;
+            Some(GPSBounds::from(vec![
+                LonLat::new(georef.origin_lon, georef.origin_lat),
+                LonLat::new(
+                    georef.origin_lon + georef.step_lon * (width as f64 - 1.0),
+                    georef.origin_lat - georef.step_lat * (height as f64 - 1.0),
+                ),
+            ]))
+        } else {
+            None
+        }
+    }
+
+    /// Reads an SRTM .hgt tile. These are named after their southwest corner, like N47W123.hgt,
+    /// cover one degree square, and contain big-endian 16-bit samples. The samples on the edges
+    /// overlap with neighboring tiles.
+    pub fn load_hgt(path: &str) -> Result<ElevationGrid> {
+        let (lon, lat) = parse_hgt_name(path)?;
+        let mut bytes = Vec::new();
+        File::open(path)?.read_to_end(&mut bytes)?;
+        let size = match bytes.len() {
+            // SRTM1 (1 arcsecond)
+            25_934_402 => 3601,
+            // SRTM3 (3 arcseconds)
+            2_884_802 => 1201,
+            n => bail!(
+                "{} has {} bytes, which isn't a SRTM1 or SRTM3 tile",
+                path,
+                n
+            ),
+        };
+        let values = bytes
+            .chunks_exact(2)
+            .map(|pair| {
+                let x = i16::from_be_bytes([pair[0], pair[1]]);
+                // Voids
+                if x == -32768 {
+                    f32::NAN
+                } else {
+                    x as f32
+                }
+            })
+            .collect();
+        let step = 1.0 / (size as f64 - 1.0);
+        Ok(ElevationGrid {
+            path: path.to_string(),
+            origin_lon: lon,
+            origin_lat: lat + 1.0,
+            step_lon: step,
+            step_lat: step,
+            width: size,
+            height: size,
+            values,
+        })
+    }
+
+    /// Reads a single-band GeoTIFF in EPSG:4326.
+    pub fn load_geotiff(path: &str) -> Result<ElevationGrid> {
+        let mut decoder = Decoder::new(BufReader::new(File::open(path)?))?;
+        let (width, height) = decoder.dimensions()?;
+        let georef = GeoReference::read(&mut decoder)?;
+        let values: Vec<f32> = match decoder.read_image()? {
+            DecodingResult::U8(x) => x.into_iter().map(|x| x as f32).collect(),
+            DecodingResult::U16(x) => x.into_iter().map(|x| x as f32).collect(),
+            DecodingResult::U32(x) => x.into_iter().map(|x| x as f32).collect(),
+            DecodingResult::I8(x) => x.into_iter().map(|x| x as f32).collect(),
+            DecodingResult::I16(x) => x.into_iter().map(|x| x as f32).collect(),
+            DecodingResult::I32(x) => x.into_iter().map(|x| x as f32).collect(),
+            DecodingResult::F32(x) => x,
+            DecodingResult::F64(x) => x.into_iter().map(|x| x as f32).collect(),
+            _ => bail!("{} has an unsupported sample format", path),
+        };
+        if values.len() != (width as usize) * (height as usize) {
+            bail!(
+                "{} has {} samples, but is {}x{}. Only single-band rasters are supported.",
+                path,
+                values.len(),
+                width,
+                height
+            );
+        }
+        let values = values
+            .into_iter()
+            .map(|x| {
+                if Some(x) == georef.nodata || !x.is_finite() {
+                    f32::NAN
+                } else {
+                    x
+                }
+            })
+            .collect();
+        Ok(ElevationGrid {
+            path: path.to_string(),
+            origin_lon: georef.origin_lon,
+            origin_lat: georef.origin_lat,
+            step_lon: georef.step_lon,
+            step_lat: georef.step_lat,
+            width: width as usize,
+            height: height as usize,
+            values,
+        })
+    }
+
+    /// A grid where the top-left sample is at (lon, lat), with samples `step` degrees apart. Rows
+    /// of `values` run from north to south.
+    #[cfg(test)]
+    pub fn synthetic(
+        lon: f64,
+        lat: f64,
+        step: f64,
+        width: usize,
+        values: Vec<f32>,
+    ) -> ElevationGrid {
+        ElevationGrid {
+            path: "synthetic".to_string(),
+            origin_lon: lon,
+            origin_lat: lat,
+            step_lon: step,
+            step_lat: step,
+            width,
+            height: values.len() / width,
+            values,
+        }
+    }
+
+    /// Degrees between samples. Smaller is more detailed.
+    pub fn resolution(&self) -> f64 {
+        self.step_lon.max(self.step_lat)
+    }
+
+    /// Bilinearly interpolates the elevation at a point, ignoring missing samples. Returns `None`
+    /// outside the grid or if all of the surrounding samples are missing.
+    pub fn sample(&self, pt: LonLat) -> Option<Distance> {
+        let col = (pt.x() - self.origin_lon) / self.step_lon;
+        let row = (self.origin_lat - pt.y()) / self.step_lat;
+        if col < 0.0 || row < 0.0 || col > (self.width - 1) as f64 || row > (self.height - 1) as f64
+        {
+            return None;
+        }
+        let col0 = (col.floor() as usize).min(self.width - 2);
+        let row0 = (row.floor() as usize).min(self.height - 2);
+        let dx = col - col0 as f64;
+        let dy = row - row0 as f64;
+
+        let mut sum = 0.0;
+        let mut total_weight = 0.0;
+        for (c, r, weight) in vec![
+            (col0, row0, (1.0 - dx) * (1.0 - dy)),
+            (col0 + 1, row0, dx * (1.0 - dy)),
+            (col0, row0 + 1, (1.0 - dx) * dy),
+            (col0 + 1, row0 + 1, dx * dy),
+        ] {
+            let value = self.values[r * self.width + c];
+            if value.is_nan() || weight == 0.0 {
+                continue;
+            }
+            sum += weight * (value as f64);
+            total_weight += weight;
+        }
+        if total_weight == 0.0 {
+            return None;
+        }
+        Some(Distance::meters(sum / total_weight))
+    }
+}
+
+/// Parses names like N47W123.hgt into the (longitude, latitude) of the southwest corner.
+fn parse_hgt_name(path: &str) -> Result<(f64, f64)> {
+    let name = abstutil::basename(path).to_uppercase();
+    if name.len() != 7 || !name.is_ascii() {
+        bail!("{} isn't named like N47W123.hgt", path);
+    }
+    let lat = name[1..3].parse::<f64>()?;
+    let lon = name[4..7].parse::<f64>()?;
+    let lat = match &name[0..1] {
+        "N" => lat,
+        "S" => -lat,
+        _ => bail!("{} isn't named like N47W123.hgt", path),
+    };
+    let lon = match &name[3..4] {
+        "E" => lon,
+        "W" => -lon,
+        _ => bail!("{} isn't named like N47W123.hgt", path),
+    };
+    Ok((lon, lat))
+}
+
+/// Where a GeoTIFF is on the globe, from its tags.
+struct GeoReference {
+    origin_lon: f64,
+    origin_lat: f64,
+    step_lon: f64,
+    step_lat: f64,
+    nodata: Option<f32>,
+}
+
+impl GeoReference {
+    fn read<R: Read + std::io::Seek>(decoder: &mut Decoder<R>) -> Result<GeoReference> {
+        let model_pixel_scale = Tag::from_u16_exhaustive(33550);
+        let model_tiepoint = Tag::from_u16_exhaustive(33922);
+        let geo_key_directory = Tag::from_u16_exhaustive(34735);
+        let gdal_nodata = Tag::from_u16_exhaustive(42113);
+
+        // Each key is (key ID, tag location, count, value)
+        let mut pixel_is_point = false;
+        let keys = decoder.get_tag_u16_vec(geo_key_directory)?;
+        for key in keys.chunks_exact(4).skip(1) {
+            match (key[0], key[3]) {
+                // GTModelTypeGeoKey
+                (1024, 1) => bail!(
+                    "Projected GeoTIFFs aren't supported; use gdalwarp -t_srs EPSG:4326 first"
+                ),
+                (1024, 2) => {}
+                (1024, x) => bail!("Unsupported GeoTIFF model type {}", x),
+                // RasterTypeGeoKey
+                (1025, 2) => {
+                    pixel_is_point = true;
+                }
+                _ => {}
+            }
+        }
+
+        let scale = decoder.get_tag_f64_vec(model_pixel_scale)?;
+        let tiepoint = decoder.get_tag_f64_vec(model_tiepoint)?;
+        if scale.len() < 2 || tiepoint.len() < 6 {
+            bail!("GeoTIFF has a malformed ModelPixelScale or ModelTiepoint");
+        }
+        let (step_lon, step_lat) = (scale[0], scale[1]);
+        // The tiepoint maps a raster position (I, J) to a model position (X, Y)
+        let mut origin_lon = tiepoint[3] - tiepoint[0] * step_lon;
+        let mut origin_lat = tiepoint[4] + tiepoint[1] * step_lat;
+        if !pixel_is_point {
+            // The tiepoint refers to the corner of the pixel, not its center
+            origin_lon += step_lon / 2.0;
+            origin_lat -= step_lat / 2.0;
+        }
+
+        let nodata = decoder
+            .get_tag_ascii_string(gdal_nodata)
+            .ok()
+            .and_then(|x| x.trim_end_matches('\0').trim().parse::<f32>().ok());
+
+        Ok(GeoReference {
+            origin_lon,
+            origin_lat,
+            step_lon,
+            step_lat,
+            nodata,
+        })
+    }
+}
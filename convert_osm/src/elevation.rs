@@ -1,148 +1,289 @@
-use std::fs::File;
-use std::io::{BufRead, BufReader, BufWriter, Write};
-use std::process::Command;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap};
 
 use anyhow::Result;
 
-use geom::{Distance, PolyLine};
-use map_model::raw::{OriginalRoad, RawMap};
+use abstutil::{prettyprint_usize, Tags};
+use geom::{Distance, GPSBounds, LonLat, Pt2D};
+use map_model::osm;
+use map_model::raw::{OriginalRoad, RawMap, RawRoad};
 
+use crate::dem::ElevationGrid;
+
+/// Sets the elevation of every intersection and the incline of every road, using SRTM .hgt or
+/// GeoTIFF files in data/input/shared/elevation. The terrain is sampled along every road at ground
+/// level. Bridges and tunnels aren't, so intersections on them are interpolated between the
+/// nearest intersections that touch the ground.
 pub fn add_data(map: &mut RawMap) -> Result<()> {
-    // TODO It'd be nice to include more timing breakdown here, but if we bail out early,
-    // it's tedious to call timer.stop().
-    let ids = generate_input(map)?;
-
-    std::fs::create_dir_all("elevation_output")?;
-    std::fs::create_dir_all(abstio::path_shared_input("elevation"))?;
-    let pwd = std::env::current_dir()?.display().to_string();
-    // Because elevation_lookups has so many dependencies, just depend on Docker.
-    // TODO This is only going to run on Linux, unless we can also build images for other OSes.
-    // TODO On Linux, data/input/shared/elevation files wind up being owned by root, due to how
-    // docker runs. For the moment, one workaround is to manually fix the owner afterwards:
-    // find data/ -user root -exec sudo chown $USER:$USER '{}' \;
-    let status = Command::new("docker")
-        .arg("run")
-        // Bind the input directory to the temporary place we just created
-        .arg("--mount")
-        .arg(format!(
-            "type=bind,source={}/elevation_input,target=/elevation/input,readonly",
-            pwd
-        ))
-        // We want to cache the elevation data sources in A/B Street's S3 bucket, so bind to
-        // our data/input/shared directory.
-        .arg("--mount")
-        .arg(format!(
-            "type=bind,source={},target=/elevation/data",
-            // Docker requires absolute paths
-            format!("{}/{}", pwd, abstio::path_shared_input("elevation"))
-        ))
-        .arg("--mount")
-        .arg(format!(
-            "type=bind,source={}/elevation_output,target=/elevation/output",
-            pwd
-        ))
-        .arg("-t")
-        // https://hub.docker.com/r/abstreet/elevation_lookups
-        .arg("abstreet/elevation_lookups")
-        .arg("python3")
-        .arg("main.py")
-        .arg("query")
-        // TODO How to tune this? Pretty machine dependant, and using ALL available cores may
-        // melt memory.
-        .arg("--n_threads=1")
-        .status()?;
-    if !status.success() {
-        bail!("Command failed: {}", status);
-    }
-
-    scrape_output(map, ids)?;
-
-    // Clean up temporary files
-    std::fs::remove_file("elevation_input/query")?;
-    std::fs::remove_dir("elevation_input")?;
-    std::fs::remove_file("elevation_output/query")?;
-    std::fs::remove_dir("elevation_output")?;
+    let grids = load_grids(map)?;
+    apply(map, &grids)
+}
+
+fn apply(map: &mut RawMap, grids: &[ElevationGrid]) -> Result<()> {
+    let mut roads_per_intersection: BTreeMap<osm::NodeID, Vec<OriginalRoad>> = BTreeMap::new();
+    for id in map.roads.keys() {
+        roads_per_intersection.entry(id.i1).or_default().push(*id);
+        roads_per_intersection.entry(id.i2).or_default().push(*id);
+    }
+
+    let mut profiles: BTreeMap<OriginalRoad, Vec<(Distance, Distance)>> = BTreeMap::new();
+    for (id, road) in &map.roads {
+        if !is_bridge_or_tunnel(&road.osm_tags) {
+            profiles.insert(*id, sample_profile(road, &map.gps_bounds, grids));
+        }
+    }
+
+    // Sample the terrain at every intersection touching a road at ground level
+    let mut known: BTreeMap<osm::NodeID, Distance> = BTreeMap::new();
+    let mut off_ground = BTreeSet::new();
+    let mut missing = BTreeSet::new();
+    for (id, i) in &map.intersections {
+        let roads = roads_per_intersection
+            .get(id)
+            .cloned()
+            .unwrap_or_else(Vec::new);
+        if !roads.is_empty() && roads.iter().all(|r| !profiles.contains_key(r)) {
+            off_ground.insert(*id);
+            continue;
+        }
+        let gps = i.point.to_gps(&map.gps_bounds);
+        if let Some(elevation) = sample(grids, gps) {
+            known.insert(*id, elevation);
+        } else {
+            missing.insert(*id);
+        }
+    }
+    if known.is_empty() {
+        bail!("None of the elevation data covers this map");
+    }
+    if !missing.is_empty() {
+        warn!(
+            "{} intersections aren't covered by elevation data; interpolating them",
+            prettyprint_usize(missing.len())
+        );
+    }
+
+    // Bridges and tunnels only span between their ends. If a bridge is cut off by the map
+    // boundary, fall back to interpolating across anything.
+    let remaining = interpolate(map, &roads_per_intersection, &mut known, off_ground, |r| {
+        is_bridge_or_tunnel(&r.osm_tags)
+    });
+    missing.extend(remaining);
+    let remaining = interpolate(map, &roads_per_intersection, &mut known, missing, |_| true);
+    if !remaining.is_empty() {
+        warn!(
+            "{} intersections aren't connected to anything with elevation data",
+            prettyprint_usize(remaining.len())
+        );
+    }
 
+    for (id, elevation) in known {
+        map.intersections.get_mut(&id).unwrap().elevation = elevation;
+    }
+    set_inclines(map, &profiles);
     Ok(())
 }
 
-fn generate_input(map: &RawMap) -> Result<Vec<OriginalRoad>> {
-    std::fs::create_dir_all("elevation_input")?;
-    let mut f = BufWriter::new(File::create("elevation_input/query")?);
-    let mut ids = Vec::new();
-    for (id, r) in &map.roads {
-        // TODO Handle cul-de-sacs
-        if let Ok(pl) = PolyLine::new(r.center_points.clone()) {
-            ids.push(*id);
-            // Sample points along the road. Smaller step size gives more detail, but is slower.
-            let mut pts = Vec::new();
-            for (pt, _) in pl.step_along(Distance::meters(5.0), Distance::ZERO) {
-                pts.push(pt);
-            }
-            // Always ask for the intersection
-            if *pts.last().unwrap() != pl.last_pt() {
-                pts.push(pl.last_pt());
-            }
-            for (idx, gps) in map.gps_bounds.convert_back(&pts).into_iter().enumerate() {
-                write!(f, "{},{}", gps.x(), gps.y())?;
-                if idx != pts.len() - 1 {
-                    write!(f, " ")?;
-                }
-            }
-            writeln!(f)?;
+/// Samples the terrain along a road, at every point and at least every 10 meters in between.
+/// Returns (distance along the road, elevation), skipping anywhere the data doesn't cover.
+fn sample_profile(
+    road: &RawRoad,
+    gps_bounds: &GPSBounds,
+    grids: &[ElevationGrid],
+) -> Vec<(Distance, Distance)> {
+    let spacing = Distance::meters(10.0);
+    let mut pts = Vec::new();
+    let mut dist = Distance::ZERO;
+    for pair in road.center_points.windows(2) {
+        let length = pair[0].dist_to(pair[1]);
+        let steps = (length / spacing).ceil().max(1.0) as usize;
+        for step in 0..steps {
+            let pct = (step as f64) / (steps as f64);
+            pts.push((
+                dist + length * pct,
+                Pt2D::new(
+                    pair[0].x() + pct * (pair[1].x() - pair[0].x()),
+                    pair[0].y() + pct * (pair[1].y() - pair[0].y()),
+                ),
+            ));
         }
+        dist += length;
     }
-    Ok(ids)
+    if let Some(last) = road.center_points.last() {
+        pts.push((dist, *last));
+    }
+
+    pts.into_iter()
+        .filter_map(|(dist, pt)| {
+            let elevation = sample(grids, pt.to_gps(gps_bounds))?;
+            Some((dist, elevation))
+        })
+        .collect()
 }
 
-fn scrape_output(map: &mut RawMap, ids: Vec<OriginalRoad>) -> Result<()> {
-    let num_ids = ids.len();
-    let mut cnt = 0;
-    for (line, id) in BufReader::new(File::open("elevation_output/query")?)
-        .lines()
-        .zip(ids)
-    {
-        cnt += 1;
-        let line = line?;
-        let mut values = Vec::new();
-        for x in line.split('\t') {
-            if let Ok(x) = x.parse::<f64>() {
-                if !x.is_finite() {
-                    // TODO Warn
-                    continue;
-                }
-                if x < 0.0 {
-                    // TODO Temporary
+/// Uses the most detailed grid covering a point.
+fn sample(grids: &[ElevationGrid], pt: LonLat) -> Option<Distance> {
+    grids.iter().find_map(|grid| grid.sample(pt))
+}
+
+/// Loads every DEM overlapping the map, finest resolution first.
+fn load_grids(map: &RawMap) -> Result<Vec<ElevationGrid>> {
+    let dir = abstio::path_shared_input("elevation");
+    let mut grids = Vec::new();
+    for path in abstio::list_dir(dir.clone()) {
+        let bounds = if let Some(b) = ElevationGrid::peek_bounds(&path) {
+            b
+        } else {
+            continue;
+        };
+        if bounds.min_lon > map.gps_bounds.max_lon
+            || bounds.max_lon < map.gps_bounds.min_lon
+            || bounds.min_lat > map.gps_bounds.max_lat
+            || bounds.max_lat < map.gps_bounds.min_lat
+        {
+            continue;
+        }
+        info!("Loading elevation data from {}", path);
+        grids.push(ElevationGrid::load(&path)?);
+    }
+    if grids.is_empty() {
+        bail!(
+            "No SRTM .hgt or GeoTIFF files in {} cover this map. Download some, like from \
+             https://dwtkns.com/srtm30m/",
+            dir
+        );
+    }
+    grids.sort_by(|a, b| a.resolution().partial_cmp(&b.resolution()).unwrap());
+    Ok(grids)
+}
+
+fn is_bridge_or_tunnel(tags: &Tags) -> bool {
+    // A building passage is a tunnel through a building at ground level
+    (tags.contains_key("bridge") && !tags.is("bridge", "no"))
+        || (tags.contains_key("tunnel") && !tags.is_any("tunnel", vec!["no", "building_passage"]))
+}
+
+/// Searches outwards from every intersection with known elevation at once, along roads that
+/// `can_cross`, to find the two closest known intersections for everything in `unknown`. Each is
+/// weighted by inverse distance, so along a simple chain of roads, this is linear interpolation
+/// between the two ends. Returns the intersections that couldn't reach anything known.
+fn interpolate<F: Fn(&RawRoad) -> bool>(
+    map: &RawMap,
+    roads_per_intersection: &BTreeMap<osm::NodeID, Vec<OriginalRoad>>,
+    known: &mut BTreeMap<osm::NodeID, Distance>,
+    unknown: BTreeSet<osm::NodeID>,
+    can_cross: F,
+) -> BTreeSet<osm::NodeID> {
+    // For each unknown intersection, up to two (source, distance) pairs
+    let mut reached: BTreeMap<osm::NodeID, Vec<(osm::NodeID, Distance)>> = BTreeMap::new();
+    let mut queue = BinaryHeap::new();
+    for node in known.keys() {
+        queue.push(Item {
+            dist: Distance::ZERO,
+            node: *node,
+            source: *node,
+        });
+    }
+    while let Some(Item { dist, node, source }) = queue.pop() {
+        if node != source {
+            let found = reached.entry(node).or_default();
+            if found.len() == 2 || found.iter().any(|(s, _)| *s == source) {
+                continue;
+            }
+            found.push((source, dist));
+        }
+        for id in roads_per_intersection.get(&node).into_iter().flatten() {
+            let road = &map.roads[id];
+            if !can_cross(road) {
+                continue;
+            }
+            // Stop searching past intersections with known elevation
+            let next = if id.i1 == node { id.i2 } else { id.i1 };
+            if !unknown.contains(&next) {
+                continue;
+            }
+            if let Some(found) = reached.get(&next) {
+                if found.len() == 2 || found.iter().any(|(s, _)| *s == source) {
                     continue;
                 }
-                values.push(Distance::meters(x));
-            } else {
-                // Blank lines mean the tool failed to figure out what happened
-                continue;
             }
+            queue.push(Item {
+                dist: dist + road.length(),
+                node: next,
+                source,
+            });
         }
-        if values.len() != 4 {
-            error!("Elevation output line \"{}\" doesn't have 4 numbers", line);
+    }
+
+    let mut results = Vec::new();
+    let mut remaining = BTreeSet::new();
+    for node in unknown {
+        let found = if let Some(found) = reached.remove(&node) {
+            found
+        } else {
+            remaining.insert(node);
             continue;
+        };
+        let mut sum = 0.0;
+        let mut total_weight = 0.0;
+        for (source, dist) in found {
+            // Avoid dividing by zero for degenerate roads
+            let weight = 1.0 / dist.inner_meters().max(0.1);
+            sum += weight * known[&source].inner_meters();
+            total_weight += weight;
         }
-        // TODO Also put total_climb and total_descent on the roads
-        map.intersections.get_mut(&id.i1).unwrap().elevation = values[0];
-        map.intersections.get_mut(&id.i2).unwrap().elevation = values[1];
+        results.push((node, Distance::meters(sum / total_weight)));
     }
-    if cnt != num_ids {
-        bail!("Output had {} lines, but we made {} queries", cnt, num_ids);
+
+    // Don't let interpolated values influence each other
+    known.extend(results);
+    remaining
+}
+
+#[derive(PartialEq)]
+struct Item {
+    dist: Distance,
+    node: osm::NodeID,
+    source: osm::NodeID,
+}
+
+impl PartialOrd for Item {
+    fn partial_cmp(&self, other: &Item) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Eq for Item {}
+
+impl Ord for Item {
+    fn cmp(&self, other: &Item) -> Ordering {
+        // BinaryHeap is a max-heap, so reverse the comparison to get smallest dist first
+        other
+            .dist
+            .partial_cmp(&self.dist)
+            .unwrap()
+            .then_with(|| self.node.cmp(&other.node))
+            .then_with(|| self.source.cmp(&other.source))
     }
+}
 
+fn set_inclines(map: &mut RawMap, profiles: &BTreeMap<OriginalRoad, Vec<(Distance, Distance)>>) {
     // Calculate the incline for each road here, before the road gets trimmed for intersection
     // geometry. If we did this after trimming, we'd miss some of the horizontal distance.
     for (id, road) in &mut map.roads {
-        let rise = map.intersections[&id.i2].elevation - map.intersections[&id.i1].elevation;
-        let run = road.length();
-        if !(rise / run).is_finite() {
+        // Fitting a line through the terrain along the road smooths over noise in the data at
+        // either end. Bridges and tunnels go straight between their ends.
+        let incline = if let Some(slope) = profiles.get(id).and_then(|p| fit_slope(p)) {
+            slope
+        } else {
+            let rise = map.intersections[&id.i2].elevation - map.intersections[&id.i1].elevation;
+            rise / road.length()
+        };
+        if !incline.is_finite() {
             // TODO Warn?
             continue;
         }
-        road.percent_incline = rise / run;
+        road.percent_incline = incline;
         // Per https://wiki.openstreetmap.org/wiki/Key:incline#Common_.26_extreme_inclines, we
         // shouldn't often see values outside a certain range. Adjust this when we import
         // somewhere exceeding this...
@@ -154,6 +295,165 @@ fn scrape_output(map: &mut RawMap, ids: Vec<OriginalRoad>) -> Result<()> {
             );
         }
     }
+}
 
-    Ok(())
+/// The least-squares slope of elevation over distance, if there are at least two samples.
+fn fit_slope(profile: &[(Distance, Distance)]) -> Option<f64> {
+    if profile.len() < 2 {
+        return None;
+    }
+    let n = profile.len() as f64;
+    let mean_x = profile.iter().map(|(x, _)| x.inner_meters()).sum::<f64>() / n;
+    let mean_y = profile.iter().map(|(_, y)| y.inner_meters()).sum::<f64>() / n;
+    let mut numerator = 0.0;
+    let mut denominator = 0.0;
+    for (x, y) in profile {
+        let dx = x.inner_meters() - mean_x;
+        numerator += dx * (y.inner_meters() - mean_y);
+        denominator += dx * dx;
+    }
+    if denominator == 0.0 {
+        return None;
+    }
+    Some(numerator / denominator)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use abstio::MapName;
+    use map_model::raw::RawIntersection;
+    use map_model::IntersectionType;
+
+    const LAT: f64 = 47.0;
+
+    /// A valley running north-south, deepest at column 20, with the east side higher
+    fn valley() -> ElevationGrid {
+        let (width, height) = (41, 21);
+        let mut values = Vec::new();
+        for _ in 0..height {
+            for col in 0..width {
+                values.push(terrain(col as f64) as f32);
+            }
+        }
+        ElevationGrid::synthetic(-122.02, LAT + 0.01, 0.001, width, values)
+    }
+
+    fn terrain(col: f64) -> f64 {
+        2.0 * (col - 20.0).abs() + col
+    }
+
+    fn lon(col: f64) -> f64 {
+        -122.02 + 0.001 * col
+    }
+
+    fn close(actual: Distance, expected: f64) -> bool {
+        (actual.inner_meters() - expected).abs() < 0.01
+    }
+
+    /// Intersections at each column along the middle row, connected in order. Columns past the
+    /// edge of the grid aren't covered.
+    fn make_map(cols: Vec<f64>, bridges: Vec<bool>) -> RawMap {
+        let mut map = RawMap::blank(MapName::new("zz", "test", "elevation"));
+        map.gps_bounds = GPSBounds::from(vec![
+            LonLat::new(lon(-10.0), LAT - 0.01),
+            LonLat::new(lon(60.0), LAT + 0.02),
+        ]);
+        for (idx, col) in cols.iter().enumerate() {
+            map.intersections.insert(
+                osm::NodeID(idx as i64),
+                RawIntersection {
+                    point: LonLat::new(lon(*col), LAT).to_pt(&map.gps_bounds),
+                    intersection_type: IntersectionType::StopSign,
+                    elevation: Distance::ZERO,
+                    trim_roads_for_merging: BTreeMap::new(),
+                },
+            );
+        }
+        for (idx, bridge) in bridges.into_iter().enumerate() {
+            let mut tags = Tags::empty();
+            tags.insert(osm::HIGHWAY, "residential");
+            if bridge {
+                tags.insert("bridge", "yes");
+            }
+            let i1 = osm::NodeID(idx as i64);
+            let i2 = osm::NodeID(idx as i64 + 1);
+            map.roads.insert(
+                OriginalRoad::new(idx as i64, (i1.0, i2.0)),
+                RawRoad {
+                    center_points: vec![map.intersections[&i1].point, map.intersections[&i2].point],
+                    osm_tags: tags,
+                    turn_restrictions: Vec::new(),
+                    complicated_turn_restrictions: Vec::new(),
+                    percent_incline: 0.0,
+                },
+            );
+        }
+        map
+    }
+
+    #[test]
+    fn bilinear_sampling() {
+        let grid = valley();
+        assert!(close(
+            grid.sample(LonLat::new(lon(12.0), LAT)).unwrap(),
+            terrain(12.0)
+        ));
+        // Halfway between two samples
+        assert!(close(
+            grid.sample(LonLat::new(lon(12.5), LAT)).unwrap(),
+            (terrain(12.0) + terrain(13.0)) / 2.0
+        ));
+        assert!(grid.sample(LonLat::new(lon(45.0), LAT)).is_none());
+    }
+
+    #[test]
+    fn bridge_over_valley() {
+        // A ground road, two bridge roads meeting over the bottom of the valley, two more ground
+        // roads, the last of which leaves the grid
+        let mut map = make_map(
+            vec![12.0, 15.0, 20.0, 25.0, 28.0, 45.0],
+            vec![false, true, true, false, false],
+        );
+        apply(&mut map, &[valley()]).unwrap();
+        let elevation = |idx: i64| map.intersections[&osm::NodeID(idx)].elevation;
+
+        assert!(close(elevation(0), terrain(12.0)));
+        assert!(close(elevation(1), terrain(15.0)));
+        // The middle of the bridge is halfway between its ends, not down in the valley
+        assert!(close(elevation(2), (terrain(15.0) + terrain(25.0)) / 2.0));
+        assert!(close(elevation(3), terrain(25.0)));
+        // Beyond the data, use the closest intersection that's covered
+        assert!(close(elevation(5), terrain(28.0)));
+
+        // The terrain is straight along each road covered by the grid
+        for (id, road) in map.roads.iter().take(4) {
+            let rise = map.intersections[&id.i2].elevation - map.intersections[&id.i1].elevation;
+            let expected = rise / road.length();
+            assert!(
+                (road.percent_incline - expected).abs() < 0.001,
+                "{} has incline {}, but expected {}",
+                id,
+                road.percent_incline,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn incline_follows_terrain() {
+        // One ground road across the valley, with both ends at the same height. Most of it is a
+        // gentle descent, followed by a short, steep climb.
+        let mut map = make_map(vec![5.0, 25.0], vec![false]);
+        apply(&mut map, &[valley()]).unwrap();
+        assert!(close(
+            map.intersections[&osm::NodeID(0)].elevation,
+            terrain(25.0)
+        ));
+        let road = map.roads.values().next().unwrap();
+        assert!(road.percent_incline < 0.0);
+        // The profile has a sample at least every 10 meters
+        let profile = sample_profile(road, &map.gps_bounds, &[valley()]);
+        assert!(profile.len() as f64 >= road.length() / Distance::meters(10.0));
+    }
 }
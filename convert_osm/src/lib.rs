@@ -13,6 +13,7 @@ use map_model::{osm, raw, Amenity, MapConfig};
 use serde::{Deserialize, Serialize};
 
 mod clip;
+mod dem;
 mod elevation;
 mod extract;
 pub mod osm_geom;
@@ -39,6 +40,12 @@ pub struct Options {
     /// Only include highways and arterials. This may make sense for some region-wide maps for
     /// particular use cases.
     pub skip_local_roads: bool,
+    /// Split roads at mapped crossings (`highway=crossing` nodes), so people can walk across
+    /// mid-block and drivers yield to them there.
+    pub mid_block_crossings: bool,
+    /// If set, also add informal crossings along long blocks of low-speed roads, about this far
+    /// apart, so people can walk across without detouring to the nearest intersection.
+    pub informal_crossings: Option<Distance>,
 }
 
 /// What roads will have on-street parking lanes? Data from
@@ -78,15 +85,18 @@ pub enum PrivateOffstreetParking {
 
 pub fn convert(opts: Options, timer: &mut abstutil::Timer) -> RawMap {
     let mut map = RawMap::blank(opts.name.clone());
+    map.gps_bounds.projection = opts.map_config.projection;
     if let Some(ref path) = opts.clip {
         let pts = LonLat::read_osmosis_polygon(path).unwrap();
-        let gps_bounds = GPSBounds::from(pts.clone());
+        let mut gps_bounds = GPSBounds::from(pts.clone());
+        gps_bounds.projection = opts.map_config.projection;
+        gps_bounds.resolve_projection().unwrap();
         map.boundary_polygon = Ring::must_new(gps_bounds.convert(&pts)).into_polygon();
         map.gps_bounds = gps_bounds;
     }
 
     let extract = extract::extract_osm(&mut map, &opts, timer);
-    let (amenities, pt_to_road) = split_ways::split_up_roads(&mut map, extract, timer);
+    let (amenities, pt_to_road) = split_ways::split_up_roads(&mut map, extract, &opts, timer);
     clip::clip_map(&mut map, timer);
 
     // Need to do a first pass of removing cul-de-sacs here, or we wind up with loop PolyLines when
@@ -123,6 +133,9 @@ pub fn convert(opts: Options, timer: &mut abstutil::Timer) -> RawMap {
     }
 
     map.config = opts.map_config;
+    // Record what AutoUtm or an EPSG code turned into
+    map.config.projection = map.gps_bounds.projection;
+    map.projection = map.gps_bounds.projection;
     map
 }
 
@@ -55,6 +55,8 @@ pub fn read(path: &str, input_gps_bounds: &GPSBounds, timer: &mut Timer) -> Resu
         relations: BTreeMap::new(),
     };
 
+    let mut projection_resolved = false;
+
     timer.start("scrape objects");
     for obj in tree.descendants() {
         if !obj.is_element() {
@@ -63,7 +65,7 @@ pub fn read(path: &str, input_gps_bounds: &GPSBounds, timer: &mut Timer) -> Resu
         match obj.tag_name().name() {
             "bounds" => {
                 // If we weren't provided with GPSBounds, use this.
-                if doc.gps_bounds != GPSBounds::new() {
+                if !doc.gps_bounds.is_empty() {
                     continue;
                 }
                 doc.gps_bounds.update(LonLat::new(
@@ -76,12 +78,20 @@ pub fn read(path: &str, input_gps_bounds: &GPSBounds, timer: &mut Timer) -> Resu
                 ));
             }
             "node" => {
-                if doc.gps_bounds == GPSBounds::new() {
+                if doc.gps_bounds.is_empty() {
                     warn!(
                         "No clipping polygon provided and the .osm is missing a <bounds> element, \
                          so figuring out the bounds manually."
                     );
+                    let projection = doc.gps_bounds.projection;
                     doc.gps_bounds = scrape_bounds(&tree);
+                    doc.gps_bounds.projection = projection;
+                }
+                // The bounds are final now, so a projection depending on them can be picked
+                if !projection_resolved {
+                    doc.gps_bounds.resolve_projection()?;
+                    info!("Using the {} projection", doc.gps_bounds.projection);
+                    projection_resolved = true;
                 }
 
                 let id = NodeID(obj.attribute("id").unwrap().parse::<i64>().unwrap());
@@ -1,17 +1,19 @@
-use std::collections::{hash_map::Entry, BTreeMap, HashMap};
+use std::collections::{hash_map::Entry, BTreeMap, HashMap, HashSet};
 
 use abstutil::{Counter, Timer};
-use geom::{Distance, HashablePt2D, Pt2D};
+use geom::{Distance, HashablePt2D, PolyLine, Pt2D, Speed};
 use map_model::raw::{OriginalRoad, RawIntersection, RawMap, RawRoad};
 use map_model::{osm, Amenity, Direction, IntersectionType};
 
 use crate::extract::OsmExtract;
+use crate::Options;
 
 /// Returns amenities and a mapping of all points to split road. (Some internal points on roads get
 /// removed in this call, so this mapping isn't redundant.)
 pub fn split_up_roads(
     map: &mut RawMap,
     mut input: OsmExtract,
+    opts: &Options,
     timer: &mut Timer,
 ) -> (Vec<(Pt2D, Amenity)>, HashMap<HashablePt2D, OriginalRoad>) {
     timer.start("splitting up roads");
@@ -55,6 +57,35 @@ pub fn split_up_roads(
         }
     }
 
+    if !opts.mid_block_crossings {
+        input.crossings.clear();
+    }
+    // Mapped crossings in the middle of a road split it too.
+    for (_, r) in &input.roads {
+        if r.is_footway() {
+            continue;
+        }
+        for pt in &r.center_points {
+            let pt = pt.to_hashable();
+            if input.crossings.contains(&pt) {
+                if let Entry::Vacant(e) = pt_to_intersection.entry(pt) {
+                    e.insert(input.osm_node_ids[&pt]);
+                }
+            }
+        }
+    }
+    if let Some(spacing) = opts.informal_crossings {
+        add_informal_crossings(&mut input, &mut pt_to_intersection, spacing);
+    }
+
+    // Anywhere along a roundabout that isn't collapsed, circulating traffic has priority.
+    let mut roundabout_pts: HashSet<HashablePt2D> = HashSet::new();
+    for (_, r) in &input.roads {
+        if r.osm_tags.is("junction", "roundabout") {
+            roundabout_pts.extend(r.center_points.iter().map(|pt| pt.to_hashable()));
+        }
+    }
+
     for (pt, id) in &pt_to_intersection {
         map.intersections.insert(
             *id,
@@ -62,6 +93,8 @@ pub fn split_up_roads(
                 point: pt.to_pt2d(),
                 intersection_type: if input.traffic_signals.remove(pt).is_some() {
                     IntersectionType::TrafficSignal
+                } else if roundabout_pts.contains(pt) && !input.crossings.contains(pt) {
+                    IntersectionType::Roundabout
                 } else {
                     IntersectionType::StopSign
                 },
@@ -72,7 +105,8 @@ pub fn split_up_roads(
         );
     }
 
-    // Set roundabouts to their center
+    // Set roundabouts to their center. The ring is gone, so there's no circulating traffic to
+    // yield to; keep treating these like all-way stops.
     for (id, point) in roundabout_centers {
         map.intersections.insert(
             id,
@@ -112,6 +146,14 @@ pub fn split_up_roads(
                     r.osm_tags
                         .insert(osm::ENDPT_FWD.to_string(), "true".to_string());
                 }
+                if input.crossings.contains(&pts[0].to_hashable()) {
+                    r.osm_tags
+                        .insert(osm::CROSSING_BACK.to_string(), "true".to_string());
+                }
+                if input.crossings.contains(&pt.to_hashable()) {
+                    r.osm_tags
+                        .insert(osm::CROSSING_FWD.to_string(), "true".to_string());
+                }
                 let id = OriginalRoad {
                     osm_way_id: *osm_way_id,
                     i1,
@@ -130,6 +172,8 @@ pub fn split_up_roads(
                 map.roads.insert(id, r.clone());
                 r.osm_tags.remove(osm::ENDPT_FWD);
                 r.osm_tags.remove(osm::ENDPT_BACK);
+                r.osm_tags.remove(osm::CROSSING_FWD);
+                r.osm_tags.remove(osm::CROSSING_BACK);
                 i1 = *i2;
                 pts.push(*pt);
             }
@@ -137,6 +181,29 @@ pub fn split_up_roads(
         assert!(pts.len() == 1);
     }
 
+    // A crossing mapped at a real junction isn't mid-block; the normal crosswalks handle it.
+    let crossings: Vec<osm::NodeID> = input
+        .crossings
+        .iter()
+        .filter_map(|pt| pt_to_intersection.get(pt))
+        .cloned()
+        .collect();
+    for id in crossings {
+        let roads = map.roads_per_intersection(id);
+        if roads.len() == 2 {
+            continue;
+        }
+        for r in roads {
+            let tags = &mut map.roads.get_mut(&r).unwrap().osm_tags;
+            if r.i1 == id {
+                tags.remove(osm::CROSSING_BACK);
+            }
+            if r.i2 == id {
+                tags.remove(osm::CROSSING_FWD);
+            }
+        }
+    }
+
     // Resolve simple turn restrictions (via a node)
     let mut restrictions = Vec::new();
     for (restriction, from_osm, via_osm, to_osm) in input.simple_turn_restrictions {
@@ -238,6 +305,80 @@ pub fn split_up_roads(
     (input.amenities, pt_to_road)
 }
 
+/// Along long blocks of local roads, add informal crossings about `spacing` apart, so pedestrians
+/// don't have to detour to the nearest intersection. These get new OSM node IDs and split up roads
+/// just like mapped crossings.
+fn add_informal_crossings(
+    input: &mut OsmExtract,
+    pt_to_intersection: &mut HashMap<HashablePt2D, osm::NodeID>,
+    spacing: Distance,
+) {
+    let used_ids: HashSet<osm::NodeID> = input
+        .osm_node_ids
+        .values()
+        .filter(|id| id.0 < 0)
+        .cloned()
+        .collect();
+    let mut next_id = -1;
+
+    for (_, r) in &mut input.roads {
+        if !is_low_speed(r)
+            || r.is_footway()
+            || r.is_service()
+            || r.osm_tags.is("junction", "roundabout")
+        {
+            continue;
+        }
+
+        let mut pts = vec![r.center_points[0]];
+        let mut block = vec![r.center_points[0]];
+        for pt in r.center_points.iter().skip(1) {
+            block.push(*pt);
+            if !pt_to_intersection.contains_key(&pt.to_hashable()) {
+                continue;
+            }
+            // The block ends here
+            let block_pts = std::mem::replace(&mut block, vec![*pt]);
+            let pl = match PolyLine::new(block_pts.clone()) {
+                Ok(pl) => pl,
+                Err(_) => {
+                    pts.extend(block_pts.into_iter().skip(1));
+                    continue;
+                }
+            };
+            let num_pieces = (pl.length() / spacing).floor() as usize;
+            if num_pieces < 2 {
+                pts.extend(block_pts.into_iter().skip(1));
+                continue;
+            }
+            let piece_length = pl.length() / (num_pieces as f64);
+            for idx in 0..num_pieces {
+                let mut piece = pl
+                    .exact_slice(
+                        piece_length * (idx as f64),
+                        piece_length * ((idx + 1) as f64),
+                    )
+                    .into_points();
+                if idx == num_pieces - 1 {
+                    // Make sure the block still ends exactly at the intersection
+                    piece.pop();
+                    piece.push(*pt);
+                } else {
+                    while used_ids.contains(&osm::NodeID(next_id)) {
+                        next_id -= 1;
+                    }
+                    let crossing = piece.last().unwrap().to_hashable();
+                    pt_to_intersection.insert(crossing, osm::NodeID(next_id));
+                    input.crossings.insert(crossing);
+                    next_id -= 1;
+                }
+                pts.extend(piece.into_iter().skip(1));
+            }
+        }
+        r.center_points = pts;
+    }
+}
+
 // TODO Consider doing this in PolyLine::new always. extend() there does this too.
 fn dedupe_angles(pts: Vec<Pt2D>) -> Vec<Pt2D> {
     let mut result: Vec<Pt2D> = Vec::new();
@@ -255,6 +396,22 @@ fn dedupe_angles(pts: Vec<Pt2D>) -> Vec<Pt2D> {
     result
 }
 
+/// People only cross informally where traffic is slow. If the speed limit isn't tagged, guess from
+/// the type of road.
+fn is_low_speed(r: &RawRoad) -> bool {
+    if let Some(speed) = r
+        .osm_tags
+        .get(osm::MAXSPEED)
+        .and_then(|x| osm::parse_maxspeed(x))
+    {
+        return speed <= Speed::miles_per_hour(25.0);
+    }
+    r.osm_tags
+        .get(osm::HIGHWAY)
+        .map(|hwy| osm::RoadRank::from_highway(hwy) == osm::RoadRank::Local)
+        .unwrap_or(false)
+}
+
 /// Many "roundabouts" like https://www.openstreetmap.org/way/427144965 are so tiny that they wind
 /// up with ridiculous geometry and cause constant gridlock.
 ///
@@ -16,7 +16,7 @@ use map_model::{
 use sim::{Sim, TripEndpoint};
 use widgetry::{
     lctrl, Cached, Choice, Color, DrawBaselayer, Drawable, EventCtx, GeomBatch, GfxCtx,
-    HorizontalAlignment, Key, Line, Outcome, Panel, ScreenDims, State, Text, Toggle, UpdateType,
+    HorizontalAlignment, Key, Line, Outcome, Panel, State, Text, Toggle, UpdateType,
     VerticalAlignment, Widget,
 };
 
@@ -78,10 +78,14 @@ impl DebugMode {
                         .text("unhide everything")
                         .hotkey(lctrl(Key::H))
                         .build_def(ctx),
-                    ctx.style()
-                        .btn_outline
-                        .text("screenshot everything (for leaflet)")
-                        .build_def(ctx),
+                    if cfg!(not(target_arch = "wasm32")) {
+                        ctx.style()
+                            .btn_outline
+                            .text("screenshot everything (for leaflet)")
+                            .build_def(ctx)
+                    } else {
+                        Widget::nothing()
+                    },
                     ctx.style()
                         .btn_outline
                         .text("screenshot all of the everything")
@@ -232,10 +236,14 @@ impl State<App> for DebugMode {
                                 .primary
                                 .sim
                                 .find_previous_savestate(app.primary.sim.time());
-                            match prev_state
-                                .clone()
-                                .and_then(|path| Sim::load_savestate(path, &mut timer).ok())
-                            {
+                            match prev_state.clone().and_then(|path| {
+                                Sim::load_savestate(
+                                    path,
+                                    &app.primary.current_flags.sim_flags.opts,
+                                    &mut timer,
+                                )
+                                .ok()
+                            }) {
                                 Some(new_sim) => {
                                     app.primary.sim = new_sim;
                                     app.recalculate_current_selection(ctx);
@@ -259,10 +267,14 @@ impl State<App> for DebugMode {
                     if let Some(t) = ctx.loading_screen("load next savestate", |ctx, mut timer| {
                         let next_state =
                             app.primary.sim.find_next_savestate(app.primary.sim.time());
-                        match next_state
-                            .clone()
-                            .and_then(|path| Sim::load_savestate(path, &mut timer).ok())
-                        {
+                        match next_state.clone().and_then(|path| {
+                            Sim::load_savestate(
+                                path,
+                                &app.primary.current_flags.sim_flags.opts,
+                                &mut timer,
+                            )
+                            .ok()
+                        }) {
                             Some(new_sim) => {
                                 app.primary.sim = new_sim;
                                 app.recalculate_current_selection(ctx);
@@ -288,8 +300,12 @@ impl State<App> for DebugMode {
                             let ss_path = format!("{}/{}.bin", app.primary.sim.save_dir(), ss);
 
                             ctx.loading_screen("load savestate", |ctx, mut timer| {
-                                app.primary.sim = Sim::load_savestate(ss_path, &mut timer)
-                                    .expect("Can't load savestate");
+                                app.primary.sim = Sim::load_savestate(
+                                    ss_path,
+                                    &app.primary.current_flags.sim_flags.opts,
+                                    &mut timer,
+                                )
+                                .expect("Can't load savestate");
                                 app.recalculate_current_selection(ctx);
                             });
                             Transition::Pop
@@ -313,10 +329,19 @@ impl State<App> for DebugMode {
                     self.search_results = None;
                     self.reset_info(ctx);
                 }
+                #[cfg(not(target_arch = "wasm32"))]
                 "screenshot everything (for leaflet)" => {
                     app.change_color_scheme(ctx, ColorSchemeChoice::DayMode);
-                    export_for_leaflet(ctx, app);
-                    return Transition::Keep;
+                    return Transition::Push(match export_for_leaflet(app) {
+                        Ok(dir) => PopupMsg::new_state(
+                            ctx,
+                            "Screenshots taken",
+                            vec![format!("Wrote tiles to {}", dir)],
+                        ),
+                        Err(err) => {
+                            PopupMsg::new_state(ctx, "Screenshots failed", vec![err.to_string()])
+                        }
+                    });
                 }
                 "screenshot all of the everything" => {
                     return Transition::Push(ScreenshotTest::new_state(
@@ -992,26 +1017,32 @@ impl State<App> for ScreenshotTest {
     fn draw(&self, _: &mut GfxCtx, _: &App) {}
 }
 
-fn export_for_leaflet(ctx: &mut EventCtx, app: &App) {
+/// Renders tiles offscreen, so this doesn't depend on the window size or video memory. Returns
+/// the directory containing every zoom level.
+#[cfg(not(target_arch = "wasm32"))]
+fn export_for_leaflet(app: &App) -> anyhow::Result<String> {
     let name = app.primary.map.get_name();
     let bounds = app.primary.map.get_bounds();
     let map_length = bounds.width().max(bounds.height());
+    let dir = format!(
+        "screenshots/{}/{}/{}",
+        name.city.country, name.city.city, name.map
+    );
 
     // At zoom level N, the entire map fits into (N + 1) * (N + 1) tiles
     for zoom_level in 0..=25 {
         let num_tiles = zoom_level + 1;
         // How do we fit the entire map_length into this many tiles?
         let zoom = 256.0 * (num_tiles as f64) / map_length;
-        ctx.request_update(UpdateType::ScreenCaptureEverything {
-            dir: format!(
-                "screenshots/{}/{}/{}/{}",
-                name.city.country, name.city.city, name.map, zoom_level
-            ),
+        map_gui::tools::screenshot_tiles(
+            app,
+            &format!("{}/{}", dir, zoom_level),
             zoom,
-            dims: ScreenDims::new(256.0, 256.0),
-            leaflet_naming: true,
-        });
+            widgetry::ScreenDims::new(256.0, 256.0),
+            true,
+        )?;
     }
+    Ok(dir)
 }
 
 fn draw_banned_turns(ctx: &mut EventCtx, app: &App) -> Drawable {
@@ -6,7 +6,7 @@ use map_gui::options::OptionsPanel;
 use map_gui::render::DrawMap;
 use map_gui::tools::{grey_out_map, ChooseSomething, ColorLegend, PopupMsg};
 use map_gui::ID;
-use map_model::{EditCmd, IntersectionID, LaneID, MapEdits};
+use map_model::{ControlStopSign, EditCmd, EditIntersection, IntersectionID, LaneID, MapEdits};
 use widgetry::{
     lctrl, Choice, Color, ControlState, Drawable, EventCtx, GfxCtx, HorizontalAlignment, Image,
     Key, Line, Menu, Outcome, Panel, State, Text, TextBox, TextExt, VerticalAlignment, Widget,
@@ -168,7 +168,8 @@ impl State<App> for EditMode {
                 Some(ID::Lane(l)) => !self.mode.can_edit_roads() || !can_edit_lane(app, l),
                 Some(ID::Intersection(i)) => {
                     !self.mode.can_edit_stop_signs()
-                        && app.primary.map.maybe_get_stop_sign(i).is_some()
+                        && (app.primary.map.maybe_get_stop_sign(i).is_some()
+                            || app.primary.map.get_i(i).is_roundabout())
                 }
                 Some(ID::Road(_)) => false,
                 _ => true,
@@ -792,6 +793,22 @@ pub fn maybe_edit_intersection(
         ));
     }
 
+    if app.primary.map.get_i(id).is_roundabout()
+        && mode.can_edit_stop_signs()
+        && app
+            .per_obj
+            .left_click(ctx, "convert roundabout to stop signs")
+    {
+        let mut edits = app.primary.map.get_edits().clone();
+        edits.commands.push(EditCmd::ChangeIntersection {
+            i: id,
+            old: app.primary.map.get_i_edit(id),
+            new: EditIntersection::StopSign(ControlStopSign::new(&app.primary.map, id)),
+        });
+        apply_map_edits(ctx, app, edits);
+        return Some(StopSignEditor::new_state(ctx, app, id, mode.clone()));
+    }
+
     if app.primary.map.get_i(id).is_closed()
         && app.per_obj.left_click(ctx, "re-open closed intersection")
     {
@@ -68,6 +68,17 @@ impl StopSignEditor {
                 .btn_outline
                 .text("convert to traffic signal")
                 .build_def(ctx),
+            ctx.style()
+                .btn_outline
+                .text("convert to roundabout")
+                .disabled(
+                    !app.primary
+                        .map
+                        .get_i(id)
+                        .is_on_roundabout_ring(&app.primary.map),
+                )
+                .disabled_tooltip("Only intersections along the ring of a roundabout can yield to circulating traffic")
+                .build_def(ctx),
             ctx.style()
                 .btn_solid_primary
                 .text("Finish")
@@ -148,6 +159,16 @@ impl SimpleState<App> for StopSignEditor {
                     self.mode.clone(),
                 ))
             }
+            "convert to roundabout" => {
+                let mut edits = app.primary.map.get_edits().clone();
+                edits.commands.push(EditCmd::ChangeIntersection {
+                    i: self.id,
+                    old: app.primary.map.get_i_edit(self.id),
+                    new: EditIntersection::Roundabout,
+                });
+                apply_map_edits(ctx, app, edits);
+                Transition::Pop
+            }
             _ => unreachable!(),
         }
     }
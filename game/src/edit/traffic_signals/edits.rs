@@ -1,7 +1,8 @@
 use geom::Duration;
 use map_gui::tools::{ChooseSomething, FilePicker, PopupMsg};
 use map_model::{
-    ControlStopSign, ControlTrafficSignal, EditCmd, EditIntersection, IntersectionID, StageType,
+    ControlStopSign, ControlTrafficSignal, EditCmd, EditIntersection, IntersectionID,
+    PedestrianTiming, StageType,
 };
 use widgetry::{
     Choice, DrawBaselayer, EventCtx, Key, Line, Panel, SimpleState, Spinner, State, Text, TextExt,
@@ -15,6 +16,7 @@ use crate::sandbox::GameplayMode;
 
 pub struct ChangeDuration {
     idx: usize,
+    min_crossing_time: Duration,
 }
 
 impl ChangeDuration {
@@ -91,6 +93,7 @@ impl ChangeDuration {
             .padding(10)
             .bg(app.cs.inner_panel_bg)
             .outline(ctx.style().section_outline),
+            pedestrian_timing_widget(ctx, app, signal, idx),
             ctx.style()
                 .btn_solid_primary
                 .text("Apply")
@@ -98,12 +101,18 @@ impl ChangeDuration {
                 .build_def(ctx),
         ]))
         .build(ctx);
-        <dyn SimpleState<_>>::new_state(panel, Box::new(ChangeDuration { idx }))
+        <dyn SimpleState<_>>::new_state(
+            panel,
+            Box::new(ChangeDuration {
+                idx,
+                min_crossing_time: signal.get_min_crossing_time(idx),
+            }),
+        )
     }
 }
 
 impl SimpleState<App> for ChangeDuration {
-    fn on_click(&mut self, _: &mut EventCtx, _: &mut App, x: &str, panel: &Panel) -> Transition {
+    fn on_click(&mut self, ctx: &mut EventCtx, _: &mut App, x: &str, panel: &Panel) -> Transition {
         match x {
             "close" => Transition::Pop,
             "Apply" => {
@@ -115,6 +124,37 @@ impl SimpleState<App> for ChangeDuration {
                 } else {
                     StageType::Variable(dt, delay, additional)
                 };
+                let walk = panel.spinner("walk");
+                let new_timing = if walk == Duration::ZERO {
+                    None
+                } else {
+                    let timing = PedestrianTiming {
+                        leading_interval: panel.spinner("leading interval"),
+                        walk,
+                        flashing_dont_walk: panel.spinner("flashing don't walk"),
+                    };
+                    if timing.flashing_dont_walk < self.min_crossing_time {
+                        return Transition::Push(PopupMsg::new_state(
+                            ctx,
+                            "Error",
+                            vec![format!(
+                                "Flashing don't walk must last at least {}, the time to cross",
+                                self.min_crossing_time
+                            )],
+                        ));
+                    }
+                    if (timing.walk + timing.flashing_dont_walk).max(timing.leading_interval) > dt {
+                        return Transition::Push(PopupMsg::new_state(
+                            ctx,
+                            "Error",
+                            vec![format!(
+                                "The pedestrian timing doesn't fit in the stage, which lasts {}",
+                                dt
+                            )],
+                        ));
+                    }
+                    Some(timing)
+                };
                 let idx = self.idx;
                 Transition::Multi(vec![
                     Transition::Pop,
@@ -122,6 +162,7 @@ impl SimpleState<App> for ChangeDuration {
                         let editor = state.downcast_mut::<TrafficSignalEditor>().unwrap();
                         editor.add_new_edit(ctx, app, idx, |ts| {
                             ts.stages[idx].stage_type = new_type.clone();
+                            ts.stages[idx].pedestrian_timing = new_timing.clone();
                         });
                     })),
                 ])
@@ -168,6 +209,59 @@ impl SimpleState<App> for ChangeDuration {
     }
 }
 
+fn pedestrian_timing_widget(
+    ctx: &mut EventCtx,
+    app: &App,
+    signal: &ControlTrafficSignal,
+    idx: usize,
+) -> Widget {
+    let timing = signal.stages[idx].pedestrian_timing.as_ref();
+    Widget::col(vec![
+        Text::from_all(vec![
+            Line("Pedestrian signals").small_heading(),
+            Line(" (Set the walk interval to 0 to let people cross any time)"),
+        ])
+        .into_widget(ctx),
+        Widget::row(vec![
+            "Leading pedestrian interval:"
+                .text_widget(ctx)
+                .centered_vert(),
+            Spinner::widget(
+                ctx,
+                "leading interval",
+                (Duration::ZERO, Duration::seconds(30.0)),
+                timing.map(|t| t.leading_interval).unwrap_or(Duration::ZERO),
+                Duration::seconds(1.0),
+            ),
+        ]),
+        Widget::row(vec![
+            "Walk:".text_widget(ctx).centered_vert(),
+            Spinner::widget(
+                ctx,
+                "walk",
+                (Duration::ZERO, Duration::minutes(5)),
+                timing.map(|t| t.walk).unwrap_or(Duration::ZERO),
+                Duration::seconds(1.0),
+            ),
+        ]),
+        Widget::row(vec![
+            "Flashing don't walk:".text_widget(ctx).centered_vert(),
+            Spinner::widget(
+                ctx,
+                "flashing don't walk",
+                (Duration::ZERO, Duration::minutes(5)),
+                timing
+                    .map(|t| t.flashing_dont_walk)
+                    .unwrap_or_else(|| signal.get_min_crossing_time(idx)),
+                Duration::seconds(1.0),
+            ),
+        ]),
+    ])
+    .padding(10)
+    .bg(app.cs.inner_panel_bg)
+    .outline(ctx.style().section_outline)
+}
+
 pub fn edit_entire_signal(
     ctx: &mut EventCtx,
     app: &App,
@@ -1,8 +1,10 @@
-use map_gui::tools::{grey_out_map, HeatmapOptions};
+use map_gui::tools::{
+    export_map, grey_out_map, nice_map_name, ExportLayer, ExportOptions, HeatmapOptions, PopupMsg,
+};
 use sim::AgentType;
 use widgetry::{
-    DrawBaselayer, EventCtx, GfxCtx, HorizontalAlignment, Image, Key, Line, Outcome, Panel, State,
-    TextExt, VerticalAlignment, Widget,
+    DrawBaselayer, EventCtx, GfxCtx, HorizontalAlignment, Image, Key, Line, Outcome, Panel,
+    Spinner, State, TextExt, VerticalAlignment, Widget,
 };
 
 use crate::app::{App, Transition};
@@ -28,6 +30,10 @@ pub trait Layer {
     fn draw(&self, g: &mut GfxCtx, app: &App);
     // Just draw contents and do it always
     fn draw_minimap(&self, g: &mut GfxCtx);
+    /// Describes the contents for exporting to SVG or PDF. Not every layer supports this yet.
+    fn export_vector(&self, _: &App) -> Option<ExportLayer> {
+        None
+    }
 }
 
 impl dyn Layer {
@@ -145,6 +151,18 @@ impl PickLayer {
             .evenly_spaced(),
         );
 
+        if cfg!(not(target_arch = "wasm32")) {
+            let fit = ExportOptions::fit_page(ctx.canvas.get_screen_bounds());
+            col.push(Widget::row(vec![
+                "Export the current view at 1:"
+                    .text_widget(ctx)
+                    .centered_vert(),
+                Spinner::widget(ctx, "export scale", (500, 500_000), fit.scale as usize, 500),
+                ctx.style().btn_outline.text("export to SVG").build_def(ctx),
+                ctx.style().btn_outline.text("export to PDF").build_def(ctx),
+            ]));
+        }
+
         Box::new(PickLayer {
             panel: Panel::new_builder(Widget::col(col))
                 .exact_size_percent(35, 70)
@@ -251,6 +269,42 @@ impl State<App> for PickLayer {
                 "commuter patterns" => {
                     return Transition::Replace(dashboards::CommuterPatterns::new_state(ctx, app));
                 }
+                "export to SVG" | "export to PDF" => {
+                    let mut opts = ExportOptions::fit_page(ctx.canvas.get_screen_bounds());
+                    opts.scale = self.panel.spinner::<usize>("export scale") as f64;
+                    opts.title = Some(nice_map_name(app.primary.map.get_name()).to_string());
+                    let mut layers = Vec::new();
+                    let mut unsupported = None;
+                    if let Some(ref layer) = app.primary.layer {
+                        match layer.export_vector(app) {
+                            Some(l) => layers.push(l),
+                            None => {
+                                unsupported = Some(layer.name().unwrap_or("current"));
+                            }
+                        }
+                    }
+                    let doc = export_map(ctx, app, &opts, layers);
+                    let path = format!(
+                        "{}.{}",
+                        app.primary.map.get_name().as_filename(),
+                        if x == "export to SVG" { "svg" } else { "pdf" }
+                    );
+                    return Transition::Replace(match doc.save(&path) {
+                        Ok(()) => {
+                            let mut lines = vec![format!("Wrote {}", path)];
+                            if let Some(name) = unsupported {
+                                lines.push(format!(
+                                    "The {} layer can't be exported yet, so only the map is included",
+                                    name
+                                ));
+                            }
+                            PopupMsg::new_state(ctx, "Exported", lines)
+                        }
+                        Err(err) => {
+                            PopupMsg::new_state(ctx, "Export failed", vec![err.to_string()])
+                        }
+                    });
+                }
                 _ => unreachable!(),
             },
             _ => {
@@ -5,7 +5,7 @@ use maplit::btreeset;
 use abstutil::{prettyprint_usize, Counter};
 use geom::{Circle, Distance, Duration, Percent, Polygon, Pt2D, Time};
 use map_gui::render::unzoomed_agent_radius;
-use map_gui::tools::{ColorLegend, ColorNetwork, DivergingScale};
+use map_gui::tools::{ColorLegend, ColorNetwork, DivergingScale, ExportLayer, ExportLegend};
 use map_gui::ID;
 use map_model::{IntersectionID, Map, Traversable};
 use sim::{AgentType, VehicleType};
@@ -46,25 +46,20 @@ impl Layer for Backpressure {
     fn draw_minimap(&self, g: &mut GfxCtx) {
         g.redraw(&self.unzoomed);
     }
+    fn export_vector(&self, app: &App) -> Option<ExportLayer> {
+        Some(ExportLayer {
+            name: "Backpressure".to_string(),
+            batch: Backpressure::colorer(app).zoomed,
+            legend: ExportLegend::Gradient(
+                app.cs.good_to_bad_red.0.clone(),
+                vec!["lowest count".to_string(), "highest".to_string()],
+            ),
+        })
+    }
 }
 
 impl Backpressure {
     pub fn new(ctx: &mut EventCtx, app: &App) -> Backpressure {
-        let mut cnt_per_r = Counter::new();
-        let mut cnt_per_i = Counter::new();
-        for path in app.primary.sim.get_all_driving_paths() {
-            for step in path.get_steps() {
-                match step.as_traversable() {
-                    Traversable::Lane(l) => {
-                        cnt_per_r.inc(app.primary.map.get_l(l).parent);
-                    }
-                    Traversable::Turn(t) => {
-                        cnt_per_i.inc(t.parent);
-                    }
-                }
-            }
-        }
-
         let panel = Panel::new_builder(Widget::col(vec![
             header(ctx, "Backpressure"),
             Text::from(
@@ -82,10 +77,7 @@ impl Backpressure {
         .aligned_pair(PANEL_PLACEMENT)
         .build(ctx);
 
-        let mut colorer = ColorNetwork::new(app);
-        colorer.pct_roads(cnt_per_r, &app.cs.good_to_bad_red);
-        colorer.pct_intersections(cnt_per_i, &app.cs.good_to_bad_red);
-        let (unzoomed, zoomed) = colorer.build(ctx);
+        let (unzoomed, zoomed) = Backpressure::colorer(app).build(ctx);
 
         Backpressure {
             time: app.primary.sim.time(),
@@ -94,6 +86,28 @@ impl Backpressure {
             panel,
         }
     }
+
+    fn colorer(app: &App) -> ColorNetwork {
+        let mut cnt_per_r = Counter::new();
+        let mut cnt_per_i = Counter::new();
+        for path in app.primary.sim.get_all_driving_paths() {
+            for step in path.get_steps() {
+                match step.as_traversable() {
+                    Traversable::Lane(l) => {
+                        cnt_per_r.inc(app.primary.map.get_l(l).parent);
+                    }
+                    Traversable::Turn(t) => {
+                        cnt_per_i.inc(t.parent);
+                    }
+                }
+            }
+        }
+
+        let mut colorer = ColorNetwork::new(app);
+        colorer.pct_roads(cnt_per_r, &app.cs.good_to_bad_red);
+        colorer.pct_intersections(cnt_per_i, &app.cs.good_to_bad_red);
+        colorer
+    }
 }
 
 pub struct Throughput {
@@ -199,13 +213,20 @@ impl Layer for Throughput {
     fn draw_minimap(&self, g: &mut GfxCtx) {
         g.redraw(&self.unzoomed);
     }
+    fn export_vector(&self, app: &App) -> Option<ExportLayer> {
+        Some(ExportLayer {
+            name: "Throughput".to_string(),
+            batch: Throughput::colorer(app, &self.agent_types).zoomed,
+            legend: ExportLegend::Gradient(
+                app.cs.good_to_bad_red.0.clone(),
+                vec!["0".to_string(), "highest".to_string()],
+            ),
+        })
+    }
 }
 
 impl Throughput {
     pub fn new(ctx: &mut EventCtx, app: &App, agent_types: BTreeSet<AgentType>) -> Throughput {
-        let stats = &app.primary.sim.get_analytics();
-        let road_counter = stats.road_thruput.all_total_counts(&agent_types);
-        let intersection_counter = stats.intersection_thruput.all_total_counts(&agent_types);
         let panel = Panel::new_builder(Widget::col(vec![
             header(ctx, "Throughput"),
             Text::from(Line("This counts all people crossing since midnight").secondary())
@@ -235,10 +256,7 @@ impl Throughput {
         .aligned_pair(PANEL_PLACEMENT)
         .build(ctx);
 
-        let mut colorer = ColorNetwork::new(app);
-        colorer.ranked_roads(road_counter, &app.cs.good_to_bad_red);
-        colorer.ranked_intersections(intersection_counter, &app.cs.good_to_bad_red);
-        let (unzoomed, zoomed) = colorer.build(ctx);
+        let (unzoomed, zoomed) = Throughput::colorer(app, &agent_types).build(ctx);
 
         Throughput {
             time: app.primary.sim.time(),
@@ -249,6 +267,16 @@ impl Throughput {
             panel,
         }
     }
+
+    fn colorer<'a>(app: &'a App, agent_types: &BTreeSet<AgentType>) -> ColorNetwork<'a> {
+        let stats = &app.primary.sim.get_analytics();
+        let road_counter = stats.road_thruput.all_total_counts(agent_types);
+        let intersection_counter = stats.intersection_thruput.all_total_counts(agent_types);
+        let mut colorer = ColorNetwork::new(app);
+        colorer.ranked_roads(road_counter, &app.cs.good_to_bad_red);
+        colorer.ranked_intersections(intersection_counter, &app.cs.good_to_bad_red);
+        colorer
+    }
 }
 
 pub struct CompareThroughput {
@@ -586,16 +614,45 @@ impl Layer for Delay {
     fn draw_minimap(&self, g: &mut GfxCtx) {
         g.redraw(&self.unzoomed);
     }
+    fn export_vector(&self, app: &App) -> Option<ExportLayer> {
+        Some(ExportLayer {
+            name: "Delay per agent (minutes)".to_string(),
+            batch: Delay::draw_delays(app),
+            legend: ExportLegend::Gradient(
+                app.cs.good_to_bad_red.0.clone(),
+                vec!["0", "5", "10", "15+"]
+                    .into_iter()
+                    .map(|x| x.to_string())
+                    .collect(),
+            ),
+        })
+    }
 }
 
 impl Delay {
     pub fn new(ctx: &mut EventCtx, app: &App) -> Delay {
-        let mut delays = app.primary.sim.all_waiting_people();
         let mut unzoomed = GeomBatch::new();
         unzoomed.push(
             app.cs.fade_map_dark,
             app.primary.map.get_boundary_polygon().clone(),
         );
+        unzoomed.append(Delay::draw_delays(app));
+
+        Delay {
+            time: app.primary.sim.time(),
+            unzoomed: ctx.upload(unzoomed),
+            panel: Panel::new_builder(Widget::col(vec![
+                header(ctx, "Delay per agent (minutes)"),
+                ColorLegend::gradient(ctx, &app.cs.good_to_bad_red, vec!["0", "5", "10", "15+"]),
+            ]))
+            .aligned_pair(PANEL_PLACEMENT)
+            .build(ctx),
+        }
+    }
+
+    fn draw_delays(app: &App) -> GeomBatch {
+        let mut delays = app.primary.sim.all_waiting_people();
+        let mut batch = GeomBatch::new();
         // A bit of copied code from draw_unzoomed_agents
         let car_circle = Circle::new(
             Pt2D::new(0.0, 0.0),
@@ -610,22 +667,13 @@ impl Delay {
                     .good_to_bad_red
                     .eval((delay / Duration::minutes(15)).min(1.0));
                 if agent.id.to_vehicle_type().is_some() {
-                    unzoomed.push(color, car_circle.translate(agent.pos.x(), agent.pos.y()));
+                    batch.push(color, car_circle.translate(agent.pos.x(), agent.pos.y()));
                 } else {
-                    unzoomed.push(color, ped_circle.translate(agent.pos.x(), agent.pos.y()));
+                    batch.push(color, ped_circle.translate(agent.pos.x(), agent.pos.y()));
                 }
             }
         }
 
-        Delay {
-            time: app.primary.sim.time(),
-            unzoomed: ctx.upload(unzoomed),
-            panel: Panel::new_builder(Widget::col(vec![
-                header(ctx, "Delay per agent (minutes)"),
-                ColorLegend::gradient(ctx, &app.cs.good_to_bad_red, vec!["0", "5", "10", "15+"]),
-            ]))
-            .aligned_pair(PANEL_PLACEMENT)
-            .build(ctx),
-        }
+        batch
     }
 }
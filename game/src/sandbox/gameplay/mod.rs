@@ -183,7 +183,9 @@ impl GameplayMode {
                 }
                 EditCmd::ChangeIntersection { ref new, .. } => match new {
                     // TODO Conflating construction
-                    EditIntersection::StopSign(_) | EditIntersection::Closed => {
+                    EditIntersection::StopSign(_)
+                    | EditIntersection::Roundabout
+                    | EditIntersection::Closed => {
                         if !self.can_edit_stop_signs() {
                             return false;
                         }
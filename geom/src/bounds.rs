@@ -1,8 +1,12 @@
+use std::borrow::Cow;
+
+use anyhow::Result;
 use serde::{Deserialize, Serialize};
 
 use aabb_quadtree::geom::{Point, Rect};
 
-use crate::{Distance, LonLat, Polygon, Pt2D, Ring};
+use crate::projection::TransverseMercator;
+use crate::{Distance, LonLat, Polygon, Projection, Pt2D, Ring};
 
 /// Represents a rectangular boundary of `Pt2D` points.
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
@@ -118,12 +122,55 @@ impl Bounds {
 /// Represents a rectangular boundary of `LonLat` points. After building one of these, `LonLat`s
 /// can be transformed into `Pt2D`s, treating the top-left of the boundary as (0, 0), and growing
 /// to the right and down (screen-drawing order, not Cartesian) in meters.
-#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+///
+/// The projection isn't stored with the limits, so files written before projections existed still
+/// load, using `Projection::Linear`. Maps and raw maps store it separately; see
+/// `deserialize_trailing_projection`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(from = "SerializedGPSBounds")]
 pub struct GPSBounds {
     pub min_lon: f64,
     pub min_lat: f64,
     pub max_lon: f64,
     pub max_lat: f64,
+    /// How `LonLat`s are flattened into map-space
+    #[serde(skip_serializing, skip_deserializing)]
+    pub projection: Projection,
+    /// Calculated once the projection is resolved, so that converting each point doesn't redo
+    /// it. Ignored if the fields above have changed since.
+    #[serde(skip_serializing, skip_deserializing)]
+    frame: Option<Frame>,
+}
+
+/// The fields of `GPSBounds` that're stored, in the same order.
+#[derive(Deserialize)]
+struct SerializedGPSBounds {
+    min_lon: f64,
+    min_lat: f64,
+    max_lon: f64,
+    max_lat: f64,
+}
+
+impl From<SerializedGPSBounds> for GPSBounds {
+    fn from(b: SerializedGPSBounds) -> GPSBounds {
+        GPSBounds::from_limits(
+            b.min_lon,
+            b.min_lat,
+            b.max_lon,
+            b.max_lat,
+            Projection::Linear,
+        )
+    }
+}
+
+impl PartialEq for GPSBounds {
+    fn eq(&self, other: &GPSBounds) -> bool {
+        self.min_lon == other.min_lon
+            && self.min_lat == other.min_lat
+            && self.max_lon == other.max_lon
+            && self.max_lat == other.max_lat
+            && self.projection == other.projection
+    }
 }
 
 impl GPSBounds {
@@ -134,9 +181,31 @@ impl GPSBounds {
             min_lat: f64::MAX,
             max_lon: f64::MIN,
             max_lat: f64::MIN,
+            projection: Projection::default(),
+            frame: None,
         }
     }
 
+    /// A boundary with exactly these limits.
+    pub fn from_limits(
+        min_lon: f64,
+        min_lat: f64,
+        max_lon: f64,
+        max_lat: f64,
+        projection: Projection,
+    ) -> GPSBounds {
+        let mut bounds = GPSBounds {
+            min_lon,
+            min_lat,
+            max_lon,
+            max_lat,
+            projection: Projection::Linear,
+            frame: None,
+        };
+        bounds.restore_projection(projection);
+        bounds
+    }
+
     /// Create a boundary covering some points.
     pub fn from(pts: Vec<LonLat>) -> GPSBounds {
         let mut b = GPSBounds::new();
@@ -154,6 +223,11 @@ impl GPSBounds {
         self.max_lat = self.max_lat.max(pt.y());
     }
 
+    /// True if no points have been added to the boundary yet.
+    pub fn is_empty(&self) -> bool {
+        self.min_lon > self.max_lon || self.min_lat > self.max_lat
+    }
+
     /// True if the point is within the boundary.
     pub fn contains(&self, pt: LonLat) -> bool {
         pt.x() >= self.min_lon
@@ -162,14 +236,34 @@ impl GPSBounds {
             && pt.y() <= self.max_lat
     }
 
+    /// The midpoint of the boundary, in longitude and latitude.
+    pub fn center(&self) -> LonLat {
+        LonLat::new(
+            (self.min_lon + self.max_lon) / 2.0,
+            (self.min_lat + self.max_lat) / 2.0,
+        )
+    }
+
+    /// Replaces `Projection::AutoUtm` and `Projection::Epsg` with the concrete projection they
+    /// refer to, failing if an EPSG code isn't supported. This must be called after the boundary
+    /// is known and before converting any points.
+    pub fn resolve_projection(&mut self) -> Result<()> {
+        self.projection = self.projection.resolve(self)?;
+        self.frame = Some(self.calculate_frame()?);
+        Ok(())
+    }
+
+    /// Switches to a projection that's already resolved, like one stored separately from the
+    /// boundary. If it's not actually resolved, the problem will come up when the bounds are used.
+    pub fn restore_projection(&mut self, projection: Projection) {
+        self.projection = projection;
+        self.frame = self.calculate_frame().ok();
+    }
+
     /// The bottom-right corner of the boundary, in map-space.
-    // TODO cache this
     pub fn get_max_world_pt(&self) -> Pt2D {
-        let width = LonLat::new(self.min_lon, self.min_lat)
-            .gps_dist(LonLat::new(self.max_lon, self.min_lat));
-        let height = LonLat::new(self.min_lon, self.min_lat)
-            .gps_dist(LonLat::new(self.min_lon, self.max_lat));
-        Pt2D::new(width.inner_meters(), height.inner_meters())
+        let frame = self.frame();
+        Pt2D::new(frame.width, frame.height)
     }
 
     /// Converts the boundary to map-space.
@@ -182,24 +276,141 @@ impl GPSBounds {
 
     /// Convert all points to map-space, failing if any points are outside this boundary.
     pub fn try_convert(&self, pts: &[LonLat]) -> Option<Vec<Pt2D>> {
+        let frame = self.frame();
         let mut result = Vec::new();
         for gps in pts {
             if !self.contains(*gps) {
                 return None;
             }
-            result.push(gps.to_pt(self));
+            result.push(frame.to_pt(self, *gps));
         }
         Some(result)
     }
 
     /// Convert all points to map-space. The points may be outside this boundary.
     pub fn convert(&self, pts: &[LonLat]) -> Vec<Pt2D> {
-        pts.iter().map(|gps| gps.to_pt(self)).collect()
+        let frame = self.frame();
+        pts.iter().map(|gps| frame.to_pt(self, *gps)).collect()
     }
 
     /// Convert map-space points back to `LonLat`s. This is only valid if the `GPSBounds` used
     /// is the same as the one used to originally produce the `Pt2D`s.
     pub fn convert_back(&self, pts: &[Pt2D]) -> Vec<LonLat> {
-        pts.iter().map(|pt| pt.to_gps(self)).collect()
+        let frame = self.frame();
+        pts.iter().map(|pt| frame.to_gps(self, *pt)).collect()
+    }
+
+    /// The projected coordinates of map-space's origin, as (easting, northing) in meters. A point
+    /// in map-space is at (easting - origin easting, origin northing - northing). `Linear` has no
+    /// projected coordinates.
+    pub fn projected_origin(&self) -> Option<(f64, f64)> {
+        let frame = self.frame();
+        frame.tm.as_ref()?;
+        Some((frame.min_x, frame.max_y))
+    }
+
+    /// Panics if the projection can't be resolved. `resolve_projection` reports that as an error
+    /// instead, so call it first.
+    pub(crate) fn frame(&self) -> Cow<Frame> {
+        match self.try_frame() {
+            Ok(frame) => frame,
+            Err(err) => panic!("Can't convert points using {:?}: {}", self, err),
+        }
+    }
+
+    pub(crate) fn try_frame(&self) -> Result<Cow<Frame>> {
+        if let Some(ref frame) = self.frame {
+            if frame.matches(self) {
+                return Ok(Cow::Borrowed(frame));
+            }
+        }
+        Ok(Cow::Owned(self.calculate_frame()?))
+    }
+
+    fn calculate_frame(&self) -> Result<Frame> {
+        let limits = [self.min_lon, self.min_lat, self.max_lon, self.max_lat];
+        Ok(
+            if let Some(tm) = self.projection.transverse_mercator(self)? {
+                let mut min_x = f64::MAX;
+                let mut max_x = f64::MIN;
+                let mut min_y = f64::MAX;
+                let mut max_y = f64::MIN;
+                for pt in tm.extreme_points(self) {
+                    let (x, y) = tm.forward(pt);
+                    min_x = min_x.min(x);
+                    max_x = max_x.max(x);
+                    min_y = min_y.min(y);
+                    max_y = max_y.max(y);
+                }
+                Frame {
+                    limits,
+                    projection: self.projection,
+                    tm: Some(tm),
+                    min_x,
+                    max_y,
+                    width: max_x - min_x,
+                    height: max_y - min_y,
+                }
+            } else {
+                let width = LonLat::new(self.min_lon, self.min_lat)
+                    .gps_dist(LonLat::new(self.max_lon, self.min_lat));
+                let height = LonLat::new(self.min_lon, self.min_lat)
+                    .gps_dist(LonLat::new(self.min_lon, self.max_lat));
+                Frame {
+                    limits,
+                    projection: self.projection,
+                    tm: None,
+                    min_x: 0.0,
+                    max_y: 0.0,
+                    width: width.inner_meters(),
+                    height: height.inner_meters(),
+                }
+            },
+        )
+    }
+}
+
+/// Everything needed to convert between `LonLat` and map-space for one `GPSBounds`, calculated
+/// once up-front.
+#[derive(Clone, Debug)]
+pub(crate) struct Frame {
+    /// The boundary and projection this was calculated for
+    limits: [f64; 4],
+    projection: Projection,
+    /// `None` means `Projection::Linear`
+    tm: Option<TransverseMercator>,
+    min_x: f64,
+    max_y: f64,
+    width: f64,
+    height: f64,
+}
+
+impl Frame {
+    fn matches(&self, b: &GPSBounds) -> bool {
+        self.limits == [b.min_lon, b.min_lat, b.max_lon, b.max_lat]
+            && self.projection == b.projection
+    }
+
+    pub fn to_pt(&self, b: &GPSBounds, gps: LonLat) -> Pt2D {
+        if let Some(ref tm) = self.tm {
+            let (x, y) = tm.forward(gps);
+            // Invert y, so that the northernmost point is 0. Screen drawing order, not Cartesian
+            // grid.
+            return Pt2D::new(x - self.min_x, self.max_y - y);
+        }
+
+        let x = (gps.x() - b.min_lon) / (b.max_lon - b.min_lon) * self.width;
+        let y = self.height - ((gps.y() - b.min_lat) / (b.max_lat - b.min_lat) * self.height);
+        Pt2D::new(x, y)
+    }
+
+    pub fn to_gps(&self, b: &GPSBounds, pt: Pt2D) -> LonLat {
+        if let Some(ref tm) = self.tm {
+            return tm.inverse(pt.x() + self.min_x, self.max_y - pt.y());
+        }
+
+        let lon = (pt.x() / self.width * (b.max_lon - b.min_lon)) + b.min_lon;
+        let lat = b.min_lat + ((b.max_lat - b.min_lat) * (self.height - pt.y()) / self.height);
+        LonLat::new(lon, lat)
     }
 }
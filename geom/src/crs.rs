@@ -0,0 +1,201 @@
+use std::fmt;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::bounds::Frame;
+use crate::projection::TransverseMercator;
+use crate::{GPSBounds, LonLat, Projection, Pt2D};
+
+/// A coordinate reference system to export geometry in.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Crs {
+    /// The map's own coordinates: meters from the top-left corner of the boundary, with Y
+    /// pointing down.
+    MapSpace,
+    /// Longitude and latitude (EPSG:4326)
+    Wgs84,
+    /// Easting and northing in meters, like EPSG:32610. `Linear` isn't allowed.
+    Projected(Projection),
+}
+
+impl Crs {
+    /// Parses "map", "wgs84", "utm" (the zone containing the map), "local-tm" (the same
+    /// transverse Mercator projection that map-space uses, but not shifted or flipped), or
+    /// "EPSG:1234".
+    pub fn parse(x: &str) -> Result<Crs> {
+        let lower = x.trim().to_lowercase();
+        match lower.as_str() {
+            "map" | "mapspace" | "map-space" => Ok(Crs::MapSpace),
+            "wgs84" | "lonlat" | "epsg:4326" => Ok(Crs::Wgs84),
+            "utm" => Ok(Crs::Projected(Projection::AutoUtm)),
+            "local-tm" => Ok(Crs::Projected(Projection::LocalTransverseMercator)),
+            _ => {
+                if let Some(code) = lower.strip_prefix("epsg:") {
+                    let code = code.parse::<u32>()?;
+                    // Validate now, rather than when exporting
+                    Projection::from_epsg(code)?;
+                    Ok(Crs::Projected(Projection::Epsg(code)))
+                } else {
+                    bail!(
+                        "Unknown CRS {}; use map, wgs84, utm, local-tm, or EPSG:1234",
+                        x
+                    )
+                }
+            }
+        }
+    }
+
+    /// Prepares to convert geometry from a map with these bounds into this CRS.
+    pub fn transform(self, gps_bounds: &GPSBounds) -> Result<CrsTransform> {
+        let crs = match self {
+            Crs::Projected(projection) => {
+                let projection = projection.resolve(gps_bounds)?;
+                if projection == Projection::Linear {
+                    bail!("The linear projection can't be used as an export CRS");
+                }
+                Crs::Projected(projection)
+            }
+            x => x,
+        };
+        let tm = if let Crs::Projected(projection) = crs {
+            projection.transverse_mercator(gps_bounds)?
+        } else {
+            None
+        };
+        Ok(CrsTransform {
+            crs,
+            gps_bounds: Some(gps_bounds.clone()),
+            frame: Some(gps_bounds.try_frame()?.into_owned()),
+            tm,
+        })
+    }
+}
+
+impl fmt::Display for Crs {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Crs::MapSpace => write!(f, "map-space"),
+            Crs::Wgs84 => write!(f, "WGS84"),
+            Crs::Projected(projection) => write!(f, "{}", projection),
+        }
+    }
+}
+
+/// Converts map-space points into GeoJSON positions, either in WGS84 or left in map-space. This
+/// reuses the projection the bounds already set up, so it's cheaper than a `CrsTransform` for
+/// one-off conversions.
+pub(crate) fn geojson_positions(gps: Option<&GPSBounds>, pts: &[Pt2D]) -> Vec<Vec<f64>> {
+    match gps {
+        Some(gps) => gps
+            .convert_back(pts)
+            .into_iter()
+            .map(|pt| vec![pt.x(), pt.y()])
+            .collect(),
+        None => pts.iter().map(|pt| vec![pt.x(), pt.y()]).collect(),
+    }
+}
+
+/// Converts map-space points and `LonLat`s into one `Crs`.
+pub struct CrsTransform {
+    crs: Crs,
+    /// Only `None` for `map_space`, which can't convert `LonLat`s
+    gps_bounds: Option<GPSBounds>,
+    frame: Option<Frame>,
+    tm: Option<TransverseMercator>,
+}
+
+impl CrsTransform {
+    /// Leaves map-space points unchanged, without needing a map's bounds.
+    pub fn map_space() -> CrsTransform {
+        CrsTransform {
+            crs: Crs::MapSpace,
+            gps_bounds: None,
+            frame: None,
+            tm: None,
+        }
+    }
+
+    pub fn crs(&self) -> Crs {
+        self.crs
+    }
+
+    /// Converts a point in map-space.
+    pub fn map_pt(&self, pt: Pt2D) -> (f64, f64) {
+        if self.crs == Crs::MapSpace {
+            return (pt.x(), pt.y());
+        }
+        let gps = self
+            .frame
+            .as_ref()
+            .unwrap()
+            .to_gps(self.gps_bounds.as_ref().unwrap(), pt);
+        self.gps_pt(gps)
+    }
+
+    /// Converts a longitude and latitude. Panics for `CrsTransform::map_space`.
+    pub fn gps_pt(&self, gps: LonLat) -> (f64, f64) {
+        match self.crs {
+            Crs::MapSpace => {
+                let pt = self
+                    .frame
+                    .as_ref()
+                    .expect("CrsTransform::map_space can't convert LonLats")
+                    .to_pt(self.gps_bounds.as_ref().unwrap(), gps);
+                (pt.x(), pt.y())
+            }
+            Crs::Wgs84 => (gps.x(), gps.y()),
+            Crs::Projected(_) => self.tm.as_ref().unwrap().forward(gps),
+        }
+    }
+
+    /// Converts map-space points into GeoJSON positions.
+    pub fn positions(&self, pts: &[Pt2D]) -> Vec<Vec<f64>> {
+        pts.iter()
+            .map(|pt| {
+                let (x, y) = self.map_pt(*pt);
+                vec![x, y]
+            })
+            .collect()
+    }
+
+    /// The EPSG code of the output, if there is one
+    pub fn epsg(&self) -> Option<u32> {
+        match self.crs {
+            Crs::MapSpace => None,
+            Crs::Wgs84 => Some(4326),
+            Crs::Projected(projection) => projection.epsg(self.gps_bounds.as_ref()?),
+        }
+    }
+
+    /// A PROJ definition of the output, if there is one. Map-space has none, since it's shifted
+    /// and flipped; use `GPSBounds::projected_origin` to relate it to the map's projection.
+    pub fn to_proj4(&self) -> Option<String> {
+        match self.crs {
+            Crs::MapSpace => None,
+            Crs::Wgs84 => Some("+proj=longlat +datum=WGS84 +no_defs".to_string()),
+            Crs::Projected(projection) => projection.to_proj4(self.gps_bounds.as_ref()?),
+        }
+    }
+
+    /// The legacy GeoJSON "crs" member, which GDAL and QGIS still understand. RFC 7946 only
+    /// allows WGS84, so this is the only way to label projected output. `None` for WGS84 and
+    /// anything without an EPSG code.
+    pub fn geojson_foreign_members(&self) -> Option<geojson::JsonObject> {
+        let code = self.epsg()?;
+        if code == 4326 {
+            return None;
+        }
+        let mut properties = geojson::JsonObject::new();
+        properties.insert(
+            "name".to_string(),
+            format!("urn:ogc:def:crs:EPSG::{}", code).into(),
+        );
+        let mut crs = geojson::JsonObject::new();
+        crs.insert("type".to_string(), "name".into());
+        crs.insert("properties".to_string(), properties.into());
+        let mut members = geojson::JsonObject::new();
+        members.insert("crs".to_string(), crs.into());
+        Some(members)
+    }
+}
@@ -37,16 +37,7 @@ impl LonLat {
 
     /// Transform this to a world-space point. Can go out of bounds.
     pub fn to_pt(self, b: &GPSBounds) -> Pt2D {
-        let (width, height) = {
-            let pt = b.get_max_world_pt();
-            (pt.x(), pt.y())
-        };
-
-        let x = (self.x() - b.min_lon) / (b.max_lon - b.min_lon) * width;
-        // Invert y, so that the northernmost latitude is 0. Screen drawing order, not Cartesian
-        // grid.
-        let y = height - ((self.y() - b.min_lat) / (b.max_lat - b.min_lat) * height);
-        Pt2D::new(x, y)
+        b.frame().to_pt(b, self)
     }
 
     /// Returns the Haversine distance to another point.
@@ -149,6 +140,15 @@ impl LonLat {
         Some(pts)
     }
 
+    /// Produces a WKT-style line-string, in the format `parse_wkt_linestring` expects.
+    pub fn to_wkt_linestring(pts: &[LonLat]) -> String {
+        let pairs: Vec<String> = pts
+            .iter()
+            .map(|pt| format!("{} {}", pt.x(), pt.y()))
+            .collect();
+        format!("LINESTRING ({})", pairs.join(", "))
+    }
+
     /// Extract polygons from a raw GeoJSON string. For multipolygons, only returns the first
     /// member.
     pub fn parse_geojson_polygons(raw: String) -> Result<Vec<Vec<LonLat>>> {
@@ -8,6 +8,7 @@ use serde::{Deserialize, Deserializer, Serialize, Serializer};
 pub use crate::angle::Angle;
 pub use crate::bounds::{Bounds, GPSBounds};
 pub use crate::circle::Circle;
+pub use crate::crs::{Crs, CrsTransform};
 pub use crate::distance::Distance;
 pub use crate::duration::Duration;
 pub use crate::find_closest::FindClosest;
@@ -16,6 +17,7 @@ pub use crate::line::{InfiniteLine, Line};
 pub use crate::percent::Percent;
 pub use crate::polygon::{Polygon, Triangle};
 pub use crate::polyline::{ArrowCap, PolyLine};
+pub use crate::projection::{deserialize_trailing_projection, Projection};
 pub use crate::pt::{HashablePt2D, Pt2D};
 pub use crate::ring::Ring;
 pub use crate::speed::Speed;
@@ -25,6 +27,7 @@ pub use crate::time::Time;
 mod angle;
 mod bounds;
 mod circle;
+mod crs;
 mod distance;
 mod duration;
 mod find_closest;
@@ -33,6 +36,7 @@ mod line;
 mod percent;
 mod polygon;
 mod polyline;
+mod projection;
 mod pt;
 mod ring;
 mod speed;
@@ -136,4 +140,148 @@ mod tests {
         assert_eq!(json_roundtrip, 1.2346);
         assert_eq!(bincode_roundtrip, 1.2346);
     }
+
+    #[test]
+    fn projection_roundtrip() {
+        // A big map far from the equator, where the old linear stretching distorted the most
+        let bounds = GPSBounds::from(vec![LonLat::new(17.8, 59.1), LonLat::new(18.9, 59.6)]);
+        for projection in vec![
+            Projection::LocalTransverseMercator,
+            Projection::AutoUtm,
+            Projection::Epsg(32634),
+            Projection::Linear,
+        ] {
+            let mut b = bounds.clone();
+            b.projection = projection;
+            b.resolve_projection().unwrap();
+            let mut rng = rand_xorshift::XorShiftRng::seed_from_u64(42);
+            for _ in 0..1_000 {
+                let gps = LonLat::new(rng.gen_range(17.8..18.9), rng.gen_range(59.1..59.6));
+                let pt = gps.to_pt(&b);
+                assert!(
+                    b.to_bounds().contains(pt),
+                    "{} outside {:?}",
+                    pt,
+                    projection
+                );
+                let back = pt.to_gps(&b);
+                assert!(
+                    (gps.x() - back.x()).abs() < 1e-7 && (gps.y() - back.y()).abs() < 1e-7,
+                    "{:?} roundtripped to {:?} with {:?}",
+                    gps,
+                    back,
+                    projection
+                );
+            }
+        }
+
+        // Distances in map-space should match the real distances
+        let b = bounds;
+        let pt1 = LonLat::new(17.9, 59.2);
+        let pt2 = LonLat::new(18.8, 59.5);
+        let expected = pt1.gps_dist(pt2).inner_meters();
+        let actual = pt1.to_pt(&b).dist_to(pt2.to_pt(&b)).inner_meters();
+        assert!(
+            (expected - actual).abs() / expected < 0.005,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn projection_cache() {
+        let mut b = GPSBounds::from(vec![LonLat::new(17.8, 59.1), LonLat::new(18.9, 59.6)]);
+        b.resolve_projection().unwrap();
+        let gps = LonLat::new(18.3, 59.3);
+        let pt = gps.to_pt(&b);
+
+        // The projection is stored separately, and restoring it after a roundtrip calculates
+        // everything again
+        let mut copy: GPSBounds = abstutil::from_binary(&abstutil::to_binary(&b)).unwrap();
+        assert_eq!(copy.projection, Projection::Linear);
+        copy.restore_projection(b.projection);
+        assert_eq!(copy, b);
+        assert_eq!(gps.to_pt(&copy), pt);
+
+        // Changing the boundary afterwards isn't ignored
+        let mut bigger = b.clone();
+        bigger.update(LonLat::new(17.0, 60.0));
+        let mut fresh = GPSBounds::from(vec![LonLat::new(17.0, 59.1), LonLat::new(18.9, 60.0)]);
+        fresh.resolve_projection().unwrap();
+        assert_eq!(gps.to_pt(&bigger), gps.to_pt(&fresh));
+        assert_ne!(gps.to_pt(&bigger), pt);
+
+        // An unsupported projection is an error, not a panic
+        let mut bad = b.clone();
+        bad.projection = Projection::Epsg(3857);
+        assert!(bad.resolve_projection().is_err());
+        assert!(Crs::Wgs84.transform(&bad).is_err());
+    }
+
+    #[test]
+    fn trailing_projection() {
+        #[derive(Serialize)]
+        struct Old {
+            bounds: GPSBounds,
+        }
+        #[derive(Serialize, Deserialize)]
+        struct New {
+            bounds: GPSBounds,
+            #[serde(deserialize_with = "deserialize_trailing_projection")]
+            projection: Projection,
+        }
+
+        // Files from before projections were recorded fall back to the old linear stretching
+        let bounds = GPSBounds::from(vec![LonLat::new(17.8, 59.1), LonLat::new(18.9, 59.6)]);
+        let old: New = abstutil::from_binary(&abstutil::to_binary(&Old {
+            bounds: bounds.clone(),
+        }))
+        .unwrap();
+        assert_eq!(old.projection, Projection::Linear);
+
+        // Newer files keep what they stored
+        let utm = Projection::Utm {
+            zone: 34,
+            north: true,
+        };
+        let new: New = abstutil::from_binary(&abstutil::to_binary(&New {
+            bounds,
+            projection: utm,
+        }))
+        .unwrap();
+        assert_eq!(new.projection, utm);
+    }
+
+    #[test]
+    fn crs_export() {
+        let mut b = GPSBounds::from(vec![LonLat::new(17.8, 59.1), LonLat::new(18.9, 59.6)]);
+        b.projection = Projection::Epsg(32634);
+        b.resolve_projection().unwrap();
+        let (origin_x, origin_y) = b.projected_origin().unwrap();
+
+        let utm = Crs::parse("EPSG:32634").unwrap().transform(&b).unwrap();
+        let wgs84 = Crs::parse("wgs84").unwrap().transform(&b).unwrap();
+        let map_space = Crs::parse("map").unwrap().transform(&b).unwrap();
+        assert_eq!(utm.epsg(), Some(32634));
+        assert_eq!(wgs84.epsg(), Some(4326));
+        assert_eq!(map_space.epsg(), None);
+
+        let mut rng = rand_xorshift::XorShiftRng::seed_from_u64(42);
+        for _ in 0..100 {
+            let gps = LonLat::new(rng.gen_range(17.8..18.9), rng.gen_range(59.1..59.6));
+            let pt = gps.to_pt(&b);
+            // The projected CRS matches the map's own projection, just unshifted and unflipped
+            let (x, y) = utm.map_pt(pt);
+            assert!((x - (origin_x + pt.x())).abs() < 1e-6);
+            assert!((y - (origin_y - pt.y())).abs() < 1e-6);
+            let (lon, lat) = wgs84.map_pt(pt);
+            assert!((lon - gps.x()).abs() < 1e-7 && (lat - gps.y()).abs() < 1e-7);
+            let (x, y) = map_space.gps_pt(gps);
+            assert!((x - pt.x()).abs() < 1e-6 && (y - pt.y()).abs() < 1e-6);
+        }
+
+        assert!(Crs::parse("EPSG:3857").is_err());
+        assert!(Crs::Projected(Projection::Linear).transform(&b).is_err());
+    }
 }
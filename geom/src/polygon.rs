@@ -10,8 +10,10 @@ use serde::{Deserialize, Serialize};
 
 use abstutil::Tags;
 
+use crate::crs::geojson_positions;
 use crate::{
-    Angle, Bounds, CornerRadii, Distance, GPSBounds, HashablePt2D, LonLat, PolyLine, Pt2D, Ring,
+    Angle, Bounds, CornerRadii, CrsTransform, Distance, GPSBounds, HashablePt2D, LonLat, PolyLine,
+    Pt2D, Ring,
 };
 
 #[derive(PartialEq, Serialize, Deserialize, Clone, Debug)]
@@ -194,6 +196,22 @@ impl Polygon {
         Ring::must_new(self.into_points())
     }
 
+    /// Describes the polygon as closed loops of points for vector formats like SVG. Filling them
+    /// with the even-odd rule produces the polygon. If the outline isn't known, this falls back
+    /// to the individual triangles.
+    pub fn fill_loops(&self) -> Vec<Vec<Pt2D>> {
+        if let Some(ref rings) = self.rings {
+            return rings.iter().map(|r| r.points().clone()).collect();
+        }
+        if let Ok(ring) = Ring::new(self.points.clone()) {
+            return vec![ring.into_points()];
+        }
+        self.triangles()
+            .into_iter()
+            .map(|t| vec![t.pt1, t.pt2, t.pt3, t.pt1])
+            .collect()
+    }
+
     /// Get the outer ring of this polygon. This should usually succeed.
     pub fn get_outer_ring(&self) -> Option<Ring> {
         if let Some(ref rings) = self.rings {
@@ -469,24 +487,27 @@ impl Polygon {
     /// a GeoJSON multipolygon consisting of individual triangles. Optionally map the world-space
     /// points back to GPS.
     pub fn to_geojson(&self, gps: Option<&GPSBounds>) -> geojson::Geometry {
+        self.to_geojson_with(|pts| geojson_positions(gps, pts))
+    }
+
+    /// Like `to_geojson`, but in some coordinate system.
+    pub fn to_geojson_crs(&self, crs: &CrsTransform) -> geojson::Geometry {
+        self.to_geojson_with(|pts| crs.positions(pts))
+    }
+
+    fn to_geojson_with<F: Fn(&[Pt2D]) -> Vec<Vec<f64>>>(&self, positions: F) -> geojson::Geometry {
         if let Ok(ring) = Ring::new(self.points.clone()) {
-            return ring.to_geojson(gps);
+            return geojson::Geometry::new(geojson::Value::Polygon(vec![positions(ring.points())]));
         }
 
         let mut polygons = Vec::new();
         for triangle in self.triangles() {
-            let raw_pts = vec![triangle.pt1, triangle.pt2, triangle.pt3, triangle.pt1];
-            let mut pts = Vec::new();
-            if let Some(gps) = gps {
-                for pt in gps.convert_back(&raw_pts) {
-                    pts.push(vec![pt.x(), pt.y()]);
-                }
-            } else {
-                for pt in raw_pts {
-                    pts.push(vec![pt.x(), pt.y()]);
-                }
-            }
-            polygons.push(vec![pts]);
+            polygons.push(vec![positions(&[
+                triangle.pt1,
+                triangle.pt2,
+                triangle.pt3,
+                triangle.pt1,
+            ])]);
         }
 
         geojson::Geometry::new(geojson::Value::MultiPolygon(polygons))
@@ -5,9 +5,10 @@ use anyhow::{Context, Result};
 use geo::prelude::ClosestPoint;
 use serde::{Deserialize, Serialize};
 
+use crate::crs::geojson_positions;
 use crate::{
-    Angle, Bounds, Distance, GPSBounds, HashablePt2D, InfiniteLine, Line, Polygon, Pt2D, Ring,
-    EPSILON_DIST,
+    Angle, Bounds, CrsTransform, Distance, GPSBounds, HashablePt2D, InfiniteLine, Line, Polygon,
+    Pt2D, Ring, EPSILON_DIST,
 };
 
 // TODO How to tune this?
@@ -877,17 +878,14 @@ impl PolyLine {
 
     /// Produces a GeoJSON linestring, optionally mapping the world-space points back to GPS.
     pub fn to_geojson(&self, gps: Option<&GPSBounds>) -> geojson::Geometry {
-        let mut pts = Vec::new();
-        if let Some(gps) = gps {
-            for pt in gps.convert_back(&self.pts) {
-                pts.push(vec![pt.x(), pt.y()]);
-            }
-        } else {
-            for pt in &self.pts {
-                pts.push(vec![pt.x(), pt.y()]);
-            }
-        }
-        geojson::Geometry::new(geojson::Value::LineString(pts))
+        geojson::Geometry::new(geojson::Value::LineString(geojson_positions(
+            gps, &self.pts,
+        )))
+    }
+
+    /// Produces a GeoJSON linestring in some coordinate system.
+    pub fn to_geojson_crs(&self, crs: &CrsTransform) -> geojson::Geometry {
+        geojson::Geometry::new(geojson::Value::LineString(crs.positions(&self.pts)))
     }
 
     /// Returns the point on the polyline closest to the query.
@@ -0,0 +1,280 @@
+use std::fmt;
+
+use anyhow::Result;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::{GPSBounds, LonLat};
+
+// WGS84 ellipsoid
+const SEMI_MAJOR_AXIS: f64 = 6_378_137.0;
+const FLATTENING: f64 = 1.0 / 298.257_223_563;
+
+/// How `LonLat`s are flattened into map-space. The boundary's top-left corner always winds up at
+/// (0, 0), with units in meters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Projection {
+    /// Stretch longitude and latitude linearly to fill the boundary. Every map was built this way
+    /// before real projections were supported. Shapes and distances are noticeably distorted for
+    /// large or high-latitude maps, so only use this to match old data.
+    Linear,
+    /// Transverse Mercator with the central meridian running through the middle of the boundary
+    /// and no scale reduction. Distortion is negligible for anything city-sized.
+    LocalTransverseMercator,
+    /// Use the UTM zone containing the center of the boundary.
+    AutoUtm,
+    /// A UTM zone on the WGS84 ellipsoid, like EPSG:32610 for zone 10 north.
+    Utm { zone: u8, north: bool },
+    /// A projected coordinate system specified by EPSG code. Only the WGS84 and ETRS89 UTM zones
+    /// are supported.
+    Epsg(u32),
+}
+
+/// Reads a projection stored as the very last field of a file, like a `Map` or `RawMap`. Files
+/// written before projections were recorded just end early, so they fall back to
+/// `Projection::Linear`, the way every map used to be built. bincode can't skip missing fields, so
+/// this only works for the last one.
+pub fn deserialize_trailing_projection<'de, D: Deserializer<'de>>(
+    d: D,
+) -> std::result::Result<Projection, D::Error> {
+    Ok(Projection::deserialize(d).unwrap_or(Projection::Linear))
+}
+
+impl Default for Projection {
+    fn default() -> Projection {
+        Projection::LocalTransverseMercator
+    }
+}
+
+impl Projection {
+    pub fn from_epsg(code: u32) -> Result<Projection> {
+        match code {
+            32601..=32660 => Ok(Projection::Utm {
+                zone: (code - 32600) as u8,
+                north: true,
+            }),
+            32701..=32760 => Ok(Projection::Utm {
+                zone: (code - 32700) as u8,
+                north: false,
+            }),
+            // ETRS89 is within a meter of WGS84
+            25801..=25860 => Ok(Projection::Utm {
+                zone: (code - 25800) as u8,
+                north: true,
+            }),
+            4326 => bail!("EPSG:4326 is longitude/latitude, not a projected coordinate system"),
+            3857 | 900913 => bail!(
+                "EPSG:{} (Web Mercator) doesn't preserve distances, so it can't be used for map-space",
+                code
+            ),
+            _ => bail!("Unsupported projection EPSG:{}", code),
+        }
+    }
+
+    /// The UTM zone containing a point. Ignores the irregular zones around Norway and Svalbard.
+    pub fn utm_zone(pt: LonLat) -> Projection {
+        let zone = (((pt.x() + 180.0) / 6.0).floor() as i64 + 1).max(1).min(60);
+        Projection::Utm {
+            zone: zone as u8,
+            north: pt.y() >= 0.0,
+        }
+    }
+
+    /// Turns `AutoUtm` and `Epsg` into a concrete projection for this boundary. Other projections
+    /// are returned unchanged.
+    pub fn resolve(self, bounds: &GPSBounds) -> Result<Projection> {
+        match self {
+            Projection::AutoUtm => Ok(Projection::utm_zone(bounds.center())),
+            Projection::Epsg(code) => Projection::from_epsg(code),
+            Projection::Utm { zone, .. } if zone == 0 || zone > 60 => {
+                bail!("Invalid UTM zone {}", zone)
+            }
+            _ => Ok(self),
+        }
+    }
+
+    /// The EPSG code, if this projection has one.
+    pub fn epsg(self, bounds: &GPSBounds) -> Option<u32> {
+        match self.resolve(bounds).ok()? {
+            Projection::Utm { zone, north } => {
+                Some(if north { 32600 } else { 32700 } + zone as u32)
+            }
+            _ => None,
+        }
+    }
+
+    /// A PROJ definition, for exporting to tools that understand it. `Linear` has none.
+    pub fn to_proj4(self, bounds: &GPSBounds) -> Option<String> {
+        match self.resolve(bounds).ok()? {
+            Projection::Linear => None,
+            Projection::LocalTransverseMercator => Some(format!(
+                "+proj=tmerc +lat_0=0 +lon_0={} +k=1 +x_0=0 +y_0=0 +ellps=WGS84 +datum=WGS84 \
+                 +units=m +no_defs",
+                bounds.center().x()
+            )),
+            Projection::Utm { zone, north } => Some(format!(
+                "+proj=utm +zone={}{} +ellps=WGS84 +datum=WGS84 +units=m +no_defs",
+                zone,
+                if north { "" } else { " +south" }
+            )),
+            Projection::AutoUtm | Projection::Epsg(_) => unreachable!(),
+        }
+    }
+
+    /// Returns the transverse Mercator parameters for this projection, or `None` for `Linear`.
+    /// Fails if the projection can't be resolved.
+    pub(crate) fn transverse_mercator(
+        self,
+        bounds: &GPSBounds,
+    ) -> Result<Option<TransverseMercator>> {
+        Ok(match self.resolve(bounds)? {
+            Projection::Linear => None,
+            Projection::LocalTransverseMercator => Some(TransverseMercator {
+                central_meridian: bounds.center().x(),
+                scale_factor: 1.0,
+                false_easting: 0.0,
+                false_northing: 0.0,
+            }),
+            Projection::Utm { zone, north } => Some(TransverseMercator {
+                central_meridian: -183.0 + 6.0 * (zone as f64),
+                scale_factor: 0.9996,
+                false_easting: 500_000.0,
+                false_northing: if north { 0.0 } else { 10_000_000.0 },
+            }),
+            Projection::AutoUtm | Projection::Epsg(_) => unreachable!(),
+        })
+    }
+}
+
+impl fmt::Display for Projection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Projection::Linear => write!(f, "linear (legacy)"),
+            Projection::LocalTransverseMercator => write!(f, "local transverse Mercator"),
+            Projection::AutoUtm => write!(f, "automatic UTM zone"),
+            Projection::Utm { zone, north } => {
+                write!(f, "UTM zone {}{}", zone, if *north { "N" } else { "S" })
+            }
+            Projection::Epsg(code) => write!(f, "EPSG:{}", code),
+        }
+    }
+}
+
+/// The ellipsoidal transverse Mercator projection, using the series from Snyder's "Map
+/// Projections: A Working Manual" (USGS Professional Paper 1395). Accurate to well under a
+/// millimeter within a few degrees of the central meridian.
+#[derive(Clone, Debug)]
+pub(crate) struct TransverseMercator {
+    central_meridian: f64,
+    scale_factor: f64,
+    false_easting: f64,
+    false_northing: f64,
+}
+
+impl TransverseMercator {
+    /// Returns (easting, northing) in meters.
+    pub fn forward(&self, pt: LonLat) -> (f64, f64) {
+        let e2 = eccentricity_squared();
+        let ep2 = e2 / (1.0 - e2);
+        let k0 = self.scale_factor;
+
+        let lat = pt.y().to_radians();
+        let (sin, cos) = lat.sin_cos();
+        let tan = lat.tan();
+        let n = SEMI_MAJOR_AXIS / (1.0 - e2 * sin * sin).sqrt();
+        let t = tan * tan;
+        let c = ep2 * cos * cos;
+        let a = (pt.x() - self.central_meridian).to_radians() * cos;
+        let m = meridian_arc(lat);
+
+        let x = k0
+            * n
+            * (a + (1.0 - t + c) * a.powi(3) / 6.0
+                + (5.0 - 18.0 * t + t * t + 72.0 * c - 58.0 * ep2) * a.powi(5) / 120.0);
+        let y = k0
+            * (m + n
+                * tan
+                * (a * a / 2.0
+                    + (5.0 - t + 9.0 * c + 4.0 * c * c) * a.powi(4) / 24.0
+                    + (61.0 - 58.0 * t + t * t + 600.0 * c - 330.0 * ep2) * a.powi(6) / 720.0));
+        (x + self.false_easting, y + self.false_northing)
+    }
+
+    /// The inverse of `forward`.
+    pub fn inverse(&self, easting: f64, northing: f64) -> LonLat {
+        let e2 = eccentricity_squared();
+        let ep2 = e2 / (1.0 - e2);
+        let k0 = self.scale_factor;
+        let x = easting - self.false_easting;
+        let y = northing - self.false_northing;
+
+        // The footpoint latitude
+        let mu = (y / k0)
+            / (SEMI_MAJOR_AXIS
+                * (1.0 - e2 / 4.0 - 3.0 * e2.powi(2) / 64.0 - 5.0 * e2.powi(3) / 256.0));
+        let e1 = (1.0 - (1.0 - e2).sqrt()) / (1.0 + (1.0 - e2).sqrt());
+        let lat1 = mu
+            + (3.0 * e1 / 2.0 - 27.0 * e1.powi(3) / 32.0) * (2.0 * mu).sin()
+            + (21.0 * e1 * e1 / 16.0 - 55.0 * e1.powi(4) / 32.0) * (4.0 * mu).sin()
+            + (151.0 * e1.powi(3) / 96.0) * (6.0 * mu).sin()
+            + (1097.0 * e1.powi(4) / 512.0) * (8.0 * mu).sin();
+
+        let (sin1, cos1) = lat1.sin_cos();
+        let tan1 = lat1.tan();
+        let c1 = ep2 * cos1 * cos1;
+        let t1 = tan1 * tan1;
+        let n1 = SEMI_MAJOR_AXIS / (1.0 - e2 * sin1 * sin1).sqrt();
+        let r1 = SEMI_MAJOR_AXIS * (1.0 - e2) / (1.0 - e2 * sin1 * sin1).powf(1.5);
+        let d = x / (n1 * k0);
+
+        let lat = lat1
+            - (n1 * tan1 / r1)
+                * (d * d / 2.0
+                    - (5.0 + 3.0 * t1 + 10.0 * c1 - 4.0 * c1 * c1 - 9.0 * ep2) * d.powi(4) / 24.0
+                    + (61.0 + 90.0 * t1 + 298.0 * c1 + 45.0 * t1 * t1
+                        - 252.0 * ep2
+                        - 3.0 * c1 * c1)
+                        * d.powi(6)
+                        / 720.0);
+        let lon = (d - (1.0 + 2.0 * t1 + c1) * d.powi(3) / 6.0
+            + (5.0 - 2.0 * c1 + 28.0 * t1 - 3.0 * c1 * c1 + 8.0 * ep2 + 24.0 * t1 * t1)
+                * d.powi(5)
+                / 120.0)
+            / cos1;
+        LonLat::new(self.central_meridian + lon.to_degrees(), lat.to_degrees())
+    }
+
+    /// Points on the edges of a boundary that might be the most extreme once projected: the
+    /// corners, where the top and bottom edges cross the central meridian, and where the left and
+    /// right edges cross the equator.
+    pub fn extreme_points(&self, b: &GPSBounds) -> Vec<LonLat> {
+        let mut pts = vec![
+            LonLat::new(b.min_lon, b.min_lat),
+            LonLat::new(b.min_lon, b.max_lat),
+            LonLat::new(b.max_lon, b.min_lat),
+            LonLat::new(b.max_lon, b.max_lat),
+        ];
+        let lon = self.central_meridian.max(b.min_lon).min(b.max_lon);
+        pts.push(LonLat::new(lon, b.min_lat));
+        pts.push(LonLat::new(lon, b.max_lat));
+        let lat = 0.0_f64.max(b.min_lat).min(b.max_lat);
+        pts.push(LonLat::new(b.min_lon, lat));
+        pts.push(LonLat::new(b.max_lon, lat));
+        pts
+    }
+}
+
+fn eccentricity_squared() -> f64 {
+    FLATTENING * (2.0 - FLATTENING)
+}
+
+/// Distance along the meridian from the equator to some latitude, in meters
+fn meridian_arc(lat: f64) -> f64 {
+    let e2 = eccentricity_squared();
+    let e4 = e2 * e2;
+    let e6 = e4 * e2;
+    SEMI_MAJOR_AXIS
+        * ((1.0 - e2 / 4.0 - 3.0 * e4 / 64.0 - 5.0 * e6 / 256.0) * lat
+            - (3.0 * e2 / 8.0 + 3.0 * e4 / 32.0 + 45.0 * e6 / 1024.0) * (2.0 * lat).sin()
+            + (15.0 * e4 / 256.0 + 45.0 * e6 / 1024.0) * (4.0 * lat).sin()
+            - (35.0 * e6 / 3072.0) * (6.0 * lat).sin())
+}
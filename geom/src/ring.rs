@@ -4,7 +4,8 @@ use std::fmt;
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 
-use crate::{Distance, GPSBounds, Line, PolyLine, Polygon, Pt2D};
+use crate::crs::geojson_positions;
+use crate::{CrsTransform, Distance, GPSBounds, Line, PolyLine, Polygon, Pt2D};
 
 /// Maybe a misnomer, but like a PolyLine, but closed.
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -156,17 +157,14 @@ impl Ring {
 
     /// Produces a GeoJSON polygon, optionally mapping the world-space points back to GPS.
     pub fn to_geojson(&self, gps: Option<&GPSBounds>) -> geojson::Geometry {
-        let mut pts = Vec::new();
-        if let Some(gps) = gps {
-            for pt in gps.convert_back(&self.pts) {
-                pts.push(vec![pt.x(), pt.y()]);
-            }
-        } else {
-            for pt in &self.pts {
-                pts.push(vec![pt.x(), pt.y()]);
-            }
-        }
-        geojson::Geometry::new(geojson::Value::Polygon(vec![pts]))
+        geojson::Geometry::new(geojson::Value::Polygon(vec![geojson_positions(
+            gps, &self.pts,
+        )]))
+    }
+
+    /// Produces a GeoJSON polygon in some coordinate system.
+    pub fn to_geojson_crs(&self, crs: &CrsTransform) -> geojson::Geometry {
+        geojson::Geometry::new(geojson::Value::Polygon(vec![crs.positions(&self.pts)]))
     }
 
     /// Translates the ring by a fixed offset.
@@ -26,14 +26,14 @@ use serde::{Deserialize, Serialize};
 
 use abstio::MapName;
 use abstutil::{serialize_btreemap, CmdArgs, Timer};
-use geom::{Distance, Duration, LonLat, Time};
+use geom::{Crs, Distance, Duration, LonLat, Time};
 use map_model::{
     CompressedMovementID, ControlTrafficSignal, EditCmd, EditIntersection, IntersectionID, Map,
     MovementID, PermanentMapEdits, RoadID, TurnID,
 };
 use sim::{
-    AgentID, AgentType, DelayCause, ExternalPerson, PersonID, Scenario, ScenarioModifier, Sim,
-    SimFlags, SimOptions, TripID, TripMode, VehicleType,
+    AgentID, AgentType, DelayCause, ExternalPerson, Incident, IncidentID, PersonID, Scenario,
+    ScenarioModifier, Sim, SimFlags, SimOptions, TripID, TripMode, VehicleType,
 };
 
 lazy_static::lazy_static! {
@@ -44,6 +44,7 @@ lazy_static::lazy_static! {
             scenario: abstio::path_scenario(&MapName::seattle("montlake"), "weekday"),
             modifiers: Vec::new(),
             edits: None,
+            incidents: Vec::new(),
             rng_seed: SimFlags::RNG_SEED,
             opts: SimOptions::default(),
         }
@@ -141,6 +142,7 @@ fn handle_command(
             load.scenario = args.scenario;
             load.modifiers = args.modifiers;
             load.edits = args.edits;
+            load.incidents = args.incidents;
 
             // Also reset
             let (new_map, new_sim) = load.setup(&mut Timer::new("reset sim"));
@@ -181,6 +183,11 @@ fn handle_command(
                 sim.get_all_people().last().unwrap().id
             ))
         }
+        "/sim/add-incident" => {
+            let incident: Incident = abstutil::from_json(body)?;
+            let id = sim.schedule_incident(incident, map)?;
+            Ok(format!("{} scheduled", id))
+        }
         // Traffic signals
         "/traffic-signals/get" => {
             let i = IntersectionID(get("id")?.parse::<usize>()?);
@@ -207,6 +214,23 @@ fn handle_command(
 
             Ok(format!("{} has been updated", id))
         }
+        "/traffic-signals/import-gmns" => {
+            let timing = map_model::gmns::SignalTiming::load(
+                get("timing")?,
+                params.get("movements").map(|x| x.as_str()),
+            )?;
+            let results = timing.import_all(map, &mut Timer::new("import GMNS signal timing"));
+
+            let mut edits = map.get_edits().clone();
+            edits.commands.extend(results.commands);
+            map.must_apply_edits(edits);
+            map.recalculate_pathfinding_after_edits(&mut Timer::throwaway());
+
+            Ok(format!(
+                "{} traffic signals imported, {} without any data, {} other failures",
+                results.successes, results.failures_no_match, results.failures_other
+            ))
+        }
         "/traffic-signals/get-delays" => {
             let i = IntersectionID(get("id")?.parse::<usize>()?);
             let t1 = Time::parse(get("t1")?)?;
@@ -304,21 +328,36 @@ fn handle_command(
             }
             Ok(abstutil::to_json(&trips))
         }
-        "/data/get-agent-positions" => Ok(abstutil::to_json(&AgentPositions {
-            agents: sim
-                .get_unzoomed_agents(map)
-                .into_iter()
-                .chain(sim.get_unzoomed_transit_riders(map))
-                .map(|a| AgentPosition {
-                    id: a.id,
-                    trip: sim.agent_to_trip(a.id),
-                    person: a.person,
-                    vehicle_type: a.id.to_vehicle_type(),
-                    pos: a.pos.to_gps(map.get_gps_bounds()),
-                    distance_crossed: sim.agent_properties(map, a.id).dist_crossed,
-                })
-                .collect(),
-        })),
+        "/data/get-experienced-plans" => {
+            let ids = popdat::matsim::PersonIDs::load(
+                map.get_name(),
+                &abstutil::basename(&load.scenario),
+            );
+            popdat::matsim::write_experienced_plans(sim, map, &ids)
+        }
+        "/data/get-agent-positions" => {
+            let crs = params
+                .get("crs")
+                .map(|crs| Crs::parse(crs)?.transform(map.get_gps_bounds()))
+                .transpose()?;
+            Ok(abstutil::to_json(&AgentPositions {
+                crs: crs.as_ref().map(|crs| crs.crs().to_string()),
+                agents: sim
+                    .get_unzoomed_agents(map)
+                    .into_iter()
+                    .chain(sim.get_unzoomed_transit_riders(map))
+                    .map(|a| AgentPosition {
+                        id: a.id,
+                        trip: sim.agent_to_trip(a.id),
+                        person: a.person,
+                        vehicle_type: a.id.to_vehicle_type(),
+                        pos: a.pos.to_gps(map.get_gps_bounds()),
+                        pos_crs: crs.as_ref().map(|crs| crs.map_pt(a.pos)),
+                        distance_crossed: sim.agent_properties(map, a.id).dist_crossed,
+                    })
+                    .collect(),
+            }))
+        }
         "/data/get-road-thruput" => Ok(abstutil::to_json(&RoadThroughput {
             counts: sim
                 .get_analytics()
@@ -340,6 +379,24 @@ fn handle_command(
                 })
                 .collect(),
         })),
+        "/data/get-gridlock-report" => {
+            let min_delay = Duration::seconds(get("min_delay_seconds")?.parse::<f64>()?);
+            Ok(abstutil::to_json(&sim.diagnose_gridlock(map, min_delay)))
+        }
+        "/data/get-incident-delays" => Ok(abstutil::to_json(
+            &sim.get_all_incidents()
+                .into_iter()
+                .map(|(id, incident)| {
+                    let (num_delayed, total_delay) = sim.get_analytics().total_incident_delay(id);
+                    IncidentDelays {
+                        id,
+                        incident: incident.clone(),
+                        num_delayed,
+                        total_delay,
+                    }
+                })
+                .collect::<Vec<_>>(),
+        )),
         "/data/trip-time-lower-bound" => {
             let id = TripID(get("id")?.parse::<usize>()?);
             let duration = sim.get_trip_time_lower_bound(map, id)?;
@@ -376,9 +433,26 @@ fn handle_command(
         }
         "/map/get-intersection-geometry" => {
             let i = IntersectionID(get("id")?.parse::<usize>()?);
-            Ok(abstutil::to_json(&export_geometry(map, i)))
+            // Without a CRS, keep the original behavior of centering around the intersection
+            match params.get("crs") {
+                Some(crs) => {
+                    let crs = Crs::parse(crs)?.transform(map.get_gps_bounds())?;
+                    Ok(abstutil::to_json(
+                        &map.export_intersection_geometry(i, &crs),
+                    ))
+                }
+                None => Ok(abstutil::to_json(&export_geometry(map, i))),
+            }
+        }
+        "/map/get-all-geometry" => {
+            let crs = match params.get("crs") {
+                Some(crs) => Crs::parse(crs)?,
+                None => Crs::Wgs84,
+            };
+            Ok(abstutil::to_json(
+                &map.export_geometry(&crs.transform(map.get_gps_bounds())?),
+            ))
         }
-        "/map/get-all-geometry" => Ok(abstutil::to_json(&export_all_geometry(map))),
         _ => Err(anyhow!("Unknown command")),
     }
 }
@@ -408,6 +482,9 @@ struct Throughput {
 
 #[derive(Serialize)]
 struct AgentPositions {
+    /// The coordinate system of `pos_crs`, if the `crs` parameter was passed in
+    #[serde(skip_serializing_if = "Option::is_none")]
+    crs: Option<String>,
     agents: Vec<AgentPosition>,
 }
 
@@ -424,6 +501,9 @@ struct AgentPosition {
     /// The agent's current position. For pedestrians, this is their center. For vehicles, this
     /// represents the front of the vehicle.
     pos: LonLat,
+    /// With the `crs` parameter, the same position as (x, y) in that coordinate system
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pos_crs: Option<(f64, f64)>,
     /// The distance crossed so far by the agent, in meters. There are some caveats to this value:
     /// - The distance along driveways between buildings/parking lots and the road doesn't count
     ///   here.
@@ -461,11 +541,24 @@ struct BlockedByGraph {
     blocked_by: BTreeMap<AgentID, (Duration, DelayCause, Option<TripID>, Option<PersonID>)>,
 }
 
+#[derive(Serialize)]
+struct IncidentDelays {
+    id: IncidentID,
+    incident: Incident,
+    /// How many agents were delayed by the incident so far
+    num_delayed: usize,
+    /// The sum of the delays for all of those agents
+    total_delay: Duration,
+}
+
 #[derive(Deserialize)]
 struct LoadSim {
     scenario: String,
     modifiers: Vec<ScenarioModifier>,
     edits: Option<PermanentMapEdits>,
+    /// Scheduled every time the simulation is reset
+    #[serde(default)]
+    incidents: Vec<Incident>,
     // These are fixed from the initial command line flags
     #[serde(skip_deserializing)]
     rng_seed: u64,
@@ -491,6 +584,12 @@ impl LoadSim {
         let mut rng = XorShiftRng::seed_from_u64(self.rng_seed);
         let mut sim = Sim::new(&map, self.opts.clone());
         scenario.instantiate(&mut sim, &map, &mut rng, timer);
+        for incident in &self.incidents {
+            // Map edits might've made an incident nonsensical; just skip it
+            if let Err(err) = sim.schedule_incident(incident.clone(), &map) {
+                error!("Skipping incident: {}", err);
+            }
+        }
 
         (map, sim)
     }
@@ -546,45 +645,3 @@ fn export_geometry(map: &Map, i: IntersectionID) -> geojson::GeoJson {
         foreign_members: None,
     })
 }
-
-fn export_all_geometry(map: &Map) -> geojson::GeoJson {
-    use geojson::{Feature, FeatureCollection, GeoJson};
-
-    let mut features = Vec::new();
-    let gps_bounds = Some(map.get_gps_bounds());
-
-    for i in map.all_intersections() {
-        let mut props = serde_json::Map::new();
-        props.insert("type".to_string(), "intersection".into());
-        props.insert("id".to_string(), i.orig_id.to_string().into());
-        features.push(Feature {
-            bbox: None,
-            geometry: Some(i.polygon.clone().into_ring().to_geojson(gps_bounds)),
-            id: None,
-            properties: Some(props),
-            foreign_members: None,
-        });
-    }
-    for r in map.all_roads() {
-        let mut props = serde_json::Map::new();
-        props.insert("type".to_string(), "road".into());
-        props.insert("id".to_string(), r.orig_id.osm_way_id.to_string().into());
-        features.push(Feature {
-            bbox: None,
-            geometry: Some(
-                r.center_pts
-                    .to_thick_ring(r.get_width(map))
-                    .to_geojson(gps_bounds),
-            ),
-            id: None,
-            properties: Some(props),
-            foreign_members: None,
-        });
-    }
-
-    GeoJson::from(FeatureCollection {
-        bbox: None,
-        features,
-        foreign_members: None,
-    })
-}
@@ -1,9 +1,21 @@
+use anyhow::Result;
+
 use abstutil::{CmdArgs, Timer};
+use geom::Crs;
 use map_model::Map;
 
-fn main() {
+/// Prints a map as JSON. With `--crs`, instead prints the outlines of intersections and roads as
+/// GeoJSON in that coordinate system, like `--crs=EPSG:32610`, `--crs=wgs84`, or `--crs=map`.
+fn main() -> Result<()> {
     let mut args = CmdArgs::new();
+    let crs = args.optional("--crs").map(|x| Crs::parse(&x)).transpose()?;
     let map = Map::load_synchronously(args.required_free(), &mut Timer::throwaway());
-    println!("{}", abstutil::to_json(&map));
     args.done();
+    if let Some(crs) = crs {
+        let crs = crs.transform(map.get_gps_bounds())?;
+        println!("{}", abstutil::to_json(&map.export_geometry(&crs)));
+    } else {
+        println!("{}", abstutil::to_json(&map));
+    }
+    Ok(())
 }
@@ -0,0 +1,42 @@
+use anyhow::Result;
+
+use abstutil::{CmdArgs, Timer};
+use geom::Crs;
+use kml::ExtraShapes;
+use map_model::Map;
+
+/// Prints a KML or CSV file (or shapes already imported from one) as GeoJSON, keeping only shapes
+/// within a map's boundary. With `--crs`, the GeoJSON is in that coordinate system, like
+/// `--crs=EPSG:32610` or `--crs=map`. Defaults to WGS84.
+fn main() -> Result<()> {
+    let mut args = CmdArgs::new();
+    let map = args.required("--map");
+    let crs = Crs::parse(
+        &args
+            .optional("--crs")
+            .unwrap_or_else(|| "wgs84".to_string()),
+    )?;
+    let input = args.required_free();
+    args.done();
+
+    let mut timer = Timer::new("dump shapes");
+    let map = Map::load_synchronously(map, &mut timer);
+    let gps_bounds = map.get_gps_bounds();
+    let shapes = if input.ends_with(".kml") {
+        kml::load(input, gps_bounds, true, &mut timer)?
+    } else if input.ends_with(".csv") {
+        ExtraShapes::load_csv(input, gps_bounds, &mut timer)?
+    } else {
+        let shapes: ExtraShapes = abstio::maybe_read_binary(input, &mut timer)?;
+        ExtraShapes {
+            shapes: shapes
+                .shapes
+                .into_iter()
+                .filter(|s| s.points.iter().all(|pt| gps_bounds.contains(*pt)))
+                .collect(),
+        }
+    };
+    let crs = crs.transform(gps_bounds)?;
+    println!("{}", abstutil::to_json(&shapes.to_geojson(&crs)));
+    Ok(())
+}
@@ -0,0 +1,18 @@
+use anyhow::Result;
+
+use abstutil::{CmdArgs, Timer};
+use map_model::Map;
+
+/// Export a map as GMNS node, link, lane, movement, and signal timing tables.
+fn main() -> Result<()> {
+    let mut args = CmdArgs::new();
+    let map = args.required("--map");
+    let output = args.required("--output");
+    args.done();
+
+    let mut timer = Timer::new("export GMNS");
+    let map = Map::load_synchronously(map, &mut timer);
+    map_model::gmns::export(&map, &output, &mut timer)?;
+    println!("Wrote GMNS tables to {}", output);
+    Ok(())
+}
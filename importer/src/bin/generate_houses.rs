@@ -10,7 +10,7 @@ use rand::{Rng, SeedableRng};
 use rand_xorshift::XorShiftRng;
 
 use abstutil::{CmdArgs, Timer};
-use geom::{Distance, Polygon};
+use geom::{Distance, Polygon, Projection};
 use map_model::{osm, Map};
 
 fn main() {
@@ -194,6 +194,7 @@ fn import_map(
                 bikes_can_use_bus_lanes: true,
                 inferred_sidewalks: true,
                 street_parking_spot_length: Distance::meters(8.0),
+                projection: Projection::default(),
             },
 
             onstreet_parking: convert_osm::OnstreetParking::JustOSM,
@@ -202,6 +203,8 @@ fn import_map(
             include_railroads: true,
             extra_buildings: None,
             skip_local_roads: false,
+            mid_block_crossings: false,
+            informal_crossings: None,
         },
         timer,
     );
@@ -0,0 +1,34 @@
+use anyhow::Result;
+
+use abstutil::{CmdArgs, Timer};
+use map_model::gmns::SignalTiming;
+use map_model::Map;
+
+/// Import traffic signal timing from a GMNS timing.csv (and optionally the movement.csv it refers
+/// to) for every signal in a map, saving the result as map edits.
+fn main() -> Result<()> {
+    let mut args = CmdArgs::new();
+    let map = args.required("--map");
+    let timing_path = args.required("--timing");
+    let movement_path = args.optional("--movements");
+    let edits_name = args
+        .optional("--edits_name")
+        .unwrap_or_else(|| "GMNS signal timing".to_string());
+    args.done();
+
+    let mut timer = Timer::new("import GMNS signal timing");
+    let timing = SignalTiming::load(&timing_path, movement_path.as_deref())?;
+    let mut map = Map::load_synchronously(map, &mut timer);
+    let results = timing.import_all(&map, &mut timer);
+    println!(
+        "{} traffic signals imported, {} without any data, {} other failures",
+        results.successes, results.failures_no_match, results.failures_other
+    );
+
+    let mut edits = map.new_edits();
+    edits.edits_name = edits_name;
+    edits.commands.extend(results.commands);
+    map.must_apply_edits(edits);
+    map.save_edits();
+    Ok(())
+}
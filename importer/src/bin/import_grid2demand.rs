@@ -54,6 +54,7 @@ fn parse_trips(csv_path: String) -> Result<Vec<ExternalPerson>> {
         // For each row in the CSV file, create a person who takes a single trip from the origin to
         // the destination. They do not take a later trip to return home.
         people.push(ExternalPerson {
+            orig_id: None,
             trips: vec![ExternalTrip {
                 departure,
                 origin: ExternalTripEndpoint::Position(origin),
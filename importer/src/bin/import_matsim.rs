@@ -0,0 +1,38 @@
+use anyhow::Result;
+
+use abstutil::{prettyprint_usize, CmdArgs, Timer};
+use map_model::Map;
+use sim::{ExternalPerson, Scenario};
+
+/// Import a scenario from a MATSim plans.xml file. Activity coordinates must be WGS84.
+fn main() -> Result<()> {
+    let mut args = CmdArgs::new();
+    let input = args.required("--input");
+    let map = args.required("--map");
+    let scenario_name = args
+        .optional("--scenario_name")
+        .unwrap_or_else(|| "matsim".to_string());
+    args.done();
+
+    let mut timer = Timer::new("import MATSim plans");
+    let (people, ids) = popdat::matsim::read_plans(&input, &mut timer)?;
+    let map = Map::load_synchronously(map, &mut timer);
+
+    let mut s = Scenario::empty(&map, &scenario_name);
+    // Include all buses/trains
+    s.only_seed_buses = None;
+    let orig_num = people.len();
+    let skip_problems = true;
+    s.people = ExternalPerson::import(&map, people, skip_problems)?;
+    // Always clean up people with no-op trips (going between the same buildings)
+    s = s.remove_weird_schedules();
+    println!(
+        "Imported {}/{} people",
+        prettyprint_usize(s.people.len()),
+        prettyprint_usize(orig_num)
+    );
+    s.save();
+    ids.save(&s.map_name, &s.scenario_name);
+
+    Ok(())
+}
@@ -1,9 +1,18 @@
 use serde::{Deserialize, Serialize};
 
+use anyhow::Result;
+use rand::SeedableRng;
+use rand_xorshift::XorShiftRng;
+
 use abstio::MapName;
+use abstutil::Timer;
+use geom::Distance;
 use map_model::raw::RawMap;
+use map_model::Map;
+use sim::Scenario;
 
 use crate::configuration::ImporterConfiguration;
+use crate::od_matrix::OdMatrixConfig;
 use crate::utils::{download, osmconvert};
 
 /// Importing a new city can be done just by filling out this config file and specifying some
@@ -29,6 +38,20 @@ pub struct GenericCityImporter {
     pub include_railroads: bool,
     /// If provided, read polygons from this GeoJSON file and add them to the RawMap as buildings.
     pub extra_buildings: Option<String>,
+    /// Split roads at mapped pedestrian crossings, so people can walk across mid-block.
+    #[serde(default)]
+    pub mid_block_crossings: bool,
+    /// In meters. If provided, add informal pedestrian crossings along long blocks of low-speed
+    /// roads, about this far apart.
+    #[serde(default)]
+    pub informal_crossings_meters: Option<f64>,
+    /// If provided, generate a weekday scenario from local census data, using popdat's activity
+    /// model.
+    #[serde(default)]
+    pub census: Option<popdat::CensusSource>,
+    /// If provided, generate a scenario from zone-to-zone OD matrices.
+    #[serde(default)]
+    pub od_matrix: Option<OdMatrixConfig>,
 }
 
 impl GenericCityImporter {
@@ -81,10 +104,57 @@ impl GenericCityImporter {
                 extra_buildings: self.extra_buildings.clone(),
                 // TODO Total hack! Need to figure out how to express per-map config overrides
                 skip_local_roads: name == MapName::new("us", "phoenix", "loop101"),
+                mid_block_crossings: self.mid_block_crossings,
+                informal_crossings: self.informal_crossings_meters.map(Distance::meters),
             },
             timer,
         );
         map.save();
         map
     }
+
+    /// Every file used to generate scenarios for this city
+    pub fn scenario_inputs(&self) -> Vec<String> {
+        let mut inputs = Vec::new();
+        if let Some(ref census) = self.census {
+            inputs.push(census.path.clone());
+        }
+        if let Some(ref od) = self.od_matrix {
+            inputs.extend(od.all_inputs());
+        }
+        inputs
+    }
+
+    /// Generates and saves scenarios from census data and OD matrices, if this city has any.
+    pub fn generate_scenario(&self, map: &Map, timer: &mut Timer) -> Result<()> {
+        if let Some(ref source) = self.census {
+            let areas = popdat::CensusArea::load_all_for_map(
+                source,
+                map.get_boundary_polygon(),
+                map.get_gps_bounds(),
+                timer,
+            )?;
+            if areas.is_empty() {
+                bail!(
+                    "None of the census areas in {} overlap the map",
+                    source.path
+                );
+            }
+            let mut rng = XorShiftRng::seed_from_u64(42);
+            timer.start("generate scenario from census");
+            let scenario = popdat::generate_scenario(
+                "weekday",
+                areas,
+                popdat::Config::default(),
+                map,
+                &mut rng,
+            );
+            timer.stop("generate scenario from census");
+            scenario.save();
+        }
+        if let Some(ref od) = self.od_matrix {
+            od.generate_scenario(map, timer)?.save();
+        }
+        Ok(())
+    }
 }
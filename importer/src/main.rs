@@ -11,21 +11,22 @@ extern crate log;
 
 use abstio::{CityName, MapName};
 use abstutil::Timer;
-use geom::Distance;
+use geom::{Distance, Projection};
 use map_model::RawToMapOptions;
 
 use configuration::{load_configuration, ImporterConfiguration};
+use pipeline::{Cache, Report, Spec, Stage};
 
 mod berlin;
 mod configuration;
 mod generic;
+mod od_matrix;
+mod pipeline;
 mod seattle;
 mod soundcast;
 mod uk;
 mod utils;
 
-// TODO Might be cleaner to express as a dependency graph?
-
 #[tokio::main]
 async fn main() {
     let config: ImporterConfiguration = load_configuration();
@@ -36,13 +37,25 @@ async fn main() {
         consolidate_all_intersections: args.enabled("--consolidate_all_intersections"),
         keep_bldg_tags: args.enabled("--keep_bldg_tags"),
     };
+    // Rebuild stages even if their inputs haven't changed
+    let force = args.enabled("--force");
 
     if let Some(path) = args.optional("--oneshot") {
         let clip = args.optional("--oneshot_clip");
         let drive_on_left = args.enabled("--oneshot_drive_on_left");
+        let projection = match args.optional_parse("--oneshot_epsg", |s| s.parse::<u32>()) {
+            Some(code) => match Projection::from_epsg(code) {
+                Ok(projection) => projection,
+                Err(err) => {
+                    println!("Can't use --oneshot_epsg={}: {}", code, err);
+                    std::process::exit(1);
+                }
+            },
+            None => Projection::default(),
+        };
         args.done();
 
-        oneshot(path, clip, !drive_on_left, opts);
+        oneshot(path, clip, !drive_on_left, projection, opts);
         return;
     }
 
@@ -55,7 +68,7 @@ async fn main() {
         let num_shards = args
             .optional_parse("--num_shards", |s| s.parse::<usize>())
             .unwrap_or(1);
-        regenerate_everything(config, shard_num, num_shards).await;
+        regenerate_everything(config, force, shard_num, num_shards).await;
         return;
     }
     if args.enabled("--regen_all_maps_parallel") {
@@ -80,9 +93,10 @@ async fn main() {
         // Produce a city overview from all of the individual maps in a city.
         city_overview: args.enabled("--city_overview"),
 
-        // Only process one map. If not specified, process all maps defined by clipping polygons in
-        // importer/config/$city/.
-        only_map: args.optional_free(),
+        // Only process some maps, separated by commas. If not specified, process all maps
+        // defined by clipping polygons in importer/config/$city/.
+        only_maps: args.optional_free(),
+        force,
     };
     args.done();
 
@@ -95,11 +109,17 @@ async fn main() {
     }
 
     let mut timer = Timer::new("import map data");
-
-    job.run(&config, opts, &mut timer).await;
+    let mut report = Report::new();
+    job.run(&config, opts, &mut report, &mut timer).await;
+    report.save();
 }
 
-async fn regenerate_everything(config: ImporterConfiguration, shard_num: usize, num_shards: usize) {
+async fn regenerate_everything(
+    config: ImporterConfiguration,
+    force: bool,
+    shard_num: usize,
+    num_shards: usize,
+) {
     // Discover all cities by looking at config. But always operate on Seattle first. Special
     // treatment ;)
     let mut all_cities = CityName::list_all_cities_from_importer_config();
@@ -107,6 +127,7 @@ async fn regenerate_everything(config: ImporterConfiguration, shard_num: usize,
     all_cities.insert(0, CityName::seattle());
 
     let mut timer = Timer::new("regenerate all maps");
+    let mut report = Report::new();
     for (cnt, city) in all_cities.into_iter().enumerate() {
         let mut job = Job {
             city: city.clone(),
@@ -114,10 +135,11 @@ async fn regenerate_everything(config: ImporterConfiguration, shard_num: usize,
             raw_to_map: true,
             scenario: false,
             city_overview: false,
-            only_map: None,
+            only_maps: None,
+            force,
         };
         // Only some maps run extra tasks
-        if city == CityName::seattle() || city.country == "gb" {
+        if city == CityName::seattle() || city.country == "gb" || has_scenario_inputs(&city) {
             job.scenario = true;
         }
         // TODO Autodetect this based on number of maps per city?
@@ -132,10 +154,21 @@ async fn regenerate_everything(config: ImporterConfiguration, shard_num: usize,
         }
 
         if cnt % num_shards == shard_num {
-            job.run(&config, RawToMapOptions::default(), &mut timer)
+            job.run(&config, RawToMapOptions::default(), &mut report, &mut timer)
                 .await;
         }
     }
+    report.save();
+}
+
+/// Cities configured with local census data or OD matrices get a generated scenario.
+fn has_scenario_inputs(city: &CityName) -> bool {
+    abstio::maybe_read_json::<generic::GenericCityImporter>(
+        format!("importer/config/{}/{}/cfg.json", city.country, city.city),
+        &mut Timer::throwaway(),
+    )
+    .map(|cfg| !cfg.scenario_inputs().is_empty())
+    .unwrap_or(false)
 }
 
 fn regenerate_all_maps(opts: RawToMapOptions) {
@@ -162,52 +195,76 @@ struct Job {
     scenario: bool,
     city_overview: bool,
 
-    only_map: Option<String>,
+    only_maps: Option<String>,
+    force: bool,
 }
 
 impl Job {
+    /// The stages explicitly asked for. Stages they depend on also run if they're out-of-date.
+    fn requested_stages(&self) -> Vec<Stage> {
+        let mut stages = Vec::new();
+        if self.osm_to_raw {
+            stages.push(Stage::Raw);
+        }
+        if self.raw_to_map {
+            stages.push(Stage::Map);
+        }
+        if self.scenario {
+            stages.push(Stage::Scenario);
+        }
+        if self.city_overview {
+            stages.push(Stage::CityOverview);
+        }
+        stages
+    }
+
+    /// Should a stage run for one map? Only if something requested needs it and it's out-of-date.
+    fn decide(
+        &self,
+        stage: Stage,
+        requested: &[Stage],
+        report: &mut Report,
+        cache: &mut Cache,
+        spec: &Spec,
+    ) -> bool {
+        stage.needed_by(requested)
+            && report.decide(cache, spec, requested.contains(&stage), self.force)
+    }
+
     async fn run(
         self,
         config: &ImporterConfiguration,
         opts: RawToMapOptions,
+        report: &mut Report,
         timer: &mut Timer<'_>,
     ) {
         timer.start(format!("import {}", self.city.describe()));
-        let names = if let Some(n) = self.only_map {
-            println!("- Just working on {}", n);
-            vec![MapName::from_city(&self.city, &n)]
+        let names = if let Some(ref maps) = self.only_maps {
+            println!("- Just working on {}", maps);
+            maps.split(',')
+                .map(|n| MapName::from_city(&self.city, n))
+                .collect()
         } else {
             println!("- Working on all {} maps", self.city.describe());
             self.city.list_all_maps_in_city_from_importer_config()
         };
+        let requested = self.requested_stages();
+        let mut cache = Cache::load(&self.city, timer);
 
         // When regenerating everything, huge_seattle gets created twice! This is expensive enough
         // to hack in a way to avoid the work.
         let mut built_raw_huge_seattle = false;
         let mut built_map_huge_seattle = false;
-        let (maybe_popdat, maybe_huge_map, maybe_zoning_parcels) = if self.scenario
-            && self.city == CityName::seattle()
-        {
-            timer.start("ensure_popdat_exists");
-            let (popdat, huge_map) = seattle::ensure_popdat_exists(
-                timer,
-                config,
-                &mut built_raw_huge_seattle,
-                &mut built_map_huge_seattle,
-            )
-            .await;
-            // Just assume --raw has been called...
-            let shapes: kml::ExtraShapes =
-                abstio::read_binary(CityName::seattle().input_path("zoning_parcels.bin"), timer);
-            timer.stop("ensure_popdat_exists");
-            (Some(popdat), Some(huge_map), Some(shapes))
-        } else {
-            (None, None, None)
-        };
+        // Only load these when a Seattle scenario actually needs to be generated
+        let mut maybe_popdat = None;
+        let mut maybe_huge_map = None;
+        let mut maybe_zoning_parcels = None;
 
         for name in names {
             timer.start(name.describe());
-            if self.osm_to_raw {
+
+            let raw_spec = Spec::for_map(Stage::Raw, &name, &opts);
+            if self.decide(Stage::Raw, &requested, report, &mut cache, &raw_spec) {
                 // Still special-cased
                 if name.city == CityName::seattle() {
                     if !built_raw_huge_seattle || name.map != "huge_seattle" {
@@ -235,48 +292,81 @@ impl Job {
                         uk::import_collision_data(&raw, config, timer).await;
                     }
                 }
+                cache.record(&raw_spec);
             }
 
-            let mut maybe_map = if self.raw_to_map {
-                let mut map = if built_map_huge_seattle && name == MapName::seattle("huge_seattle")
-                {
-                    map_model::Map::load_synchronously(name.path(), timer)
+            let map_spec = Spec::for_map(Stage::Map, &name, &opts);
+            let mut maybe_map =
+                if self.decide(Stage::Map, &requested, report, &mut cache, &map_spec) {
+                    let mut map =
+                        if built_map_huge_seattle && name == MapName::seattle("huge_seattle") {
+                            map_model::Map::load_synchronously(name.path(), timer)
+                        } else {
+                            utils::raw_to_map(&name, opts.clone(), timer)
+                        };
+
+                    // Another strange step in the pipeline.
+                    if name == MapName::new("de", "berlin", "center") {
+                        timer.start(format!(
+                            "distribute residents from planning areas for {}",
+                            name.describe()
+                        ));
+                        berlin::distribute_residents(&mut map, timer);
+                        timer.stop(format!(
+                            "distribute residents from planning areas for {}",
+                            name.describe()
+                        ));
+                    } else if name.city == CityName::seattle() {
+                        // TODO Slightly misleading, but hijack --skip_ch to also skip GTFS. The
+                        // intention of --skip_ch is usually to quickly iterate on the map importer,
+                        // not in release mode. This import is broken/unused right now anyway and takes
+                        // way too much time in debug mode.
+                        if opts.build_ch {
+                            timer.start(format!("add GTFS schedules for {}", name.describe()));
+                            seattle::add_gtfs_schedules(&mut map);
+                            timer.stop(format!("add GTFS schedules for {}", name.describe()));
+                        }
+                    }
+                    cache.record(&map_spec);
+
+                    Some(map)
                 } else {
-                    utils::raw_to_map(&name, opts.clone(), timer)
+                    None
                 };
 
-                // Another strange step in the pipeline.
-                if name == MapName::new("de", "berlin", "center") {
-                    timer.start(format!(
-                        "distribute residents from planning areas for {}",
-                        name.describe()
-                    ));
-                    berlin::distribute_residents(&mut map, timer);
-                    timer.stop(format!(
-                        "distribute residents from planning areas for {}",
-                        name.describe()
-                    ));
-                } else if name.city == CityName::seattle() {
-                    // TODO Slightly misleading, but hijack --skip_ch to also skip GTFS. The
-                    // intention of --skip_ch is usually to quickly iterate on the map importer,
-                    // not in release mode. This import is broken/unused right now anyway and takes
-                    // way too much time in debug mode.
-                    if opts.build_ch {
-                        timer.start(format!("add GTFS schedules for {}", name.describe()));
-                        seattle::add_gtfs_schedules(&mut map);
-                        timer.stop(format!("add GTFS schedules for {}", name.describe()));
-                    }
+            let scenario_spec = Spec::for_map(Stage::Scenario, &name, &opts);
+            if self.decide(
+                Stage::Scenario,
+                &requested,
+                report,
+                &mut cache,
+                &scenario_spec,
+            ) {
+                if maybe_map.is_none() {
+                    maybe_map = Some(map_model::Map::load_synchronously(name.path(), timer));
                 }
 
-                Some(map)
-            } else if self.scenario {
-                Some(map_model::Map::load_synchronously(name.path(), timer))
-            } else {
-                None
-            };
-
-            if self.scenario {
                 if self.city == CityName::seattle() {
+                    if maybe_popdat.is_none() {
+                        timer.start("ensure_popdat_exists");
+                        let (popdat, huge_map) = seattle::ensure_popdat_exists(
+                            timer,
+                            config,
+                            &mut built_raw_huge_seattle,
+                            &mut built_map_huge_seattle,
+                        )
+                        .await;
+                        // Just assume --raw has been called...
+                        let shapes: kml::ExtraShapes = abstio::read_binary(
+                            CityName::seattle().input_path("zoning_parcels.bin"),
+                            timer,
+                        );
+                        timer.stop("ensure_popdat_exists");
+                        maybe_popdat = Some(popdat);
+                        maybe_huge_map = Some(huge_map);
+                        maybe_zoning_parcels = Some(shapes);
+                    }
+
                     timer.start(format!("scenario for {}", name.describe()));
                     let scenario = soundcast::make_weekday_scenario(
                         maybe_map.as_ref().unwrap(),
@@ -312,11 +402,33 @@ impl Job {
                         .await
                         .unwrap();
                 }
+                if let Ok(city_cfg) = abstio::maybe_read_json::<generic::GenericCityImporter>(
+                    format!(
+                        "importer/config/{}/{}/cfg.json",
+                        self.city.country, self.city.city
+                    ),
+                    timer,
+                ) {
+                    if let Err(err) = city_cfg.generate_scenario(maybe_map.as_ref().unwrap(), timer)
+                    {
+                        panic!("Can't generate scenario for {}: {}", name.describe(), err);
+                    }
+                }
+                cache.record(&scenario_spec);
             }
             timer.stop(name.describe());
         }
 
-        if self.city_overview {
+        let overview_spec = Spec::city_overview(&self.city);
+        if self.city_overview
+            && self.decide(
+                Stage::CityOverview,
+                &requested,
+                report,
+                &mut cache,
+                &overview_spec,
+            )
+        {
             timer.start(format!(
                 "generate city overview for {}",
                 self.city.describe()
@@ -332,13 +444,20 @@ impl Job {
                 "generate city overview for {}",
                 self.city.describe()
             ));
+            cache.record(&overview_spec);
         }
 
         timer.stop(format!("import {}", self.city.describe()));
     }
 }
 
-fn oneshot(osm_path: String, clip: Option<String>, drive_on_right: bool, opts: RawToMapOptions) {
+fn oneshot(
+    osm_path: String,
+    clip: Option<String>,
+    drive_on_right: bool,
+    projection: Projection,
+    opts: RawToMapOptions,
+) {
     let mut timer = abstutil::Timer::new("oneshot");
     println!("- Running convert_osm on {}", osm_path);
     let name = abstutil::basename(&osm_path);
@@ -357,6 +476,7 @@ fn oneshot(osm_path: String, clip: Option<String>, drive_on_right: bool, opts: R
                 bikes_can_use_bus_lanes: true,
                 inferred_sidewalks: true,
                 street_parking_spot_length: Distance::meters(8.0),
+                projection,
             },
 
             onstreet_parking: convert_osm::OnstreetParking::JustOSM,
@@ -365,6 +485,8 @@ fn oneshot(osm_path: String, clip: Option<String>, drive_on_right: bool, opts: R
             include_railroads: true,
             extra_buildings: None,
             skip_local_roads: false,
+            mid_block_crossings: false,
+            informal_crossings: None,
         },
         &mut timer,
     );
@@ -0,0 +1,449 @@
+//! Imports travel demand from zone-to-zone origin-destination matrices, the way most transport
+//! models and consultancies deliver it. The zones are polygons in a GeoJSON file, and each matrix
+//! is either a CSV file or an Open Matrix (OMX) file.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+
+use anyhow::Result;
+use rand::SeedableRng;
+use rand_xorshift::XorShiftRng;
+use serde::{Deserialize, Serialize};
+
+use abstutil::{prettyprint_usize, Timer};
+use geom::{GPSBounds, Polygon};
+use map_model::Map;
+use popdat::od::{DepartureProfile, FlowOptions, NormalDistribution, ZoneFlow};
+use sim::{Scenario, TripMode, TripPurpose};
+
+/// Describes where to find OD matrices for a city and how to turn them into trips.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OdMatrixConfig {
+    /// A GeoJSON file with the zone polygons, in WGS84
+    pub zones: String,
+    /// The property of each zone matching the IDs in the matrices
+    pub zone_id: String,
+    pub matrices: Vec<MatrixInput>,
+    /// The name of the scenario to create. Defaults to "od_matrix", so it doesn't overwrite the
+    /// "weekday" scenario generated from census data.
+    #[serde(default = "default_scenario_name")]
+    pub scenario_name: String,
+}
+
+fn default_scenario_name() -> String {
+    "od_matrix".to_string()
+}
+
+/// One matrix, usually covering one trip purpose and period of the day
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MatrixInput {
+    pub file: MatrixFile,
+    #[serde(default = "default_origin_purpose")]
+    pub origin_purpose: TripPurpose,
+    pub destination_purpose: TripPurpose,
+    /// If the matrix doesn't break down trips by mode, split them using these shares. They should
+    /// sum to 1.
+    #[serde(default)]
+    pub mode_split: BTreeMap<TripMode, f64>,
+    pub departure_profile: DepartureProfile,
+    /// If set, everybody returns to their origin after spending this long at the destination.
+    #[serde(default)]
+    pub return_after: Option<NormalDistribution>,
+}
+
+fn default_origin_purpose() -> TripPurpose {
+    TripPurpose::Home
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum MatrixFile {
+    /// One row per origin and destination (and optionally mode), with the number of trips
+    Csv {
+        path: String,
+        origin_column: String,
+        destination_column: String,
+        trips_column: String,
+        /// Values like "walk", "bike", "transit", or "drive"
+        #[serde(default)]
+        mode_column: Option<String>,
+    },
+    /// An HDF5 file with square matrices. Each table listed here is read, optionally for a
+    /// specific mode. Requires the `omx` feature.
+    Omx {
+        path: String,
+        tables: BTreeMap<String, Option<TripMode>>,
+        /// The lookup mapping matrix indices to zone IDs. If omitted, zones are numbered from 1.
+        #[serde(default)]
+        mapping: Option<String>,
+    },
+}
+
+impl OdMatrixConfig {
+    /// Every file this reads
+    pub fn all_inputs(&self) -> Vec<String> {
+        let mut inputs = vec![self.zones.clone()];
+        for matrix in &self.matrices {
+            inputs.push(match matrix.file {
+                MatrixFile::Csv { ref path, .. } | MatrixFile::Omx { ref path, .. } => path.clone(),
+            });
+        }
+        inputs
+    }
+
+    pub fn generate_scenario(&self, map: &Map, timer: &mut Timer) -> Result<Scenario> {
+        timer.start("load OD zones");
+        let zones = load_zones(map.get_gps_bounds(), &self.zones, &self.zone_id)?;
+        timer.stop("load OD zones");
+
+        // Could plumb this in as a flag to the importer, but it's not critical.
+        let mut rng = XorShiftRng::seed_from_u64(42);
+        let mut scenario = Scenario::empty(map, &self.scenario_name);
+        for matrix in &self.matrices {
+            timer.start("read OD matrix");
+            let raw = matrix.file.read()?;
+            timer.stop("read OD matrix");
+            let flows = matrix.to_flows(raw)?;
+            scenario.people.extend(popdat::od::disaggregate_flows(
+                map,
+                zones.clone(),
+                flows,
+                FlowOptions {
+                    departure_profile: matrix.departure_profile.clone(),
+                    return_after: matrix.return_after.clone(),
+                },
+                &mut rng,
+                timer,
+            ));
+        }
+        scenario = scenario.remove_weird_schedules();
+        info!(
+            "Generated {} scenario with {} people from OD matrices",
+            self.scenario_name,
+            prettyprint_usize(scenario.people.len())
+        );
+        Ok(scenario)
+    }
+}
+
+/// (origin, destination, mode if known, number of trips)
+type RawEntry = (String, String, Option<TripMode>, f64);
+
+impl MatrixInput {
+    fn to_flows(&self, raw: Vec<RawEntry>) -> Result<Vec<ZoneFlow>> {
+        let mut flows = Vec::new();
+        for (origin_zone, destination_zone, mode, number_trips) in raw {
+            if number_trips <= 0.0 {
+                continue;
+            }
+            let split = if let Some(mode) = mode {
+                vec![(mode, 1.0)]
+            } else if self.mode_split.is_empty() {
+                bail!("An OD matrix doesn't say the mode of trips, and there's no mode_split");
+            } else {
+                self.mode_split.iter().map(|(m, x)| (*m, *x)).collect()
+            };
+            for (mode, share) in split {
+                flows.push(ZoneFlow {
+                    origin_zone: origin_zone.clone(),
+                    destination_zone: destination_zone.clone(),
+                    mode,
+                    origin_purpose: self.origin_purpose,
+                    destination_purpose: self.destination_purpose,
+                    number_trips: number_trips * share,
+                });
+            }
+        }
+        Ok(flows)
+    }
+}
+
+impl MatrixFile {
+    fn read(&self) -> Result<Vec<RawEntry>> {
+        match self {
+            MatrixFile::Csv {
+                path,
+                origin_column,
+                destination_column,
+                trips_column,
+                mode_column,
+            } => read_csv(
+                path,
+                origin_column,
+                destination_column,
+                trips_column,
+                mode_column.as_ref(),
+            ),
+            MatrixFile::Omx {
+                path,
+                tables,
+                mapping,
+            } => read_omx(path, tables, mapping.as_ref()),
+        }
+    }
+}
+
+fn read_csv(
+    path: &str,
+    origin_column: &str,
+    destination_column: &str,
+    trips_column: &str,
+    mode_column: Option<&String>,
+) -> Result<Vec<RawEntry>> {
+    let mut reader = csv::Reader::from_reader(File::open(path)?);
+    let headers = reader.headers()?.clone();
+    let find = |column: &str| -> Result<usize> {
+        headers
+            .iter()
+            .position(|x| x == column)
+            .ok_or_else(|| anyhow!("{} has no column {}", path, column))
+    };
+    let origin_idx = find(origin_column)?;
+    let destination_idx = find(destination_column)?;
+    let trips_idx = find(trips_column)?;
+    let mode_idx = match mode_column {
+        Some(column) => Some(find(column)?),
+        None => None,
+    };
+
+    let mut results = Vec::new();
+    for rec in reader.records() {
+        let rec = rec?;
+        let trips = rec[trips_idx].trim();
+        let number_trips = trips
+            .parse::<f64>()
+            .map_err(|_| anyhow!("{}: {} isn't a number of trips", path, trips))?;
+        let mode = match mode_idx {
+            Some(idx) => Some(parse_mode(&rec[idx])?),
+            None => None,
+        };
+        results.push((
+            rec[origin_idx].trim().to_string(),
+            rec[destination_idx].trim().to_string(),
+            mode,
+            number_trips,
+        ));
+    }
+    Ok(results)
+}
+
+fn parse_mode(x: &str) -> Result<TripMode> {
+    match x.trim().to_lowercase().as_str() {
+        "walk" | "walking" | "foot" | "pedestrian" => Ok(TripMode::Walk),
+        "bike" | "bicycle" | "cycle" | "cycling" => Ok(TripMode::Bike),
+        "transit" | "pt" | "public_transport" | "bus" | "rail" => Ok(TripMode::Transit),
+        "drive" | "car" | "driving" | "auto" => Ok(TripMode::Drive),
+        _ => bail!("Unknown mode {}", x),
+    }
+}
+
+#[cfg(feature = "omx")]
+fn read_omx(
+    path: &str,
+    tables: &BTreeMap<String, Option<TripMode>>,
+    mapping: Option<&String>,
+) -> Result<Vec<RawEntry>> {
+    let file = hdf5::File::open(path)?;
+    let zone_ids: Option<Vec<String>> = match mapping {
+        Some(name) => Some(
+            file.dataset(&format!("lookup/{}", name))?
+                .read_raw::<i64>()?
+                .into_iter()
+                .map(|x| x.to_string())
+                .collect(),
+        ),
+        None => None,
+    };
+
+    let mut results = Vec::new();
+    for (table, mode) in tables {
+        let dataset = file.dataset(&format!("data/{}", table))?;
+        let shape = dataset.shape();
+        if shape.len() != 2 || shape[0] != shape[1] {
+            bail!("{} table {} isn't a square matrix", path, table);
+        }
+        let n = shape[0];
+        if let Some(ref ids) = zone_ids {
+            if ids.len() != n {
+                bail!(
+                    "{} table {} has {} zones, but the mapping has {}",
+                    path,
+                    table,
+                    n,
+                    ids.len()
+                );
+            }
+        }
+        let zone = |idx: usize| -> String {
+            match zone_ids {
+                Some(ref ids) => ids[idx].clone(),
+                None => (idx + 1).to_string(),
+            }
+        };
+        for (idx, value) in dataset.read_raw::<f64>()?.into_iter().enumerate() {
+            if value > 0.0 {
+                results.push((zone(idx / n), zone(idx % n), *mode, value));
+            }
+        }
+    }
+    Ok(results)
+}
+
+#[cfg(not(feature = "omx"))]
+fn read_omx(
+    path: &str,
+    _: &BTreeMap<String, Option<TripMode>>,
+    _: Option<&String>,
+) -> Result<Vec<RawEntry>> {
+    bail!(
+        "Can't read {}; build the importer with --features omx to read OMX files",
+        path
+    )
+}
+
+/// Transforms all zones into the map's coordinate space, no matter how far out-of-bounds they are.
+fn load_zones(
+    gps_bounds: &GPSBounds,
+    path: &str,
+    zone_id: &str,
+) -> Result<HashMap<String, Polygon>> {
+    let mut zones = HashMap::new();
+    let require_in_bounds = false;
+    for (polygon, tags) in Polygon::from_geojson_bytes(
+        &abstio::slurp_file(path.to_string())?,
+        gps_bounds,
+        require_in_bounds,
+    )? {
+        zones.insert(tags.get_result(zone_id)?.to_string(), polygon);
+    }
+    Ok(zones)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_csv(name: &str, contents: &str) -> String {
+        let path = std::env::temp_dir()
+            .join(format!("od_matrix_{}_{}.csv", name, std::process::id()))
+            .display()
+            .to_string();
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn matrix(mode_split: Vec<(TripMode, f64)>) -> MatrixInput {
+        MatrixInput {
+            file: MatrixFile::Csv {
+                path: String::new(),
+                origin_column: String::new(),
+                destination_column: String::new(),
+                trips_column: String::new(),
+                mode_column: None,
+            },
+            origin_purpose: TripPurpose::Home,
+            destination_purpose: TripPurpose::Shopping,
+            mode_split: mode_split.into_iter().collect(),
+            departure_profile: DepartureProfile::daytime(),
+            return_after: None,
+        }
+    }
+
+    #[test]
+    fn csv() {
+        let path = write_csv(
+            "csv",
+            "trips,to,from,how\n12.5, B ,A,Car\n0,A,A,walk\n3,A,B, bicycle \n",
+        );
+        let mode_column = "how".to_string();
+        let rows = read_csv(&path, "from", "to", "trips", Some(&mode_column)).unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                (
+                    "A".to_string(),
+                    "B".to_string(),
+                    Some(TripMode::Drive),
+                    12.5
+                ),
+                ("A".to_string(), "A".to_string(), Some(TripMode::Walk), 0.0),
+                ("B".to_string(), "A".to_string(), Some(TripMode::Bike), 3.0),
+            ]
+        );
+
+        // Without a mode column, modes are unknown
+        let rows = read_csv(&path, "from", "to", "trips", None).unwrap();
+        assert!(rows.iter().all(|(_, _, mode, _)| mode.is_none()));
+
+        // A missing column
+        assert!(read_csv(&path, "origin", "to", "trips", None).is_err());
+        std::fs::remove_file(&path).unwrap();
+
+        // A bad number of trips
+        let path = write_csv("bad_trips", "from,to,trips\nA,B,lots\n");
+        assert!(read_csv(&path, "from", "to", "trips", None).is_err());
+        std::fs::remove_file(&path).unwrap();
+
+        // An unknown mode
+        let path = write_csv("bad_mode", "from,to,trips,mode\nA,B,1,hovercraft\n");
+        assert!(read_csv(&path, "from", "to", "trips", Some(&"mode".to_string())).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn modes() {
+        assert_eq!(parse_mode("walk").unwrap(), TripMode::Walk);
+        assert_eq!(parse_mode(" Cycling ").unwrap(), TripMode::Bike);
+        assert_eq!(parse_mode("PT").unwrap(), TripMode::Transit);
+        assert_eq!(parse_mode("auto").unwrap(), TripMode::Drive);
+        assert!(parse_mode("teleport").is_err());
+        assert!(parse_mode("").is_err());
+    }
+
+    #[test]
+    fn flows() {
+        let raw = vec![
+            ("A".to_string(), "B".to_string(), None, 10.0),
+            ("A".to_string(), "C".to_string(), None, 0.0),
+            ("B".to_string(), "A".to_string(), Some(TripMode::Walk), 2.5),
+        ];
+
+        // Trips with an unknown mode are split by share, and fractional flows are kept as-is
+        let flows = matrix(vec![(TripMode::Walk, 0.25), (TripMode::Drive, 0.75)])
+            .to_flows(raw.clone())
+            .unwrap();
+        let summary: Vec<(&str, &str, TripMode, f64)> = flows
+            .iter()
+            .map(|f| {
+                (
+                    f.origin_zone.as_str(),
+                    f.destination_zone.as_str(),
+                    f.mode,
+                    f.number_trips,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("A", "B", TripMode::Walk, 2.5),
+                ("A", "B", TripMode::Drive, 7.5),
+                ("B", "A", TripMode::Walk, 2.5),
+            ]
+        );
+        assert!(flows.iter().all(|f| f.origin_purpose == TripPurpose::Home
+            && f.destination_purpose == TripPurpose::Shopping));
+
+        // The mode split is only required when some entry lacks a mode
+        assert!(matrix(Vec::new()).to_flows(raw).is_err());
+        let flows = matrix(Vec::new())
+            .to_flows(vec![(
+                "A".to_string(),
+                "B".to_string(),
+                Some(TripMode::Transit),
+                1.0,
+            )])
+            .unwrap();
+        assert_eq!(flows.len(), 1);
+        assert_eq!(flows[0].mode, TripMode::Transit);
+    }
+}
@@ -0,0 +1,518 @@
+//! The import is split into stages. Each stage declares the files it reads and writes, plus any
+//! settings that affect its output. After a stage runs, the checksums of its inputs are
+//! remembered, so the next import can skip it if nothing changed. Downstream stages read the
+//! outputs of upstream stages, so rebuilding one stage naturally invalidates everything after it.
+//!
+//! Stages that weren't requested, but that requested stages depend on, only run if they're
+//! out-of-date. The first time, when there's no record of them, their existing outputs are
+//! trusted. Changes to the importer code itself aren't tracked; pass `--force` to rebuild the
+//! requested stages anyway.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use serde::{Deserialize, Serialize};
+
+use abstio::{CityName, MapName};
+use abstutil::Timer;
+use map_model::RawToMapOptions;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Stage {
+    /// Download raw input files, clip OSM, and convert it to a RawMap, including elevation
+    Raw,
+    /// Convert the RawMap to the final Map format
+    Map,
+    /// Produce the typical weekday scenario from trip demand data
+    Scenario,
+    /// Combine all of the individual maps in a city
+    CityOverview,
+}
+
+impl Stage {
+    pub fn describe(self) -> &'static str {
+        match self {
+            Stage::Raw => "raw",
+            Stage::Map => "map",
+            Stage::Scenario => "scenario",
+            Stage::CityOverview => "city overview",
+        }
+    }
+
+    /// The stages whose outputs this stage reads
+    pub fn dependencies(self) -> Vec<Stage> {
+        match self {
+            Stage::Raw => Vec::new(),
+            Stage::Map => vec![Stage::Raw],
+            Stage::Scenario => vec![Stage::Map],
+            Stage::CityOverview => vec![Stage::Map],
+        }
+    }
+
+    /// True if this stage is one of the requested stages, or something they transitively depend
+    /// on.
+    pub fn needed_by(self, requested: &[Stage]) -> bool {
+        requested
+            .iter()
+            .any(|x| *x == self || x.dependencies().into_iter().any(|y| self.needed_by(&[y])))
+    }
+}
+
+/// Everything that determines the output of running one stage for one map or city.
+pub struct Spec {
+    pub stage: Stage,
+    /// Like "us/seattle/downtown" or "us/seattle"
+    pub target: String,
+    /// Missing inputs are fine; a file appearing or disappearing counts as a change.
+    pub inputs: Vec<String>,
+    /// If any of these files or directories don't exist, the stage has to run.
+    pub outputs: Vec<String>,
+    pub settings: String,
+}
+
+impl Spec {
+    pub fn for_map(stage: Stage, name: &MapName, opts: &RawToMapOptions) -> Spec {
+        let target = format!("{}/{}/{}", name.city.country, name.city.city, name.map);
+        let config_dir = format!("importer/config/{}/{}", name.city.country, name.city.city);
+        match stage {
+            Stage::Raw => {
+                let mut inputs = vec![
+                    format!("{}/cfg.json", config_dir),
+                    format!("{}/{}.poly", config_dir, name.map),
+                    name.city.input_path(format!("osm/{}.osm", name.map)),
+                ];
+                inputs.extend(abstio::list_dir(abstio::path_shared_input("elevation")));
+                Spec {
+                    stage,
+                    target,
+                    inputs,
+                    outputs: vec![abstio::path_raw_map(name)],
+                    settings: String::new(),
+                }
+            }
+            Stage::Map => Spec {
+                stage,
+                target,
+                inputs: vec![abstio::path_raw_map(name)],
+                outputs: vec![name.path()],
+                settings: format!(
+                    "build_ch={} consolidate_all_intersections={} keep_bldg_tags={}",
+                    opts.build_ch, opts.consolidate_all_intersections, opts.keep_bldg_tags
+                ),
+            },
+            Stage::Scenario => {
+                let mut inputs = vec![name.path()];
+                // Cities using local census data or OD matrices
+                if let Ok(cfg) = abstio::maybe_read_json::<crate::generic::GenericCityImporter>(
+                    format!("{}/cfg.json", config_dir),
+                    &mut Timer::throwaway(),
+                ) {
+                    let extra = cfg.scenario_inputs();
+                    if !extra.is_empty() {
+                        inputs.push(format!("{}/cfg.json", config_dir));
+                        inputs.extend(extra);
+                    }
+                }
+                Spec {
+                    stage,
+                    target,
+                    inputs,
+                    outputs: vec![abstio::path_all_scenarios(name)],
+                    settings: String::new(),
+                }
+            }
+            Stage::CityOverview => panic!("Use Spec::city_overview"),
+        }
+    }
+
+    pub fn city_overview(city: &CityName) -> Spec {
+        Spec {
+            stage: Stage::CityOverview,
+            target: format!("{}/{}", city.country, city.city),
+            inputs: city
+                .list_all_maps_in_city_from_importer_config()
+                .into_iter()
+                .map(|name| name.path())
+                .collect(),
+            outputs: vec![abstio::path(format!(
+                "system/{}/{}/city.bin",
+                city.country, city.city
+            ))],
+            settings: String::new(),
+        }
+    }
+
+    fn key(&self) -> String {
+        format!("{} {}", self.stage.describe(), self.target)
+    }
+}
+
+/// Why a stage has to run
+#[derive(Clone, Debug)]
+pub enum Reason {
+    Forced,
+    NeverBuilt,
+    MissingOutput(String),
+    InputChanged(String),
+    SettingsChanged,
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Reason::Forced => write!(f, "forced"),
+            Reason::NeverBuilt => write!(f, "never built before"),
+            Reason::MissingOutput(path) => write!(f, "{} doesn't exist", path),
+            Reason::InputChanged(path) => write!(f, "{} changed", path),
+            Reason::SettingsChanged => write!(f, "settings changed"),
+        }
+    }
+}
+
+/// What a stage saw the last time it ran, and checksums of files, persisted per city.
+#[derive(Serialize, Deserialize, Default)]
+struct Manifest {
+    stages: BTreeMap<String, StageRecord>,
+    files: BTreeMap<String, FileChecksum>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StageRecord {
+    /// Path to checksum. An empty checksum means the file didn't exist.
+    inputs: BTreeMap<String, String>,
+    settings: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct FileChecksum {
+    size_bytes: u64,
+    modified_secs: u64,
+    md5: String,
+}
+
+pub struct Cache {
+    path: String,
+    manifest: Manifest,
+}
+
+impl Cache {
+    pub fn load(city: &CityName, timer: &mut Timer) -> Cache {
+        // Not in data/input, so the updater doesn't sync it
+        let path = abstio::path(format!("import_cache/{}/{}.json", city.country, city.city));
+        let manifest = abstio::maybe_read_json(path.clone(), timer).unwrap_or_default();
+        Cache { path, manifest }
+    }
+
+    /// Returns `None` if the stage is up-to-date.
+    pub fn check(&mut self, spec: &Spec) -> Option<Reason> {
+        for path in &spec.outputs {
+            if !Path::new(path).exists() {
+                return Some(Reason::MissingOutput(path.clone()));
+            }
+        }
+        // Taking the record out avoids borrowing self twice
+        let record = match self.manifest.stages.remove(&spec.key()) {
+            Some(record) => record,
+            None => {
+                return Some(Reason::NeverBuilt);
+            }
+        };
+        let mut result = None;
+        if record.settings != spec.settings {
+            result = Some(Reason::SettingsChanged);
+        } else {
+            for path in &spec.inputs {
+                let previous = record.inputs.get(path).map(|x| x.as_str()).unwrap_or("");
+                // If the file can't be read, assume it changed
+                if self.checksum(path).as_deref() != Some(previous) {
+                    result = Some(Reason::InputChanged(path.clone()));
+                    break;
+                }
+            }
+            // Inputs that aren't declared anymore, like a deleted elevation file
+            if result.is_none() {
+                if let Some(path) = record
+                    .inputs
+                    .iter()
+                    .find(|(path, checksum)| !spec.inputs.contains(path) && !checksum.is_empty())
+                    .map(|(path, _)| path.clone())
+                {
+                    result = Some(Reason::InputChanged(path));
+                }
+            }
+        }
+        self.manifest.stages.insert(spec.key(), record);
+        result
+    }
+
+    /// Remembers the current state of a stage's inputs, after it successfully ran. Some stages
+    /// modify their own inputs (scenario generation adjusts parking in the map), so this has to
+    /// happen afterwards.
+    pub fn record(&mut self, spec: &Spec) {
+        let mut inputs = BTreeMap::new();
+        for path in &spec.inputs {
+            // Leaving out a file that can't be read means it'll look changed next time
+            if let Some(checksum) = self.checksum(path) {
+                inputs.insert(path.clone(), checksum);
+            }
+        }
+        self.manifest.stages.insert(
+            spec.key(),
+            StageRecord {
+                inputs,
+                settings: spec.settings.clone(),
+            },
+        );
+        // Save right away, so a later crash doesn't lose progress
+        abstio::write_json(self.path.clone(), &self.manifest);
+    }
+
+    /// Checksums a file, returns an empty string if it doesn't exist, or `None` if it couldn't be
+    /// read. Like the updater, if the size and modification time are unchanged, assumes the
+    /// contents are too, since hashing large OSM files is slow.
+    fn checksum(&mut self, path: &str) -> Option<String> {
+        let metadata = match std::fs::metadata(path) {
+            Ok(metadata) if metadata.is_file() => metadata,
+            _ => {
+                return Some(String::new());
+            }
+        };
+        let size_bytes = metadata.len();
+        let modified_secs = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        if let Some(cached) = self.manifest.files.get(path) {
+            if cached.size_bytes == size_bytes && cached.modified_secs == modified_secs {
+                return Some(cached.md5.clone());
+            }
+        }
+        let md5 = match md5sum(path) {
+            Ok(md5) => md5,
+            Err(err) => {
+                warn!("Couldn't checksum {}: {}", path, err);
+                self.manifest.files.remove(path);
+                return None;
+            }
+        };
+        self.manifest.files.insert(
+            path.to_string(),
+            FileChecksum {
+                size_bytes,
+                modified_secs,
+                md5: md5.clone(),
+            },
+        );
+        Some(md5)
+    }
+}
+
+fn md5sum(path: &str) -> std::io::Result<String> {
+    // Files can be very large, so hash in chunks
+    let mut file = File::open(path)?;
+    let mut context = md5::Context::new();
+    std::io::copy(&mut file, &mut context)?;
+    Ok(format!("{:x}", context.compute()))
+}
+
+/// Records what was rebuilt and why, across everything in one run of the importer.
+pub struct Report {
+    entries: Vec<(String, Stage, Option<Reason>)>,
+}
+
+impl Report {
+    pub fn new() -> Report {
+        Report {
+            entries: Vec::new(),
+        }
+    }
+
+    /// Checks if a stage needs to run and records the decision. `requested` means the stage was
+    /// explicitly asked for, rather than just being a dependency; only then does `force` apply.
+    pub fn decide(&mut self, cache: &mut Cache, spec: &Spec, requested: bool, force: bool) -> bool {
+        let mut reason = if requested && force {
+            Some(Reason::Forced)
+        } else {
+            cache.check(spec)
+        };
+        if let Some(Reason::NeverBuilt) = reason {
+            if !requested {
+                // The outputs exist, but were made before anything was recorded. Don't rebuild
+                // just a dependency for that.
+                cache.record(spec);
+                reason = None;
+            }
+        }
+        match reason {
+            Some(ref reason) => {
+                info!(
+                    "Running {} for {}: {}",
+                    spec.stage.describe(),
+                    spec.target,
+                    reason
+                );
+            }
+            None => {
+                info!(
+                    "Skipping {} for {}; it's up-to-date",
+                    spec.stage.describe(),
+                    spec.target
+                );
+            }
+        }
+        let run = reason.is_some();
+        self.entries.push((spec.target.clone(), spec.stage, reason));
+        run
+    }
+
+    /// Prints the report and writes it to data/import_report.txt.
+    pub fn save(&self) {
+        let mut lines = Vec::new();
+        let rebuilt = self.entries.iter().filter(|(_, _, r)| r.is_some()).count();
+        lines.push(format!(
+            "Rebuilt {} stages, skipped {} up-to-date stages",
+            rebuilt,
+            self.entries.len() - rebuilt
+        ));
+        for (target, stage, reason) in &self.entries {
+            lines.push(match reason {
+                Some(reason) => {
+                    format!("- rebuilt {} for {}: {}", stage.describe(), target, reason)
+                }
+                None => format!("- skipped {} for {}", stage.describe(), target),
+            });
+        }
+        let report = lines.join("\n");
+        println!("{}", report);
+        let path = abstio::path("import_report.txt");
+        if let Err(err) = std::fs::write(&path, report) {
+            error!("Couldn't write {}: {}", path, err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory and an empty cache stored inside it.
+    fn setup(name: &str) -> (String, Cache) {
+        let dir = std::env::temp_dir()
+            .join(format!("import_cache_{}_{}", name, std::process::id()))
+            .display()
+            .to_string();
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let cache = Cache {
+            path: format!("{}/cache.json", dir),
+            manifest: Manifest::default(),
+        };
+        (dir, cache)
+    }
+
+    fn spec(dir: &str, inputs: Vec<&str>) -> Spec {
+        Spec {
+            stage: Stage::Map,
+            target: "zz/test/map".to_string(),
+            inputs: inputs
+                .into_iter()
+                .map(|x| format!("{}/{}", dir, x))
+                .collect(),
+            outputs: vec![format!("{}/output.bin", dir)],
+            settings: "build_ch=true".to_string(),
+        }
+    }
+
+    fn write(dir: &str, file: &str, contents: &str) {
+        std::fs::write(format!("{}/{}", dir, file), contents).unwrap();
+    }
+
+    #[test]
+    fn settings_changed() {
+        let (dir, mut cache) = setup("settings");
+        write(&dir, "input.osm", "abc");
+        write(&dir, "output.bin", "");
+        let mut spec = spec(&dir, vec!["input.osm"]);
+        cache.record(&spec);
+        assert!(cache.check(&spec).is_none());
+
+        spec.settings = "build_ch=false".to_string();
+        assert!(matches!(cache.check(&spec), Some(Reason::SettingsChanged)));
+    }
+
+    #[test]
+    fn input_changed() {
+        let (dir, mut cache) = setup("input");
+        write(&dir, "input.osm", "abc");
+        write(&dir, "output.bin", "");
+        let spec = spec(&dir, vec!["input.osm", "maybe.poly"]);
+        cache.record(&spec);
+        assert!(cache.check(&spec).is_none());
+
+        write(&dir, "input.osm", "abcd");
+        let path = format!("{}/input.osm", dir);
+        assert!(matches!(cache.check(&spec), Some(Reason::InputChanged(x)) if x == path));
+
+        // A declared input appearing is a change too
+        cache.record(&spec);
+        write(&dir, "maybe.poly", "");
+        let path = format!("{}/maybe.poly", dir);
+        assert!(matches!(cache.check(&spec), Some(Reason::InputChanged(x)) if x == path));
+    }
+
+    #[test]
+    fn input_no_longer_declared() {
+        let (dir, mut cache) = setup("undeclared");
+        write(&dir, "input.osm", "abc");
+        write(&dir, "elevation.tif", "xyz");
+        write(&dir, "output.bin", "");
+        cache.record(&spec(&dir, vec!["input.osm", "elevation.tif"]));
+
+        let spec = spec(&dir, vec!["input.osm"]);
+        let path = format!("{}/elevation.tif", dir);
+        assert!(matches!(cache.check(&spec), Some(Reason::InputChanged(x)) if x == path));
+    }
+
+    #[test]
+    fn missing_output() {
+        let (dir, mut cache) = setup("output");
+        write(&dir, "input.osm", "abc");
+        let spec = spec(&dir, vec!["input.osm"]);
+        cache.record(&spec);
+
+        let path = format!("{}/output.bin", dir);
+        assert!(matches!(cache.check(&spec), Some(Reason::MissingOutput(x)) if x == path));
+    }
+
+    #[test]
+    fn trust_new_dependencies() {
+        let (dir, mut cache) = setup("dependency");
+        write(&dir, "input.osm", "abc");
+        write(&dir, "output.bin", "");
+        let dependency = spec(&dir, vec!["input.osm"]);
+        let mut report = Report::new();
+
+        // The first time a stage is only needed as a dependency, its existing output is trusted
+        // and the current inputs are recorded
+        assert!(!report.decide(&mut cache, &dependency, false, false));
+        assert!(cache.check(&dependency).is_none());
+
+        // But not when it's explicitly requested
+        let (dir, mut cache) = setup("requested");
+        write(&dir, "input.osm", "abc");
+        write(&dir, "output.bin", "");
+        let requested = spec(&dir, vec!["input.osm"]);
+        assert!(report.decide(&mut cache, &requested, true, false));
+        assert!(matches!(cache.check(&requested), Some(Reason::NeverBuilt)));
+
+        // Forcing only applies to requested stages
+        cache.record(&requested);
+        assert!(report.decide(&mut cache, &requested, true, true));
+        assert!(!report.decide(&mut cache, &requested, false, true));
+    }
+}
@@ -6,7 +6,7 @@ use serde::Deserialize;
 
 use abstio::{CityName, MapName};
 use abstutil::{MultiMap, Timer};
-use geom::{Distance, Duration, Polygon, Ring, Time};
+use geom::{Distance, Duration, Polygon, Projection, Ring, Time};
 use kml::ExtraShapes;
 use map_model::{BuildingID, BuildingType, BusRouteID, Map};
 use sim::Scenario;
@@ -133,6 +133,7 @@ pub async fn osm_to_raw(name: &str, timer: &mut Timer<'_>, config: &ImporterConf
                 bikes_can_use_bus_lanes: true,
                 inferred_sidewalks: true,
                 street_parking_spot_length: Distance::meters(8.0),
+                projection: Projection::default(),
             },
 
             onstreet_parking: convert_osm::OnstreetParking::Blockface(
@@ -159,6 +160,8 @@ pub async fn osm_to_raw(name: &str, timer: &mut Timer<'_>, config: &ImporterConf
             include_railroads: false,
             extra_buildings: None,
             skip_local_roads: false,
+            mid_block_crossings: false,
+            informal_crossings: None,
         },
         timer,
     );
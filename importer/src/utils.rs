@@ -114,7 +114,7 @@ pub fn osmconvert(
 /// Converts a RawMap to a Map.
 pub fn raw_to_map(name: &MapName, opts: RawToMapOptions, timer: &mut Timer) -> map_model::Map {
     timer.start(format!("Raw->Map for {}", name.describe()));
-    let raw: map_model::raw::RawMap = abstio::read_binary(abstio::path_raw_map(name), timer);
+    let raw = map_model::raw::RawMap::load(abstio::path_raw_map(name), timer);
     let map = map_model::Map::create_from_raw(raw, opts, timer);
     timer.start("save map");
     map.save();
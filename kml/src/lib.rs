@@ -11,7 +11,7 @@ use anyhow::Result;
 use serde::{Deserialize, Serialize};
 
 use abstutil::{prettyprint_usize, Timer};
-use geom::{GPSBounds, LonLat};
+use geom::{CrsTransform, GPSBounds, LonLat};
 
 /// Some dataset imported from KML, CSV, or something else. If the dataset is large, converting to
 /// this format and serializing is faster than parsing the original again.
@@ -192,4 +192,43 @@ impl ExtraShapes {
         timer.stop(format!("read {}", path));
         Ok(ExtraShapes { shapes })
     }
+
+    /// Exports the shapes as GeoJSON in some coordinate system, with the attributes as
+    /// properties. The transform must come from the same `GPSBounds` used to load the shapes.
+    pub fn to_geojson(&self, crs: &CrsTransform) -> geojson::GeoJson {
+        let mut features = Vec::new();
+        for shape in &self.shapes {
+            let pts: Vec<Vec<f64>> = shape
+                .points
+                .iter()
+                .map(|pt| {
+                    let (x, y) = crs.gps_pt(*pt);
+                    vec![x, y]
+                })
+                .collect();
+            let geometry = if pts.len() == 1 {
+                geojson::Value::Point(pts[0].clone())
+            } else if pts.len() > 3 && shape.points[0] == *shape.points.last().unwrap() {
+                geojson::Value::Polygon(vec![pts])
+            } else {
+                geojson::Value::LineString(pts)
+            };
+            let mut properties = geojson::JsonObject::new();
+            for (k, v) in &shape.attributes {
+                properties.insert(k.clone(), v.clone().into());
+            }
+            features.push(geojson::Feature {
+                bbox: None,
+                geometry: Some(geojson::Geometry::new(geometry)),
+                id: None,
+                properties: Some(properties),
+                foreign_members: None,
+            });
+        }
+        geojson::GeoJson::from(geojson::FeatureCollection {
+            bbox: None,
+            features,
+            foreign_members: crs.geojson_foreign_members(),
+        })
+    }
 }
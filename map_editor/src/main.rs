@@ -218,7 +218,7 @@ impl State<App> for MainState {
                         txt.add_appended(vec![
                             Line("- Press "),
                             Key::T.txt(ctx),
-                            Line(" to toggle stop sign / traffic signal"),
+                            Line(" to toggle stop sign / traffic signal / roundabout"),
                         ]);
                         txt.add_appended(vec![
                             Line("- Press "),
@@ -3,7 +3,10 @@ use std::io::Write;
 
 use abstio::{CityName, MapName};
 use abstutil::{Tags, Timer};
-use geom::{Bounds, Circle, Distance, FindClosest, GPSBounds, HashablePt2D, LonLat, Polygon, Pt2D};
+use geom::{
+    Bounds, Circle, Distance, FindClosest, GPSBounds, HashablePt2D, LonLat, Polygon, Projection,
+    Pt2D,
+};
 use map_model::raw::{OriginalRoad, RawBuilding, RawIntersection, RawMap, RawRoad};
 use map_model::{osm, IntersectionType};
 use widgetry::{Color, Drawable, EventCtx, GeomBatch, Line, Text};
@@ -61,6 +64,7 @@ impl Model {
                         bikes_can_use_bus_lanes: true,
                         inferred_sidewalks: true,
                         street_parking_spot_length: Distance::meters(8.0),
+                        projection: Projection::default(),
                     },
                     onstreet_parking: convert_osm::OnstreetParking::JustOSM,
                     public_offstreet_parking: convert_osm::PublicOffstreetParking::None,
@@ -70,11 +74,13 @@ impl Model {
                     include_railroads: true,
                     extra_buildings: None,
                     skip_local_roads: false,
+                    mid_block_crossings: false,
+                    informal_crossings: None,
                 },
                 &mut timer,
             )
         } else {
-            abstio::read_binary(path, &mut timer)
+            RawMap::load(path, &mut timer)
         };
 
         model.recreate_world(ctx, &mut timer);
@@ -180,6 +186,7 @@ impl Model {
             IntersectionType::StopSign => Color::RED,
             IntersectionType::Border => Color::BLUE,
             IntersectionType::Construction => Color::ORANGE,
+            IntersectionType::Roundabout => Color::PURPLE,
         };
 
         let poly = if self.intersection_geom && !self.map.roads_per_intersection(id).is_empty() {
@@ -228,8 +235,17 @@ impl Model {
     pub fn toggle_i(&mut self, ctx: &EventCtx, id: osm::NodeID) {
         self.world.delete(ID::Intersection(id));
 
+        let on_ring = self
+            .map
+            .roads_per_intersection(id)
+            .into_iter()
+            .any(|r| self.map.roads[&r].osm_tags.is("junction", "roundabout"));
         let i = self.map.intersections.get_mut(&id).unwrap();
-        if i.intersection_type == IntersectionType::TrafficSignal {
+        if i.intersection_type == IntersectionType::TrafficSignal && on_ring {
+            i.intersection_type = IntersectionType::Roundabout;
+        } else if i.intersection_type == IntersectionType::TrafficSignal {
+            i.intersection_type = IntersectionType::StopSign;
+        } else if i.intersection_type == IntersectionType::Roundabout {
             i.intersection_type = IntersectionType::StopSign;
         } else if i.intersection_type == IntersectionType::StopSign {
             i.intersection_type = IntersectionType::TrafficSignal;
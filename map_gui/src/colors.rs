@@ -150,6 +150,13 @@ pub struct ColorScheme {
 
 impl ColorScheme {
     pub fn new(ctx: &mut EventCtx, scheme: ColorSchemeChoice) -> ColorScheme {
+        let cs = ColorScheme::headless(scheme);
+        ctx.set_style(cs.gui_style.clone());
+        cs
+    }
+
+    /// Creates a color scheme without changing the GUI's style, for rendering outside of a window.
+    pub fn headless(scheme: ColorSchemeChoice) -> ColorScheme {
         let mut cs = match scheme {
             ColorSchemeChoice::DayMode => ColorScheme::day_mode(),
             ColorSchemeChoice::NightMode => ColorScheme::night_mode(),
@@ -158,10 +165,18 @@ impl ColorScheme {
             ColorSchemeChoice::ClassicDayMode => ColorScheme::classic(),
         };
         cs.scheme = scheme;
-        ctx.set_style(cs.gui_style.clone());
         cs
     }
 
+    /// Textured backgrounds aren't worth tiling when rendering offscreen; use a flat color
+    /// instead.
+    pub fn flat_map_background(&self) -> Color {
+        match self.map_background {
+            Fill::Color(c) => c,
+            _ => Color::grey(0.87),
+        }
+    }
+
     // TODO This is still based on classic
     fn pregame() -> ColorScheme {
         let mut cs = Self::light_background(Style::pregame());
@@ -109,7 +109,7 @@ impl DrawIntersection {
                         .centered_on(i.polygon.center()),
                 );
             }
-            IntersectionType::TrafficSignal => {}
+            IntersectionType::TrafficSignal | IntersectionType::Roundabout => {}
         }
 
         let zorder = i.get_zorder(map);
@@ -211,7 +211,14 @@ impl DrawMap {
         timer: &mut Timer,
     ) -> Drawable {
         timer.start("generate unzoomed roads and intersections");
+        let draw_all_unzoomed_roads_and_intersections =
+            DrawMap::unzoomed_roads_and_intersections(map, cs).upload(ctx);
+        timer.stop("generate unzoomed roads and intersections");
+        draw_all_unzoomed_roads_and_intersections
+    }
 
+    /// The simplified roads and intersections shown when zoomed out.
+    pub fn unzoomed_roads_and_intersections(map: &Map, cs: &ColorScheme) -> GeomBatch {
         // TODO Different in night mode
         let outline_color = Color::BLACK;
         let outline_thickness = Distance::meters(1.0);
@@ -290,9 +297,37 @@ impl DrawMap {
         for (_, color, poly) in unzoomed_pieces {
             unzoomed_batch.push(color, poly);
         }
-        let draw_all_unzoomed_roads_and_intersections = unzoomed_batch.upload(ctx);
-        timer.stop("generate unzoomed roads and intersections");
-        draw_all_unzoomed_roads_and_intersections
+        unzoomed_batch
+    }
+
+    /// Everything that doesn't change over time, without uploading anything to the GPU. This is
+    /// much simpler than what the GUI draws, but enough for rendering offscreen.
+    pub fn static_batch(map: &Map, cs: &ColorScheme, zoomed: bool) -> GeomBatch {
+        let mut batch = GeomBatch::new();
+        for a in map.all_areas() {
+            batch.push(DrawArea::fill(a.area_type, cs), a.polygon.clone());
+        }
+        for b in map.all_buildings() {
+            batch.push(cs.residential_building, b.polygon.clone());
+        }
+        if zoomed {
+            for l in map.all_lanes().values() {
+                let rank = map.get_r(l.parent).get_rank();
+                batch.push(
+                    cs.zoomed_road_surface(l.lane_type, rank),
+                    l.lane_center_pts.make_polygons(l.width),
+                );
+            }
+            for i in map.all_intersections() {
+                batch.push(
+                    cs.zoomed_intersection_surface(i.get_rank(map)),
+                    i.polygon.clone(),
+                );
+            }
+        } else {
+            batch.append(DrawMap::unzoomed_roads_and_intersections(map, cs));
+        }
+        batch
     }
 
     // The alt to these is implementing std::ops::Index, but that's way more verbose!
@@ -14,11 +14,14 @@ pub use self::navigate::Navigator;
 pub use self::turn_explorer::TurnExplorer;
 pub use self::ui::{ChooseSomething, FilePicker, PopupMsg, PromptInput};
 pub use self::url::URLManager;
+pub use self::vector_export::{export_map, ExportLayer, ExportLegend, ExportOptions};
 use crate::AppLike;
 
 #[cfg(not(target_arch = "wasm32"))]
 pub use self::command::RunCommand;
 #[cfg(not(target_arch = "wasm32"))]
+pub use self::screenshot::screenshot_tiles;
+#[cfg(not(target_arch = "wasm32"))]
 pub use self::updater::prompt_to_download_missing_data;
 
 mod camera;
@@ -32,11 +35,14 @@ mod icons;
 mod importer;
 mod minimap;
 mod navigate;
+#[cfg(not(target_arch = "wasm32"))]
+mod screenshot;
 mod turn_explorer;
 mod ui;
 #[cfg(not(target_arch = "wasm32"))]
 mod updater;
 mod url;
+mod vector_export;
 
 // TODO This is A/B Street specific
 pub fn loading_tips() -> Text {
@@ -0,0 +1,31 @@
+use anyhow::Result;
+
+use widgetry::ScreenDims;
+
+use crate::render::DrawMap;
+use crate::AppLike;
+
+/// Renders the whole map as a grid of PNG tiles on the CPU. Unlike widgetry's screen capture,
+/// this doesn't depend on the window size or video memory, but it only draws the static map, not
+/// agents or whatever the current state shows.
+pub fn screenshot_tiles(
+    app: &dyn AppLike,
+    dir_path: &str,
+    zoom: f64,
+    dims: ScreenDims,
+    leaflet_naming: bool,
+) -> Result<()> {
+    let map = app.map();
+    let cs = app.cs();
+    let zoomed = zoom >= app.opts().min_zoom_for_detail;
+    let bounds = map.get_bounds();
+    widgetry::render_tiles(
+        &DrawMap::static_batch(map, cs, zoomed),
+        cs.flat_map_background(),
+        (bounds.width(), bounds.height()),
+        zoom,
+        dims,
+        dir_path,
+        leaflet_naming,
+    )
+}
@@ -0,0 +1,246 @@
+//! Exports part of the map as a printable page of vector graphics, at a fixed scale, with a
+//! legend for any layers drawn on top and a scale bar.
+
+use geom::{Bounds, Distance, Polygon, Pt2D};
+use widgetry::{Color, EventCtx, GeomBatch, Line, Text, VectorDocument};
+
+use crate::render::DrawMap;
+use crate::tools::ColorScale;
+use crate::AppLike;
+
+/// Page coordinates are in points, 1/72 of an inch.
+const POINTS_PER_METER: f64 = 72.0 / 0.0254;
+const MARGIN: f64 = 36.0;
+const LEGEND_WIDTH: f64 = 180.0;
+/// An A3 page in landscape, minus margins
+const FIT_WIDTH: f64 = 1190.0 - 2.0 * MARGIN;
+const FIT_HEIGHT: f64 = 842.0 - 2.0 * MARGIN - 120.0;
+
+pub struct ExportOptions {
+    /// The part of the map to include, in map-space
+    pub bounds: Bounds,
+    /// The denominator of the map's scale, like 5000 for 1:5000
+    pub scale: f64,
+    pub title: Option<String>,
+}
+
+impl ExportOptions {
+    /// Picks a round scale that fits the bounds on an A3 page.
+    pub fn fit_page(bounds: Bounds) -> ExportOptions {
+        let exact = (bounds.width() * POINTS_PER_METER / FIT_WIDTH)
+            .max(bounds.height() * POINTS_PER_METER / FIT_HEIGHT);
+        // Round up to the next multiple of 500, so the map only gets smaller
+        let scale = ((exact / 500.0).ceil() * 500.0).max(500.0);
+        ExportOptions {
+            bounds,
+            scale,
+            title: None,
+        }
+    }
+}
+
+/// Something drawn on top of the map, like a layer showing throughput or delay.
+pub struct ExportLayer {
+    pub name: String,
+    /// In map-space
+    pub batch: GeomBatch,
+    pub legend: ExportLegend,
+}
+
+pub enum ExportLegend {
+    None,
+    /// A continuous scale, with labels spaced evenly beneath it
+    Gradient(Vec<Color>, Vec<String>),
+    Categories(Vec<(Color, String)>),
+}
+
+/// Renders the map at full detail, with layers on top. Everything is in vector form, including
+/// text.
+pub fn export_map(
+    ctx: &EventCtx,
+    app: &dyn AppLike,
+    opts: &ExportOptions,
+    layers: Vec<ExportLayer>,
+) -> VectorDocument {
+    let zoom = POINTS_PER_METER / opts.scale;
+    let map_width = opts.bounds.width() * zoom;
+    let map_height = opts.bounds.height() * zoom;
+
+    let mut header = GeomBatch::new();
+    let mut map_top = MARGIN;
+    if let Some(ref title) = opts.title {
+        let txt = text(ctx, title, 18).translate(MARGIN, MARGIN);
+        map_top += txt.get_dims().height + 12.0;
+        header.append(txt);
+    }
+
+    // The footer has the scale bar and attribution on the left, then a column for each legend
+    let footer_top = map_top + map_height + 18.0;
+    let mut footer = scale_bar(ctx, app, zoom, opts.scale).translate(MARGIN, footer_top);
+    let mut footer_height = footer.get_bounds().height();
+    let attribution = text(ctx, "Map data © OpenStreetMap contributors", 9)
+        .translate(MARGIN, footer_top + footer_height + 12.0);
+    footer_height = footer_height + 12.0 + attribution.get_dims().height;
+    footer.append(attribution);
+    let mut legend_x = MARGIN + LEGEND_WIDTH + 18.0;
+    for layer in &layers {
+        if let Some(legend) = legend(ctx, &layer.name, &layer.legend) {
+            footer_height = footer_height.max(legend.get_dims().height);
+            footer.append(legend.translate(legend_x, footer_top));
+            legend_x += LEGEND_WIDTH + 18.0;
+        }
+    }
+
+    let page_width = (map_width + 2.0 * MARGIN).max(legend_x - 18.0 + MARGIN);
+    let page_height = footer_top + footer_height + MARGIN;
+    let mut doc = VectorDocument::new(page_width, page_height);
+    let clip = Bounds::from(&[
+        Pt2D::new(MARGIN, map_top),
+        Pt2D::new(MARGIN + map_width, map_top + map_height),
+    ]);
+    // Only keep polygons that might be visible, to keep files small
+    let to_page = |batch: GeomBatch| {
+        batch
+            .crop(&opts.bounds)
+            .translate(-opts.bounds.min_x, -opts.bounds.min_y)
+            .scale(zoom)
+            .translate(MARGIN, map_top)
+    };
+
+    doc.push_clipped(
+        "map",
+        to_page(DrawMap::zoomed_batch(ctx, app)),
+        clip.clone(),
+    );
+    for layer in layers {
+        doc.push_clipped(layer.name, to_page(layer.batch), clip.clone());
+    }
+
+    let mut frame = GeomBatch::new();
+    if let Ok(p) = Polygon::rectangle(map_width, map_height).to_outline(Distance::meters(1.0)) {
+        frame.push(Color::BLACK, p.translate(MARGIN, map_top));
+    }
+    doc.push("frame", frame);
+    doc.push("header", header);
+    doc.push("footer", footer);
+    doc
+}
+
+fn text(ctx: &EventCtx, x: &str, size: usize) -> GeomBatch {
+    Text::from(Line(x).fg(Color::BLACK).size(size)).render_autocropped(ctx)
+}
+
+fn scale_bar(ctx: &EventCtx, app: &dyn AppLike, zoom: f64, scale: f64) -> GeomBatch {
+    let length = nice_distance(
+        Distance::meters(LEGEND_WIDTH * 0.8 / zoom),
+        app.opts().units.metric,
+    );
+    let width = length.inner_meters() * zoom;
+    let height = 6.0;
+
+    let mut batch = GeomBatch::new();
+    let label = text(ctx, &format!("1:{}", scale.round()), 10);
+    let label_height = label.get_dims().height;
+    batch.append(label);
+    let top = label_height + 6.0;
+    for i in 0..4 {
+        let color = if i % 2 == 0 {
+            Color::BLACK
+        } else {
+            Color::WHITE
+        };
+        batch.push(
+            color,
+            Polygon::rectangle(width / 4.0, height).translate((i as f64) * width / 4.0, top),
+        );
+    }
+    if let Ok(p) = Polygon::rectangle(width, height).to_outline(Distance::meters(0.5)) {
+        batch.push(Color::BLACK, p.translate(0.0, top));
+    }
+    batch.append(text(ctx, "0", 9).translate(0.0, top + height + 3.0));
+    let end = text(ctx, &length.to_string(&app.opts().units), 9);
+    let end_width = end.get_dims().width;
+    batch.append(end.translate(width - end_width / 2.0, top + height + 3.0));
+    batch
+}
+
+/// The longest round distance no longer than `max`. Imperial distances are feet below a tenth of
+/// a mile, and miles beyond, to match how `Distance` is displayed.
+fn nice_distance(max: Distance, metric: bool) -> Distance {
+    let mut candidates = Vec::new();
+    for power in 0..7 {
+        for x in &[1.0, 2.0, 5.0] {
+            let x = x * 10.0_f64.powi(power);
+            if metric {
+                candidates.push(Distance::meters(x));
+            } else if x < 528.0 {
+                candidates.push(Distance::feet(x));
+            } else {
+                candidates.push(Distance::miles(x / 10_000.0));
+            }
+        }
+    }
+    candidates
+        .into_iter()
+        .take_while(|x| *x <= max)
+        .last()
+        .unwrap_or_else(|| {
+            if metric {
+                Distance::meters(1.0)
+            } else {
+                Distance::feet(1.0)
+            }
+        })
+}
+
+fn legend(ctx: &EventCtx, name: &str, legend: &ExportLegend) -> Option<GeomBatch> {
+    let mut batch = GeomBatch::new();
+    let title = text(ctx, name, 11);
+    let mut y = title.get_dims().height + 6.0;
+    batch.append(title);
+
+    match legend {
+        ExportLegend::None => {
+            return None;
+        }
+        ExportLegend::Gradient(colors, labels) => {
+            let scale = ColorScale(colors.clone());
+            let height = 10.0;
+            // Vector viewers handle gradients inconsistently, so use lots of thin slices
+            let slices = 50;
+            let width_each = LEGEND_WIDTH / (slices as f64);
+            for i in 0..slices {
+                batch.push(
+                    scale.eval((i as f64 + 0.5) / (slices as f64)),
+                    Polygon::rectangle(width_each, height).translate((i as f64) * width_each, y),
+                );
+            }
+            y += height + 3.0;
+            for (idx, label) in labels.iter().enumerate() {
+                let txt = text(ctx, label, 9);
+                let dims = txt.get_dims();
+                let x = if labels.len() == 1 {
+                    0.0
+                } else {
+                    LEGEND_WIDTH * (idx as f64) / ((labels.len() - 1) as f64)
+                };
+                // Keep the first and last labels inside the legend
+                let x = (x - dims.width / 2.0)
+                    .max(0.0)
+                    .min(LEGEND_WIDTH - dims.width);
+                batch.append(txt.translate(x, y));
+            }
+        }
+        ExportLegend::Categories(pairs) => {
+            for (color, label) in pairs {
+                let swatch = 10.0;
+                batch.push(*color, Polygon::rectangle(swatch, swatch).translate(0.0, y));
+                let txt = text(ctx, label, 9);
+                let height = txt.get_dims().height.max(swatch);
+                batch.append(txt.translate(swatch + 6.0, y));
+                y += height + 3.0;
+            }
+        }
+    }
+    Some(batch)
+}
@@ -52,6 +52,7 @@ pub enum EditIntersection {
     // Don't keep ControlTrafficSignal here, because it contains movements that should be
     // generated after all lane edits are applied.
     TrafficSignal(traffic_signal_data::TrafficSignal),
+    Roundabout,
     Closed,
 }
 
@@ -313,6 +314,7 @@ impl EditCmd {
             EditCmd::ChangeIntersection { i, new, .. } => match new {
                 EditIntersection::StopSign(_) => format!("stop sign #{}", i.0),
                 EditIntersection::TrafficSignal(_) => format!("traffic signal #{}", i.0),
+                EditIntersection::Roundabout => format!("roundabout #{}", i.0),
                 EditIntersection::Closed => format!("close {}", i),
             },
             EditCmd::ChangeRouteSchedule { id, .. } => {
@@ -374,7 +376,8 @@ impl EditCmd {
                     }
                     EditIntersection::TrafficSignal(ref raw_ts) => {
                         map.intersections[i.0].intersection_type = IntersectionType::TrafficSignal;
-                        if old == &EditIntersection::Closed {
+                        if old == &EditIntersection::Closed || old == &EditIntersection::Roundabout
+                        {
                             recalculate_turns(*i, map, effects);
                         }
                         map.traffic_signals.insert(
@@ -382,12 +385,22 @@ impl EditCmd {
                             ControlTrafficSignal::import(raw_ts.clone(), *i, map).unwrap(),
                         );
                     }
+                    EditIntersection::Roundabout => {
+                        map.intersections[i.0].intersection_type = IntersectionType::Roundabout;
+                    }
                     EditIntersection::Closed => {
                         map.intersections[i.0].intersection_type = IntersectionType::Construction;
                     }
                 }
 
-                if old == &EditIntersection::Closed || new == &EditIntersection::Closed {
+                // Roundabouts restrict which lanes can enter and exit, so the turns change when
+                // converting to or from one.
+                if old == &EditIntersection::Closed
+                    || new == &EditIntersection::Closed
+                    || new == &EditIntersection::Roundabout
+                    || (old == &EditIntersection::Roundabout
+                        && matches!(new, EditIntersection::StopSign(_)))
+                {
                     recalculate_turns(*i, map, effects);
                 }
             }
@@ -462,6 +475,7 @@ fn recalculate_turns(id: IntersectionID, map: &mut Map, effects: &mut EditEffect
             map.traffic_signals
                 .insert(id, ControlTrafficSignal::new(map, id));
         }
+        IntersectionType::Roundabout => {}
         IntersectionType::Border | IntersectionType::Construction => unreachable!(),
     }
 }
@@ -769,6 +783,7 @@ impl Map {
             IntersectionType::TrafficSignal => {
                 EditIntersection::TrafficSignal(self.get_traffic_signal(i).export(self))
             }
+            IntersectionType::Roundabout => EditIntersection::Roundabout,
             IntersectionType::Construction => EditIntersection::Closed,
             IntersectionType::Border => unreachable!(),
         }
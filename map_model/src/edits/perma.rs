@@ -41,6 +41,7 @@ pub enum PermanentEditIntersection {
         must_stop: BTreeMap<OriginalRoad, bool>,
     },
     TrafficSignal(traffic_signal_data::TrafficSignal),
+    Roundabout,
     Closed,
 }
 
@@ -207,6 +208,7 @@ impl EditIntersection {
             EditIntersection::TrafficSignal(ref raw_ts) => {
                 PermanentEditIntersection::TrafficSignal(raw_ts.clone())
             }
+            EditIntersection::Roundabout => PermanentEditIntersection::Roundabout,
             EditIntersection::Closed => PermanentEditIntersection::Closed,
         }
     }
@@ -241,6 +243,12 @@ impl PermanentEditIntersection {
                 Ok(EditIntersection::StopSign(ss))
             }
             PermanentEditIntersection::TrafficSignal(ts) => Ok(EditIntersection::TrafficSignal(ts)),
+            PermanentEditIntersection::Roundabout => {
+                if !map.get_i(i).is_on_roundabout_ring(map) {
+                    bail!("{} isn't along the ring of a roundabout", i);
+                }
+                Ok(EditIntersection::Roundabout)
+            }
             PermanentEditIntersection::Closed => Ok(EditIntersection::Closed),
         }
     }
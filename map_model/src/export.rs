@@ -0,0 +1,73 @@
+use geojson::{Feature, FeatureCollection, GeoJson};
+
+use geom::CrsTransform;
+
+use crate::{IntersectionID, Map};
+
+impl Map {
+    /// Exports the outline of every intersection and road as GeoJSON, in some coordinate system.
+    /// Intersections are labeled with their OSM node ID and roads with their OSM way ID.
+    pub fn export_geometry(&self, crs: &CrsTransform) -> GeoJson {
+        let mut features = Vec::new();
+        for i in self.all_intersections() {
+            features.push(feature(
+                "intersection",
+                i.orig_id.to_string(),
+                i.polygon.clone().into_ring().to_geojson_crs(crs),
+            ));
+        }
+        for r in self.all_roads() {
+            features.push(feature(
+                "road",
+                r.orig_id.osm_way_id.to_string(),
+                r.center_pts
+                    .to_thick_ring(r.get_width(self))
+                    .to_geojson_crs(crs),
+            ));
+        }
+        collection(features, crs)
+    }
+
+    /// Exports one intersection and the roads connected to it as GeoJSON, in some coordinate
+    /// system.
+    pub fn export_intersection_geometry(&self, i: IntersectionID, crs: &CrsTransform) -> GeoJson {
+        let i = self.get_i(i);
+        let mut features = vec![feature(
+            "intersection",
+            i.orig_id.to_string(),
+            i.polygon.clone().into_ring().to_geojson_crs(crs),
+        )];
+        for r in &i.roads {
+            let r = self.get_r(*r);
+            features.push(feature(
+                "road",
+                r.orig_id.osm_way_id.to_string(),
+                r.center_pts
+                    .to_thick_ring(r.get_width(self))
+                    .to_geojson_crs(crs),
+            ));
+        }
+        collection(features, crs)
+    }
+}
+
+fn feature(kind: &str, id: String, geometry: geojson::Geometry) -> Feature {
+    let mut props = geojson::JsonObject::new();
+    props.insert("type".to_string(), kind.into());
+    props.insert("id".to_string(), id.into());
+    Feature {
+        bbox: None,
+        geometry: Some(geometry),
+        id: None,
+        properties: Some(props),
+        foreign_members: None,
+    }
+}
+
+fn collection(features: Vec<Feature>, crs: &CrsTransform) -> GeoJson {
+    GeoJson::from(FeatureCollection {
+        bbox: None,
+        features,
+        foreign_members: crs.geojson_foreign_members(),
+    })
+}
@@ -0,0 +1,294 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use abstutil::Timer;
+use geom::{LonLat, Pt2D};
+
+use super::timing::Snapper;
+use super::{link_id, link_lanes, movement_code};
+use crate::{
+    osm, Direction, IntersectionID, IntersectionType, LaneID, LaneType, Map, Movement, MovementID,
+    TurnType,
+};
+
+/// Writes node.csv, link.csv, lane.csv, movement.csv and timing.csv to a directory. The timing
+/// table uses the same format that `SignalTiming` reads, so signals survive a round-trip.
+pub fn export(map: &Map, dir: &str, timer: &mut Timer) -> Result<()> {
+    std::fs::create_dir_all(dir)?;
+
+    timer.start("export nodes");
+    let mut nodes = csv::Writer::from_path(format!("{}/node.csv", dir))?;
+    for i in map.all_intersections() {
+        let pt = i.polygon.center().to_gps(map.get_gps_bounds());
+        nodes.serialize(Node {
+            node_id: i.id.0,
+            osm_node_id: i.orig_id.0,
+            x_coord: pt.x(),
+            y_coord: pt.y(),
+            ctrl_type: ctrl_type(map, i.id),
+        })?;
+    }
+    nodes.flush()?;
+    timer.stop("export nodes");
+
+    timer.start("export links and lanes");
+    let mut links = csv::Writer::from_path(format!("{}/link.csv", dir))?;
+    let mut lanes = csv::Writer::from_path(format!("{}/lane.csv", dir))?;
+    for r in map.all_roads() {
+        for dr in r.id.both_directions() {
+            let lane_ids = link_lanes(map, dr);
+            if lane_ids.is_empty() {
+                continue;
+            }
+            let mut pts = r.center_pts.points().clone();
+            if dr.dir == Direction::Back {
+                pts.reverse();
+            }
+            let mut uses = Vec::new();
+            for l in &lane_ids {
+                let allowed = allowed_uses(map.get_l(*l).lane_type);
+                if allowed != "none" && !uses.contains(&allowed) {
+                    uses.push(allowed);
+                }
+            }
+
+            links.serialize(Link {
+                link_id: link_id(dr),
+                name: r.get_name(None),
+                from_node_id: dr.src_i(map).0,
+                to_node_id: dr.dst_i(map).0,
+                directed: true,
+                length: r.center_pts.length().inner_meters(),
+                lanes: lane_ids
+                    .iter()
+                    .filter(|l| map.get_l(**l).is_driving() || map.get_l(**l).is_bus())
+                    .count(),
+                free_speed: r.speed_limit.inner_meters_per_second() * 3.6,
+                facility_type: r
+                    .osm_tags
+                    .get(osm::HIGHWAY)
+                    .cloned()
+                    .unwrap_or_else(String::new),
+                allowed_uses: uses.join(","),
+                osm_way_id: r.orig_id.osm_way_id.0,
+                geometry: wkt(map, &pts),
+            })?;
+
+            for (idx, l) in lane_ids.into_iter().enumerate() {
+                let lane = map.get_l(l);
+                lanes.serialize(Lane {
+                    lane_id: l.0,
+                    link_id: link_id(dr),
+                    lane_num: idx + 1,
+                    allowed_uses: allowed_uses(lane.lane_type),
+                    width: lane.width.inner_meters(),
+                })?;
+            }
+        }
+    }
+    links.flush()?;
+    lanes.flush()?;
+    timer.stop("export links and lanes");
+
+    let mut movements = csv::Writer::from_path(format!("{}/movement.csv", dir))?;
+    let mut timing = csv::Writer::from_path(format!("{}/timing.csv", dir))?;
+    let mut mvmt_ids: BTreeMap<MovementID, usize> = BTreeMap::new();
+    timer.start_iter("export movements and timing", map.all_intersections().len());
+    for i in map.all_intersections() {
+        timer.next();
+        if i.is_border() {
+            continue;
+        }
+        let all_movements = match Movement::for_i(i.id, map) {
+            Ok(x) => x,
+            Err(err) => {
+                warn!("No movements for {}: {}", i.id, err);
+                continue;
+            }
+        };
+        for mvmnt in all_movements.values() {
+            if mvmnt.id.crosswalk {
+                continue;
+            }
+            let code = movement_code(map, mvmnt)?;
+            let mvmt_id = mvmt_ids.len() + 1;
+            mvmt_ids.insert(mvmnt.id, mvmt_id);
+
+            let ib_lanes = link_lanes(map, mvmnt.id.from);
+            let ob_lanes = link_lanes(map, mvmnt.id.to);
+            let (start_ib_lane, end_ib_lane) =
+                lane_range(&ib_lanes, mvmnt.members.iter().map(|t| t.src));
+            let (start_ob_lane, end_ob_lane) =
+                lane_range(&ob_lanes, mvmnt.members.iter().map(|t| t.dst));
+
+            movements.serialize(MovementRow {
+                mvmt_id,
+                node_id: i.id.0,
+                osm_node_id: i.orig_id.0,
+                ib_link_id: link_id(mvmnt.id.from),
+                start_ib_lane,
+                end_ib_lane,
+                ob_link_id: link_id(mvmnt.id.to),
+                start_ob_lane,
+                end_ob_lane,
+                r#type: match mvmnt.turn_type {
+                    TurnType::Left => "left",
+                    TurnType::Right => "right",
+                    TurnType::UTurn => "uturn",
+                    _ => "thru",
+                },
+                mvmt_txt_id: code,
+                geometry: wkt(map, mvmnt.geom.points()),
+            })?;
+        }
+
+        if let Some(signal) = map.maybe_get_traffic_signal(i.id) {
+            // Describe every movement by points that the importer will snap to again
+            let snapper = Snapper::new(map, i.id)?;
+            for (idx, stage) in signal.stages.iter().enumerate() {
+                for (movements, protection) in vec![
+                    (&stage.protected_movements, "protected"),
+                    (&stage.yield_movements, "permitted"),
+                ] {
+                    for id in movements {
+                        if id.crosswalk {
+                            continue;
+                        }
+                        timing.serialize(Timing {
+                            osm_node_id: i.orig_id.0,
+                            timing_plan_id: 1,
+                            green_time: stage.stage_type.simple_duration().inner_seconds().round()
+                                as usize,
+                            stage_no: idx + 1,
+                            geometry: wkt(
+                                map,
+                                &[
+                                    snapper.roads_incoming[&id.from],
+                                    snapper.roads_outgoing[&id.to],
+                                ],
+                            ),
+                            protection,
+                            mvmt_txt_id: movement_code(map, &all_movements[id])?,
+                            mvmt_id: mvmt_ids[id],
+                        })?;
+                    }
+                }
+            }
+        }
+    }
+    movements.flush()?;
+    timing.flush()?;
+
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct Node {
+    node_id: usize,
+    osm_node_id: i64,
+    x_coord: f64,
+    y_coord: f64,
+    ctrl_type: &'static str,
+}
+
+#[derive(Serialize)]
+struct Link {
+    link_id: usize,
+    name: String,
+    from_node_id: usize,
+    to_node_id: usize,
+    directed: bool,
+    length: f64,
+    lanes: usize,
+    free_speed: f64,
+    facility_type: String,
+    allowed_uses: String,
+    osm_way_id: i64,
+    geometry: String,
+}
+
+#[derive(Serialize)]
+struct Lane {
+    lane_id: usize,
+    link_id: usize,
+    lane_num: usize,
+    allowed_uses: &'static str,
+    width: f64,
+}
+
+#[derive(Serialize)]
+struct MovementRow {
+    mvmt_id: usize,
+    node_id: usize,
+    osm_node_id: i64,
+    ib_link_id: usize,
+    start_ib_lane: usize,
+    end_ib_lane: usize,
+    ob_link_id: usize,
+    start_ob_lane: usize,
+    end_ob_lane: usize,
+    r#type: &'static str,
+    mvmt_txt_id: String,
+    geometry: String,
+}
+
+#[derive(Serialize)]
+struct Timing {
+    osm_node_id: i64,
+    timing_plan_id: usize,
+    green_time: usize,
+    stage_no: usize,
+    geometry: String,
+    protection: &'static str,
+    mvmt_txt_id: String,
+    mvmt_id: usize,
+}
+
+fn ctrl_type(map: &Map, i: IntersectionID) -> &'static str {
+    match map.get_i(i).intersection_type {
+        IntersectionType::TrafficSignal => "signal",
+        IntersectionType::StopSign => {
+            let ss = map.get_stop_sign(i);
+            let stops = ss.roads.values().filter(|r| r.must_stop).count();
+            if stops == 0 {
+                "no_control"
+            } else if stops == ss.roads.len() {
+                "4_stop"
+            } else {
+                "2_stop"
+            }
+        }
+        IntersectionType::Roundabout => "roundabout",
+        IntersectionType::Border | IntersectionType::Construction => "no_control",
+    }
+}
+
+fn allowed_uses(lt: LaneType) -> &'static str {
+    match lt {
+        LaneType::Driving | LaneType::SharedLeftTurn => "auto",
+        LaneType::Bus => "bus",
+        LaneType::Biking => "bike",
+        LaneType::Parking => "parking",
+        LaneType::Sidewalk | LaneType::Shoulder => "walk",
+        LaneType::LightRail => "rail",
+        LaneType::Construction | LaneType::Buffer(_) => "none",
+    }
+}
+
+/// The 1-indexed range of lanes used on one side of a movement
+fn lane_range<I: Iterator<Item = LaneID>>(link_lanes: &[LaneID], used: I) -> (usize, usize) {
+    let nums: Vec<usize> = used
+        .filter_map(|l| link_lanes.iter().position(|x| *x == l))
+        .map(|idx| idx + 1)
+        .collect();
+    (
+        nums.iter().min().cloned().unwrap_or(0),
+        nums.iter().max().cloned().unwrap_or(0),
+    )
+}
+
+fn wkt(map: &Map, pts: &[Pt2D]) -> String {
+    LonLat::to_wkt_linestring(&map.get_gps_bounds().convert_back(pts))
+}
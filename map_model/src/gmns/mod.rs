@@ -0,0 +1,80 @@
+//! Exchange networks and traffic signal timing with the [General Modeling Network
+//! Specification](https://github.com/zephyr-data-specs/GMNS). All tables are CSV files. Coordinates
+//! are WGS84, lengths and widths are in meters, and speeds are in km/h.
+//!
+//! IDs used in the exported tables:
+//! - nodes use the `IntersectionID`
+//! - links are directed roads, numbered `2 * RoadID + (0 forwards, 1 backwards)`
+//! - lanes use the `LaneID`, and are numbered per link from the inside (closest to the center of
+//!   the road) out, starting with 1
+
+use anyhow::Result;
+
+use geom::Angle;
+
+use crate::{DirectedRoadID, Direction, DrivingSide, LaneID, Map, Movement, TurnType};
+
+pub use self::export::export;
+pub use self::timing::{ImportResults, SignalTiming};
+
+mod export;
+mod timing;
+
+fn link_id(dr: DirectedRoadID) -> usize {
+    2 * dr.id.0
+        + match dr.dir {
+            Direction::Fwd => 0,
+            Direction::Back => 1,
+        }
+}
+
+/// All lanes of a directed road, from the inside out.
+fn link_lanes(map: &Map, dr: DirectedRoadID) -> Vec<LaneID> {
+    let mut lanes: Vec<LaneID> = map
+        .get_r(dr.id)
+        .lanes_ltr()
+        .into_iter()
+        .filter(|(_, dir, _)| *dir == dr.dir)
+        .map(|(l, _, _)| l)
+        .collect();
+    // lanes_ltr is oriented for the forwards direction
+    if (dr.dir == Direction::Back) == (map.get_config().driving_side == DrivingSide::Right) {
+        lanes.reverse();
+    }
+    lanes
+}
+
+/// Something like "EBL" or "NBT" -- eastbound left, northbound through.
+fn movement_code(map: &Map, mvmnt: &Movement) -> Result<String> {
+    let turn = match mvmnt.turn_type {
+        TurnType::Straight => 'T',
+        TurnType::Left => 'L',
+        TurnType::Right => 'R',
+        TurnType::UTurn => 'U',
+        x => bail!("{:?} isn't a vehicle movement", x),
+    };
+    Ok(format!(
+        "{}{}",
+        cardinal_direction(
+            map.get_l(mvmnt.members[0].src)
+                .lane_center_pts
+                .overall_angle()
+        ),
+        turn
+    ))
+}
+
+fn cardinal_direction(angle: Angle) -> &'static str {
+    // Note Y inversion, as usual
+    let deg = angle.normalized_degrees();
+    if deg >= 335.0 || deg <= 45.0 {
+        return "EB";
+    }
+    if (45.0..=135.0).contains(&deg) {
+        return "SB";
+    }
+    if (135.0..=225.0).contains(&deg) {
+        return "WB";
+    }
+    "NB"
+}
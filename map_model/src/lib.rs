@@ -34,7 +34,7 @@ use serde::{Deserialize, Serialize};
 
 use abstio::MapName;
 use abstutil::{deserialize_btreemap, serialize_btreemap, MultiMap};
-use geom::{Bounds, GPSBounds, Polygon};
+use geom::{Bounds, GPSBounds, Polygon, Projection};
 
 pub use crate::city::City;
 pub use crate::edits::{
@@ -55,7 +55,9 @@ pub use crate::objects::lane::{
 pub use crate::objects::parking_lot::{ParkingLot, ParkingLotID};
 pub use crate::objects::road::{DirectedRoadID, Direction, Road, RoadID};
 pub use crate::objects::stop_signs::{ControlStopSign, RoadWithStopSign};
-pub use crate::objects::traffic_signals::{ControlTrafficSignal, Stage, StageType};
+pub use crate::objects::traffic_signals::{
+    ControlTrafficSignal, PedestrianTiming, Stage, StageType,
+};
 pub use crate::objects::turn::{
     CompressedMovementID, Movement, MovementID, Turn, TurnID, TurnPriority, TurnType,
 };
@@ -70,8 +72,11 @@ pub use crate::traversable::{Position, Traversable, MAX_BIKE_SPEED, MAX_WALKING_
 mod city;
 pub mod connectivity;
 mod edits;
+mod export;
+pub mod gmns;
 mod make;
 mod map;
+pub mod map_matching;
 mod objects;
 pub mod osm;
 mod pathfind;
@@ -112,6 +117,10 @@ pub struct Map {
     zones: Vec<Zone>,
 
     name: MapName,
+    // The source of truth for gps_bounds and config, which don't store it. This must stay last;
+    // see deserialize_trailing_projection.
+    #[serde(deserialize_with = "geom::deserialize_trailing_projection")]
+    projection: Projection,
 
     #[serde(skip_serializing, skip_deserializing)]
     edits: MapEdits,
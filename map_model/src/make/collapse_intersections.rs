@@ -12,11 +12,13 @@ use crate::{osm, IntersectionType, LaneSpec, LaneType};
 /// Collapse degenerate intersections:
 /// - between two cycleways
 /// - when the lane specs match and only "unimportant" OSM tags differ
+///
+/// Mid-block crossings are never collapsed.
 pub fn collapse(raw: &mut RawMap) {
     let mut merge: Vec<NodeID> = Vec::new();
     for id in raw.intersections.keys() {
         let roads = raw.roads_per_intersection(*id);
-        if roads.len() != 2 {
+        if roads.len() != 2 || raw.is_mid_block_crossing(*id) {
             continue;
         }
         match should_collapse(roads[0], roads[1], raw) {
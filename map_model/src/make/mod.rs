@@ -85,6 +85,7 @@ impl Map {
             pathfinder_dirty: false,
             routing_params: RoutingParams::default(),
             name: raw.name.clone(),
+            projection: raw.projection,
             edits: MapEdits::new(),
             edits_generation: 0,
             road_to_buildings: MultiMap::new(),
@@ -296,7 +297,9 @@ impl Map {
                         stop_signs.insert(i.id, ControlStopSign::new(&map, i.id));
                     }
                 },
-                IntersectionType::Border | IntersectionType::Construction => {}
+                IntersectionType::Border
+                | IntersectionType::Construction
+                | IntersectionType::Roundabout => {}
             };
         }
         map.stop_signs = stop_signs;
@@ -349,6 +352,8 @@ impl Map {
         map.map_loaded_directly();
         map.bounds = bounds;
         map.gps_bounds = gps_bounds;
+        map.projection = map.gps_bounds.projection;
+        map.config.projection = map.projection;
         map.boundary_polygon = map.bounds.get_rectangle();
         map.intersections = intersections;
         map.roads = roads;
@@ -5,7 +5,9 @@ use nbez::{Bez3o, BezCurve, Point2d};
 
 use geom::{Angle, Distance, Line, PolyLine, Pt2D};
 
-use crate::{Intersection, Lane, LaneID, LaneType, Map, RoadID, Turn, TurnID, TurnType};
+use crate::{
+    DrivingSide, Intersection, Lane, LaneID, LaneType, Map, RoadID, Turn, TurnID, TurnType,
+};
 
 /// Generate all driving and walking turns at an intersection, accounting for OSM turn restrictions.
 pub fn make_all_turns(map: &Map, i: &Intersection) -> Vec<Turn> {
@@ -56,6 +58,10 @@ pub fn make_all_turns(map: &Map, i: &Intersection) -> Vec<Turn> {
         });
     }
 
+    if i.is_roundabout() {
+        filtered_turns = roundabout_lane_discipline(map, i, filtered_turns);
+    }
+
     // But then see how all of that filtering affects lane connectivity.
     match verify_vehicle_connectivity(&filtered_turns, i, map) {
         Ok(()) => filtered_turns,
@@ -276,6 +282,47 @@ fn from_pt(pt: Point2d<f64>) -> Pt2D {
     Pt2D::new(pt.x, pt.y)
 }
 
+/// At multi-lane roundabouts, vehicles may only exit from the outermost circulating lane. Vehicles
+/// entering from the outermost lane of an approach have to stay in the outermost circulating lane,
+/// leaving the inner lanes for traffic going further around.
+fn roundabout_lane_discipline(map: &Map, i: &Intersection, input: Vec<Turn>) -> Vec<Turn> {
+    let circulating = |l: LaneID| map.get_parent(l).is_roundabout_ring();
+    let outermost = |l: LaneID, incoming: bool| {
+        let road = map.get_parent(l);
+        let lanes: Vec<LaneID> = if incoming {
+            road.incoming_lanes(i.id)
+        } else {
+            road.outgoing_lanes(i.id)
+        }
+        .into_iter()
+        .filter(|(_, lt)| *lt == LaneType::Driving)
+        .map(|(l, _)| l)
+        .collect();
+        let edge = if map.get_config().driving_side == DrivingSide::Right {
+            lanes.last()
+        } else {
+            lanes.first()
+        };
+        edge == Some(&l)
+    };
+
+    input
+        .into_iter()
+        .filter(|t| {
+            if !map.get_l(t.id.src).is_driving() || !map.get_l(t.id.dst).is_driving() {
+                return true;
+            }
+            match (circulating(t.id.src), circulating(t.id.dst)) {
+                // Exiting
+                (true, false) => outermost(t.id.src, true),
+                // Entering
+                (false, true) => !outermost(t.id.src, true) || outermost(t.id.dst, false),
+                _ => true,
+            }
+        })
+        .collect()
+}
+
 fn remove_merging_turns(map: &Map, input: Vec<Turn>, turn_type: TurnType) -> Vec<Turn> {
     let mut turns = Vec::new();
 
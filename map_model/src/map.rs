@@ -8,15 +8,15 @@ use serde::{Deserialize, Serialize};
 
 use abstio::{CityName, MapName};
 use abstutil::{prettyprint_usize, serialized_size_bytes, MultiMap, Tags, Timer};
-use geom::{Bounds, Distance, Duration, GPSBounds, Polygon, Pt2D, Ring, Time};
+use geom::{Bounds, Distance, Duration, GPSBounds, Polygon, Projection, Pt2D, Ring, Time};
 
 use crate::raw::{OriginalRoad, RawMap};
 use crate::{
     osm, Area, AreaID, AreaType, Building, BuildingID, BuildingType, BusRoute, BusRouteID, BusStop,
     BusStopID, ControlStopSign, ControlTrafficSignal, DirectedRoadID, Intersection, IntersectionID,
     Lane, LaneID, LaneType, Map, MapEdits, MovementID, OffstreetParking, ParkingLot, ParkingLotID,
-    Path, PathConstraints, PathRequest, PathV2, Pathfinder, Position, Road, RoadID, RoutingParams,
-    Turn, TurnID, TurnType, Zone,
+    Path, PathConstraints, PathRequest, PathStep, PathV2, Pathfinder, Position, Road, RoadID,
+    RoutingParams, Turn, TurnID, TurnType, Zone,
 };
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -34,6 +34,11 @@ pub struct MapConfig {
     /// value can be smaller than the hardcoded maximum car length; cars may render on top of each
     /// other, but otherwise the simulation doesn't care.
     pub street_parking_spot_length: Distance,
+    /// How the map was flattened from longitude and latitude. After importing, this is the
+    /// resolved projection, never `AutoUtm` or `Epsg`. It's stored at the end of maps and raw maps
+    /// instead of here, so older files still load.
+    #[serde(skip_serializing, skip_deserializing)]
+    pub projection: Projection,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
@@ -78,14 +83,17 @@ impl Map {
             }
         }
 
-        let raw: RawMap = abstio::read_binary(path, timer);
+        let raw = RawMap::load(path, timer);
         Map::create_from_raw(raw, crate::RawToMapOptions::default(), timer)
     }
 
     /// After deserializing a map directly, call this after.
     pub fn map_loaded_directly(&mut self) {
+        self.gps_bounds.restore_projection(self.projection);
+        self.config.projection = self.projection;
         self.edits = self.new_edits();
         self.recalculate_road_to_buildings();
+        ControlTrafficSignal::restore_pedestrian_timing(self);
 
         // Enable to work on shrinking map file sizes. Never run this on the web though --
         // trying to serialize fast_paths in wasm melts the browser, because the usize<->u32
@@ -176,11 +184,13 @@ impl Map {
                 bikes_can_use_bus_lanes: true,
                 inferred_sidewalks: true,
                 street_parking_spot_length: Distance::meters(8.0),
+                projection: Projection::default(),
             },
             pathfinder: Pathfinder::empty(),
             pathfinder_dirty: false,
             routing_params: RoutingParams::default(),
             name: MapName::new("zz", "blank city", "blank"),
+            projection: Projection::default(),
             edits: MapEdits::new(),
             edits_generation: 0,
             road_to_buildings: MultiMap::new(),
@@ -578,6 +588,43 @@ impl Map {
     pub fn pathfind_with_params(&self, req: PathRequest, params: &RoutingParams) -> Result<Path> {
         self.pathfind_v2_with_params(req, params)?.into_v1(self)
     }
+
+    /// Follows an existing path from the start of the request, then finds the rest of the way to
+    /// the end. Used to replay a route observed somewhere else. Fails if the path doesn't begin on
+    /// the lane where the request starts, or the two parts don't join up.
+    pub fn pathfind_via(&self, req: PathRequest, prefix: &Path) -> Result<Path> {
+        let mut steps: Vec<PathStep> = prefix.get_steps().iter().cloned().collect();
+        match steps.first() {
+            Some(PathStep::Lane(l)) | Some(PathStep::ContraflowLane(l))
+                if *l == req.start.lane() => {}
+            _ => bail!("{} doesn't begin where {} does", prefix.get_req(), req),
+        }
+        let rest = self.pathfind(PathRequest {
+            start: prefix.get_req().end,
+            end: req.end,
+            constraints: req.constraints,
+            alt_start: None,
+        })?;
+        let mut rest = rest.get_steps().iter().cloned().peekable();
+        // Both parts include the lane where they meet
+        if steps.last() == rest.peek() {
+            rest.next();
+        }
+        steps.extend(rest);
+        for pair in steps.windows(2) {
+            if !pair[0].connects_to(pair[1], self) {
+                bail!(
+                    "Can't continue {} to {}: {:?} doesn't lead to {:?}",
+                    prefix.get_req(),
+                    req,
+                    pair[0],
+                    pair[1]
+                );
+            }
+        }
+        Ok(Path::new(self, steps, req, Vec::new(), Vec::new()))
+    }
+
     pub fn pathfind_v2(&self, req: PathRequest) -> Result<PathV2> {
         assert!(!self.pathfinder_dirty);
         self.pathfinder
@@ -0,0 +1,324 @@
+//! Map matching relates a noisy GPS trace to the route somebody actually took through the map.
+//! This uses the hidden Markov model from "Hidden Markov Map Matching Through Noise and Sparseness"
+//! by Newson and Krumm. Each GPS fix could have been on any nearby lane; the likelihood of each
+//! candidate depends on how far away it is from the fix, and the likelihood of moving between
+//! candidates of consecutive fixes depends on how close the route distance is to the straight-line
+//! distance. The Viterbi algorithm finds the most likely sequence of candidates, and the routes
+//! between them form the matched path.
+
+use anyhow::Result;
+
+use abstutil::Timer;
+use geom::{Distance, Duration, FindClosest, LonLat, Pt2D, Speed, Time};
+
+use crate::{LaneID, Map, Path, PathConstraints, PathRequest, PathStep, Position};
+
+/// Parameters for map matching. The defaults come from the paper and work for typical phone and
+/// vehicle GPS.
+#[derive(Clone, Debug)]
+pub struct MatchingOptions {
+    /// The standard deviation of GPS noise
+    pub gps_sigma: Distance,
+    /// Lanes further than this from a fix aren't considered
+    pub search_radius: Distance,
+    /// Only consider this many of the closest lanes for each fix
+    pub max_candidates: usize,
+    /// How much the difference between route and straight-line distance is tolerated. Larger
+    /// values accept more roundabout routes between fixes.
+    pub beta: Distance,
+    /// Fixes closer than this to the previous one are dropped, since they mostly add noise. The
+    /// paper uses twice `gps_sigma`.
+    pub min_spacing: Distance,
+    /// Routes between consecutive fixes longer than this multiple of the straight-line distance
+    /// (plus the search radius) are considered impossible.
+    pub max_detour: f64,
+}
+
+impl MatchingOptions {
+    pub fn default() -> MatchingOptions {
+        MatchingOptions {
+            gps_sigma: Distance::meters(4.07),
+            search_radius: Distance::meters(50.0),
+            max_candidates: 5,
+            beta: Distance::meters(5.0),
+            min_spacing: Distance::meters(2.0 * 4.07),
+            max_detour: 3.0,
+        }
+    }
+}
+
+/// The result of matching a GPS trace.
+pub struct MatchedTrace {
+    /// The route taken, from the first matched fix to the last. Uber-turns aren't tracked.
+    pub path: Path,
+    /// Where each used fix was matched to. Dropped fixes (too close to the previous one, with no
+    /// nearby lanes, or where somebody walking turned around) are omitted.
+    pub positions: Vec<(Time, Position)>,
+    /// For each consecutive pair of `positions`, the distance travelled along `path`
+    pub distances: Vec<Distance>,
+}
+
+impl MatchedTrace {
+    /// The average speed between each consecutive pair of matched fixes, along with the lane the
+    /// second fix was matched to. Pairs with no time between them are skipped.
+    pub fn observed_speeds(&self) -> Vec<(LaneID, Speed)> {
+        let mut results = Vec::new();
+        for (pair, dist) in self.positions.windows(2).zip(self.distances.iter()) {
+            let dt = pair[1].0 - pair[0].0;
+            if dt > Duration::ZERO {
+                results.push((pair[1].1.lane(), Speed::from_dist_time(*dist, dt)));
+            }
+        }
+        results
+    }
+
+    pub fn start_time(&self) -> Time {
+        self.positions[0].0
+    }
+
+    pub fn end_time(&self) -> Time {
+        self.positions.last().unwrap().0
+    }
+}
+
+/// Snaps a sequence of timestamped fixes to a path usable by some type of agent. Fails if there are
+/// fewer than two usable fixes, or if no route connects some consecutive fixes.
+pub fn match_trace(
+    map: &Map,
+    trace: &[(Time, LonLat)],
+    constraints: PathConstraints,
+    opts: &MatchingOptions,
+    timer: &mut Timer,
+) -> Result<MatchedTrace> {
+    timer.start("find candidates");
+    let mut closest: FindClosest<LaneID> = FindClosest::new(map.get_bounds());
+    for l in map.all_lanes().values() {
+        if constraints.can_use(l, map) {
+            closest.add(l.id, l.lane_center_pts.points());
+        }
+    }
+
+    let mut fixes: Vec<(Time, Pt2D, Vec<Candidate>)> = Vec::new();
+    let mut no_candidates = 0;
+    for (time, gps) in trace {
+        let pt = gps.to_pt(map.get_gps_bounds());
+        if let Some((_, prev, _)) = fixes.last() {
+            if prev.dist_to(pt) < opts.min_spacing {
+                continue;
+            }
+        }
+        let candidates = find_candidates(map, &closest, pt, opts);
+        if candidates.is_empty() {
+            no_candidates += 1;
+            continue;
+        }
+        fixes.push((*time, pt, candidates));
+    }
+    timer.stop("find candidates");
+    if no_candidates > 0 {
+        warn!(
+            "{} GPS fixes have no lanes within {}",
+            no_candidates, opts.search_radius
+        );
+    }
+    if fixes.len() < 2 {
+        bail!("Only {} GPS fixes are usable", fixes.len());
+    }
+
+    // Viterbi, working with log probabilities. For each fix and candidate, remember the best
+    // previous candidate and the route from it.
+    let mut scores: Vec<f64> = fixes[0].2.iter().map(|c| c.emission).collect();
+    let mut backpointers: Vec<Vec<Option<(usize, Transition)>>> = Vec::new();
+    timer.start_iter("match fixes", fixes.len() - 1);
+    for idx in 1..fixes.len() {
+        timer.next();
+        let (_, prev_pt, ref prev_candidates) = fixes[idx - 1];
+        let (_, pt, ref candidates) = fixes[idx];
+        let straight_line = prev_pt.dist_to(pt);
+        let max_route = straight_line * opts.max_detour + opts.search_radius;
+
+        let mut next_scores = vec![f64::NEG_INFINITY; candidates.len()];
+        let mut pointers: Vec<Option<(usize, Transition)>> = vec![None; candidates.len()];
+        for (i, from) in prev_candidates.iter().enumerate() {
+            if scores[i] == f64::NEG_INFINITY {
+                continue;
+            }
+            for (j, to) in candidates.iter().enumerate() {
+                let transition = match route(map, from.pos, to.pos, constraints) {
+                    Some(t) if t.dist <= max_route => t,
+                    _ => continue,
+                };
+                let score = scores[i]
+                    + to.emission
+                    + -(transition.dist - straight_line).abs().inner_meters()
+                        / opts.beta.inner_meters();
+                if score > next_scores[j] {
+                    next_scores[j] = score;
+                    pointers[j] = Some((i, transition));
+                }
+            }
+        }
+        if next_scores.iter().all(|x| *x == f64::NEG_INFINITY) {
+            bail!(
+                "No route connects the GPS fixes at {} and {}",
+                fixes[idx - 1].0,
+                fixes[idx].0
+            );
+        }
+        scores = next_scores;
+        backpointers.push(pointers);
+    }
+
+    // Trace back the best sequence
+    let mut best = (0..scores.len())
+        .max_by(|a, b| scores[*a].partial_cmp(&scores[*b]).unwrap())
+        .unwrap();
+    let mut chosen = vec![best];
+    let mut transitions = Vec::new();
+    for pointers in backpointers.into_iter().rev() {
+        let (prev, transition) = pointers[best].clone().unwrap();
+        transitions.push(transition);
+        chosen.push(prev);
+        best = prev;
+    }
+    chosen.reverse();
+    transitions.reverse();
+
+    let mut positions: Vec<(Time, Position)> = fixes
+        .iter()
+        .zip(chosen.iter())
+        .map(|((time, _, candidates), idx)| (*time, candidates[*idx].pos))
+        .collect();
+
+    // Consecutive routes usually both include the lane of the fix between them. But somebody
+    // walking might turn around partway along a sidewalk, which a path can't express. In that
+    // case, drop the fix where they turned and route straight from the one before to the one
+    // after.
+    let mut idx = 1;
+    while idx < transitions.len() {
+        let prev = *transitions[idx - 1].steps.last().unwrap();
+        let next = transitions[idx].steps[0];
+        if prev == next || prev.connects_to(next, map) {
+            idx += 1;
+            continue;
+        }
+        let (from, to) = (positions[idx - 1], positions[idx + 1]);
+        let bridge = route(map, from.1, to.1, constraints).ok_or_else(|| {
+            anyhow!(
+                "The route doubles back at {}, and nothing connects {} to {} instead",
+                positions[idx].0,
+                from.0,
+                to.0
+            )
+        })?;
+        positions.remove(idx);
+        transitions.remove(idx);
+        transitions[idx - 1] = bridge;
+        // The new route has to join up with the one before it
+        idx = (idx - 1).max(1);
+    }
+    let distances = transitions.iter().map(|t| t.dist).collect();
+
+    // Glue the routes together, without repeating the lane they share
+    let mut steps: Vec<PathStep> = Vec::new();
+    for transition in transitions {
+        let mut iter = transition.steps.into_iter().peekable();
+        if steps.last() == iter.peek() {
+            iter.next();
+        }
+        steps.extend(iter);
+    }
+    for pair in steps.windows(2) {
+        if !pair[0].connects_to(pair[1], map) {
+            bail!(
+                "The matched route isn't continuous: {:?} doesn't lead to {:?}",
+                pair[0],
+                pair[1]
+            );
+        }
+    }
+    let req = PathRequest {
+        start: positions[0].1,
+        end: positions.last().unwrap().1,
+        constraints,
+        alt_start: None,
+    };
+    Ok(MatchedTrace {
+        path: Path::new(map, steps, req, Vec::new(), Vec::new()),
+        positions,
+        distances,
+    })
+}
+
+struct Candidate {
+    pos: Position,
+    /// The log probability of observing the fix from here
+    emission: f64,
+}
+
+#[derive(Clone)]
+struct Transition {
+    dist: Distance,
+    steps: Vec<PathStep>,
+}
+
+fn find_candidates(
+    map: &Map,
+    closest: &FindClosest<LaneID>,
+    pt: Pt2D,
+    opts: &MatchingOptions,
+) -> Vec<Candidate> {
+    let mut hits = closest.all_close_pts(pt, opts.search_radius);
+    hits.sort_by_key(|(_, _, dist)| *dist);
+    hits.truncate(opts.max_candidates);
+    hits.into_iter()
+        .map(|(l, snapped, dist)| {
+            let lane = map.get_l(l);
+            let dist_along = lane
+                .lane_center_pts
+                .dist_along_of_point(lane.lane_center_pts.project_pt(snapped))
+                .map(|(d, _)| d)
+                .unwrap_or(Distance::ZERO);
+            let z = dist.inner_meters() / opts.gps_sigma.inner_meters();
+            Candidate {
+                pos: Position::new(l, dist_along.min(lane.length())),
+                emission: -0.5 * z * z,
+            }
+        })
+        .collect()
+}
+
+/// Finds the route between two candidate positions. Moving forwards along the same lane doesn't
+/// need the pathfinder.
+fn route(
+    map: &Map,
+    from: Position,
+    to: Position,
+    constraints: PathConstraints,
+) -> Option<Transition> {
+    if from.lane() == to.lane() {
+        if to.dist_along() >= from.dist_along() {
+            return Some(Transition {
+                dist: to.dist_along() - from.dist_along(),
+                steps: vec![PathStep::Lane(from.lane())],
+            });
+        }
+        // Pedestrians can walk either way along a sidewalk
+        if constraints == PathConstraints::Pedestrian {
+            return Some(Transition {
+                dist: from.dist_along() - to.dist_along(),
+                steps: vec![PathStep::ContraflowLane(from.lane())],
+            });
+        }
+    }
+    let req = if constraints == PathConstraints::Pedestrian {
+        PathRequest::walking(from, to)
+    } else {
+        PathRequest::vehicle(from, to, constraints)
+    };
+    let path = map.pathfind(req).ok()?;
+    Some(Transition {
+        dist: path.total_length(),
+        steps: path.get_steps().iter().cloned().collect(),
+    })
+}
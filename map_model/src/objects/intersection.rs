@@ -29,6 +29,8 @@ pub enum IntersectionType {
     TrafficSignal,
     Border,
     Construction,
+    /// Circulating traffic has priority; entering vehicles wait for a gap.
+    Roundabout,
 }
 
 /// An intersection connects roads. Most have >2 roads and are controlled by stop signs or traffic
@@ -70,6 +72,17 @@ impl Intersection {
         self.intersection_type == IntersectionType::Border && !self.incoming_lanes.is_empty()
     }
 
+    /// Is this just a place for pedestrians to cross in the middle of a road? Drivers yield to
+    /// anybody waiting to cross.
+    pub fn is_mid_block_crossing(&self, map: &Map) -> bool {
+        self.roads.len() == 2
+            && self.roads.iter().all(|r| {
+                let r = map.get_r(*r);
+                (r.dst_i == self.id && r.osm_tags.contains_key(osm::CROSSING_FWD))
+                    || (r.src_i == self.id && r.osm_tags.contains_key(osm::CROSSING_BACK))
+            })
+    }
+
     pub fn is_closed(&self) -> bool {
         self.intersection_type == IntersectionType::Construction
     }
@@ -82,6 +95,18 @@ impl Intersection {
         self.intersection_type == IntersectionType::TrafficSignal
     }
 
+    pub fn is_roundabout(&self) -> bool {
+        self.intersection_type == IntersectionType::Roundabout
+    }
+
+    /// Is this somewhere along the ring of a roundabout, with traffic circulating through? Only
+    /// these can be controlled as a roundabout; elsewhere, there's nobody to yield to.
+    pub fn is_on_roundabout_ring(&self, map: &Map) -> bool {
+        self.roads
+            .iter()
+            .any(|r| map.get_r(*r).is_roundabout_ring())
+    }
+
     pub fn is_light_rail(&self, map: &Map) -> bool {
         self.roads.iter().all(|r| map.get_r(*r).is_light_rail())
     }
@@ -209,24 +209,23 @@ impl Road {
         self.find_closest_lane(parking, |l| l.is_driving(), map)
     }
 
-    pub(crate) fn speed_limit_from_osm(&self) -> Speed {
-        if let Some(limit) = self.osm_tags.get(osm::MAXSPEED) {
-            if let Ok(kmph) = limit.parse::<f64>() {
-                if kmph == 0.0 {
-                    warn!("{} has a speed limit of 0", self.orig_id.osm_way_id);
-                    return Speed::miles_per_hour(1.0);
-                }
-                return Speed::km_per_hour(kmph);
-            }
+    /// Is this part of the ring of a roundabout? Traffic circulating along it has priority over
+    /// traffic entering.
+    pub fn is_roundabout_ring(&self) -> bool {
+        self.osm_tags.is("junction", "roundabout")
+    }
 
-            if let Some(mph) = limit
-                .strip_suffix(" mph")
-                .and_then(|x| x.parse::<f64>().ok())
-            {
-                return Speed::miles_per_hour(mph);
+    pub(crate) fn speed_limit_from_osm(&self) -> Speed {
+        if let Some(limit) = self
+            .osm_tags
+            .get(osm::MAXSPEED)
+            .and_then(|x| osm::parse_maxspeed(x))
+        {
+            if limit == Speed::ZERO {
+                warn!("{} has a speed limit of 0", self.orig_id.osm_way_id);
+                return Speed::miles_per_hour(1.0);
             }
-
-            // TODO Handle implicits, like PL:zone30
+            return limit;
         }
 
         // These're half reasonable guesses. Better to explicitly tag in OSM.
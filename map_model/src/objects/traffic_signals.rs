@@ -41,6 +41,26 @@ pub struct Stage {
     // TODO Not renaming this, because this is going to change radically in
     // https://github.com/a-b-street/abstreet/pull/298 anyway
     pub stage_type: StageType,
+    /// If present, pedestrians may only start crossing at the beginning of the stage.
+    ///
+    /// This isn't stored in map files, so adding it didn't invalidate every existing map. Map
+    /// edits keep it, and restore_pedestrian_timing recovers it from manually specified settings.
+    #[serde(skip_serializing, skip_deserializing)]
+    pub pedestrian_timing: Option<PedestrianTiming>,
+}
+
+/// When pedestrians may cross during a stage, measured from the start of the stage. A stage with
+/// only crosswalks is a pedestrian scramble (or Barnes dance); it can use this timing too.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct PedestrianTiming {
+    /// Vehicles wait this long at the start of the stage, giving pedestrians a head start. This is
+    /// a leading pedestrian interval (LPI).
+    pub leading_interval: Duration,
+    /// Pedestrians may start crossing during this long from the start of the stage.
+    pub walk: Duration,
+    /// After the walk interval, the signal flashes "don't walk" for this long. Nobody may start
+    /// crossing, but anybody who already started has time to finish.
+    pub flashing_dont_walk: Duration,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
@@ -147,6 +167,28 @@ impl ControlTrafficSignal {
             }
             // Is there enough time in each stage to walk across the crosswalk
             let min_crossing_time = self.get_min_crossing_time(stage_index);
+            if let Some(ref timing) = stage.pedestrian_timing {
+                if timing.walk == Duration::ZERO {
+                    bail!("Stage {} has no walk interval", stage_index);
+                }
+                if timing.flashing_dont_walk < min_crossing_time {
+                    bail!(
+                        "Stage {} flashes don't walk for {}, but crossing takes {}",
+                        stage_index,
+                        timing.flashing_dont_walk,
+                        min_crossing_time
+                    );
+                }
+                let ped_time = timing.walk + timing.flashing_dont_walk;
+                if ped_time.max(timing.leading_interval) > stage.stage_type.simple_duration() {
+                    bail!(
+                        "Stage {} lasts {}, less than its pedestrian timing {:?}",
+                        stage_index,
+                        stage.stage_type.simple_duration(),
+                        timing
+                    );
+                }
+            }
             if stage.stage_type.simple_duration() < min_crossing_time {
                 bail!(
                     "Traffic signal does not allow enough time in stage to complete the \
@@ -328,6 +370,7 @@ impl Stage {
             yield_movements: BTreeSet::new(),
             // TODO Set a default
             stage_type: StageType::Fixed(Duration::seconds(30.0)),
+            pedestrian_timing: None,
         }
     }
 
@@ -453,6 +496,17 @@ impl ControlTrafficSignal {
                                 )
                             }
                         },
+                        pedestrian_timing: s.pedestrian_timing.as_ref().map(|timing| {
+                            traffic_signal_data::PedestrianTiming {
+                                leading_interval_seconds: timing.leading_interval.inner_seconds()
+                                    as usize,
+                                walk_seconds: timing.walk.inner_seconds() as usize,
+                                flashing_dont_walk_seconds: timing
+                                    .flashing_dont_walk
+                                    .inner_seconds()
+                                    as usize,
+                            }
+                        }),
                     })
                     .collect(),
                 offset_seconds: self.offset.inner_seconds() as usize,
@@ -460,6 +514,55 @@ impl ControlTrafficSignal {
         }
     }
 
+    /// Pedestrian timing isn't stored in map files, so after loading a map, fill it in again for
+    /// any signals still using the manually specified settings.
+    pub(crate) fn restore_pedestrian_timing(map: &mut Map) {
+        let mut handmapped = traffic_signal_data::load_all_data().unwrap();
+        let mut restore = Vec::new();
+        for ts in map.traffic_signals.values() {
+            let raw = match handmapped.remove(&map.get_i(ts.id).orig_id.0) {
+                Some(raw) => raw,
+                None => {
+                    continue;
+                }
+            };
+            if raw.plans[0]
+                .stages
+                .iter()
+                .all(|s| s.pedestrian_timing.is_none())
+            {
+                continue;
+            }
+            let imported = match ControlTrafficSignal::import(raw, ts.id, map) {
+                Ok(imported) => imported,
+                Err(_) => {
+                    continue;
+                }
+            };
+            let timings: Vec<Option<PedestrianTiming>> = imported
+                .stages
+                .iter()
+                .map(|s| s.pedestrian_timing.clone())
+                .collect();
+            let mut stages = imported.stages;
+            for stage in &mut stages {
+                stage.pedestrian_timing = None;
+            }
+            // If the manual settings were out of date when the map was built, something else
+            // replaced them, and the timing doesn't apply.
+            if stages == ts.stages {
+                restore.push((ts.id, timings));
+            }
+        }
+
+        for (id, timings) in restore {
+            let ts = map.traffic_signals.get_mut(&id).unwrap();
+            for (stage, timing) in ts.stages.iter_mut().zip(timings) {
+                stage.pedestrian_timing = timing;
+            }
+        }
+    }
+
     pub(crate) fn import(
         mut raw: traffic_signal_data::TrafficSignal,
         id: IntersectionID,
@@ -508,6 +611,13 @@ impl ControlTrafficSignal {
                             )
                         }
                     },
+                    pedestrian_timing: s.pedestrian_timing.map(|timing| PedestrianTiming {
+                        leading_interval: Duration::seconds(timing.leading_interval_seconds as f64),
+                        walk: Duration::seconds(timing.walk_seconds as f64),
+                        flashing_dont_walk: Duration::seconds(
+                            timing.flashing_dont_walk_seconds as f64,
+                        ),
+                    }),
                 });
             } else {
                 bail!("{}", errors.join("; "));
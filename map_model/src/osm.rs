@@ -4,6 +4,8 @@ use std::fmt;
 
 use serde::{Deserialize, Serialize};
 
+use geom::Speed;
+
 // These are common OSM keys. Keys used in just one or two places don't really need to be defined
 // here.
 
@@ -26,11 +28,27 @@ pub const OSM_REL_ID: &str = "abst:osm_rel_id";
 // for interpreting turn restrictions.
 pub const ENDPT_FWD: &str = "abst:endpt_fwd";
 pub const ENDPT_BACK: &str = "abst:endpt_back";
+// Roads split at a mid-block crossing are marked at the end touching the crossing. This keeps the
+// crossing out of the intersection structs, so older map files still load.
+pub const CROSSING_FWD: &str = "abst:crossing_fwd";
+pub const CROSSING_BACK: &str = "abst:crossing_back";
 
 // Any roads might have these.
 pub const INFERRED_PARKING: &str = "abst:parking_inferred";
 pub const INFERRED_SIDEWALKS: &str = "abst:sidewalks_inferred";
 
+/// Parses a `maxspeed` value, either in km/h or like "25 mph".
+// TODO Handle implicits, like PL:zone30
+pub fn parse_maxspeed(value: &str) -> Option<Speed> {
+    if let Ok(kmph) = value.parse::<f64>() {
+        return Some(Speed::km_per_hour(kmph));
+    }
+    value
+        .strip_suffix(" mph")
+        .and_then(|x| x.parse::<f64>().ok())
+        .map(Speed::miles_per_hour)
+}
+
 #[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
 pub enum RoadRank {
     Local,
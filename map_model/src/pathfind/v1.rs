@@ -34,6 +34,21 @@ impl PathStep {
         self.as_traversable().as_turn()
     }
 
+    /// True if a path can go directly from this step to the next one.
+    pub fn connects_to(&self, next: PathStep, map: &Map) -> bool {
+        match (*self, next) {
+            (PathStep::Lane(l), PathStep::Turn(t)) => t.src == l && t.parent == map.get_l(l).dst_i,
+            (PathStep::ContraflowLane(l), PathStep::Turn(t)) => {
+                t.src == l && t.parent == map.get_l(l).src_i
+            }
+            (PathStep::Turn(t), PathStep::Lane(l)) => t.dst == l && t.parent == map.get_l(l).src_i,
+            (PathStep::Turn(t), PathStep::ContraflowLane(l)) => {
+                t.dst == l && t.parent == map.get_l(l).dst_i
+            }
+            _ => false,
+        }
+    }
+
     // start is relative to the start of the actual geometry -- so from the lane's real start for
     // ContraflowLane.
     fn exact_slice(
@@ -308,6 +323,26 @@ impl Path {
         // TODO Maybe need to amend uber_turns?
     }
 
+    /// Keeps the current step, then replaces everything after it with a turn and a new path
+    /// starting from that turn's destination. The new path must end at the same place as this
+    /// one. Used to change routes mid-trip.
+    pub fn replace_after_current_step(&mut self, turn: TurnID, rest: Path, map: &Map) {
+        assert!(self.currently_inside_ut.is_none());
+        assert_eq!(rest.orig_req.end, self.orig_req.end);
+
+        let current = self.steps[0];
+        self.steps.clear();
+        self.steps.push_back(current);
+        self.steps.push_back(PathStep::Turn(turn));
+        self.steps.extend(rest.steps);
+        self.uber_turns = rest.uber_turns;
+
+        self.total_length = self.crossed_so_far;
+        for step in &self.steps {
+            self.total_length += self.dist_crossed_from_step(map, step);
+        }
+    }
+
     pub fn is_upcoming_uber_turn_component(&self, t: TurnID) -> bool {
         self.uber_turns
             .front()
@@ -503,7 +538,7 @@ impl Path {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Serialize, Deserialize)]
 pub struct PathRequest {
     pub start: Position,
     pub end: Position,
@@ -11,7 +11,7 @@ use serde::{Deserialize, Serialize};
 
 use abstio::{CityName, MapName};
 use abstutil::{deserialize_btreemap, serialize_btreemap, Tags, Timer};
-use geom::{Distance, GPSBounds, PolyLine, Polygon, Pt2D};
+use geom::{Distance, GPSBounds, PolyLine, Polygon, Projection, Pt2D};
 
 use crate::make::initial::lane_specs::get_lane_specs_ltr;
 use crate::{
@@ -44,6 +44,10 @@ pub struct RawMap {
     pub boundary_polygon: Polygon,
     pub gps_bounds: GPSBounds,
     pub config: MapConfig,
+    /// The source of truth for `gps_bounds` and `config`, which don't store it. Use `RawMap::load`
+    /// to restore them. This must stay last; see `deserialize_trailing_projection`.
+    #[serde(deserialize_with = "geom::deserialize_trailing_projection")]
+    pub projection: Projection,
 }
 
 /// A way to refer to roads across many maps and over time. Also trivial to relate with OSM to find
@@ -123,10 +127,20 @@ impl RawMap {
                 bikes_can_use_bus_lanes: true,
                 inferred_sidewalks: true,
                 street_parking_spot_length: Distance::meters(8.0),
+                projection: Projection::default(),
             },
+            projection: Projection::default(),
         }
     }
 
+    /// Reads a raw map from a file, restoring its projection.
+    pub fn load(path: String, timer: &mut Timer) -> RawMap {
+        let mut raw: RawMap = abstio::read_binary(path, timer);
+        raw.gps_bounds.restore_projection(raw.projection);
+        raw.config.projection = raw.projection;
+        raw
+    }
+
     // TODO Might be better to maintain this instead of doing a search everytime.
     pub fn roads_per_intersection(&self, i: osm::NodeID) -> Vec<OriginalRoad> {
         let mut results = Vec::new();
@@ -138,6 +152,18 @@ impl RawMap {
         results
     }
 
+    /// Is this a place for pedestrians to cross in the middle of a road, not a junction between
+    /// roads?
+    pub fn is_mid_block_crossing(&self, i: osm::NodeID) -> bool {
+        let roads = self.roads_per_intersection(i);
+        roads.len() == 2
+            && roads.into_iter().all(|id| {
+                let tags = &self.roads[&id].osm_tags;
+                (id.i2 == i && tags.contains_key(osm::CROSSING_FWD))
+                    || (id.i1 == i && tags.contains_key(osm::CROSSING_BACK))
+            })
+    }
+
     pub fn new_osm_node_id(&self, start: i64) -> osm::NodeID {
         assert!(start < 0);
         // Slow, but deterministic.
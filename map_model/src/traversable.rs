@@ -265,8 +265,8 @@ fn bike_speed_on_incline(max_speed: Speed, percent_incline: f64) -> Speed {
 
     // So, we'll adapt the table from Valhalla --
     // https://valhalla.readthedocs.io/en/latest/sif/elevation_costing/ describes how this works.
-    // Their "weighted grade" should be roughly equivalent to how the elevation_lookups package we
-    // use calculates things.  This table comes from
+    // Their "weighted grade" should be roughly equivalent to the incline we fit through the
+    // terrain along each road. This table comes from
     // https://github.com/valhalla/valhalla/blob/f899a940ccbd0bc986769197dec5bb9383014afb/src/sif/bicyclecost.cc#L139.
     // Valhalla is MIT licensed: https://github.com/valhalla/valhalla/blob/master/COPYING.
 
@@ -1,4 +1,5 @@
 use geo::algorithm::{area::Area, contains::Contains};
+use rand::distributions::{Distribution, WeightedIndex};
 use rand::Rng;
 use rand_xorshift::XorShiftRng;
 
@@ -15,16 +16,26 @@ pub fn assign_people_to_houses(
 ) -> Vec<CensusPerson> {
     let mut people = Vec::new();
     for area in areas {
+        let age_bands = area.age_bands;
+        let age_weights = WeightedIndex::new(age_bands.iter().map(|band| band.count)).ok();
+        let pct_car_ownership = area.pct_car_ownership.unwrap_or(0.5).max(0.0).min(1.0);
         for (home, n) in distribute_population_to_homes(area.polygon, area.population, map, rng) {
             for _ in 0..n {
+                let age = if let Some(ref weights) = age_weights {
+                    let band = &age_bands[weights.sample(rng)];
+                    rng.gen_range(band.min_age..=band.max_age)
+                } else {
+                    // TODO Making this up when the census doesn't say
+                    rng.gen_range(5..95)
+                };
                 people.push(CensusPerson {
                     home,
+                    age,
                     // TODO Making this up for now. We can either move this to Config or see if we
                     // can extract it from the census. Also, not even sure which of these
                     // attributes are useful later in the pipeline.
-                    age: rng.gen_range(5..95),
                     employed: rng.gen_bool(0.7),
-                    owns_car: rng.gen_bool(0.5),
+                    owns_car: rng.gen_bool(pct_car_ownership),
                 });
             }
         }
@@ -13,12 +13,8 @@ impl CensusArea {
         use flatgeobuf::HttpFgbReader;
         use geozero::geo_types::GeoWriter;
 
-        use geo::algorithm::{bounding_rect::BoundingRect, map_coords::MapCoordsInplace};
-        let mut geo_map_area: geo::Polygon<_> = map_area.clone().into();
-        geo_map_area.map_coords_inplace(|c| {
-            let projected = geom::Pt2D::new(c.0, c.1).to_gps(bounds);
-            (projected.x(), projected.y())
-        });
+        use geo::algorithm::bounding_rect::BoundingRect;
+        let geo_map_area = map_area_to_gps(map_area, bounds);
 
         // See the import handbook for how to prepare this file.
         let mut fgb =
@@ -55,10 +51,6 @@ impl CensusArea {
             let mut geo = GeoWriter::new();
             geometry.process(&mut geo, flatgeobuf::GeometryType::MultiPolygon)?;
             if let geo::Geometry::MultiPolygon(multi_poly) = geo.geometry() {
-                let geo_polygon = multi_poly
-                    .0
-                    .first()
-                    .ok_or_else(|| anyhow!("multipolygon was unexpectedly empty"))?;
                 if multi_poly.0.len() > 1 {
                     warn!(
                         "dropping {} extra polygons from census area: {:?}",
@@ -66,24 +58,18 @@ impl CensusArea {
                         props
                     );
                 }
-
-                if !geo_polygon.intersects(&geo_map_area) {
-                    debug!(
-                        "skipping polygon outside of map area. polygon: {:?}, map_area: {:?}",
-                        geo_polygon, geo_map_area
-                    );
-                    continue;
+                let geo_polygon = multi_poly
+                    .0
+                    .first()
+                    .ok_or_else(|| anyhow!("multipolygon was unexpectedly empty"))?;
+                if let Some(polygon) = project_if_overlapping(geo_polygon, &geo_map_area, bounds) {
+                    results.push(CensusArea {
+                        polygon,
+                        population,
+                        age_bands: Vec::new(),
+                        pct_car_ownership: None,
+                    });
                 }
-
-                let mut polygon = geo_polygon.clone();
-                polygon.map_coords_inplace(|(x, y)| {
-                    let point = geom::LonLat::new(*x, *y).to_pt(bounds);
-                    (point.x(), point.y())
-                });
-                results.push(CensusArea {
-                    polygon,
-                    population,
-                });
             } else {
                 warn!("skipping unexpected geometry");
                 continue;
@@ -93,3 +79,39 @@ impl CensusArea {
         Ok(results)
     }
 }
+
+/// Transforms the map's boundary to longitude and latitude.
+pub(crate) fn map_area_to_gps(map_area: &Polygon, bounds: &GPSBounds) -> geo::Polygon<f64> {
+    use geo::algorithm::map_coords::MapCoordsInplace;
+    let mut geo_map_area: geo::Polygon<_> = map_area.clone().into();
+    geo_map_area.map_coords_inplace(|c| {
+        let projected = geom::Pt2D::new(c.0, c.1).to_gps(bounds);
+        (projected.x(), projected.y())
+    });
+    geo_map_area
+}
+
+/// Transforms a census area in longitude and latitude to map-space, or returns `None` if it
+/// doesn't overlap the map at all. Areas only partly inside the map aren't clipped, since their
+/// population describes the entire area.
+pub(crate) fn project_if_overlapping(
+    geo_polygon: &geo::Polygon<f64>,
+    geo_map_area: &geo::Polygon<f64>,
+    bounds: &GPSBounds,
+) -> Option<geo::Polygon<f64>> {
+    use geo::algorithm::map_coords::MapCoordsInplace;
+    if !geo_polygon.intersects(geo_map_area) {
+        debug!(
+            "skipping polygon outside of map area. polygon: {:?}, map_area: {:?}",
+            geo_polygon, geo_map_area
+        );
+        return None;
+    }
+
+    let mut polygon = geo_polygon.clone();
+    polygon.map_coords_inplace(|(x, y)| {
+        let point = geom::LonLat::new(*x, *y).to_pt(bounds);
+        (point.x(), point.y())
+    });
+    Some(polygon)
+}
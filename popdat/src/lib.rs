@@ -30,11 +30,14 @@ use map_model::{BuildingID, Map};
 use sim::Scenario;
 
 pub use self::distribute_people::distribute_population_to_homes;
+pub use self::load_census::{AgeBandColumn, CarOwnershipColumns, CensusColumns, CensusSource};
 
 mod activities;
 mod distribute_people;
 mod import_census;
+mod load_census;
 mod make_person;
+pub mod matsim;
 pub mod od;
 
 /// Represents aggregate demographic data for some part of a city. These could be census tracts or
@@ -45,7 +48,19 @@ pub mod od;
 pub struct CensusArea {
     pub polygon: geo::Polygon<f64>,
     pub population: usize,
-    // TODO Not sure what goes here, whatever census data actually has that could be useful
+    /// How many people are in different age ranges. These don't have to sum to the population;
+    /// they're just used as a distribution. If empty, ages are made up.
+    pub age_bands: Vec<AgeBand>,
+    /// What fraction of people have access to a car, from 0 to 1. If unknown, it's a coin flip.
+    pub pct_car_ownership: Option<f64>,
+}
+
+/// Some number of people with an age in `[min_age, max_age]`
+#[derive(Clone, Debug, PartialEq)]
+pub struct AgeBand {
+    pub min_age: usize,
+    pub max_age: usize,
+    pub count: usize,
 }
 
 /// Demographic information for a single person
@@ -0,0 +1,724 @@
+//! Reads `CensusArea`s from local files, so any city with its own statistical-area data can use
+//! the same pipeline as the US. Every country names its columns differently, so a `CensusSource`
+//! says which columns hold what.
+//!
+//! Supported formats are GeoJSON, GeoPackage, FlatGeobuf, and shapefiles. Coordinates must be
+//! longitude and latitude (WGS84). Reproject other data first, with something like
+//! `ogr2ogr -t_srs EPSG:4326 out.gpkg in.gpkg`.
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use abstutil::Timer;
+use geom::{GPSBounds, Polygon};
+
+use crate::import_census::{map_area_to_gps, project_if_overlapping};
+use crate::{AgeBand, CensusArea};
+
+/// Where to find census data and how to interpret it
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CensusSource {
+    /// A .geojson, .gpkg, .fgb, or .shp file
+    pub path: String,
+    /// Which table to use from a GeoPackage. If there's only one, this can be omitted.
+    #[serde(default)]
+    pub layer: Option<String>,
+    pub columns: CensusColumns,
+}
+
+/// Names of the columns holding each piece of information
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CensusColumns {
+    /// The total number of people living in the area
+    pub population: String,
+    /// Counts of people in different age ranges
+    #[serde(default)]
+    pub age_bands: Vec<AgeBandColumn>,
+    #[serde(default)]
+    pub car_ownership: Option<CarOwnershipColumns>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AgeBandColumn {
+    pub column: String,
+    pub min_age: usize,
+    /// Inclusive
+    pub max_age: usize,
+}
+
+/// Censuses usually describe car ownership per household, which is treated as the probability of
+/// each person having a car.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum CarOwnershipColumns {
+    /// One column with the fraction (from 0 to 1) of households with at least one car
+    Fraction(String),
+    /// Columns counting households by number of cars, like "1 car" and "2+ cars", and a column
+    /// with the total number of households
+    Counts {
+        with_car: Vec<String>,
+        total: String,
+    },
+}
+
+impl CensusColumns {
+    fn all(&self) -> Vec<&str> {
+        let mut columns = vec![self.population.as_str()];
+        for band in &self.age_bands {
+            columns.push(&band.column);
+        }
+        match self.car_ownership {
+            Some(CarOwnershipColumns::Fraction(ref column)) => {
+                columns.push(column);
+            }
+            Some(CarOwnershipColumns::Counts {
+                ref with_car,
+                ref total,
+            }) => {
+                columns.extend(with_car.iter().map(|x| x.as_str()));
+                columns.push(total);
+            }
+            None => {}
+        }
+        columns
+    }
+}
+
+/// One feature from the input file, in longitude and latitude, with the values of the needed
+/// columns
+struct RawArea {
+    polygons: Vec<geo::Polygon<f64>>,
+    properties: BTreeMap<String, String>,
+}
+
+impl CensusArea {
+    /// Loads all areas from a local file overlapping the map. Areas with multiple polygons are
+    /// split, dividing the population by area.
+    pub fn load_all_for_map(
+        source: &CensusSource,
+        map_area: &Polygon,
+        bounds: &GPSBounds,
+        timer: &mut Timer,
+    ) -> Result<Vec<CensusArea>> {
+        for band in &source.columns.age_bands {
+            if band.min_age > band.max_age {
+                bail!("Age band {} has min_age > max_age", band.column);
+            }
+        }
+
+        timer.start(format!("read {}", source.path));
+        let columns = source.columns.all();
+        let lower = source.path.to_lowercase();
+        let raw_areas = if lower.ends_with(".geojson") || lower.ends_with(".json") {
+            read_geojson(std::fs::read_to_string(&source.path)?, &columns)
+        } else if lower.ends_with(".gpkg") {
+            read_geopackage(&source.path, source.layer.as_ref(), &columns)
+        } else if lower.ends_with(".fgb") {
+            read_flatgeobuf(&source.path, &columns)
+        } else if lower.ends_with(".shp") {
+            read_shapefile(&source.path, &columns)
+        } else {
+            Err(anyhow!("Unknown census file format for {}", source.path))
+        };
+        timer.stop(format!("read {}", source.path));
+        let raw_areas = raw_areas?;
+
+        let geo_map_area = map_area_to_gps(map_area, bounds);
+        let mut results = Vec::new();
+        let mut skipped = 0;
+        for raw in raw_areas {
+            let attributes = match Attributes::new(&source.columns, &raw.properties) {
+                Ok(x) => x,
+                Err(err) => {
+                    warn!("Skipping census area: {}", err);
+                    skipped += 1;
+                    continue;
+                }
+            };
+
+            use geo::algorithm::area::Area;
+            let total_area: f64 = raw.polygons.iter().map(|p| p.unsigned_area()).sum();
+            let num_polygons = raw.polygons.len();
+            for geo_polygon in raw.polygons {
+                let pct = if num_polygons == 1 || total_area == 0.0 {
+                    1.0
+                } else {
+                    geo_polygon.unsigned_area() / total_area
+                };
+                if let Some(polygon) = project_if_overlapping(&geo_polygon, &geo_map_area, bounds) {
+                    results.push(attributes.to_area(polygon, pct));
+                }
+            }
+        }
+        if skipped > 0 {
+            warn!(
+                "Skipped {} census areas with missing or bad values",
+                skipped
+            );
+        }
+        info!(
+            "Found {} census areas from {} overlapping the map",
+            results.len(),
+            source.path
+        );
+        Ok(results)
+    }
+}
+
+/// The parsed values for one area
+struct Attributes {
+    population: f64,
+    age_bands: Vec<AgeBand>,
+    pct_car_ownership: Option<f64>,
+}
+
+impl Attributes {
+    fn new(columns: &CensusColumns, properties: &BTreeMap<String, String>) -> Result<Attributes> {
+        let get = |column: &str| -> Result<f64> {
+            let value = properties
+                .get(column)
+                .ok_or_else(|| anyhow!("missing {}", column))?;
+            let x = value
+                .trim()
+                .parse::<f64>()
+                .map_err(|_| anyhow!("{} = {} isn't a number", column, value))?;
+            if !x.is_finite() || x < 0.0 {
+                bail!("{} = {} is negative or invalid", column, value);
+            }
+            Ok(x)
+        };
+
+        let population = get(&columns.population)?;
+        let mut age_bands = Vec::new();
+        for band in &columns.age_bands {
+            age_bands.push(AgeBand {
+                min_age: band.min_age,
+                max_age: band.max_age,
+                count: get(&band.column)?.round() as usize,
+            });
+        }
+        let pct_car_ownership = match columns.car_ownership {
+            Some(CarOwnershipColumns::Fraction(ref column)) => Some(get(column)?.min(1.0)),
+            Some(CarOwnershipColumns::Counts {
+                ref with_car,
+                ref total,
+            }) => {
+                let total = get(total)?;
+                let mut sum = 0.0;
+                for column in with_car {
+                    sum += get(column)?;
+                }
+                if total > 0.0 {
+                    Some((sum / total).min(1.0))
+                } else {
+                    None
+                }
+            }
+            None => None,
+        };
+        Ok(Attributes {
+            population,
+            age_bands,
+            pct_car_ownership,
+        })
+    }
+
+    /// `pct` scales counts when the area is split into multiple polygons
+    fn to_area(&self, polygon: geo::Polygon<f64>, pct: f64) -> CensusArea {
+        CensusArea {
+            polygon,
+            population: (self.population * pct).round() as usize,
+            age_bands: self
+                .age_bands
+                .iter()
+                .map(|band| AgeBand {
+                    min_age: band.min_age,
+                    max_age: band.max_age,
+                    count: ((band.count as f64) * pct).round() as usize,
+                })
+                .collect(),
+            pct_car_ownership: self.pct_car_ownership,
+        }
+    }
+}
+
+fn polygons(geometry: geo::Geometry<f64>) -> Vec<geo::Polygon<f64>> {
+    match geometry {
+        geo::Geometry::Polygon(p) => vec![p],
+        geo::Geometry::MultiPolygon(mp) => mp.0,
+        geo::Geometry::GeometryCollection(gc) => gc.0.into_iter().flat_map(polygons).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn read_geojson(raw: String, columns: &[&str]) -> Result<Vec<RawArea>> {
+    let geojson = raw.parse::<geojson::GeoJson>()?;
+    let features = match geojson {
+        geojson::GeoJson::FeatureCollection(collection) => collection.features,
+        geojson::GeoJson::Feature(feature) => vec![feature],
+        _ => bail!("GeoJSON doesn't have any features"),
+    };
+    let mut results = Vec::new();
+    for feature in features {
+        let polygons = match feature.geometry {
+            Some(geometry) => polygons(geo::Geometry::try_from(geometry.value)?),
+            None => Vec::new(),
+        };
+        if polygons.is_empty() {
+            continue;
+        }
+        let mut properties = BTreeMap::new();
+        for column in columns {
+            let value = match feature.property(column) {
+                Some(serde_json::Value::String(x)) => x.clone(),
+                Some(serde_json::Value::Number(x)) => x.to_string(),
+                _ => continue,
+            };
+            properties.insert(column.to_string(), value);
+        }
+        results.push(RawArea {
+            polygons,
+            properties,
+        });
+    }
+    Ok(results)
+}
+
+#[cfg(target_arch = "wasm32")]
+fn read_geopackage(path: &str, _: Option<&String>, _: &[&str]) -> Result<Vec<RawArea>> {
+    bail!("Can't read GeoPackage {} on the web", path)
+}
+
+fn read_flatgeobuf(path: &str, columns: &[&str]) -> Result<Vec<RawArea>> {
+    // The simplest way to handle all geometry types is to go through GeoJSON
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut fgb = flatgeobuf::FgbReader::open(&mut reader)?;
+    fgb.select_all()?;
+    let mut out = Vec::new();
+    let mut writer = geozero::geojson::GeoJsonWriter::new(&mut out);
+    fgb.process_features(&mut writer)?;
+    read_geojson(String::from_utf8(out)?, columns)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn read_geopackage(path: &str, layer: Option<&String>, columns: &[&str]) -> Result<Vec<RawArea>> {
+    let conn =
+        rusqlite::Connection::open_with_flags(path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+
+    let mut tables = Vec::new();
+    {
+        let mut stmt = conn.prepare(
+            "SELECT c.table_name, g.column_name, g.srs_id FROM gpkg_contents c JOIN \
+             gpkg_geometry_columns g ON c.table_name = g.table_name WHERE c.data_type = \
+             'features'",
+        )?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let table: String = row.get(0)?;
+            let geometry_column: String = row.get(1)?;
+            let srs_id: i64 = row.get(2)?;
+            tables.push((table, geometry_column, srs_id));
+        }
+    }
+    let (table, geometry_column, srs_id) = match layer {
+        Some(layer) => tables
+            .into_iter()
+            .find(|(table, _, _)| table == layer)
+            .ok_or_else(|| anyhow!("{} doesn't have a layer called {}", path, layer))?,
+        None => {
+            if tables.len() != 1 {
+                bail!(
+                    "{} has {} feature tables; specify which layer to use",
+                    path,
+                    tables.len()
+                );
+            }
+            tables.pop().unwrap()
+        }
+    };
+    if srs_id != 4326 {
+        bail!(
+            "{} is in EPSG:{}; reproject it to EPSG:4326 first",
+            path,
+            srs_id
+        );
+    }
+
+    let mut select = vec![format!("\"{}\"", geometry_column)];
+    for column in columns {
+        select.push(format!("\"{}\"", column.replace('"', "\"\"")));
+    }
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM \"{}\"",
+        select.join(", "),
+        table.replace('"', "\"\"")
+    ))?;
+    let mut rows = stmt.query([])?;
+    let mut results = Vec::new();
+    while let Some(row) = rows.next()? {
+        let blob: Vec<u8> = match row.get::<_, Option<Vec<u8>>>(0)? {
+            Some(blob) => blob,
+            None => continue,
+        };
+        let polygons = polygons(parse_gpkg_geometry(&blob)?);
+        if polygons.is_empty() {
+            continue;
+        }
+        let mut properties = BTreeMap::new();
+        for (idx, column) in columns.iter().enumerate() {
+            use rusqlite::types::ValueRef;
+            let value = match row.get_ref(idx + 1)? {
+                ValueRef::Integer(x) => x.to_string(),
+                ValueRef::Real(x) => x.to_string(),
+                ValueRef::Text(x) => String::from_utf8_lossy(x).to_string(),
+                ValueRef::Null | ValueRef::Blob(_) => continue,
+            };
+            properties.insert(column.to_string(), value);
+        }
+        results.push(RawArea {
+            polygons,
+            properties,
+        });
+    }
+    Ok(results)
+}
+
+/// Parses the GeoPackage binary header, then the standard WKB geometry after it. See
+/// http://www.geopackage.org/spec/#gpb_format.
+#[cfg(not(target_arch = "wasm32"))]
+fn parse_gpkg_geometry(blob: &[u8]) -> Result<geo::Geometry<f64>> {
+    if blob.len() < 8 || &blob[0..2] != b"GP" {
+        bail!("Not a GeoPackage geometry");
+    }
+    let flags = blob[3];
+    let envelope_bytes = match (flags >> 1) & 0b111 {
+        0 => 0,
+        1 => 32,
+        2 | 3 => 48,
+        4 => 64,
+        x => bail!("Bad GeoPackage envelope type {}", x),
+    };
+    if blob.len() < 8 + envelope_bytes {
+        bail!("GeoPackage geometry ended in the envelope");
+    }
+    let mut wkb = Wkb {
+        bytes: &blob[8 + envelope_bytes..],
+        little_endian: true,
+    };
+    wkb.geometry()
+}
+
+/// A minimal reader for well-known binary, only handling polygons
+#[cfg(not(target_arch = "wasm32"))]
+struct Wkb<'a> {
+    bytes: &'a [u8],
+    little_endian: bool,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<'a> Wkb<'a> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N]> {
+        if self.bytes.len() < N {
+            bail!("WKB ended early");
+        }
+        let mut result = [0; N];
+        result.copy_from_slice(&self.bytes[..N]);
+        self.bytes = &self.bytes[N..];
+        Ok(result)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let bytes = self.take::<4>()?;
+        Ok(if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    }
+
+    fn f64(&mut self) -> Result<f64> {
+        let bytes = self.take::<8>()?;
+        Ok(if self.little_endian {
+            f64::from_le_bytes(bytes)
+        } else {
+            f64::from_be_bytes(bytes)
+        })
+    }
+
+    fn geometry(&mut self) -> Result<geo::Geometry<f64>> {
+        self.little_endian = self.take::<1>()?[0] == 1;
+        let code = self.u32()?;
+        // ISO WKB adds 1000 for Z, 2000 for M, 3000 for ZM. EWKB uses high bits instead.
+        let (geometry_type, dims) = match (code & 0xFFFF) % 1000 {
+            x if code & 0x8000_0000 != 0 && code & 0x4000_0000 != 0 => (x, 4),
+            x if code & 0xC000_0000 != 0 => (x, 3),
+            x => (
+                x,
+                match (code & 0xFFFF) / 1000 {
+                    0 => 2,
+                    1 | 2 => 3,
+                    _ => 4,
+                },
+            ),
+        };
+        match geometry_type {
+            3 => Ok(geo::Geometry::Polygon(self.polygon(dims)?)),
+            6 => {
+                let n = self.u32()?;
+                let mut polygons = Vec::new();
+                for _ in 0..n {
+                    if let geo::Geometry::Polygon(p) = self.geometry()? {
+                        polygons.push(p);
+                    } else {
+                        bail!("MultiPolygon contains something besides polygons");
+                    }
+                }
+                Ok(geo::Geometry::MultiPolygon(geo::MultiPolygon(polygons)))
+            }
+            x => bail!("Unsupported WKB geometry type {}", x),
+        }
+    }
+
+    fn polygon(&mut self, dims: usize) -> Result<geo::Polygon<f64>> {
+        let num_rings = self.u32()?;
+        let mut rings = Vec::new();
+        for _ in 0..num_rings {
+            let num_pts = self.u32()?;
+            let mut pts = Vec::new();
+            for _ in 0..num_pts {
+                let x = self.f64()?;
+                let y = self.f64()?;
+                for _ in 2..dims {
+                    self.f64()?;
+                }
+                pts.push(geo::Coordinate { x, y });
+            }
+            rings.push(geo::LineString(pts));
+        }
+        if rings.is_empty() {
+            bail!("Polygon with no rings");
+        }
+        let exterior = rings.remove(0);
+        Ok(geo::Polygon::new(exterior, rings))
+    }
+}
+
+fn read_shapefile(path: &str, columns: &[&str]) -> Result<Vec<RawArea>> {
+    // Without a .prj, assume the coordinates are fine
+    if let Ok(prj) = std::fs::read_to_string(Path::new(path).with_extension("prj")) {
+        if prj.trim_start().starts_with("PROJCS") {
+            bail!(
+                "{} uses a projected coordinate system; reproject it to EPSG:4326 first",
+                path
+            );
+        }
+    }
+
+    let mut results = Vec::new();
+    for (shape, record) in shapefile::read(path)? {
+        let rings = match shape {
+            shapefile::Shape::Polygon(p) => p
+                .rings()
+                .iter()
+                .map(|r| {
+                    (
+                        r.points().iter().map(|pt| (pt.x, pt.y)).collect(),
+                        matches!(r, shapefile::PolygonRing::Outer(_)),
+                    )
+                })
+                .collect(),
+            shapefile::Shape::PolygonZ(p) => p
+                .rings()
+                .iter()
+                .map(|r| {
+                    (
+                        r.points().iter().map(|pt| (pt.x, pt.y)).collect(),
+                        matches!(r, shapefile::PolygonRing::Outer(_)),
+                    )
+                })
+                .collect(),
+            _ => continue,
+        };
+        let polygon = polygon_from_rings(rings);
+        if polygon.is_empty() {
+            continue;
+        }
+
+        let mut properties = BTreeMap::new();
+        for column in columns {
+            use shapefile::dbase::FieldValue;
+            let value = match record.get(column) {
+                Some(FieldValue::Character(Some(x))) => x.clone(),
+                Some(FieldValue::Numeric(Some(x))) => x.to_string(),
+                Some(FieldValue::Float(Some(x))) => x.to_string(),
+                Some(FieldValue::Integer(x)) => x.to_string(),
+                Some(FieldValue::Double(x)) => x.to_string(),
+                Some(FieldValue::Currency(x)) => x.to_string(),
+                _ => continue,
+            };
+            properties.insert(column.to_string(), value);
+        }
+        results.push(RawArea {
+            polygons: polygon,
+            properties,
+        });
+    }
+    Ok(results)
+}
+
+/// Shapefiles list rings flatly. Each outer ring starts a new polygon, and the inner rings after
+/// it are its holes.
+fn polygon_from_rings(rings: Vec<(Vec<(f64, f64)>, bool)>) -> Vec<geo::Polygon<f64>> {
+    let mut polygons: Vec<geo::Polygon<f64>> = Vec::new();
+    for (pts, outer) in rings {
+        let ring: geo::LineString<f64> = pts.into();
+        if outer {
+            polygons.push(geo::Polygon::new(ring, Vec::new()));
+        } else if let Some(polygon) = polygons.last_mut() {
+            polygon.interiors_push(ring);
+        }
+    }
+    polygons
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+
+    /// A little-endian WKB polygon with one ring
+    fn wkb_polygon(code: u32, ring: &[(f64, f64)], dims: usize) -> Vec<u8> {
+        let mut wkb = vec![1];
+        wkb.extend_from_slice(&code.to_le_bytes());
+        wkb.extend_from_slice(&1_u32.to_le_bytes());
+        wkb.extend_from_slice(&(ring.len() as u32).to_le_bytes());
+        for (x, y) in ring {
+            wkb.extend_from_slice(&x.to_le_bytes());
+            wkb.extend_from_slice(&y.to_le_bytes());
+            for _ in 2..dims {
+                wkb.extend_from_slice(&99.0_f64.to_le_bytes());
+            }
+        }
+        wkb
+    }
+
+    /// Wraps WKB in a GeoPackage header with an envelope of the given type
+    fn gpkg(envelope_type: u8, wkb: &[u8]) -> Vec<u8> {
+        let mut blob = b"GP".to_vec();
+        // Version, then flags: little-endian header and the envelope type
+        blob.push(0);
+        blob.push(1 | (envelope_type << 1));
+        // SRS ID
+        blob.extend_from_slice(&4326_i32.to_le_bytes());
+        let envelope_values = match envelope_type {
+            0 => 0,
+            1 => 4,
+            2 | 3 => 6,
+            _ => 8,
+        };
+        for _ in 0..envelope_values {
+            blob.extend_from_slice(&(-1.0_f64).to_le_bytes());
+        }
+        blob.extend_from_slice(wkb);
+        blob
+    }
+
+    fn square(offset: f64) -> Vec<(f64, f64)> {
+        vec![
+            (offset, offset),
+            (offset + 1.0, offset),
+            (offset + 1.0, offset + 1.0),
+            (offset, offset + 1.0),
+            (offset, offset),
+        ]
+    }
+
+    fn expected_polygon(offset: f64) -> geo::Polygon<f64> {
+        geo::Polygon::new(
+            geo::LineString(
+                square(offset)
+                    .into_iter()
+                    .map(|(x, y)| geo::Coordinate { x, y })
+                    .collect(),
+            ),
+            Vec::new(),
+        )
+    }
+
+    #[test]
+    fn envelopes() {
+        let wkb = wkb_polygon(3, &square(0.0), 2);
+        for envelope_type in 0..=4 {
+            assert_eq!(
+                parse_gpkg_geometry(&gpkg(envelope_type, &wkb)).unwrap(),
+                geo::Geometry::Polygon(expected_polygon(0.0)),
+                "envelope type {}",
+                envelope_type
+            );
+        }
+
+        // Invalid envelope types, a missing magic number, and truncated blobs are errors
+        assert!(parse_gpkg_geometry(&gpkg(5, &wkb)).is_err());
+        assert!(parse_gpkg_geometry(&wkb).is_err());
+        assert!(parse_gpkg_geometry(&gpkg(4, &wkb)[0..40]).is_err());
+        let full = gpkg(0, &wkb);
+        assert!(parse_gpkg_geometry(&full[0..full.len() - 4]).is_err());
+    }
+
+    #[test]
+    fn polygons() {
+        // ISO codes for 3D and 4D coordinates; the extra values are skipped
+        for (code, dims) in vec![(3, 2), (1003, 3), (2003, 3), (3003, 4)] {
+            assert_eq!(
+                parse_gpkg_geometry(&gpkg(0, &wkb_polygon(code, &square(0.0), dims))).unwrap(),
+                geo::Geometry::Polygon(expected_polygon(0.0)),
+                "WKB code {}",
+                code
+            );
+        }
+
+        // Big-endian WKB
+        let mut wkb = vec![0];
+        wkb.extend_from_slice(&3_u32.to_be_bytes());
+        wkb.extend_from_slice(&1_u32.to_be_bytes());
+        wkb.extend_from_slice(&5_u32.to_be_bytes());
+        for (x, y) in square(0.0) {
+            wkb.extend_from_slice(&x.to_be_bytes());
+            wkb.extend_from_slice(&y.to_be_bytes());
+        }
+        assert_eq!(
+            parse_gpkg_geometry(&gpkg(0, &wkb)).unwrap(),
+            geo::Geometry::Polygon(expected_polygon(0.0))
+        );
+    }
+
+    #[test]
+    fn multipolygons() {
+        let mut wkb = vec![1];
+        wkb.extend_from_slice(&6_u32.to_le_bytes());
+        wkb.extend_from_slice(&2_u32.to_le_bytes());
+        wkb.extend(wkb_polygon(3, &square(0.0), 2));
+        wkb.extend(wkb_polygon(3, &square(5.0), 2));
+        assert_eq!(
+            parse_gpkg_geometry(&gpkg(1, &wkb)).unwrap(),
+            geo::Geometry::MultiPolygon(geo::MultiPolygon(vec![
+                expected_polygon(0.0),
+                expected_polygon(5.0)
+            ]))
+        );
+
+        // Only polygons are supported inside
+        let mut wkb = vec![1];
+        wkb.extend_from_slice(&6_u32.to_le_bytes());
+        wkb.extend_from_slice(&1_u32.to_le_bytes());
+        wkb.push(1);
+        wkb.extend_from_slice(&1_u32.to_le_bytes());
+        wkb.extend_from_slice(&0.0_f64.to_le_bytes());
+        wkb.extend_from_slice(&0.0_f64.to_le_bytes());
+        assert!(parse_gpkg_geometry(&gpkg(0, &wkb)).is_err());
+    }
+}
@@ -0,0 +1,552 @@
+//! Exchange travel demand with [MATSim](https://www.matsim.org). Plans files describe each person
+//! as a sequence of activities at some location, connected by legs using some mode. Activity
+//! coordinates must be in WGS84 (x = longitude, y = latitude); plans in a projected coordinate
+//! system need to be transformed first.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use anyhow::Result;
+
+use serde::{Deserialize, Serialize};
+
+use abstio::MapName;
+use abstutil::{prettyprint_usize, Timer};
+use geom::{Duration, LonLat, Time};
+use map_model::Map;
+use sim::{
+    ExternalPerson, ExternalTrip, ExternalTripEndpoint, OrigPersonID, Sim, TripMode, TripPurpose,
+};
+
+/// MATSim person IDs can be any string, but A/B Street only tracks numbers. IDs that're numbers
+/// are kept as `OrigPersonID(0, id)`. Everybody else is numbered `OrigPersonID(1, idx)`, and their
+/// original ID is remembered here. This is saved beside the scenario the people are imported into.
+#[derive(Default, Serialize, Deserialize)]
+pub struct PersonIDs {
+    names: BTreeMap<usize, String>,
+}
+
+impl PersonIDs {
+    fn path(map: &MapName, scenario_name: &str) -> String {
+        abstio::path(format!(
+            "system/{}/{}/matsim_ids/{}/{}.json",
+            map.city.country, map.city.city, map.map, scenario_name
+        ))
+    }
+
+    /// Nothing is saved if every ID is a number.
+    pub fn save(&self, map: &MapName, scenario_name: &str) {
+        if !self.names.is_empty() {
+            abstio::write_json(PersonIDs::path(map, scenario_name), self);
+        }
+    }
+
+    /// Returns nothing if the scenario wasn't imported from MATSim or every ID was a number.
+    pub fn load(map: &MapName, scenario_name: &str) -> PersonIDs {
+        abstio::maybe_read_json(PersonIDs::path(map, scenario_name), &mut Timer::throwaway())
+            .unwrap_or_default()
+    }
+
+    fn lookup(&mut self, id: &str) -> OrigPersonID {
+        if let Ok(x) = id.parse::<usize>() {
+            return OrigPersonID(0, x);
+        }
+        let idx = self.names.len();
+        self.names.insert(idx, id.to_string());
+        OrigPersonID(1, idx)
+    }
+
+    /// The original MATSim ID
+    pub fn describe(&self, id: OrigPersonID) -> String {
+        if id.0 == 1 {
+            if let Some(name) = self.names.get(&id.1) {
+                return name.clone();
+            }
+        }
+        id.1.to_string()
+    }
+}
+
+/// Reads the selected plan of every person from a MATSim plans.xml file (both the v4 and v6 DTDs
+/// work). Consecutive activities become trips, departing at the end time of the first activity.
+/// The endpoints still need to be snapped to buildings with `ExternalPerson::import`. People whose
+/// plans can't be expressed are skipped, and so are legs with unknown modes.
+pub fn read_plans(path: &str, timer: &mut Timer) -> Result<(Vec<ExternalPerson>, PersonIDs)> {
+    timer.start(format!("read {}", path));
+    let bytes = abstio::slurp_file(path)?;
+    let raw_string = std::str::from_utf8(&bytes)?;
+    let tree = roxmltree::Document::parse(raw_string)?;
+    timer.stop(format!("read {}", path));
+
+    let mut people = Vec::new();
+    let mut ids = PersonIDs::default();
+    let mut skipped = 0;
+    for person in tree
+        .root_element()
+        .children()
+        .filter(|n| n.has_tag_name("person"))
+    {
+        let orig_id = person.attribute("id").map(|id| ids.lookup(id));
+        match parse_person(person, orig_id) {
+            Ok(Some(p)) => {
+                people.push(p);
+            }
+            Ok(None) => {}
+            Err(err) => {
+                warn!(
+                    "Skipping MATSim person {}: {}",
+                    person.attribute("id").unwrap_or("???"),
+                    err
+                );
+                skipped += 1;
+            }
+        }
+    }
+    info!(
+        "Read {} people from {}, skipped {}",
+        prettyprint_usize(people.len()),
+        path,
+        prettyprint_usize(skipped)
+    );
+    Ok((people, ids))
+}
+
+struct Activity {
+    purpose: TripPurpose,
+    pos: LonLat,
+    start_time: Option<Time>,
+    end_time: Option<Time>,
+    max_dur: Option<Duration>,
+}
+
+fn parse_person(
+    person: roxmltree::Node,
+    orig_id: Option<OrigPersonID>,
+) -> Result<Option<ExternalPerson>> {
+    let plans: Vec<roxmltree::Node> = person
+        .children()
+        .filter(|n| n.has_tag_name("plan"))
+        .collect();
+    let plan = if let Some(plan) = plans
+        .iter()
+        .find(|p| p.attribute("selected") == Some("yes"))
+        .or_else(|| plans.get(0))
+    {
+        plan
+    } else {
+        return Ok(None);
+    };
+
+    let mut trips = Vec::new();
+    let mut prev_activity: Option<Activity> = None;
+    let mut modes = Vec::new();
+    let mut num_legs = 0;
+    let mut leg_departure = None;
+    for element in plan.children().filter(|n| n.is_element()) {
+        match element.tag_name().name() {
+            "act" | "activity" => {
+                // Routed plans have stage activities like "pt interaction" between legs of one
+                // trip
+                if element
+                    .attribute("type")
+                    .map(|t| t.ends_with(" interaction"))
+                    .unwrap_or(false)
+                {
+                    continue;
+                }
+                let activity = parse_activity(element)?;
+                if num_legs > 0 && modes.is_empty() {
+                    // None of the legs were understood, so skip the whole trip
+                    prev_activity = None;
+                }
+                if let Some(prev) = prev_activity.take() {
+                    let departure = prev
+                        .end_time
+                        .or(leg_departure)
+                        .or_else(|| Some(prev.start_time? + prev.max_dur?))
+                        .ok_or_else(|| anyhow!("can't figure out when a leg departs"))?;
+                    trips.push(ExternalTrip {
+                        departure,
+                        origin: ExternalTripEndpoint::Position(prev.pos),
+                        destination: ExternalTripEndpoint::Position(activity.pos),
+                        mode: modes
+                            .drain(..)
+                            .max_by_key(|m| mode_rank(*m))
+                            .unwrap_or(TripMode::Walk),
+                        purpose: activity.purpose,
+                    });
+                }
+                modes.clear();
+                num_legs = 0;
+                leg_departure = None;
+                prev_activity = Some(activity);
+            }
+            "leg" => {
+                num_legs += 1;
+                let mode = element.attribute("mode").unwrap_or("walk");
+                if let Some(mode) = parse_mode(mode) {
+                    modes.push(mode);
+                } else {
+                    warn!(
+                        "Skipping a leg of MATSim person {} with unknown mode {}",
+                        person.attribute("id").unwrap_or("???"),
+                        mode
+                    );
+                }
+                if leg_departure.is_none() {
+                    leg_departure = element
+                        .attribute("dep_time")
+                        .and_then(|t| Time::parse(t).ok());
+                }
+            }
+            _ => {}
+        }
+    }
+    if trips.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(ExternalPerson { orig_id, trips }))
+}
+
+fn parse_activity(element: roxmltree::Node) -> Result<Activity> {
+    let coord = |key: &str| -> Result<f64> {
+        Ok(element
+            .attribute(key)
+            .ok_or_else(|| anyhow!("activity without coordinates"))?
+            .parse::<f64>()?)
+    };
+    let time = |key: &str| element.attribute(key).map(Time::parse).transpose();
+    Ok(Activity {
+        purpose: parse_purpose(element.attribute("type").unwrap_or("other")),
+        pos: LonLat::new(coord("x")?, coord("y")?),
+        start_time: time("start_time")?,
+        end_time: time("end_time")?,
+        max_dur: time("max_dur")?.map(|t| t - Time::START_OF_DAY),
+    })
+}
+
+fn parse_mode(mode: &str) -> Option<TripMode> {
+    Some(match mode {
+        "car" | "ride" | "freight" => TripMode::Drive,
+        "bike" | "bicycle" => TripMode::Bike,
+        "pt" | "bus" | "tram" | "rail" | "train" | "subway" | "ferry" => TripMode::Transit,
+        "walk" | "transit_walk" | "non_network_walk" | "access_walk" | "egress_walk" => {
+            TripMode::Walk
+        }
+        _ => return None,
+    })
+}
+
+/// A trip made of several legs is classified by the most "significant" one.
+fn mode_rank(mode: TripMode) -> usize {
+    match mode {
+        TripMode::Walk => 0,
+        TripMode::Bike => 1,
+        TripMode::Transit => 2,
+        TripMode::Drive => 3,
+    }
+}
+
+/// Activity types are free-form, and are often suffixed with a typical duration, like
+/// "work_28800". Just look at the beginning.
+fn parse_purpose(activity: &str) -> TripPurpose {
+    let activity = activity.to_lowercase();
+    for (prefix, purpose) in vec![
+        ("home", TripPurpose::Home),
+        ("work", TripPurpose::Work),
+        ("education", TripPurpose::School),
+        ("school", TripPurpose::School),
+        ("university", TripPurpose::School),
+        ("escort", TripPurpose::Escort),
+        ("shop", TripPurpose::Shopping),
+        ("meal", TripPurpose::Meal),
+        ("eat", TripPurpose::Meal),
+        ("social", TripPurpose::Social),
+        ("visit", TripPurpose::Social),
+        ("leisure", TripPurpose::Recreation),
+        ("recreation", TripPurpose::Recreation),
+        ("medical", TripPurpose::Medical),
+        ("park_and_ride", TripPurpose::ParkAndRideTransfer),
+    ] {
+        if activity.starts_with(prefix) {
+            return purpose;
+        }
+    }
+    TripPurpose::PersonalBusiness
+}
+
+fn activity_type(purpose: TripPurpose) -> &'static str {
+    match purpose {
+        TripPurpose::Home => "home",
+        TripPurpose::Work => "work",
+        TripPurpose::School => "education",
+        TripPurpose::Escort => "escort",
+        TripPurpose::PersonalBusiness => "personal_business",
+        TripPurpose::Shopping => "shopping",
+        TripPurpose::Meal => "meal",
+        TripPurpose::Social => "social",
+        TripPurpose::Recreation => "leisure",
+        TripPurpose::Medical => "medical",
+        TripPurpose::ParkAndRideTransfer => "park_and_ride",
+    }
+}
+
+fn leg_mode(mode: TripMode) -> &'static str {
+    match mode {
+        TripMode::Walk => "walk",
+        TripMode::Bike => "bike",
+        TripMode::Transit => "pt",
+        TripMode::Drive => "car",
+    }
+}
+
+/// Express the trips finished so far in a simulation as a MATSim experienced plans file (using the
+/// v6 DTD). Only successful trips are included, with their actual departure and travel times.
+/// People keep their original ID if they have one, using `ids` for IDs that weren't numbers. The
+/// first activity of every person is assumed to be at home.
+pub fn write_experienced_plans(sim: &Sim, map: &Map, ids: &PersonIDs) -> Result<String> {
+    let gps_bounds = map.get_gps_bounds();
+    let analytics = sim.get_analytics();
+    let mut finished = BTreeMap::new();
+    for (end_time, trip, _, maybe_duration) in &analytics.finished_trips {
+        if maybe_duration.is_some() {
+            finished.insert(*trip, *end_time);
+        }
+    }
+
+    let mut people = Vec::new();
+    for person in sim.get_all_people() {
+        let trips: Vec<ExperiencedTrip> = person
+            .trips
+            .iter()
+            .filter(|t| finished.contains_key(*t))
+            .map(|trip| {
+                let info = sim.trip_info(*trip);
+                ExperiencedTrip {
+                    purpose: info.purpose,
+                    mode: info.mode,
+                    from: info.start.pt(map).to_gps(gps_bounds),
+                    to: info.end.pt(map).to_gps(gps_bounds),
+                    depart: analytics.started_trips[trip],
+                    arrive: finished[trip],
+                }
+            })
+            .collect();
+        if trips.is_empty() {
+            continue;
+        }
+        let id = person
+            .orig_id
+            .map(|id| ids.describe(id))
+            .unwrap_or_else(|| person.id.0.to_string());
+        people.push((id, trips));
+    }
+    write_population(people)
+}
+
+struct ExperiencedTrip {
+    purpose: TripPurpose,
+    mode: TripMode,
+    from: LonLat,
+    to: LonLat,
+    depart: Time,
+    arrive: Time,
+}
+
+fn write_population(people: Vec<(String, Vec<ExperiencedTrip>)>) -> Result<String> {
+    let mut out = String::new();
+    writeln!(out, r#"<?xml version="1.0" encoding="utf-8"?>"#)?;
+    writeln!(
+        out,
+        r#"<!DOCTYPE population SYSTEM "http://www.matsim.org/files/dtd/population_v6.dtd">"#
+    )?;
+    writeln!(out, "<population>")?;
+    for (id, trips) in people {
+        writeln!(out, r#"    <person id="{}">"#, id)?;
+        writeln!(out, r#"        <plan selected="yes">"#)?;
+
+        let mut prev_purpose = TripPurpose::Home;
+        let mut prev_arrival: Option<Time> = None;
+        let mut last_pos = None;
+        for trip in trips {
+            write!(
+                out,
+                r#"            <activity type="{}" x="{}" y="{}""#,
+                activity_type(prev_purpose),
+                trip.from.x(),
+                trip.from.y()
+            )?;
+            if let Some(t) = prev_arrival {
+                write!(out, r#" start_time="{}""#, hms(t))?;
+            }
+            writeln!(out, r#" end_time="{}"/>"#, hms(trip.depart))?;
+            writeln!(
+                out,
+                r#"            <leg mode="{}" dep_time="{}" trav_time="{}"/>"#,
+                leg_mode(trip.mode),
+                hms(trip.depart),
+                hms(Time::START_OF_DAY + (trip.arrive - trip.depart))
+            )?;
+
+            prev_purpose = trip.purpose;
+            prev_arrival = Some(trip.arrive);
+            last_pos = Some(trip.to);
+        }
+        if let (Some(pos), Some(t)) = (last_pos, prev_arrival) {
+            writeln!(
+                out,
+                r#"            <activity type="{}" x="{}" y="{}" start_time="{}"/>"#,
+                activity_type(prev_purpose),
+                pos.x(),
+                pos.y(),
+                hms(t)
+            )?;
+        }
+        writeln!(out, "        </plan>")?;
+        writeln!(out, "    </person>")?;
+    }
+    writeln!(out, "</population>")?;
+    Ok(out)
+}
+
+/// MATSim times are always hh:mm:ss, and may exceed 24 hours.
+fn hms(t: Time) -> String {
+    let total = t.inner_seconds().round() as usize;
+    format!(
+        "{:02}:{:02}:{:02}",
+        total / 3600,
+        (total % 3600) / 60,
+        total % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        // Whole seconds, since plans don't keep anything finer
+        let hour = |h: f64| Time::START_OF_DAY + Duration::seconds(h * 3600.0);
+        let home = LonLat::new(-122.3123456789, 47.6412345678);
+        let work = LonLat::new(-122.30001, 47.65002);
+        let shop = LonLat::new(-122.29, 47.66);
+        let people = vec![
+            (
+                "17".to_string(),
+                vec![
+                    ExperiencedTrip {
+                        purpose: TripPurpose::Work,
+                        mode: TripMode::Bike,
+                        from: home,
+                        to: work,
+                        depart: hour(8.0),
+                        arrive: hour(8.5),
+                    },
+                    ExperiencedTrip {
+                        purpose: TripPurpose::Shopping,
+                        mode: TripMode::Transit,
+                        from: work,
+                        to: shop,
+                        depart: hour(17.25),
+                        arrive: hour(17.75),
+                    },
+                ],
+            ),
+            (
+                "alice".to_string(),
+                vec![ExperiencedTrip {
+                    purpose: TripPurpose::Recreation,
+                    mode: TripMode::Drive,
+                    from: shop,
+                    to: home,
+                    depart: hour(25.0),
+                    arrive: hour(25.5),
+                }],
+            ),
+        ];
+
+        let path = std::env::temp_dir()
+            .join(format!("matsim_plans_{}.xml", std::process::id()))
+            .display()
+            .to_string();
+        std::fs::write(&path, write_population(people).unwrap()).unwrap();
+        let (people, ids) = read_plans(&path, &mut Timer::throwaway()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(people.len(), 2);
+        assert_eq!(people[0].orig_id, Some(OrigPersonID(0, 17)));
+        assert_eq!(ids.describe(people[1].orig_id.unwrap()), "alice");
+
+        let summarize = |person: &ExternalPerson| -> Vec<_> {
+            person
+                .trips
+                .iter()
+                .map(|trip| {
+                    let pos = |endpoint: &ExternalTripEndpoint| match endpoint {
+                        ExternalTripEndpoint::Position(pos) => *pos,
+                        ExternalTripEndpoint::TripEndpoint(_) => unreachable!(),
+                    };
+                    (
+                        trip.departure,
+                        pos(&trip.origin),
+                        pos(&trip.destination),
+                        trip.mode,
+                        trip.purpose,
+                    )
+                })
+                .collect()
+        };
+        assert_eq!(
+            summarize(&people[0]),
+            vec![
+                (hour(8.0), home, work, TripMode::Bike, TripPurpose::Work),
+                (
+                    hour(17.25),
+                    work,
+                    shop,
+                    TripMode::Transit,
+                    TripPurpose::Shopping
+                ),
+            ]
+        );
+        assert_eq!(
+            summarize(&people[1]),
+            vec![(
+                hour(25.0),
+                shop,
+                home,
+                TripMode::Drive,
+                TripPurpose::Recreation
+            )]
+        );
+    }
+
+    #[test]
+    fn purposes_and_modes() {
+        for purpose in vec![
+            TripPurpose::Home,
+            TripPurpose::Work,
+            TripPurpose::School,
+            TripPurpose::Escort,
+            TripPurpose::PersonalBusiness,
+            TripPurpose::Shopping,
+            TripPurpose::Meal,
+            TripPurpose::Social,
+            TripPurpose::Recreation,
+            TripPurpose::Medical,
+            TripPurpose::ParkAndRideTransfer,
+        ] {
+            assert_eq!(parse_purpose(activity_type(purpose)), purpose);
+        }
+        assert_eq!(parse_purpose("work_28800"), TripPurpose::Work);
+        assert_eq!(parse_purpose("Home"), TripPurpose::Home);
+        assert_eq!(parse_purpose("errands"), TripPurpose::PersonalBusiness);
+
+        for mode in TripMode::all() {
+            assert_eq!(parse_mode(leg_mode(mode)), Some(mode));
+        }
+        assert_eq!(parse_mode("teleport"), None);
+    }
+}
@@ -4,16 +4,21 @@
 //!
 //! Maybe someday, we'll merge the two approaches, and make the first generate DesireLines as an
 //! intermediate step.
+//!
+//! `DesireLine`s only describe commuters. More general OD matrices, like the ones transport models
+//! produce, can be expressed as `ZoneFlow`s instead, with any trip purpose and a departure time
+//! profile.
 
 use std::collections::HashMap;
 
 use rand::seq::SliceRandom;
 use rand::Rng;
 use rand_xorshift::XorShiftRng;
+use serde::{Deserialize, Serialize};
 
 use abstutil::Timer;
 use geom::{Duration, Polygon, Time};
-use map_model::{BuildingID, BuildingType, Map};
+use map_model::{AmenityType, Building, BuildingID, BuildingType, Map};
 use sim::{IndividTrip, MapBorders, PersonSpec, TripEndpoint, TripMode, TripPurpose};
 
 /// This describes some number of commuters living in some named zone, working in another (or the
@@ -120,6 +125,165 @@ pub fn disaggregate(
     people
 }
 
+/// Some number of trips from one zone to another (or the same zone), using one mode. Matrices
+/// often have fractional counts, especially after splitting by mode; these are rounded randomly,
+/// so the expected total is preserved.
+#[derive(Debug)]
+pub struct ZoneFlow {
+    pub origin_zone: String,
+    pub destination_zone: String,
+    pub mode: TripMode,
+    /// What people do at the origin. This is usually `Home`.
+    pub origin_purpose: TripPurpose,
+    /// What people do at the destination. This determines which buildings are likely.
+    pub destination_purpose: TripPurpose,
+    pub number_trips: f64,
+}
+
+pub struct FlowOptions {
+    /// When do people leave the origin?
+    pub departure_profile: DepartureProfile,
+    /// If set, people return to the same place in the origin zone after spending this long at the
+    /// destination. Otherwise, each flow just produces one-way trips.
+    pub return_after: Option<NormalDistribution>,
+}
+
+/// Generates people from general OD flows. Like `disaggregate`, each trip picks a specific
+/// building in the zone or a border, depending on how much of the zone overlaps the map. Buildings
+/// are weighted by how well they match the purpose at each end: homes by number of residents,
+/// workplaces by estimated workers, and other purposes by matching amenities and OSM building
+/// types.
+pub fn disaggregate_flows(
+    map: &Map,
+    zones: HashMap<String, Polygon>,
+    flows: Vec<ZoneFlow>,
+    opts: FlowOptions,
+    rng: &mut XorShiftRng,
+    timer: &mut Timer,
+) -> Vec<PersonSpec> {
+    timer.start("match zones");
+    let zones = create_zones(map, zones);
+    timer.stop("match zones");
+
+    // Per (zone, purpose), the weighted buildings. Many flows share these.
+    let mut candidates: HashMap<(String, usize), Vec<(BuildingID, usize)>> = HashMap::new();
+
+    let mut people = Vec::new();
+    timer.start_iter("create people from flows", flows.len());
+    for flow in flows {
+        timer.next();
+        if !zones.contains_key(&flow.origin_zone) || !zones.contains_key(&flow.destination_zone) {
+            continue;
+        }
+        let origin_zone = &zones[&flow.origin_zone];
+        let destination_zone = &zones[&flow.destination_zone];
+        for (zone_name, zone, purpose) in vec![
+            (&flow.origin_zone, origin_zone, flow.origin_purpose),
+            (
+                &flow.destination_zone,
+                destination_zone,
+                flow.destination_purpose,
+            ),
+        ] {
+            candidates
+                .entry((zone_name.clone(), purpose as usize))
+                .or_insert_with(|| zone.buildings_for_purpose(purpose, map));
+        }
+        let origins = &candidates[&(flow.origin_zone.clone(), flow.origin_purpose as usize)];
+        let destinations = &candidates[&(
+            flow.destination_zone.clone(),
+            flow.destination_purpose as usize,
+        )];
+
+        for _ in 0..round_randomly(flow.number_trips, rng) {
+            if let (Some((leave_origin, goto_origin)), Some((leave_dst, goto_dst))) = (
+                origin_zone.pick(origins, flow.mode, map, rng),
+                destination_zone.pick(destinations, flow.mode, map, rng),
+            ) {
+                let depart = opts.departure_profile.sample(rng);
+                let mut trips = vec![IndividTrip::new(
+                    depart,
+                    flow.destination_purpose,
+                    leave_origin,
+                    goto_dst,
+                    flow.mode,
+                )];
+                if let Some(ref duration) = opts.return_after {
+                    trips.push(IndividTrip::new(
+                        depart + duration.sample(rng).max(Duration::minutes(1)),
+                        flow.origin_purpose,
+                        leave_dst,
+                        goto_origin,
+                        flow.mode,
+                    ));
+                }
+                people.push(PersonSpec {
+                    orig_id: None,
+                    trips,
+                });
+            }
+        }
+    }
+    people
+}
+
+/// Rounds a fractional number of trips up or down randomly, so that the expected value is
+/// preserved. Negative numbers become 0.
+fn round_randomly(x: f64, rng: &mut XorShiftRng) -> usize {
+    let x = x.max(0.0);
+    let mut result = x.floor() as usize;
+    if rng.gen_bool(x.fract()) {
+        result += 1;
+    }
+    result
+}
+
+/// The relative number of trips departing in each hour of the day
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DepartureProfile {
+    /// 24 weights, starting from midnight. They don't need to sum to anything.
+    pub hourly_weights: Vec<f64>,
+}
+
+impl DepartureProfile {
+    /// A typical morning commute, peaking between 7 and 9am
+    pub fn morning_peak() -> DepartureProfile {
+        DepartureProfile {
+            hourly_weights: vec![
+                0.2, 0.1, 0.1, 0.1, 0.3, 1.0, 3.0, 8.0, 9.0, 5.0, 3.0, 2.5, 2.5, 2.5, 2.5, 2.5,
+                2.5, 2.5, 2.0, 1.5, 1.0, 0.8, 0.5, 0.3,
+            ],
+        }
+    }
+
+    /// The same share of trips in every hour from 7am to 7pm
+    pub fn daytime() -> DepartureProfile {
+        let mut hourly_weights = vec![0.0; 24];
+        for weight in &mut hourly_weights[7..19] {
+            *weight = 1.0;
+        }
+        DepartureProfile { hourly_weights }
+    }
+
+    /// Picks an hour by weight, then a uniformly random time within it.
+    pub fn sample(&self, rng: &mut XorShiftRng) -> Time {
+        let hours: Vec<(usize, f64)> = self
+            .hourly_weights
+            .iter()
+            .cloned()
+            .enumerate()
+            .take(24)
+            .filter(|(_, weight)| *weight > 0.0)
+            .collect();
+        let hour = match hours.choose_weighted(rng, |(_, weight)| *weight) {
+            Ok((hour, _)) => *hour,
+            // No positive weights; fall back to the default commute time
+            Err(_) => 8,
+        };
+        Time::START_OF_DAY + Duration::hours(hour) + Duration::seconds(rng.gen_range(0.0..3600.0))
+    }
+}
+
 struct Zone {
     polygon: Polygon,
     pct_overlap: f64,
@@ -128,6 +292,8 @@ struct Zone {
     // and match more people to larger homes/stores.
     homes: Vec<(BuildingID, usize)>,
     workplaces: Vec<(BuildingID, usize)>,
+    /// Every building in the zone, for other trip purposes
+    buildings: Vec<BuildingID>,
     borders: MapBorders,
 }
 
@@ -154,6 +320,7 @@ fn create_zones(map: &Map, input: HashMap<String, Polygon>) -> HashMap<String, Z
                 pct_overlap,
                 homes: Vec::new(),
                 workplaces: Vec::new(),
+                buildings: Vec::new(),
                 borders: all_borders.clone(),
             },
         );
@@ -167,6 +334,7 @@ fn create_zones(map: &Map, input: HashMap<String, Polygon>) -> HashMap<String, Z
             .iter_mut()
             .find(|(_, z)| z.polygon.contains_pt(center))
         {
+            zone.buildings.push(b.id);
             match b.bldg_type {
                 // The current heuristics for num_residents sometimes assign 0 people to a
                 // building. We never want that, so just scale them all up.
@@ -215,11 +383,7 @@ impl Zone {
         map: &Map,
         rng: &mut XorShiftRng,
     ) -> Option<(TripEndpoint, TripEndpoint)> {
-        if rng.gen_bool(self.pct_overlap) && !self.homes.is_empty() {
-            let b = self.homes.choose_weighted(rng, |(_, n)| *n).unwrap().0;
-            return Some((TripEndpoint::Bldg(b), TripEndpoint::Bldg(b)));
-        }
-        self.pick_borders(mode, map, rng)
+        self.pick(&self.homes, mode, map, rng)
     }
 
     /// Returns endpoints to (leave work, goto work). These're usually the same, except in some
@@ -230,13 +394,76 @@ impl Zone {
         map: &Map,
         rng: &mut XorShiftRng,
     ) -> Option<(TripEndpoint, TripEndpoint)> {
-        if rng.gen_bool(self.pct_overlap) && !self.workplaces.is_empty() {
-            let b = self.workplaces.choose_weighted(rng, |(_, n)| *n).unwrap().0;
-            return Some((TripEndpoint::Bldg(b), TripEndpoint::Bldg(b)));
+        self.pick(&self.workplaces, mode, map, rng)
+    }
+
+    /// Returns endpoints to (leave, goto) for one of the weighted buildings, or a border. If
+    /// there are no buildings or none of them have any weight, always uses a border.
+    fn pick(
+        &self,
+        buildings: &[(BuildingID, usize)],
+        mode: TripMode,
+        map: &Map,
+        rng: &mut XorShiftRng,
+    ) -> Option<(TripEndpoint, TripEndpoint)> {
+        if rng.gen_bool(self.pct_overlap) {
+            if let Ok((b, _)) = buildings.choose_weighted(rng, |(_, n)| *n) {
+                return Some((TripEndpoint::Bldg(*b), TripEndpoint::Bldg(*b)));
+            }
         }
         self.pick_borders(mode, map, rng)
     }
 
+    /// Weights every building in the zone by how likely it is to be visited for some purpose. If
+    /// nothing specifically matches, falls back to anywhere with amenities, then any building.
+    fn buildings_for_purpose(&self, purpose: TripPurpose, map: &Map) -> Vec<(BuildingID, usize)> {
+        match purpose {
+            TripPurpose::Home => return self.homes.clone(),
+            TripPurpose::Work if !self.workplaces.is_empty() => {
+                return self
+                    .buildings
+                    .iter()
+                    .filter_map(|b| {
+                        let weight = work_weight(map.get_b(*b));
+                        if weight > 0 {
+                            Some((*b, weight))
+                        } else {
+                            None
+                        }
+                    })
+                    .collect();
+            }
+            _ => {}
+        }
+
+        let (amenities, building_types) = purpose_categories(purpose);
+        let mut results = Vec::new();
+        for id in &self.buildings {
+            let b = map.get_b(*id);
+            let mut weight = amenities
+                .iter()
+                .map(|category| {
+                    b.amenities
+                        .iter()
+                        .filter(|a| AmenityType::categorize(&a.amenity_type) == Some(*category))
+                        .count()
+                })
+                .sum::<usize>();
+            // Land use, from the OSM building type
+            if b.osm_tags
+                .get("building")
+                .map(|x| building_types.contains(&x.as_str()))
+                .unwrap_or(false)
+            {
+                weight += 1;
+            }
+            if weight > 0 {
+                results.push((*id, weight));
+            }
+        }
+        with_fallbacks(results, &self.workplaces, &self.buildings)
+    }
+
     fn pick_borders(
         &self,
         mode: TripMode,
@@ -260,7 +487,25 @@ impl Zone {
     }
 }
 
+/// If no building specifically matches a purpose, use anywhere with amenities, then any building.
+fn with_fallbacks(
+    matches: Vec<(BuildingID, usize)>,
+    workplaces: &[(BuildingID, usize)],
+    buildings: &[BuildingID],
+) -> Vec<(BuildingID, usize)> {
+    if !matches.is_empty() {
+        return matches;
+    }
+    let workplaces: Vec<(BuildingID, usize)> =
+        workplaces.iter().filter(|(_, n)| *n > 0).cloned().collect();
+    if !workplaces.is_empty() {
+        return workplaces;
+    }
+    buildings.iter().map(|b| (*b, 1)).collect()
+}
+
 /// A normal distribution of Durations.
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct NormalDistribution {
     pub mean: Duration,
     pub std_deviation: Duration,
@@ -287,3 +532,158 @@ impl NormalDistribution {
         )
     }
 }
+
+/// Roughly how many people work in a building
+fn work_weight(b: &Building) -> usize {
+    let workers = match b.bldg_type {
+        BuildingType::ResidentialCommercial(_, workers) | BuildingType::Commercial(workers) => {
+            workers
+        }
+        BuildingType::Residential { .. } | BuildingType::Empty => 0,
+    };
+    let office = b.osm_tags.is_any(
+        "building",
+        vec!["office", "commercial", "industrial", "retail"],
+    );
+    workers + b.amenities.len() + if office { 1 } else { 0 }
+}
+
+/// Which amenities and OSM building types serve each trip purpose
+fn purpose_categories(purpose: TripPurpose) -> (Vec<AmenityType>, Vec<&'static str>) {
+    match purpose {
+        TripPurpose::Home => (Vec::new(), vec!["house", "apartments", "residential"]),
+        TripPurpose::Work => (Vec::new(), vec!["office", "commercial", "industrial"]),
+        TripPurpose::School | TripPurpose::Escort => (
+            vec![
+                AmenityType::Childcare,
+                AmenityType::School,
+                AmenityType::University,
+            ],
+            vec!["school", "kindergarten", "university", "college"],
+        ),
+        TripPurpose::PersonalBusiness => (
+            vec![
+                AmenityType::Bank,
+                AmenityType::Beauty,
+                AmenityType::CarRepair,
+                AmenityType::Laundry,
+                AmenityType::PostOffice,
+            ],
+            vec!["commercial", "civic", "public"],
+        ),
+        TripPurpose::Shopping => (
+            vec![
+                AmenityType::ConvenienceStore,
+                AmenityType::Shopping,
+                AmenityType::Supermarket,
+            ],
+            vec!["retail", "supermarket", "kiosk"],
+        ),
+        TripPurpose::Meal => (
+            vec![AmenityType::Cafe, AmenityType::FastFood, AmenityType::Food],
+            Vec::new(),
+        ),
+        TripPurpose::Social => (
+            vec![
+                AmenityType::Bar,
+                AmenityType::Culture,
+                AmenityType::Religious,
+            ],
+            vec!["church", "mosque", "temple", "synagogue"],
+        ),
+        TripPurpose::Recreation => (
+            vec![
+                AmenityType::Culture,
+                AmenityType::Exercise,
+                AmenityType::GreenSpace,
+                AmenityType::Playground,
+                AmenityType::Pool,
+                AmenityType::Tourism,
+            ],
+            vec!["sports_centre", "stadium"],
+        ),
+        TripPurpose::Medical => (vec![AmenityType::Medical], vec!["hospital"]),
+        TripPurpose::ParkAndRideTransfer => (Vec::new(), vec!["parking"]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+
+    #[test]
+    fn rounding_flows() {
+        let mut rng = XorShiftRng::seed_from_u64(42);
+        for _ in 0..100 {
+            assert_eq!(round_randomly(3.0, &mut rng), 3);
+            assert_eq!(round_randomly(0.0, &mut rng), 0);
+            assert_eq!(round_randomly(-2.5, &mut rng), 0);
+            let x = round_randomly(2.25, &mut rng);
+            assert!(x == 2 || x == 3);
+        }
+
+        // The expected total is preserved
+        let samples = 10_000;
+        let total: usize = (0..samples).map(|_| round_randomly(2.25, &mut rng)).sum();
+        let mean = total as f64 / samples as f64;
+        assert!((mean - 2.25).abs() < 0.05, "mean is {}", mean);
+    }
+
+    #[test]
+    fn departure_sampling() {
+        let mut rng = XorShiftRng::seed_from_u64(42);
+        let hour = |h: usize| Time::START_OF_DAY + Duration::hours(h);
+
+        let daytime = DepartureProfile::daytime();
+        for _ in 0..1000 {
+            let t = daytime.sample(&mut rng);
+            assert!(t >= hour(7) && t < hour(19), "{} is outside the daytime", t);
+        }
+
+        // Only one hour has weight. Negative weights and anything past 24 hours are ignored.
+        let mut hourly_weights = vec![0.0; 30];
+        hourly_weights[13] = 2.0;
+        hourly_weights[5] = -1.0;
+        hourly_weights[27] = 10.0;
+        let one_hour = DepartureProfile { hourly_weights };
+        for _ in 0..1000 {
+            let t = one_hour.sample(&mut rng);
+            assert!(t >= hour(13) && t < hour(14), "{} isn't in the 1pm hour", t);
+        }
+
+        // Without any weight, fall back to 8am
+        let empty = DepartureProfile {
+            hourly_weights: vec![0.0; 24],
+        };
+        for _ in 0..100 {
+            let t = empty.sample(&mut rng);
+            assert!(t >= hour(8) && t < hour(9), "{} isn't in the 8am hour", t);
+        }
+    }
+
+    #[test]
+    fn building_fallbacks() {
+        let (b1, b2, b3) = (BuildingID(1), BuildingID(2), BuildingID(3));
+        let workplaces = vec![(b1, 0), (b2, 3)];
+        let buildings = vec![b1, b2, b3];
+
+        // Specific matches win
+        assert_eq!(
+            with_fallbacks(vec![(b3, 2)], &workplaces, &buildings),
+            vec![(b3, 2)]
+        );
+        // Otherwise anywhere with amenities
+        assert_eq!(
+            with_fallbacks(Vec::new(), &workplaces, &buildings),
+            vec![(b2, 3)]
+        );
+        // Otherwise any building in the zone, equally likely
+        assert_eq!(
+            with_fallbacks(Vec::new(), &[(b1, 0)], &buildings),
+            vec![(b1, 1), (b2, 1), (b3, 1)]
+        );
+        assert!(with_fallbacks(Vec::new(), &[], &[]).is_empty());
+    }
+}
@@ -10,7 +10,8 @@ use map_model::{
 };
 
 use crate::{
-    AgentID, AgentType, AlertLocation, CarID, Event, ParkingSpot, TripID, TripMode, TripPhaseType,
+    AgentID, AgentType, AlertLocation, CarID, Event, IncidentID, ParkingSpot, TripID, TripMode,
+    TripPhaseType,
 };
 
 /// As a simulation runs, different pieces emit Events. The Analytics object listens to these,
@@ -53,11 +54,24 @@ pub struct Analytics {
     // TODO Transit riders aren't represented here yet, just the vehicle they're riding.
     /// Only for traffic signals. The u8 is the movement index from a CompressedMovementID.
     pub intersection_delays: BTreeMap<IntersectionID, Vec<(u8, Time, Duration, AgentType)>>,
+    /// At every type of intersection, each time a pedestrian waited to use a crosswalk, and for
+    /// how long. The time is when they could finally start crossing.
+    pub crosswalk_delays: BTreeMap<TurnID, Vec<(Time, Duration)>>,
+    /// How many pedestrians have started walking along each crosswalk, whether they waited or not
+    pub crosswalk_crossings: BTreeMap<TurnID, usize>,
 
     /// Per parking lane or lot, when does a spot become filled (true) or free (false)
     pub parking_lane_changes: BTreeMap<LaneID, Vec<(Time, bool)>>,
     pub parking_lot_changes: BTreeMap<ParkingLotID, Vec<(Time, bool)>>,
 
+    /// For each incident, every agent held up by it, and for how long. The time is when the agent
+    /// could finally proceed.
+    pub incident_delays: BTreeMap<IncidentID, Vec<(Time, Duration, AgentType)>>,
+
+    /// When pedestrian crowding is simulated, the level of service along each sidewalk, measured
+    /// every time somebody starts walking along it.
+    pub sidewalk_los: BTreeMap<LaneID, Vec<(Time, LevelOfService)>>,
+
     pub(crate) alerts: Vec<(Time, AlertLocation, String)>,
 
     /// For benchmarking, we may want to disable collecting data.
@@ -76,6 +90,41 @@ pub enum Problem {
     OvertakeDesired(Traversable),
 }
 
+/// How comfortably pedestrians can walk along a sidewalk, using the Highway Capacity Manual's
+/// thresholds for space per person. A is free flow; F means people are shuffling in a crush.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum LevelOfService {
+    A,
+    B,
+    C,
+    D,
+    E,
+    F,
+}
+
+impl LevelOfService {
+    /// The density is in pedestrians per square meter.
+    pub fn from_pedestrian_density(density: f64) -> LevelOfService {
+        if density <= 0.0 {
+            return LevelOfService::A;
+        }
+        let space_per_person = 1.0 / density;
+        if space_per_person > 5.6 {
+            LevelOfService::A
+        } else if space_per_person > 3.7 {
+            LevelOfService::B
+        } else if space_per_person > 2.2 {
+            LevelOfService::C
+        } else if space_per_person > 1.4 {
+            LevelOfService::D
+        } else if space_per_person > 0.75 {
+            LevelOfService::E
+        } else {
+            LevelOfService::F
+        }
+    }
+}
+
 impl Analytics {
     pub fn new(record_anything: bool) -> Analytics {
         Analytics {
@@ -91,8 +140,12 @@ impl Analytics {
             problems_per_trip: BTreeMap::new(),
             trip_log: Vec::new(),
             intersection_delays: BTreeMap::new(),
+            crosswalk_delays: BTreeMap::new(),
+            crosswalk_crossings: BTreeMap::new(),
             parking_lane_changes: BTreeMap::new(),
             parking_lot_changes: BTreeMap::new(),
+            incident_delays: BTreeMap::new(),
+            sidewalk_los: BTreeMap::new(),
             alerts: Vec::new(),
             record_anything,
         }
@@ -121,6 +174,11 @@ impl Analytics {
                 Traversable::Turn(t) => {
                     self.intersection_thruput
                         .record(time, t.parent, a.to_type(), 1);
+                    if let AgentID::Pedestrian(_) = a {
+                        if map.get_t(t).turn_type == TurnType::Crosswalk {
+                            *self.crosswalk_crossings.entry(t).or_insert(0) += 1;
+                        }
+                    }
                     if let Some(n) = passengers {
                         self.intersection_thruput.record(
                             time,
@@ -208,6 +266,15 @@ impl Analytics {
                     .push((time, Problem::IntersectionDelay(turn_id.parent, delay)));
             }
 
+            if let AgentID::Pedestrian(_) = agent {
+                if map.get_t(turn_id).turn_type == TurnType::Crosswalk {
+                    self.crosswalk_delays
+                        .entry(turn_id)
+                        .or_insert_with(Vec::new)
+                        .push((time, delay));
+                }
+            }
+
             // SharedSidewalkCorner are always no-conflict, immediate turns; they're not
             // interesting.
             if map.get_t(turn_id).turn_type != TurnType::SharedSidewalkCorner {
@@ -221,6 +288,20 @@ impl Analytics {
             }
         }
 
+        if let Event::IncidentDelay(id, agent, delay) = ev {
+            self.incident_delays
+                .entry(id)
+                .or_insert_with(Vec::new)
+                .push((time, delay, agent.to_type()));
+        }
+
+        if let Event::PedestrianDensityMeasured(l, density) = ev {
+            self.sidewalk_los
+                .entry(l)
+                .or_insert_with(Vec::new)
+                .push((time, LevelOfService::from_pedestrian_density(density)));
+        }
+
         // Parking spot changes
         if let Event::CarReachedParkingSpot(_, spot) = ev {
             if let ParkingSpot::Onstreet(l, _) = spot {
@@ -325,6 +406,48 @@ impl Analytics {
         None
     }
 
+    /// How many agents an incident held up, and the total delay it caused them.
+    pub fn total_incident_delay(&self, id: IncidentID) -> (usize, Duration) {
+        let mut count = 0;
+        let mut total = Duration::ZERO;
+        for (_, dt, _) in self.incident_delays.get(&id).into_iter().flatten() {
+            count += 1;
+            total += *dt;
+        }
+        (count, total)
+    }
+
+    /// How many pedestrians have used a crosswalk, and their total delay waiting to start
+    /// crossing.
+    pub fn total_crosswalk_delay(&self, t: TurnID) -> (usize, Duration) {
+        let total = self
+            .crosswalk_delays
+            .get(&t)
+            .into_iter()
+            .flatten()
+            .fold(Duration::ZERO, |sum, (_, dt)| sum + *dt);
+        (
+            self.crosswalk_crossings.get(&t).cloned().unwrap_or(0),
+            total,
+        )
+    }
+
+    /// The worst level of service measured along each sidewalk before some time.
+    pub fn worst_sidewalk_los(&self, now: Time) -> BTreeMap<LaneID, LevelOfService> {
+        let mut worst = BTreeMap::new();
+        for (l, measurements) in &self.sidewalk_los {
+            if let Some(los) = measurements
+                .iter()
+                .filter(|(t, _)| *t <= now)
+                .map(|(_, los)| *los)
+                .max()
+            {
+                worst.insert(*l, los);
+            }
+        }
+        worst
+    }
+
     /// Returns pairs of trip times for finished trips in both worlds. (ID, before, after, mode)
     pub fn both_finished_trips(
         &self,
@@ -1,14 +1,25 @@
 //! A simple tool that just runs a simulation for the specified number of hours. Use for profiling
-//! and benchmarking.
+//! and benchmarking. `--compare_parallel` runs the simulation twice, with and without `--parallel`,
+//! to measure how much calculating paths ahead of time helps.
 
 fn main() {
     let mut args = abstutil::CmdArgs::new();
     let interruptible = args.enabled("--interruptible");
+    let detect_gridlock = args.enabled("--detect_gridlock");
+    let compare_parallel = args.enabled("--compare_parallel");
+    let gridlock_threshold = args
+        .optional_parse("--gridlock_threshold", geom::Duration::parse)
+        .unwrap_or(geom::Duration::minutes(5));
     let hours = geom::Duration::hours(args.required("--hours").parse::<usize>().unwrap());
-    let (mut map, mut sim, _) =
-        sim::SimFlags::from_args(&mut args).load(&mut abstutil::Timer::new("setup"));
+    let flags = sim::SimFlags::from_args(&mut args);
     args.done();
 
+    if compare_parallel {
+        compare_parallel_pathfinding(flags, hours);
+        return;
+    }
+    let (mut map, mut sim, _) = flags.load_synchronously(&mut abstutil::Timer::new("setup"));
+
     if interruptible {
         // Pressing ^C will savestate. This needs a more complex loop to check for the interrupt.
         // This is guarded by the --interruptible flag to keep the benchmarking case simple.
@@ -44,6 +55,17 @@ fn main() {
         println!("\n\nInterrupting at {}", sim.time());
         sim.save();
         println!("{}", sim.describe_scheduler_stats());
+    } else if detect_gridlock {
+        // Stop and write a report as soon as anybody's been stuck in a cycle for a while
+        sim.set_periodic_callback(geom::Duration::minutes(1));
+        let mut cb: Option<Box<dyn sim::SimCallback>> =
+            Some(Box::new(sim::GridlockDetector::new(gridlock_threshold)));
+        sim.timed_step(
+            &mut map,
+            hours,
+            &mut cb,
+            &mut abstutil::Timer::new("run simulation"),
+        );
     } else {
         sim.timed_step(
             &mut map,
@@ -53,3 +75,32 @@ fn main() {
         );
     }
 }
+
+fn compare_parallel_pathfinding(mut flags: sim::SimFlags, hours: geom::Duration) {
+    let mut results = Vec::new();
+    for parallel in vec![false, true] {
+        flags.opts.parallel_pathfinding = parallel;
+        let (mut map, mut sim, _) = flags.load_synchronously(&mut abstutil::Timer::new("setup"));
+        let start = instant::Instant::now();
+        sim.timed_step(
+            &mut map,
+            hours,
+            &mut None,
+            &mut abstutil::Timer::new("run simulation"),
+        );
+        results.push((
+            geom::Duration::realtime_elapsed(start),
+            sim.get_analytics().finished_trips.clone(),
+        ));
+    }
+    let (serial, parallel) = (&results[0], &results[1]);
+    // Prefetching paths must only change how long the simulation takes
+    assert_eq!(serial.1, parallel.1, "--parallel changed the results");
+    println!(
+        "{} trips finished. Serial took {}, parallel took {} ({:.2}x speedup)",
+        abstutil::prettyprint_usize(serial.1.len()),
+        serial.0,
+        parallel.0,
+        serial.0 / parallel.0
+    );
+}
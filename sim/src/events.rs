@@ -6,7 +6,9 @@ use map_model::{
     TurnID,
 };
 
-use crate::{AgentID, CarID, ParkingSpot, PedestrianID, PersonID, Problem, TripID, TripMode};
+use crate::{
+    AgentID, CarID, IncidentID, ParkingSpot, PedestrianID, PersonID, Problem, TripID, TripMode,
+};
 
 /// As a simulation runs, different systems emit Events. This cleanly separates the internal
 /// mechanics of the simulation from consumers that just want to know what's happening.
@@ -44,6 +46,9 @@ pub enum Event {
     AgentEntersTraversable(AgentID, Option<TripID>, Traversable, Option<usize>),
     /// TripID, TurnID (Where the delay was encountered), Time spent waiting at that turn
     IntersectionDelayMeasured(TripID, TurnID, AgentID, Duration),
+    /// When pedestrian crowding is simulated, the density (in pedestrians per square meter) along
+    /// a sidewalk when somebody starts walking along it
+    PedestrianDensityMeasured(LaneID, f64),
 
     TripFinished {
         trip: TripID,
@@ -58,6 +63,11 @@ pub enum Event {
     /// to plumb info into Analytics is Event.
     PathAmended(Path),
 
+    IncidentStarted(IncidentID),
+    IncidentEnded(IncidentID),
+    /// An agent was held up this long by an incident
+    IncidentDelay(IncidentID, AgentID, Duration),
+
     Alert(AlertLocation, String),
 }
 
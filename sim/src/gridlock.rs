@@ -0,0 +1,224 @@
+//! Detect gridlock as it happens and figure out why it happened. See
+//! <https://a-b-street.github.io/docs/tech/trafficsim/gridlock.html> for background.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use serde::Serialize;
+
+use abstio::MapName;
+use geom::{Duration, Time};
+use map_model::{IntersectionID, Map, ParkingLotID};
+
+use crate::{AgentID, CarID, DelayCause, Scenario, Sim, SimCallback};
+
+/// Everybody stuck in gridlock at one moment, grouped by the dependency cycles that cause it.
+#[derive(Clone, Debug, Serialize)]
+pub struct GridlockReport {
+    pub map: MapName,
+    pub time: Time,
+    pub cycles: Vec<GridlockCycle>,
+    /// Reproduces just the vehicles involved, starting from where they were stuck. Trips leave
+    /// through the intersections where the gridlock happened, which usually aren't borders, so
+    /// this is kept with the report instead of saved as a normal scenario. It only makes sense
+    /// after clipping the map to that area.
+    pub capture: Scenario,
+}
+
+/// A group of agents each waiting on the next, with the last one waiting on the first.
+#[derive(Clone, Debug, Serialize)]
+pub struct GridlockCycle {
+    pub agents: Vec<AgentID>,
+    /// Where the agents in the cycle are waiting
+    pub intersections: BTreeSet<IntersectionID>,
+    /// Agents not in the cycle, but stuck behind it
+    pub affected: Vec<AgentID>,
+    /// The shortest time anybody in the cycle has been stuck
+    pub stuck_for: Duration,
+    pub cause: GridlockCause,
+}
+
+/// A best guess at why some agents wound up in a cycle.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub enum GridlockCause {
+    /// Vehicles going in and out of a parking lot are holding up traffic on the road.
+    ParkingLotSpillback(ParkingLotID),
+    /// A bus or train idling at a stop is blocking the vehicles behind it.
+    BusStopBlockage(CarID),
+    /// A vehicle in the middle of an uber-turn locked up a cluster of intersections.
+    UberTurnLock(CarID),
+    /// Nothing special; turns at intersections conflict in a cycle.
+    ConflictCycle,
+}
+
+impl GridlockReport {
+    /// Writes the report, including the capture. Returns the report's path.
+    pub fn save(&self) -> String {
+        let path = abstio::path_player(format!(
+            "gridlock/{}/{}.json",
+            self.map.as_filename(),
+            self.time.as_filename()
+        ));
+        abstio::write_json(path.clone(), self);
+        path
+    }
+}
+
+/// Periodically checks a running simulation for gridlock. Once a cycle of agents has been stuck
+/// for `min_delay`, writes a report and halts the simulation. Use with
+/// `Sim::set_periodic_callback`.
+pub struct GridlockDetector {
+    min_delay: Duration,
+    pub report: Option<GridlockReport>,
+}
+
+impl GridlockDetector {
+    pub fn new(min_delay: Duration) -> GridlockDetector {
+        GridlockDetector {
+            min_delay,
+            report: None,
+        }
+    }
+}
+
+impl SimCallback for GridlockDetector {
+    fn run(&mut self, sim: &Sim, map: &Map) -> bool {
+        if let Some(report) = sim.diagnose_gridlock(map, self.min_delay) {
+            let path = report.save();
+            warn!(
+                "Gridlock at {} involving {} cycles. Report written to {}",
+                report.time,
+                report.cycles.len(),
+                path
+            );
+            self.report = Some(report);
+            return true;
+        }
+        false
+    }
+}
+
+/// Finds every cycle in the blocked-by graph where everybody has been stuck for at least
+/// `min_delay`. Each cycle starts with its smallest agent, so the results are deterministic.
+pub(crate) fn find_cycles(
+    graph: &BTreeMap<AgentID, (Duration, DelayCause)>,
+    min_delay: Duration,
+) -> Vec<Vec<AgentID>> {
+    let next = |a: AgentID| match graph.get(&a) {
+        Some((delay, DelayCause::Agent(b))) if *delay >= min_delay => Some(*b),
+        _ => None,
+    };
+
+    let mut done: HashSet<AgentID> = HashSet::new();
+    let mut cycles = Vec::new();
+    for start in graph.keys() {
+        // Everybody is blocked by at most one agent, so just follow the chain from each agent
+        // until it ends or loops.
+        let mut path = Vec::new();
+        let mut on_path: HashMap<AgentID, usize> = HashMap::new();
+        let mut current = Some(*start);
+        while let Some(a) = current {
+            if done.contains(&a) {
+                break;
+            }
+            if let Some(idx) = on_path.get(&a) {
+                let mut cycle = path[*idx..].to_vec();
+                let min_idx = (0..cycle.len()).min_by_key(|i| cycle[*i]).unwrap();
+                cycle.rotate_left(min_idx);
+                cycles.push(cycle);
+                break;
+            }
+            on_path.insert(a, path.len());
+            path.push(a);
+            current = next(a);
+        }
+        done.extend(path);
+    }
+    cycles
+}
+
+/// For each cycle, finds everybody not in it whose chain of blockers leads into it.
+pub(crate) fn find_affected(
+    graph: &BTreeMap<AgentID, (Duration, DelayCause)>,
+    cycles: &[Vec<AgentID>],
+) -> Vec<Vec<AgentID>> {
+    let mut leads_to: HashMap<AgentID, Option<usize>> = HashMap::new();
+    for (idx, cycle) in cycles.iter().enumerate() {
+        for a in cycle {
+            leads_to.insert(*a, Some(idx));
+        }
+    }
+
+    let mut affected = vec![Vec::new(); cycles.len()];
+    for start in graph.keys() {
+        if leads_to.contains_key(start) {
+            continue;
+        }
+        let mut path = Vec::new();
+        let mut seen = HashSet::new();
+        let mut current = *start;
+        let result = loop {
+            if let Some(result) = leads_to.get(&current) {
+                break *result;
+            }
+            // A cycle that hasn't been stuck long enough to count
+            if !seen.insert(current) {
+                break None;
+            }
+            path.push(current);
+            match graph.get(&current) {
+                Some((_, DelayCause::Agent(a))) => {
+                    current = *a;
+                }
+                _ => break None,
+            }
+        };
+        for a in path {
+            leads_to.insert(a, result);
+            if let Some(idx) = result {
+                affected[idx].push(a);
+            }
+        }
+    }
+    for list in &mut affected {
+        list.sort();
+    }
+    affected
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PedestrianID, VehicleType};
+
+    fn car(id: usize) -> AgentID {
+        AgentID::Car(CarID {
+            id,
+            vehicle_type: VehicleType::Car,
+        })
+    }
+
+    #[test]
+    fn test_find_cycles() {
+        let stuck = Duration::minutes(10);
+        let mut graph = BTreeMap::new();
+        // 1 -> 2 -> 3 -> 1 is a cycle, with 4 and 5 waiting behind it
+        graph.insert(car(2), (stuck, DelayCause::Agent(car(3))));
+        graph.insert(car(3), (stuck, DelayCause::Agent(car(1))));
+        graph.insert(car(1), (stuck, DelayCause::Agent(car(2))));
+        graph.insert(car(4), (stuck, DelayCause::Agent(car(2))));
+        graph.insert(car(5), (stuck, DelayCause::Agent(car(4))));
+        // 6 is just waiting at an intersection
+        graph.insert(car(6), (stuck, DelayCause::Intersection(IntersectionID(0))));
+        graph.insert(
+            AgentID::Pedestrian(PedestrianID(7)),
+            (stuck, DelayCause::Agent(car(6))),
+        );
+
+        let cycles = find_cycles(&graph, Duration::minutes(5));
+        assert_eq!(cycles, vec![vec![car(1), car(2), car(3)]]);
+        assert_eq!(find_affected(&graph, &cycles), vec![vec![car(4), car(5)]]);
+
+        // Not stuck long enough yet
+        assert!(find_cycles(&graph, Duration::minutes(15)).is_empty());
+    }
+}
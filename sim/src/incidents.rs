@@ -0,0 +1,127 @@
+//! Incidents temporarily disrupt traffic during a simulation, like a crash blocking a lane,
+//! construction slowing down a road, or police closing an intersection. Unlike map edits, the
+//! simulation applies and lifts them automatically. They're kept separate from scenarios; see
+//! `SimFlags::incidents`.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use abstutil::{deserialize_usize, serialize_usize};
+use geom::{Distance, Time};
+use map_model::{IntersectionID, Map, Position, RoadID, Traversable};
+
+use crate::{FOLLOWING_DISTANCE, MAX_CAR_LENGTH};
+
+/// How much of a lane a blockage occupies, ending at its position
+pub(crate) const BLOCKAGE_LENGTH: Distance = MAX_CAR_LENGTH;
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct IncidentID(
+    #[serde(
+        serialize_with = "serialize_usize",
+        deserialize_with = "deserialize_usize"
+    )]
+    pub usize,
+);
+
+impl fmt::Display for IncidentID {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Incident #{}", self.0)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Incident {
+    pub kind: IncidentKind,
+    pub start: Time,
+    pub end: Time,
+    /// Just for people reading reports, like "crash on the bridge"
+    pub description: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum IncidentKind {
+    /// Vehicles can't pass this position on a lane. If another vehicle is in the way when the
+    /// incident starts, it's retried a few seconds later.
+    LaneBlockage(Position),
+    /// Vehicles drive slower along every lane of the road. The factor scales the speed, and must
+    /// be more than 0 and at most 1.
+    CapacityReduction { road: RoadID, factor: f64 },
+    /// No agent may start a turn through the intersection. Agents already in the middle of a turn
+    /// finish it.
+    IntersectionClosure(IntersectionID),
+}
+
+impl Incident {
+    /// Makes sure the incident refers to something that exists and makes sense.
+    pub fn validate(&self, map: &Map) -> Result<()> {
+        if self.end <= self.start {
+            bail!(
+                "{} ends at {}, before it starts",
+                self.description,
+                self.end
+            );
+        }
+        match self.kind {
+            IncidentKind::LaneBlockage(pos) => {
+                let lane = map
+                    .maybe_get_l(pos.lane())
+                    .ok_or_else(|| anyhow!("{} doesn't exist", pos.lane()))?;
+                if !lane.lane_type.is_for_moving_vehicles() {
+                    bail!("{} isn't a lane for vehicles", lane.id);
+                }
+                if lane.length() < BLOCKAGE_LENGTH + FOLLOWING_DISTANCE {
+                    bail!("{} is too short to block", lane.id);
+                }
+            }
+            IncidentKind::CapacityReduction { road, factor } => {
+                if map.maybe_get_r(road).is_none() {
+                    bail!("{} doesn't exist", road);
+                }
+                if factor <= 0.0 || factor > 1.0 {
+                    bail!("Capacity reduction factor {} must be in (0, 1]", factor);
+                }
+            }
+            IncidentKind::IntersectionClosure(i) => {
+                if map.maybe_get_i(i).is_none() {
+                    bail!("{} doesn't exist", i);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Where the blockage sits along its lane, clamped so it fits.
+    pub(crate) fn blockage_interval(pos: Position, map: &Map) -> (Distance, Distance) {
+        let front = pos
+            .dist_along()
+            .max(BLOCKAGE_LENGTH + FOLLOWING_DISTANCE)
+            .min(map.get_l(pos.lane()).length());
+        (front, front - BLOCKAGE_LENGTH)
+    }
+}
+
+/// Roads where vehicles currently drive slower, with every incident responsible and the factor
+/// each scales speed by. Incidents may overlap on the same road.
+pub(crate) type Slowdowns = BTreeMap<RoadID, BTreeMap<IncidentID, f64>>;
+
+/// If vehicles must drive slower along a lane, returns the incident and the factor scaling speed.
+/// When several incidents overlap, the strongest one applies.
+pub(crate) fn slowdown(
+    on: Traversable,
+    slowdowns: &Slowdowns,
+    map: &Map,
+) -> Option<(IncidentID, f64)> {
+    match on {
+        Traversable::Lane(l) => slowdowns.get(&map.get_l(l).parent).and_then(|incidents| {
+            incidents
+                .iter()
+                .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+                .map(|(id, factor)| (*id, *factor))
+        }),
+        Traversable::Turn(_) => None,
+    }
+}
@@ -33,19 +33,23 @@ pub use crate::render::{
     UnzoomedAgent,
 };
 
-pub use self::analytics::{Analytics, Problem, SlidingWindow, TripPhase};
+pub use self::analytics::{Analytics, LevelOfService, Problem, SlidingWindow, TripPhase};
 pub(crate) use self::events::Event;
 pub use self::events::{AlertLocation, TripPhaseType};
+pub use self::gridlock::{GridlockCause, GridlockCycle, GridlockDetector, GridlockReport};
+pub use self::incidents::{Incident, IncidentID, IncidentKind};
 pub use self::make::{
     fork_rng, BorderSpawnOverTime, ExternalPerson, ExternalTrip, ExternalTripEndpoint, IndividTrip,
     MapBorders, PersonSpec, Scenario, ScenarioGenerator, ScenarioModifier, SimFlags, SpawnOverTime,
     TripEndpoint, TripPurpose,
 };
 pub(crate) use self::make::{StartTripArgs, TripSpec};
+pub use self::mechanics::CarFollowingModel;
 pub(crate) use self::mechanics::{
     DrivingSimState, IntersectionSimState, ParkingSim, ParkingSimState, WalkingSimState,
 };
 pub(crate) use self::pandemic::PandemicModel;
+pub(crate) use self::prefetch::PathPrefetcher;
 pub(crate) use self::recorder::TrafficRecorder;
 pub(crate) use self::router::{ActionAtEnd, Router};
 pub(crate) use self::scheduler::{Command, Scheduler};
@@ -57,9 +61,12 @@ pub(crate) use self::trips::{TripLeg, TripManager};
 
 mod analytics;
 mod events;
+mod gridlock;
+mod incidents;
 mod make;
 mod mechanics;
 mod pandemic;
+mod prefetch;
 mod recorder;
 mod render;
 mod router;
@@ -318,6 +325,38 @@ impl VehicleType {
             VehicleType::Bike => false,
         }
     }
+
+    /// Typical limits for this type of vehicle
+    pub fn default_accel_limits(self) -> AccelLimits {
+        match self {
+            VehicleType::Car => AccelLimits {
+                accel: 2.0,
+                decel: 3.0,
+            },
+            VehicleType::Bus => AccelLimits {
+                accel: 1.2,
+                decel: 2.0,
+            },
+            VehicleType::Train => AccelLimits {
+                accel: 1.0,
+                decel: 1.3,
+            },
+            VehicleType::Bike => AccelLimits {
+                accel: 1.0,
+                decel: 2.0,
+            },
+        }
+    }
+}
+
+/// How quickly a vehicle can speed up and slow down. Only acceleration-aware car-following models
+/// use this.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct AccelLimits {
+    /// The maximum acceleration, in meters per second squared
+    pub accel: f64,
+    /// The comfortable deceleration, in meters per second squared. Positive.
+    pub decel: f64,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -327,6 +366,7 @@ pub struct Vehicle {
     pub vehicle_type: VehicleType,
     pub length: Distance,
     pub max_speed: Option<Speed>,
+    pub accel_limits: AccelLimits,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -334,6 +374,7 @@ pub struct VehicleSpec {
     pub vehicle_type: VehicleType,
     pub length: Distance,
     pub max_speed: Option<Speed>,
+    pub accel_limits: AccelLimits,
 }
 
 impl VehicleSpec {
@@ -345,6 +386,7 @@ impl VehicleSpec {
             vehicle_type: self.vehicle_type,
             length: self.length,
             max_speed: self.max_speed,
+            accel_limits: self.accel_limits,
         }
     }
 }
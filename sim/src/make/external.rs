@@ -7,10 +7,13 @@ use serde::Deserialize;
 use geom::{Distance, FindClosest, LonLat, Time};
 use map_model::{IntersectionID, Map, PathConstraints};
 
-use crate::{IndividTrip, PersonSpec, TripEndpoint, TripMode, TripPurpose};
+use crate::{IndividTrip, OrigPersonID, PersonSpec, TripEndpoint, TripMode, TripPurpose};
 
 #[derive(Deserialize)]
 pub struct ExternalPerson {
+    /// Optional, just used for debugging and matching people with the original data.
+    #[serde(default)]
+    pub orig_id: Option<OrigPersonID>,
     pub trips: Vec<ExternalTrip>,
 }
 
@@ -73,7 +76,7 @@ impl ExternalPerson {
         let mut results = Vec::new();
         for person in input {
             let mut spec = PersonSpec {
-                orig_id: None,
+                orig_id: person.orig_id,
                 trips: Vec::new(),
             };
             for trip in person.trips {
@@ -5,7 +5,7 @@ use abstio::MapName;
 use abstutil::CmdArgs;
 use map_model::{Map, MapEdits};
 
-use crate::{Scenario, ScenarioModifier, Sim, SimOptions};
+use crate::{Incident, Scenario, ScenarioModifier, Sim, SimOptions};
 
 /// SimFlags specifies a simulation to setup.
 #[derive(Clone)]
@@ -16,6 +16,9 @@ pub struct SimFlags {
     /// - some kind of map: start an empty simulation on the map
     pub load: String,
     pub modifiers: Vec<ScenarioModifier>,
+    /// A path to a JSON list of incidents to schedule once the simulation is set up. These're kept
+    /// separate from scenarios, so the same scenario can be run with different disruptions.
+    pub incidents: Option<String>,
     pub rng_seed: u64,
     pub opts: SimOptions,
 }
@@ -37,6 +40,7 @@ impl SimFlags {
                 .optional_free()
                 .unwrap_or_else(|| MapName::seattle("montlake").path()),
             modifiers,
+            incidents: args.optional("--incidents"),
             rng_seed,
             opts: SimOptions::from_args(args, rng_seed),
         }
@@ -47,6 +51,7 @@ impl SimFlags {
         SimFlags {
             load: MapName::seattle("montlake").path(),
             modifiers: Vec::new(),
+            incidents: None,
             rng_seed: SimFlags::RNG_SEED,
             opts: SimOptions::new(run_name),
         }
@@ -65,7 +70,8 @@ impl SimFlags {
         if self.load.starts_with(&abstio::path_player("saves/")) {
             info!("Resuming from {}", self.load);
 
-            let sim: Sim = abstio::must_read_object(self.load.clone(), timer);
+            let sim = Sim::load_savestate(self.load.clone(), &opts, timer)
+                .unwrap_or_else(|err| panic!("Couldn't load savestate {}: {}", self.load, err));
 
             let mut map = Map::load_synchronously(sim.map_name.path(), timer);
             match MapEdits::load_from_file(
@@ -99,6 +105,7 @@ impl SimFlags {
             }
             let mut sim = Sim::new(&map, opts);
             scenario.instantiate(&mut sim, &map, &mut rng, timer);
+            self.schedule_incidents(&mut sim, &map, timer);
 
             (map, sim, rng)
         } else if self.load.contains("/raw_maps/") || self.load.contains("/maps/") {
@@ -107,12 +114,24 @@ impl SimFlags {
             let map = Map::load_synchronously(self.load.clone(), timer);
 
             timer.start("create sim");
-            let sim = Sim::new(&map, opts);
+            let mut sim = Sim::new(&map, opts);
             timer.stop("create sim");
+            self.schedule_incidents(&mut sim, &map, timer);
 
             (map, sim, rng)
         } else {
             panic!("Don't know how to load {}", self.load);
         }
     }
+
+    fn schedule_incidents(&self, sim: &mut Sim, map: &Map, timer: &mut abstutil::Timer) {
+        if let Some(ref path) = self.incidents {
+            let incidents: Vec<Incident> = abstio::read_json(path.clone(), timer);
+            for incident in incidents {
+                if let Err(err) = sim.schedule_incident(incident, map) {
+                    panic!("Bad incident in {}: {}", path, err);
+                }
+            }
+        }
+    }
 }
@@ -10,7 +10,7 @@ use serde::{Deserialize, Serialize};
 use abstio::MapName;
 use abstutil::{prettyprint_usize, Counter, Timer};
 use geom::{Distance, Speed, Time};
-use map_model::{BuildingID, Map, OffstreetParking, RoadID};
+use map_model::{map_matching, BuildingID, Map, OffstreetParking, Path, RoadID};
 
 use crate::make::fork_rng;
 use crate::{
@@ -49,6 +49,10 @@ pub struct IndividTrip {
     pub cancelled: bool,
     /// Did a ScenarioModifier affect this?
     pub modified: bool,
+    /// Follow this route from the origin, then find the rest of the way to the destination. Only
+    /// used to replay matched GPS traces, and never saved with the scenario.
+    #[serde(skip_serializing, skip_deserializing)]
+    pub route: Option<Path>,
 }
 
 impl IndividTrip {
@@ -67,12 +71,40 @@ impl IndividTrip {
             purpose,
             cancelled: false,
             modified: false,
+            route: None,
         }
     }
+
+    /// Replays a GPS trace matched to the map. The trip starts where the trace begins, follows the
+    /// matched route, then goes to the building closest to where the trace stops. Use
+    /// `IndividTrip::new` instead to let the simulation pick its own route and compare it against
+    /// `trace.path`. The route isn't saved with the scenario, so replay it in the same process.
+    pub fn from_matched_trace(
+        map: &Map,
+        trace: &map_matching::MatchedTrace,
+        mode: TripMode,
+        purpose: TripPurpose,
+    ) -> Option<IndividTrip> {
+        let end_pt = trace.positions.last()?.1.pt(map);
+        let destination = map
+            .all_buildings()
+            .iter()
+            .min_by_key(|b| b.polygon.center().dist_to(end_pt))?
+            .id;
+        let mut trip = IndividTrip::new(
+            trace.start_time(),
+            purpose,
+            TripEndpoint::SuddenlyAppear(trace.positions[0].1),
+            TripEndpoint::Bldg(destination),
+            mode,
+        );
+        trip.route = Some(trace.path.clone());
+        Some(trip)
+    }
 }
 
 /// Lifted from Seattle's Soundcast model, but seems general enough to use anyhere.
-#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
 pub enum TripPurpose {
     Home,
     Work,
@@ -178,6 +210,7 @@ impl Scenario {
                     StartTripArgs {
                         retry_if_no_room,
                         use_vehicle: maybe_idx.map(|idx| person.vehicles[idx].id),
+                        route: trip.route.clone(),
                     },
                 ));
             }
@@ -213,6 +246,7 @@ impl Scenario {
             vehicle_type: VehicleType::Car,
             length,
             max_speed: None,
+            accel_limits: VehicleType::Car.default_accel_limits(),
         }
     }
 
@@ -226,6 +260,7 @@ impl Scenario {
             vehicle_type: VehicleType::Bike,
             length: BIKE_LENGTH,
             max_speed,
+            accel_limits: VehicleType::Bike.default_accel_limits(),
         }
     }
 
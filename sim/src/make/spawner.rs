@@ -6,7 +6,8 @@ use serde::{Deserialize, Serialize};
 
 use geom::Pt2D;
 use map_model::{
-    BuildingID, BusRouteID, BusStopID, IntersectionID, Map, PathConstraints, PathRequest, Position,
+    BuildingID, BusRouteID, BusStopID, IntersectionID, Map, Path, PathConstraints, PathRequest,
+    Position,
 };
 
 use crate::{CarID, DrivingGoal, SidewalkSpot, TripLeg, TripMode, VehicleType, SPAWN_DIST};
@@ -17,6 +18,8 @@ use crate::{CarID, DrivingGoal, SidewalkSpot, TripLeg, TripMode, VehicleType, SP
 pub(crate) struct StartTripArgs {
     pub retry_if_no_room: bool,
     pub use_vehicle: Option<CarID>,
+    /// Follow this instead of finding a route from the start
+    pub route: Option<Path>,
 }
 
 // TODO Some of these fields are unused now that we separately pass TripEndpoint
@@ -368,7 +371,8 @@ impl TripEndpoint {
         }
     }
 
-    fn pos(self, mode: TripMode, from: bool, map: &Map) -> Option<Position> {
+    /// Where does a trip of some mode begin (if `from` is true) or end at this endpoint?
+    pub fn pos(self, mode: TripMode, from: bool, map: &Map) -> Option<Position> {
         match mode {
             TripMode::Walk | TripMode::Transit => (if from {
                 self.start_sidewalk_spot(map)
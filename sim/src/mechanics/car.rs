@@ -2,9 +2,11 @@ use std::collections::{BTreeSet, VecDeque};
 
 use serde::{Deserialize, Serialize};
 
-use geom::{Distance, Duration, PolyLine, Time, EPSILON_DIST};
-use map_model::{Direction, LaneID, Map, Traversable};
+use geom::{Distance, Duration, PolyLine, Speed, Time, EPSILON_DIST};
+use map_model::{Direction, LaneID, Map, Traversable, TurnPriority};
 
+use crate::incidents::{slowdown, Slowdowns};
+use crate::mechanics::car_following::CarFollowingModel;
 use crate::{
     CarID, CarStatus, DistanceInterval, DrawCarInput, Intent, ParkingSpot, PersonID, Router,
     TimeInterval, TransitSimState, TripID, Vehicle, VehicleType,
@@ -21,6 +23,9 @@ pub(crate) struct Car {
     pub trip_and_person: Option<(TripID, PersonID)>,
     pub started_at: Time,
     pub total_blocked_time: Duration,
+    /// How fast the vehicle was moving at the end of its last crossing. Only meaningful for
+    /// acceleration-aware car-following models.
+    pub speed: Speed,
 
     /// In reverse order -- most recently left is first. The sum length of these must be >=
     /// vehicle.length.
@@ -32,8 +37,17 @@ pub(crate) struct Car {
 }
 
 impl Car {
-    /// Assumes the current head of the path is the thing to cross.
-    pub fn crossing_state(&self, start_dist: Distance, start_time: Time, map: &Map) -> CarState {
+    /// Assumes the current head of the path is the thing to cross. `leader` is the position of
+    /// the back of whatever's in front along the same step, and how fast it's moving.
+    pub fn crossing_state(
+        &self,
+        start_dist: Distance,
+        start_time: Time,
+        slowdowns: &Slowdowns,
+        model: CarFollowingModel,
+        leader: Option<(Distance, Speed)>,
+        map: &Map,
+    ) -> CarState {
         let dist_int = DistanceInterval::new_driving(
             start_dist,
             if self.router.last_step() {
@@ -42,16 +56,19 @@ impl Car {
                 self.router.head().get_polyline(map).length()
             },
         );
-        self.crossing_state_with_end_dist(dist_int, start_time, map)
+        self.crossing_state_with_end_dist(dist_int, start_time, slowdowns, model, leader, map)
     }
 
     pub fn crossing_state_with_end_dist(
         &self,
         dist_int: DistanceInterval,
         start_time: Time,
+        slowdowns: &Slowdowns,
+        model: CarFollowingModel,
+        leader: Option<(Distance, Speed)>,
         map: &Map,
     ) -> CarState {
-        let (speed, percent_incline) = self
+        let (mut speed, percent_incline) = self
             .router
             .get_path()
             .current_step()
@@ -60,11 +77,84 @@ impl Car {
                 self.vehicle.vehicle_type.to_constraints(),
                 map,
             );
-        let dt = (dist_int.end - dist_int.start) / speed;
+        if let Some((_, factor)) = slowdown(self.router.head(), slowdowns, map) {
+            speed = speed * factor;
+        }
+        let (dt, end_speed) = model.cross(
+            dist_int.end - dist_int.start,
+            self.start_speed(start_time),
+            speed,
+            self.vehicle.accel_limits,
+            self.must_stop_at(dist_int.end, map),
+            leader.map(|(back, speed)| (back - dist_int.start, speed)),
+        );
         CarState::Crossing {
             time_int: TimeInterval::new(start_time, start_time + dt),
             dist_int,
             steep_uphill: percent_incline >= 0.08,
+            end_speed,
+        }
+    }
+
+    /// How fast the vehicle is moving if it starts crossing something at this time.
+    fn start_speed(&self, start_time: Time) -> Speed {
+        match self.state {
+            // Recalculating in the middle of crossing; assume they were moving at the end speed
+            CarState::Crossing { end_speed, .. } => end_speed,
+            CarState::ChangingLanes { new_end_speed, .. } => new_end_speed,
+            // If they waited at all, they came to a stop
+            CarState::Queued { blocked_since, .. }
+            | CarState::WaitingToAdvance { blocked_since } => {
+                if start_time > blocked_since {
+                    Speed::ZERO
+                } else {
+                    self.speed
+                }
+            }
+            CarState::Unparking { .. }
+            | CarState::Parking(_, _, _)
+            | CarState::IdlingAtStop(_, _) => Speed::ZERO,
+        }
+    }
+
+    /// Roughly how fast the vehicle is moving right now, averaged over whatever it's crossing.
+    pub fn current_speed(&self) -> Speed {
+        let (time_int, dist_int) = match self.state {
+            CarState::Crossing {
+                ref time_int,
+                ref dist_int,
+                ..
+            } => (time_int, dist_int),
+            CarState::ChangingLanes {
+                ref new_time,
+                ref new_dist,
+                ..
+            } => (new_time, new_dist),
+            _ => {
+                return Speed::ZERO;
+            }
+        };
+        if time_int.end == time_int.start {
+            return Speed::ZERO;
+        }
+        Speed::from_dist_time(dist_int.end - dist_int.start, time_int.end - time_int.start)
+    }
+
+    /// Does the vehicle have to stop when it reaches this distance along the current step? True
+    /// at the end of the trip and before stop signs without priority.
+    fn must_stop_at(&self, dist: Distance, map: &Map) -> bool {
+        if self.router.last_step() {
+            return dist == self.router.get_end_dist();
+        }
+        if dist != self.router.head().get_polyline(map).length() {
+            return false;
+        }
+        match (self.router.head(), self.router.next()) {
+            (Traversable::Lane(_), Traversable::Turn(t)) => map
+                .maybe_get_stop_sign(t.parent)
+                .map(|sign| sign.get_priority(t, map) == TurnPriority::Yield)
+                .unwrap_or(false),
+            _ => false,
         }
     }
 
@@ -321,6 +411,7 @@ pub(crate) enum CarState {
         time_int: TimeInterval,
         dist_int: DistanceInterval,
         steep_uphill: bool,
+        end_speed: Speed,
     },
     ChangingLanes {
         from: LaneID,
@@ -330,6 +421,7 @@ pub(crate) enum CarState {
         new_dist: DistanceInterval,
         // How long does the lane-changing itself last? This must end before new_time_int does.
         lc_time: TimeInterval,
+        new_end_speed: Speed,
     },
     Queued {
         blocked_since: Time,
@@ -0,0 +1,216 @@
+//! By default, vehicles cross each lane or turn at a constant speed, instantly starting and
+//! stopping. This is fast, but it underestimates the time lost at intersections. An
+//! acceleration-aware car-following model can be used instead.
+//!
+//! The queues still prevent vehicles from overlapping their leader, and positions in the middle
+//! of a crossing are still interpolated linearly. The model only changes how long each crossing
+//! takes: vehicles starting from a stop accelerate gradually, vehicles approaching a stop sign or
+//! the end of their trip brake beforehand, and vehicles closing on a slower leader slow down to
+//! keep their distance.
+
+use serde::{Deserialize, Serialize};
+
+use geom::{Distance, Duration, Speed};
+
+use crate::AccelLimits;
+
+/// Each crossing is integrated with fixed steps of this length.
+const TIMESTEP: f64 = 0.1;
+/// In meters. The IDM's minimum gap to a stopped leader.
+const MIN_GAP: f64 = 2.0;
+/// In seconds. The IDM's desired time gap to a leader.
+const TIME_HEADWAY: f64 = 1.0;
+const ACCEL_EXPONENT: i32 = 4;
+/// In meters. When braking to a stop, this is close enough to the end or the leader.
+const STOPPED_CLOSE_ENOUGH: f64 = 0.5;
+/// Never integrate longer than this; something's wrong with the inputs.
+const MAX_STEPS: usize = 100_000;
+
+/// How vehicles speed up and slow down
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum CarFollowingModel {
+    /// Vehicles instantly reach their maximum speed and stop instantly.
+    Discrete,
+    /// The Intelligent Driver Model. Vehicles accelerate and brake within their `AccelLimits`.
+    IDM,
+}
+
+impl CarFollowingModel {
+    pub fn parse(x: &str) -> Option<CarFollowingModel> {
+        match x {
+            "discrete" => Some(CarFollowingModel::Discrete),
+            "idm" => Some(CarFollowingModel::IDM),
+            _ => None,
+        }
+    }
+
+    /// Calculates how long a vehicle takes to cross some distance, never exceeding a maximum
+    /// speed. If `stop_at_end` is true, the vehicle brakes to a stop at the end. `leader` is the
+    /// distance from the vehicle's starting position to the back of whatever's in front, and how
+    /// fast that's moving. Also returns the speed at the end.
+    pub(crate) fn cross(
+        self,
+        dist: Distance,
+        start_speed: Speed,
+        max_speed: Speed,
+        limits: AccelLimits,
+        stop_at_end: bool,
+        leader: Option<(Distance, Speed)>,
+    ) -> (Duration, Speed) {
+        match self {
+            CarFollowingModel::Discrete => (dist / max_speed, max_speed),
+            CarFollowingModel::IDM => idm(
+                dist.inner_meters(),
+                start_speed.inner_meters_per_second(),
+                max_speed.inner_meters_per_second(),
+                limits,
+                stop_at_end,
+                leader.map(|(gap, speed)| (gap.inner_meters(), speed.inner_meters_per_second())),
+            ),
+        }
+    }
+}
+
+fn idm(
+    total: f64,
+    start_speed: f64,
+    max_speed: f64,
+    limits: AccelLimits,
+    stop_at_end: bool,
+    mut leader: Option<(f64, f64)>,
+) -> (Duration, Speed) {
+    if total <= 0.0 {
+        return (Duration::ZERO, Speed::meters_per_second(start_speed));
+    }
+    let mut x = 0.0;
+    let mut v = start_speed.max(0.0);
+    let mut t = 0.0;
+    for _ in 0..MAX_STEPS {
+        if stop_at_end && total - x <= STOPPED_CLOSE_ENOUGH {
+            return (Duration::seconds(t), Speed::ZERO);
+        }
+
+        // The IDM only brakes for the closest obstacle, so keep the strongest interaction term
+        let mut interaction: f64 = 0.0;
+        if stop_at_end {
+            // Pretend a stopped vehicle is waiting just past the end
+            let gap = total - x + MIN_GAP;
+            interaction = interaction.max((desired_gap(v, 0.0, limits) / gap).powi(2));
+        }
+        if let Some((start_gap, leader_speed)) = leader {
+            // Assume the leader keeps moving at the same speed
+            let gap = start_gap + leader_speed * t - x;
+            if gap <= MIN_GAP + STOPPED_CLOSE_ENOUGH {
+                // Caught up to a stopped leader. The queue holds the vehicle back until the leader
+                // moves on, so from here, just assume they can start again.
+                leader = None;
+            } else {
+                interaction = interaction.max((desired_gap(v, leader_speed, limits) / gap).powi(2));
+            }
+        }
+        let accel = limits.accel * (1.0 - (v / max_speed).powi(ACCEL_EXPONENT) - interaction);
+        let accel = accel.max(-limits.decel).min(limits.accel);
+
+        let new_v = (v + accel * TIMESTEP).max(0.0);
+        let avg_v = (v + new_v) / 2.0;
+        let step = avg_v * TIMESTEP;
+        if x + step >= total {
+            // Only part of this step is needed
+            t += (total - x) / avg_v;
+            return (Duration::seconds(t), Speed::meters_per_second(new_v));
+        }
+        x += step;
+        v = new_v;
+        t += TIMESTEP;
+    }
+    // Finish the rest at full speed
+    t += (total - x) / max_speed;
+    (Duration::seconds(t), Speed::meters_per_second(max_speed))
+}
+
+/// The IDM's desired gap to a leader, given the vehicle's speed and the leader's.
+fn desired_gap(speed: f64, leader_speed: f64, limits: AccelLimits) -> f64 {
+    MIN_GAP
+        + (speed * TIME_HEADWAY
+            + speed * (speed - leader_speed) / (2.0 * (limits.accel * limits.decel).sqrt()))
+        .max(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::VehicleType;
+
+    #[test]
+    fn test_idm() {
+        let limits = VehicleType::Car.default_accel_limits();
+        let dist = Distance::meters(100.0);
+        let max_speed = Speed::meters_per_second(10.0);
+        let (discrete, _) =
+            CarFollowingModel::Discrete.cross(dist, Speed::ZERO, max_speed, limits, false, None);
+
+        // Starting from a stop is slower
+        let (from_rest, end_speed) =
+            CarFollowingModel::IDM.cross(dist, Speed::ZERO, max_speed, limits, false, None);
+        assert!(from_rest > discrete);
+        assert!(end_speed <= max_speed);
+
+        // Already moving at full speed is about the same
+        let (rolling, _) =
+            CarFollowingModel::IDM.cross(dist, max_speed, max_speed, limits, false, None);
+        assert!((rolling - discrete).inner_seconds().abs() < 0.5);
+
+        // Braking to a stop at the end takes even longer
+        let (stopping, end_speed) =
+            CarFollowingModel::IDM.cross(dist, Speed::ZERO, max_speed, limits, true, None);
+        assert!(stopping > from_rest);
+        assert_eq!(end_speed, Speed::ZERO);
+    }
+
+    #[test]
+    fn test_idm_closing_on_leader() {
+        let limits = VehicleType::Car.default_accel_limits();
+        let dist = Distance::meters(100.0);
+        let max_speed = Speed::meters_per_second(10.0);
+        let (free, _) =
+            CarFollowingModel::IDM.cross(dist, max_speed, max_speed, limits, false, None);
+
+        // A slower leader 20m ahead holds the follower back. The follower can't pass them, so
+        // covering the distance takes at least as long as the leader needs to clear it.
+        let leader_speed = Speed::meters_per_second(3.0);
+        let (following, end_speed) = CarFollowingModel::IDM.cross(
+            dist,
+            max_speed,
+            max_speed,
+            limits,
+            false,
+            Some((Distance::meters(20.0), leader_speed)),
+        );
+        assert!(following > free);
+        assert!(following >= Duration::seconds(80.0 / 3.0));
+        // And the follower slows down to about the leader's speed
+        assert!(end_speed < Speed::meters_per_second(4.0));
+
+        // A leader far ahead moving just as fast doesn't matter
+        let (unbothered, _) = CarFollowingModel::IDM.cross(
+            dist,
+            max_speed,
+            max_speed,
+            limits,
+            false,
+            Some((Distance::meters(200.0), max_speed)),
+        );
+        assert!((unbothered - free).inner_seconds().abs() < 0.5);
+
+        // The discrete model ignores the leader entirely; the queue handles it
+        let (discrete, _) = CarFollowingModel::Discrete.cross(
+            dist,
+            max_speed,
+            max_speed,
+            limits,
+            false,
+            Some((Distance::meters(20.0), leader_speed)),
+        );
+        assert_eq!(discrete, dist / max_speed);
+    }
+}
@@ -3,21 +3,30 @@ use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use serde::{Deserialize, Serialize};
 
 use abstutil::{deserialize_hashmap, serialize_hashmap, FixedMap, IndexableKey};
-use geom::{Distance, Duration, PolyLine, Time};
-use map_model::{DrivingSide, IntersectionID, LaneID, Map, Path, PathStep, Position, Traversable};
+use geom::{Distance, Duration, PolyLine, Speed, Time};
+use map_model::{
+    DrivingSide, IntersectionID, LaneID, Map, Path, PathStep, Position, RoadID, Traversable,
+};
 
+use crate::incidents::{slowdown, Incident, Slowdowns};
 use crate::mechanics::car::{Car, CarState};
+use crate::mechanics::car_following::CarFollowingModel;
 use crate::mechanics::queue::{Queue, QueueEntry, Queued};
 use crate::sim::Ctx;
 use crate::{
     ActionAtEnd, AgentID, AgentProperties, CarID, CarStatus, Command, CreateCar, DelayCause,
-    DistanceInterval, DrawCarInput, Event, IntersectionSimState, ParkedCar, ParkingSim,
-    ParkingSpot, PersonID, Problem, SimOptions, TimeInterval, TransitSimState, TripID, TripManager,
-    UnzoomedAgent, Vehicle, VehicleType, WalkingSimState, FOLLOWING_DISTANCE, MAX_CAR_LENGTH,
+    DistanceInterval, DrawCarInput, Event, GridlockCause, IncidentID, IntersectionSimState,
+    ParkedCar, ParkingSim, ParkingSpot, PersonID, Problem, SimOptions, TimeInterval,
+    TransitSimState, TripID, TripManager, UnzoomedAgent, Vehicle, VehicleType, WalkingSimState,
+    FOLLOWING_DISTANCE, MAX_CAR_LENGTH,
 };
 
 const TIME_TO_WAIT_AT_BUS_STOP: Duration = Duration::const_seconds(10.0);
 const TIME_TO_CHANGE_LANES: Duration = Duration::const_seconds(1.0);
+const BLIND_RETRY_TO_CHANGE_LANES: Duration = Duration::const_seconds(2.0);
+/// With mid-block lane-changing, only move to an adjacent lane if its queue has at least this many
+/// fewer vehicles than the ones ahead of us.
+const MIN_QUEUE_DIFFERENCE_TO_CHANGE_LANES: usize = 3;
 
 // TODO Do something else.
 pub const BLIND_RETRY_TO_CREEP_FORWARDS: Duration = Duration::const_seconds(0.1);
@@ -45,6 +54,11 @@ pub(crate) struct DrivingSimState {
 
     recalc_lanechanging: bool,
     handle_uber_turns: bool,
+    informed_drivers: f64,
+    reroute_threshold: Duration,
+    car_following: CarFollowingModel,
+    mid_block_lanechanging: bool,
+    slowdowns: Slowdowns,
 
     time_to_unpark_onstreet: Duration,
     time_to_park_onstreet: Duration,
@@ -61,6 +75,11 @@ impl DrivingSimState {
             events: Vec::new(),
             recalc_lanechanging: opts.recalc_lanechanging,
             handle_uber_turns: opts.handle_uber_turns,
+            informed_drivers: opts.informed_drivers,
+            reroute_threshold: opts.reroute_threshold,
+            car_following: opts.car_following,
+            mid_block_lanechanging: opts.mid_block_lanechanging,
+            slowdowns: Slowdowns::new(),
             waiting_to_spawn: BTreeMap::new(),
 
             time_to_unpark_onstreet: Duration::seconds(10.0),
@@ -186,6 +205,7 @@ impl DrivingSimState {
                 last_steps: VecDeque::new(),
                 started_at: now,
                 total_blocked_time: Duration::ZERO,
+                speed: Speed::ZERO,
                 trip_and_person: params.trip_and_person,
                 wants_to_overtake: BTreeSet::new(),
             };
@@ -262,7 +282,14 @@ impl DrivingSimState {
                     }
                 }
 
-                car.state = car.crossing_state(start_dist, now, ctx.map);
+                car.state = car.crossing_state(
+                    start_dist,
+                    now,
+                    &self.slowdowns,
+                    self.car_following,
+                    self.find_leader(Traversable::Lane(first_lane), start_dist, now),
+                    ctx.map,
+                );
             }
             ctx.scheduler
                 .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
@@ -315,7 +342,10 @@ impl DrivingSimState {
         let mut need_distances = {
             let car = &self.cars[&id];
             match car.state {
-                CarState::Queued { .. } => car.router.last_step(),
+                CarState::Queued {
+                    want_to_change_lanes,
+                    ..
+                } => car.router.last_step() || want_to_change_lanes.is_some(),
                 CarState::Parking(_, _, _) => true,
                 CarState::IdlingAtStop(_, _) => true,
                 _ => false,
@@ -368,7 +398,8 @@ impl DrivingSimState {
         transit: &mut TransitSimState,
     ) -> bool {
         match car.state {
-            CarState::Crossing { .. } => {
+            CarState::Crossing { end_speed, .. } => {
+                car.speed = end_speed;
                 car.state = CarState::Queued {
                     blocked_since: now,
                     want_to_change_lanes: None,
@@ -381,6 +412,17 @@ impl DrivingSimState {
                 if queue.is_car_at_front(car.vehicle.id) {
                     // Want to re-run, but no urgency about it happening immediately.
                     car.state = CarState::WaitingToAdvance { blocked_since: now };
+                    if is_informed(car.vehicle.id, self.informed_drivers)
+                        && car.router.maybe_reroute(
+                            &car.vehicle,
+                            &self.queues,
+                            ctx.map,
+                            self.reroute_threshold,
+                        )
+                    {
+                        self.events
+                            .push(Event::PathAmended(car.router.get_path().clone()));
+                    }
                     if self.recalc_lanechanging {
                         car.router.opportunistically_lanechange(
                             &self.queues,
@@ -389,23 +431,31 @@ impl DrivingSimState {
                         );
                     }
                     ctx.scheduler.push(now, Command::UpdateCar(car.vehicle.id));
-                } else if let Some(slow_leader) = self.wants_to_overtake(car) {
-                    // TODO This entire check kicks in a little late; we only enter Queued after
-                    // spending the freeflow time possibly moving very slowly.
-                    let first_conflict = car.wants_to_overtake.insert(slow_leader);
-
-                    // Record when a vehicle wants to pass a bike
-                    if first_conflict
-                        && slow_leader.vehicle_type == VehicleType::Bike
-                        && car.vehicle.vehicle_type != VehicleType::Bike
-                    {
-                        self.events.push(Event::ProblemEncountered(
-                            self.cars[&slow_leader].trip_and_person.unwrap().0,
-                            Problem::OvertakeDesired(queue.id),
-                        ));
+                } else {
+                    let mut target_lane = None;
+                    if let Some(slow_leader) = self.wants_to_overtake(car) {
+                        // TODO This entire check kicks in a little late; we only enter Queued
+                        // after spending the freeflow time possibly moving very slowly.
+                        let first_conflict = car.wants_to_overtake.insert(slow_leader);
+
+                        // Record when a vehicle wants to pass a bike
+                        if first_conflict
+                            && slow_leader.vehicle_type == VehicleType::Bike
+                            && car.vehicle.vehicle_type != VehicleType::Bike
+                        {
+                            self.events.push(Event::ProblemEncountered(
+                                self.cars[&slow_leader].trip_and_person.unwrap().0,
+                                Problem::OvertakeDesired(queue.id),
+                            ));
+                        }
+
+                        target_lane = self.pick_overtaking_lane(car, ctx.map);
+                    }
+                    if target_lane.is_none() && self.mid_block_lanechanging {
+                        target_lane = self.pick_mid_block_lane(car, ctx.map);
                     }
 
-                    if let Some(target_lane) = self.pick_overtaking_lane(car, ctx.map) {
+                    if let Some(target_lane) = target_lane {
                         // We need the current position of the car to see if lane-changing is
                         // actually feasible right now, so record our intention and trigger
                         // update_car_with_distances.
@@ -455,7 +505,16 @@ impl DrivingSimState {
                         &mut self.events,
                     );
                 }
-                car.state = car.crossing_state(front, now, ctx.map);
+                // This car isn't in self.cars right now, so its own queue can't be inspected.
+                // They're starting from a stop anyway.
+                car.state = car.crossing_state(
+                    front,
+                    now,
+                    &self.slowdowns,
+                    self.car_following,
+                    None,
+                    ctx.map,
+                );
                 ctx.scheduler
                     .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
             }
@@ -508,7 +567,24 @@ impl DrivingSimState {
                     &mut self.events,
                 );
                 car.total_blocked_time += now - blocked_since;
-                car.state = car.crossing_state(Distance::ZERO, now, ctx.map);
+                // The car isn't in the goto queue yet, so anything there is ahead of them
+                car.state = car.crossing_state(
+                    Distance::ZERO,
+                    now,
+                    &self.slowdowns,
+                    self.car_following,
+                    self.find_leader(goto, Distance::ZERO, now),
+                    ctx.map,
+                );
+                if let Some((id, factor)) = slowdown(goto, &self.slowdowns, ctx.map) {
+                    // Record how much longer this takes than it would at full speed
+                    let dt = car.state.get_end_time() - now;
+                    self.events.push(Event::IncidentDelay(
+                        id,
+                        AgentID::Car(car.vehicle.id),
+                        dt - dt * factor,
+                    ));
+                }
                 ctx.scheduler
                     .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
                 self.events.push(Event::AgentEntersTraversable(
@@ -535,6 +611,9 @@ impl DrivingSimState {
                             car.vehicle.length + FOLLOWING_DISTANCE,
                         ),
                         now,
+                        &self.slowdowns,
+                        self.car_following,
+                        None,
                         ctx.map,
                     )
                     .get_end_time(),
@@ -550,6 +629,7 @@ impl DrivingSimState {
                 from,
                 new_time,
                 new_dist,
+                new_end_speed,
                 ..
             } => {
                 // The car is already in the target queue. Just set them in the crossing state; we
@@ -558,6 +638,7 @@ impl DrivingSimState {
                     time_int: new_time,
                     dist_int: new_dist,
                     steep_uphill: false,
+                    end_speed: new_end_speed,
                 };
                 ctx.scheduler
                     .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
@@ -610,7 +691,17 @@ impl DrivingSimState {
                 // Two totally different reasons we'll wind up here: we want to lane-change, and
                 // we're on our last step.
                 if let Some(target_lane) = want_to_change_lanes {
-                    self.try_start_lc(car, our_dist, idx, target_lane, now, ctx);
+                    if !self.try_start_lc(car, our_dist, idx, target_lane, now, ctx)
+                        && idx > 0
+                        && matches!(dists[idx - 1].member, Queued::Incident { .. })
+                    {
+                        // The lane is blocked, so nothing else will wake us up. Keep looking for
+                        // a gap.
+                        ctx.scheduler.update(
+                            now + BLIND_RETRY_TO_CHANGE_LANES,
+                            Command::UpdateCar(car.vehicle.id),
+                        );
+                    }
                     return true;
                 }
 
@@ -670,7 +761,14 @@ impl DrivingSimState {
                     }
                     Some(ActionAtEnd::GotoLaneEnd) => {
                         car.total_blocked_time += now - blocked_since;
-                        car.state = car.crossing_state(our_dist, now, ctx.map);
+                        car.state = car.crossing_state(
+                            our_dist,
+                            now,
+                            &self.slowdowns,
+                            self.car_following,
+                            self.leader_ahead(dists, our_dist),
+                            ctx.map,
+                        );
                         ctx.scheduler
                             .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
                         true
@@ -751,7 +849,14 @@ impl DrivingSimState {
                 car.router = transit.bus_departed_from_stop(car.vehicle.id, ctx.map);
                 self.events
                     .push(Event::PathAmended(car.router.get_path().clone()));
-                car.state = car.crossing_state(dist, now, ctx.map);
+                car.state = car.crossing_state(
+                    dist,
+                    now,
+                    &self.slowdowns,
+                    self.car_following,
+                    self.leader_ahead(dists, dist),
+                    ctx.map,
+                );
                 ctx.scheduler
                     .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
 
@@ -863,6 +968,8 @@ impl DrivingSimState {
                 }
             }
 
+            // The leader is getting out of the way, so the follower's crossing state doesn't use
+            // them. The queue still bounds the follower by anything farther ahead.
             let mut follower = self.cars.get_mut(&follower_id).unwrap();
             // TODO If the leader vanished at a border node, this still jumps a bit -- the lead
             // car's back is still sticking out. Need to still be bound by them, even though they
@@ -874,7 +981,14 @@ impl DrivingSimState {
 
                     // Prevent them from jumping forwards.
                     follower.total_blocked_time += now - blocked_since;
-                    follower.state = follower.crossing_state(follower_dist, now, ctx.map);
+                    follower.state = follower.crossing_state(
+                        follower_dist,
+                        now,
+                        &self.slowdowns,
+                        self.car_following,
+                        None,
+                        ctx.map,
+                    );
                     ctx.scheduler.update(
                         follower.state.get_end_time(),
                         Command::UpdateCar(follower_id),
@@ -884,7 +998,14 @@ impl DrivingSimState {
                     // If the follower was still Crossing, they might not've been blocked by the
                     // leader yet. But recalculating their Crossing state isn't necessarily a no-op
                     // -- this could prevent them from suddenly warping past a blockage.
-                    follower.state = follower.crossing_state(follower_dist, now, ctx.map);
+                    follower.state = follower.crossing_state(
+                        follower_dist,
+                        now,
+                        &self.slowdowns,
+                        self.car_following,
+                        None,
+                        ctx.map,
+                    );
                     ctx.scheduler.update(
                         follower.state.get_end_time(),
                         Command::UpdateCar(follower_id),
@@ -899,24 +1020,39 @@ impl DrivingSimState {
                     // middle of their lane-changing. It's guaranteed that lc_time will continue to
                     // finish before the new time interval, because there's no possible way
                     // recalculating this crossing state here will speed things up from the
-                    // original estimate.
-                    let (new_time, new_dist) = match follower.crossing_state_with_end_dist(
-                        DistanceInterval::new_driving(follower_dist, ctx.map.get_l(to).length()),
-                        now,
-                        ctx.map,
-                    ) {
+                    // original estimate. (Except for acceleration-aware models, which might assume
+                    // a higher starting speed now. Just make them finish the lane-change.)
+                    let (mut new_time, new_dist, new_end_speed) = match follower
+                        .crossing_state_with_end_dist(
+                            DistanceInterval::new_driving(
+                                follower_dist,
+                                ctx.map.get_l(to).length(),
+                            ),
+                            now,
+                            &self.slowdowns,
+                            self.car_following,
+                            None,
+                            ctx.map,
+                        ) {
                         CarState::Crossing {
-                            time_int, dist_int, ..
-                        } => (time_int, dist_int),
+                            time_int,
+                            dist_int,
+                            end_speed,
+                            ..
+                        } => (time_int, dist_int, end_speed),
                         _ => unreachable!(),
                     };
-                    assert!(new_time.end >= lc_time.end);
+                    if new_time.end < lc_time.end {
+                        assert_ne!(self.car_following, CarFollowingModel::Discrete);
+                        new_time = TimeInterval::new(new_time.start, lc_time.end);
+                    }
                     follower.state = CarState::ChangingLanes {
                         from,
                         to,
                         new_time,
                         new_dist,
                         lc_time,
+                        new_end_speed,
                     };
                 }
                 // They weren't blocked
@@ -974,6 +1110,9 @@ impl DrivingSimState {
                         self.cars[&id].vehicle.length + FOLLOWING_DISTANCE,
                     ),
                     now,
+                    &self.slowdowns,
+                    self.car_following,
+                    None,
                     ctx.map,
                 )
                 .get_end_time();
@@ -1042,6 +1181,19 @@ impl DrivingSimState {
                                     // gets out of the way. So immediately promote them to
                                     // WaitingToAdvance.
                                     follower.state = CarState::WaitingToAdvance { blocked_since };
+                                    if ctx.handling_live_edits.is_none()
+                                        && is_informed(follower.vehicle.id, self.informed_drivers)
+                                        && follower.router.maybe_reroute(
+                                            &follower.vehicle,
+                                            &self.queues,
+                                            ctx.map,
+                                            self.reroute_threshold,
+                                        )
+                                    {
+                                        self.events.push(Event::PathAmended(
+                                            follower.router.get_path().clone(),
+                                        ));
+                                    }
                                     if self.recalc_lanechanging && ctx.handling_live_edits.is_none()
                                     {
                                         follower.router.opportunistically_lanechange(
@@ -1079,8 +1231,62 @@ impl DrivingSimState {
     /// - Prefer passing on the left (for DrivingSide::Right)
     /// For now, just pick one candidate lane, even if both might be usable.
     fn pick_overtaking_lane(&self, car: &Car, map: &Map) -> Option<LaneID> {
-        // Don't overtake in the middle of a turn!
-        let current_lane = map.get_l(car.router.head().maybe_lane()?);
+        self.lanechange_candidates(car, false, map)
+            .into_iter()
+            .next()
+    }
+
+    /// With mid-block lane-changing, a vehicle that catches up to something in its lane might move
+    /// to an adjacent lane:
+    /// - It must, if an incident blocks the lane ahead.
+    /// - It may, to pass a vehicle stopped in the lane, like a bus at a stop or a car parking.
+    /// - It may, if the adjacent lane has a much shorter queue.
+    /// Whether there's actually a gap in the target lane is checked later, by try_start_lc.
+    fn pick_mid_block_lane(&self, car: &Car, map: &Map) -> Option<LaneID> {
+        let queue = &self.queues[&car.router.head()];
+        let (num_ahead, ahead) = queue.get_member_ahead(car.vehicle.id)?;
+        match ahead {
+            Queued::Incident { .. } => {
+                return self
+                    .lanechange_candidates(car, true, map)
+                    .into_iter()
+                    .next();
+            }
+            Queued::Vehicle(leader) => {
+                if matches!(
+                    self.cars[&leader].state,
+                    CarState::IdlingAtStop(_, _)
+                        | CarState::Parking(_, _, _)
+                        | CarState::Unparking { .. }
+                ) {
+                    return self
+                        .lanechange_candidates(car, false, map)
+                        .into_iter()
+                        .next();
+                }
+            }
+            Queued::StaticBlockage { .. } | Queued::DynamicBlockage { .. } => {}
+        }
+
+        self.lanechange_candidates(car, false, map)
+            .into_iter()
+            .find(|l| {
+                let (target_vehicles, _) =
+                    self.queues[&Traversable::Lane(*l)].target_lane_penalty();
+                target_vehicles + MIN_QUEUE_DIFFERENCE_TO_CHANGE_LANES <= num_ahead
+            })
+    }
+
+    /// Adjacent lanes that the car could change to, in order of preference. If `mandatory`, the
+    /// lane may lead somewhere else on the next road, as long as it continues the rest of the path.
+    fn lanechange_candidates(&self, car: &Car, mandatory: bool, map: &Map) -> Vec<LaneID> {
+        // Don't change lanes in the middle of a turn!
+        let current_lane = match car.router.head().maybe_lane() {
+            Some(l) => map.get_l(l),
+            None => {
+                return Vec::new();
+            }
+        };
         let road = map.get_r(current_lane.parent);
         let idx = road.offset(current_lane.id);
         let lanes_ltr = road.lanes_ltr();
@@ -1096,11 +1302,11 @@ impl DrivingSimState {
             candidates.reverse();
         }
 
-        for l in candidates {
-            let target_lane = map.get_l(l);
+        candidates.retain(|l| {
+            let target_lane = map.get_l(*l);
             // Must be the same direction -- no crossing into oncoming traffic yet
             if current_lane.dir != target_lane.dir {
-                continue;
+                return false;
             }
             // The lane types can differ, as long as the vehicle can use the target. Imagine
             // overtaking a slower cyclist in a bike lane using the rest of the road.
@@ -1110,20 +1316,19 @@ impl DrivingSimState {
                 .to_constraints()
                 .can_use(target_lane, map)
             {
-                continue;
+                return false;
             }
             // Is this other lane compatible with the path? We won't make any attempts to return to the
             // original lane after changing.
-            if !car
-                .router
-                .can_lanechange(current_lane.id, target_lane.id, map)
-            {
-                continue;
+            if mandatory {
+                car.router
+                    .must_lanechange(current_lane.id, target_lane.id, map)
+            } else {
+                car.router
+                    .can_lanechange(current_lane.id, target_lane.id, map)
             }
-            return Some(target_lane.id);
-        }
-
-        None
+        });
+        candidates
     }
 
     fn try_start_lc(
@@ -1134,16 +1339,16 @@ impl DrivingSimState {
         target_lane: LaneID,
         now: Time,
         ctx: &mut Ctx,
-    ) {
+    ) -> bool {
         // If we are a laggy head somewhere else (our back is still sticking into another lane or
         // turn), don't start lane-changing!
         if !car.last_steps.is_empty() {
-            return;
+            return false;
         }
         // If the lanes are very different lengths and we're too close to the end at the target,
         // not going to work.
         if front_current_queue >= ctx.map.get_l(target_lane).length() {
-            return;
+            return false;
         }
         let current_lane = car.router.head().as_lane();
         let front_target_queue = Position::new(current_lane, front_current_queue)
@@ -1152,14 +1357,20 @@ impl DrivingSimState {
 
         // Calculate the crossing state in the target queue. Pass in the DistanceInterval
         // explicitly, because we haven't modified the route yet.
-        let (new_time, new_dist) = match car.crossing_state_with_end_dist(
+        let (new_time, new_dist, new_end_speed) = match car.crossing_state_with_end_dist(
             DistanceInterval::new_driving(front_target_queue, ctx.map.get_l(target_lane).length()),
             now,
+            &self.slowdowns,
+            self.car_following,
+            self.find_leader(Traversable::Lane(target_lane), front_target_queue, now),
             ctx.map,
         ) {
             CarState::Crossing {
-                time_int, dist_int, ..
-            } => (time_int, dist_int),
+                time_int,
+                dist_int,
+                end_speed,
+                ..
+            } => (time_int, dist_int, end_speed),
             _ => unreachable!(),
         };
 
@@ -1167,7 +1378,7 @@ impl DrivingSimState {
         // possible in the target?
         let lc_time = TimeInterval::new(now, now + TIME_TO_CHANGE_LANES);
         if lc_time.end >= new_time.end {
-            return;
+            return false;
         }
 
         // Is there room for us to sliiiide on over into that lane's DMs?
@@ -1212,9 +1423,13 @@ impl DrivingSimState {
                 new_time,
                 new_dist,
                 lc_time,
+                new_end_speed,
             };
             ctx.scheduler
                 .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
+            true
+        } else {
+            false
         }
     }
 
@@ -1260,6 +1475,111 @@ impl DrivingSimState {
             self.queues.insert(key, Queue::new(key, map));
         }
     }
+
+    /// Blocks part of a lane for an incident. Returns false if a vehicle is in the way right now;
+    /// the caller should retry later.
+    pub fn start_lane_blockage(
+        &mut self,
+        id: IncidentID,
+        pos: Position,
+        now: Time,
+        ctx: &mut Ctx,
+    ) -> bool {
+        let (front, back) = Incident::blockage_interval(pos, ctx.map);
+        let on = Traversable::Lane(pos.lane());
+        // Live map edits might've removed the lane
+        if !self.queues.contains_key(&on) {
+            return false;
+        }
+        let idx = match self.queues[&on].get_idx_to_insert_car(
+            front,
+            front - back,
+            now,
+            &self.cars,
+            &self.queues,
+        ) {
+            Some(idx) => idx,
+            None => {
+                return false;
+            }
+        };
+        self.queues
+            .get_mut(&on)
+            .unwrap()
+            .add_incident_blockage(id, front, back, idx);
+        // Anybody crossing the lane behind the blockage must stop in time
+        let dists = self.queues[&on].get_car_positions(now, &self.cars, &self.queues);
+        self.update_follower(idx, &dists, now, ctx);
+        true
+    }
+
+    /// Lifts an incident blocking part of a lane, letting vehicles behind it continue. Every
+    /// vehicle stuck in the queue behind the blockage is delayed by it.
+    pub fn end_lane_blockage(
+        &mut self,
+        id: IncidentID,
+        lane: LaneID,
+        started: Time,
+        now: Time,
+        ctx: &mut Ctx,
+    ) {
+        let on = Traversable::Lane(lane);
+        if !self.queues.contains_key(&on) {
+            return;
+        }
+        let dists = self.queues[&on].get_car_positions(now, &self.cars, &self.queues);
+        let idx = match dists
+            .iter()
+            .position(|entry| matches!(entry.member, Queued::Incident { id: x, .. } if x == id))
+        {
+            Some(idx) => idx,
+            // The blockage never started
+            None => {
+                return;
+            }
+        };
+        for entry in &dists[idx + 1..] {
+            match entry.member {
+                Queued::Vehicle(car) => {
+                    if let CarState::Queued { blocked_since, .. } = self.cars[&car].state {
+                        self.events.push(Event::IncidentDelay(
+                            id,
+                            AgentID::Car(car),
+                            now - blocked_since.max(started),
+                        ));
+                    } else {
+                        // Anybody behind this isn't stuck yet
+                        break;
+                    }
+                }
+                _ => {
+                    break;
+                }
+            }
+        }
+        self.update_follower(idx, &dists, now, ctx);
+        self.queues
+            .get_mut(&on)
+            .unwrap()
+            .clear_incident_blockage(id, idx);
+    }
+
+    /// Vehicles entering any lane of the road will drive slower. Vehicles already on the road
+    /// aren't affected.
+    pub fn slow_down_road(&mut self, id: IncidentID, road: RoadID, factor: f64) {
+        self.slowdowns.entry(road).or_default().insert(id, factor);
+    }
+
+    /// Undoes slow_down_road. If other incidents are still slowing down the road, the strongest
+    /// of them applies.
+    pub fn restore_road_speed(&mut self, id: IncidentID, road: RoadID) {
+        if let Some(incidents) = self.slowdowns.get_mut(&road) {
+            incidents.remove(&id);
+            if incidents.is_empty() {
+                self.slowdowns.remove(&road);
+            }
+        }
+    }
 }
 
 // Queries
@@ -1404,6 +1724,7 @@ impl DrivingSimState {
                             None
                         }
                     }
+                    Queued::Incident { .. } => None,
                 })
                 .collect(),
             None => Vec::new(),
@@ -1604,6 +1925,91 @@ impl DrivingSimState {
         graph
     }
 
+    /// Guess why a cycle of agents is stuck, by looking for anything unusual about the vehicles
+    /// involved and whoever is sharing a queue with them.
+    pub fn gridlock_cause(&self, now: Time, cycle: &[AgentID]) -> GridlockCause {
+        let cars: Vec<&Car> = cycle
+            .iter()
+            .filter_map(|a| match a {
+                AgentID::Car(c) => self.cars.get(c),
+                _ => None,
+            })
+            .collect();
+
+        for car in &cars {
+            for entry in
+                self.queues[&car.router.head()].get_car_positions(now, &self.cars, &self.queues)
+            {
+                let other = match entry.member {
+                    Queued::Vehicle(c) => c,
+                    Queued::StaticBlockage { cause, .. } => cause,
+                    Queued::DynamicBlockage { .. } | Queued::Incident { .. } => continue,
+                };
+                match self.cars.get(&other).map(|c| &c.state) {
+                    Some(CarState::Unparking {
+                        spot: ParkingSpot::Lot(pl, _),
+                        ..
+                    })
+                    | Some(CarState::Parking(_, ParkingSpot::Lot(pl, _), _)) => {
+                        return GridlockCause::ParkingLotSpillback(*pl);
+                    }
+                    Some(CarState::IdlingAtStop(_, _)) => {
+                        return GridlockCause::BusStopBlockage(other);
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        for car in &cars {
+            let path = car.router.get_path();
+            if path.currently_inside_ut().is_some() || path.about_to_start_ut().is_some() {
+                return GridlockCause::UberTurnLock(car.vehicle.id);
+            }
+        }
+
+        GridlockCause::ConflictCycle
+    }
+
+    /// For acceleration-aware car-following models, find the back of whatever's in front of this
+    /// position on a queue, and how fast it's moving. Everything on the queue must be in
+    /// self.cars.
+    fn find_leader(
+        &self,
+        on: Traversable,
+        front: Distance,
+        now: Time,
+    ) -> Option<(Distance, Speed)> {
+        if self.car_following == CarFollowingModel::Discrete {
+            return None;
+        }
+        let dists = self.queues[&on].get_car_positions(now, &self.cars, &self.queues);
+        self.leader_ahead(&dists, front)
+    }
+
+    /// Like find_leader, but using positions that've already been calculated.
+    fn leader_ahead(&self, dists: &[QueueEntry], front: Distance) -> Option<(Distance, Speed)> {
+        if self.car_following == CarFollowingModel::Discrete {
+            return None;
+        }
+        // The farthest along is first, so find the closest thing ahead from the back
+        let entry = dists.iter().rev().find(|entry| entry.back >= front)?;
+        let speed = match entry.member {
+            // If the leader isn't in self.cars, it's being updated right now
+            Queued::Vehicle(id) => self
+                .cars
+                .get(&id)
+                .map(|car| car.current_speed())
+                .unwrap_or(Speed::ZERO),
+            // Blockages don't move. A vehicle changing lanes follows whatever's in front of it,
+            // but assume the worst.
+            Queued::StaticBlockage { .. }
+            | Queued::DynamicBlockage { .. }
+            | Queued::Incident { .. } => Speed::ZERO,
+        };
+        Some((entry.back, speed))
+    }
+
     fn get_car_front(&self, now: Time, car: &Car) -> Distance {
         self.queues[&car.router.head()]
             .get_car_positions(now, &self.cars, &self.queues)
@@ -1659,3 +2065,14 @@ impl IndexableKey for CarID {
         self.id
     }
 }
+
+/// Deterministically decides if a driver reacts to live traffic, so that about `share` of all
+/// cars do. Only cars reroute; bikes, buses, and trains stick to their routes.
+fn is_informed(car: CarID, share: f64) -> bool {
+    if car.vehicle_type != VehicleType::Car || share <= 0.0 {
+        return false;
+    }
+    // Scramble the ID, so the informed drivers aren't just the ones spawned first
+    let hash = (car.id as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 11;
+    (hash as f64) / ((1u64 << 53) as f64) < share
+}
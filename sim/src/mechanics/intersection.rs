@@ -10,13 +10,22 @@ use map_model::{
 };
 
 use crate::mechanics::car::{Car, CarState};
-use crate::mechanics::Queue;
+use crate::mechanics::queue::{Queue, Queued};
 use crate::{
-    AgentID, AlertLocation, CarID, Command, DelayCause, Event, Scheduler, SimOptions, Speed,
+    AgentID, AlertLocation, CarID, Command, DelayCause, Event, IncidentID, Scheduler, SimOptions,
+    Speed,
 };
 
 const WAIT_AT_STOP_SIGN: Duration = Duration::const_seconds(0.5);
 const WAIT_BEFORE_YIELD_AT_TRAFFIC_SIGNAL: Duration = Duration::const_seconds(0.2);
+/// When zipper merging, don't wait longer than this for a vehicle from the other lane. It might be
+/// stuck for some other reason.
+const MAX_WAIT_FOR_ZIPPER_MERGE: Duration = Duration::const_seconds(5.0);
+// Vehicles entering a roundabout need at least this much time before the next circulating vehicle
+// arrives
+const CRITICAL_GAP_AT_ROUNDABOUT: Duration = Duration::const_seconds(4.0);
+// If circulating traffic is stuck, eventually force our way in anyway
+const MAX_WAIT_TO_ENTER_ROUNDABOUT: Duration = Duration::const_seconds(30.0);
 
 /// Manages conflicts at intersections. When an agent has reached the end of a lane, they call
 /// maybe_start_turn to make a Request. Based on the intersection type (stop sign, traffic signal,
@@ -34,9 +43,18 @@ pub(crate) struct IntersectionSimState {
     break_turn_conflict_cycles: bool,
     handle_uber_turns: bool,
     disable_turn_conflicts: bool,
+    zipper_merging: bool,
     // (x, y) means x is blocked by y. It's a many-to-many relationship. TODO Better data
     // structure.
     blocked_by: BTreeSet<(CarID, CarID)>,
+    // Intersections temporarily closed by incidents, which may overlap
+    closed: BTreeMap<IntersectionID, BTreeSet<IncidentID>>,
+    // For zipper merging, the source lane of the last vehicle to turn into each destination lane
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    last_merge: BTreeMap<LaneID, LaneID>,
     events: Vec<Event>,
 
     // Count how many calls to maybe_start_turn there are aside from the initial call. Break down
@@ -74,6 +92,8 @@ struct State {
 struct SignalState {
     // The current stage of the signal, zero based
     current_stage: usize,
+    // When the current stage began, ignoring extensions
+    stage_started_at: Time,
     // The time when the signal is checked for advancing
     stage_ends_at: Time,
     // The number of times a variable signal has been extended during the current stage.
@@ -96,7 +116,10 @@ impl IntersectionSimState {
             break_turn_conflict_cycles: opts.break_turn_conflict_cycles,
             handle_uber_turns: opts.handle_uber_turns,
             disable_turn_conflicts: opts.disable_turn_conflicts,
+            zipper_merging: opts.mid_block_lanechanging,
             blocked_by: BTreeSet::new(),
+            closed: BTreeMap::new(),
+            last_merge: BTreeMap::new(),
             events: Vec::new(),
 
             total_repeat_requests: 0,
@@ -250,6 +273,14 @@ impl IntersectionSimState {
                     TurnPriority::Banned => unreachable!(),
                 }
             }
+        } else if map.get_i(i).is_roundabout() {
+            for (req, _, _) in all {
+                if is_circulating(req.turn.src, map) {
+                    protected.push(req);
+                } else {
+                    yielding.push(req);
+                }
+            }
         } else {
             // This could either be a border intersection or an intersection that was just closed
             // in the middle of simulation. In either case, there shouldn't be any other turns at
@@ -271,6 +302,45 @@ impl IntersectionSimState {
         }
     }
 
+    /// Stops agents from starting any turn through an intersection, except for sidewalk corners.
+    /// Agents already in the middle of a turn or uber-turn finish it.
+    pub fn close_intersection(&mut self, id: IncidentID, i: IntersectionID) {
+        self.closed
+            .entry(i)
+            .or_insert_with(BTreeSet::new)
+            .insert(id);
+    }
+
+    /// Undoes close_intersection. The intersection stays closed until every overlapping closure
+    /// ends; the last one to end is blamed for delaying every agent left waiting there.
+    pub fn reopen_intersection(
+        &mut self,
+        id: IncidentID,
+        i: IntersectionID,
+        started: Time,
+        now: Time,
+        scheduler: &mut Scheduler,
+        map: &Map,
+    ) {
+        if let Some(incidents) = self.closed.get_mut(&i) {
+            incidents.remove(&id);
+            if !incidents.is_empty() {
+                return;
+            }
+        }
+        if self.closed.remove(&i).is_none() {
+            return;
+        }
+        for (req, (wait_start, _)) in &self.state[&i].waiting {
+            self.events.push(Event::IncidentDelay(
+                id,
+                req.agent,
+                now - (*wait_start).max(started),
+            ));
+        }
+        self.wakeup_waiting(now, i, scheduler, map);
+    }
+
     /// This is only triggered for traffic signals.
     pub fn update_intersection(
         &mut self,
@@ -359,6 +429,9 @@ impl IntersectionSimState {
             }
         }
 
+        if signal_state.extensions_count == 0 {
+            signal_state.stage_started_at = now;
+        }
         signal_state.stage_ends_at = now + duration;
         scheduler.push(signal_state.stage_ends_at, Command::UpdateIntersection(id));
         self.wakeup_waiting(now, id, scheduler, map);
@@ -441,6 +514,9 @@ impl IntersectionSimState {
             }
 
             true
+        } else if self.closed.contains_key(&turn.parent) {
+            // Nobody starts a new turn until the incident ends
+            false
         } else if self.use_freeform_policy_everywhere {
             // If we made it this far, we don't conflict with an accepted turn
             true
@@ -448,6 +524,9 @@ impl IntersectionSimState {
             self.traffic_signal_policy(&req, map, signal, speed, now, Some(scheduler))
         } else if let Some(sign) = map.maybe_get_stop_sign(turn.parent) {
             self.stop_sign_policy(&req, map, sign, now, scheduler)
+                && (!self.zipper_merging || self.zipper_merge_policy(&req, map, now, scheduler))
+        } else if map.get_i(turn.parent).is_roundabout() {
+            self.roundabout_policy(&req, map, now, scheduler, readonly_pair)
         } else {
             unreachable!()
         };
@@ -510,7 +589,7 @@ impl IntersectionSimState {
             if !queue.try_to_reserve_entry(
                 car,
                 !self.dont_block_the_box
-                    || allow_block_the_box(map.get_i(turn.parent))
+                    || allow_block_the_box(map.get_i(turn.parent), map)
                     || inside_ut,
             ) {
                 let mut actually_did_reserve_entry = false;
@@ -556,6 +635,9 @@ impl IntersectionSimState {
         // for stop signs too.
         let state = self.state.get_mut(&turn.parent).unwrap();
         state.waiting.remove(&req).unwrap();
+        if self.zipper_merging {
+            self.last_merge.insert(req.turn.dst, req.turn.src);
+        }
         state.accepted.insert(req);
         if self.break_turn_conflict_cycles {
             if let AgentID::Car(car) = agent {
@@ -657,11 +739,24 @@ impl IntersectionSimState {
             println!("{}", abstutil::to_json(sign));
         } else if let Some(ref signal) = map.maybe_get_traffic_signal(id) {
             println!("{}", abstutil::to_json(signal));
+        } else if map.get_i(id).is_roundabout() {
+            println!("Roundabout");
         } else {
             println!("Border");
         }
     }
 
+    /// Where is every agent waiting to start or in the middle of a turn?
+    pub fn get_agent_intersections(&self) -> BTreeMap<AgentID, IntersectionID> {
+        let mut result = BTreeMap::new();
+        for state in self.state.values() {
+            for req in state.waiting.keys().chain(state.accepted.iter()) {
+                result.insert(req.agent, state.id);
+            }
+        }
+        result
+    }
+
     pub fn get_accepted_agents(&self, id: IntersectionID) -> Vec<(AgentID, TurnID)> {
         self.state[&id]
             .accepted
@@ -837,9 +932,124 @@ impl IntersectionSimState {
         // TODO Make sure we can optimistically finish this turn before an approaching
         // higher-priority vehicle wants to begin.
 
+        // At mid-block crossings, drivers yield to anybody waiting to cross. When the pedestrian
+        // finishes crossing, we'll be woken up.
+        if map.get_i(req.turn.parent).is_mid_block_crossing(map) {
+            if let AgentID::Pedestrian(_) = req.agent {
+                return true;
+            }
+            let turn = map.get_t(req.turn);
+            let ped_waiting = self.state[&req.turn.parent].waiting.keys().any(|r| {
+                matches!(r.agent, AgentID::Pedestrian(_)) && turn.conflicts_with(map.get_t(r.turn))
+            });
+            if ped_waiting {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Circulating traffic has priority at roundabouts. Vehicles entering wait until the next
+    /// circulating vehicle whose path they'd cross is far enough away.
+    fn roundabout_policy(
+        &mut self,
+        req: &Request,
+        map: &Map,
+        now: Time,
+        scheduler: &mut Scheduler,
+        maybe_cars_and_queues: Option<(&FixedMap<CarID, Car>, &HashMap<Traversable, Queue>)>,
+    ) -> bool {
+        // Pedestrians just need to avoid accepted conflicts
+        let (cars, queues) = match (req.agent, maybe_cars_and_queues) {
+            (AgentID::Pedestrian(_), _) | (_, None) => {
+                return true;
+            }
+            (_, Some(pair)) => pair,
+        };
+        if is_circulating(req.turn.src, map) {
+            return true;
+        }
+        let (our_time, _) = self.state[&req.turn.parent].waiting[req];
+        if now >= our_time + MAX_WAIT_TO_ENTER_ROUNDABOUT {
+            return true;
+        }
+
+        let turn = map.get_t(req.turn);
+        for l in &map.get_i(req.turn.parent).incoming_lanes {
+            // Crosswalks can conflict, but sidewalks have no queue
+            if !is_circulating(*l, map)
+                || !map.get_l(*l).lane_type.is_for_moving_vehicles()
+                || !map
+                    .get_turns_from_lane(*l)
+                    .into_iter()
+                    .any(|t| t.conflicts_with(turn))
+            {
+                continue;
+            }
+            let queue = &queues[&Traversable::Lane(*l)];
+            // Ignore vehicles that'll stop before reaching us
+            let leader = queue
+                .get_car_positions(now, cars, queues)
+                .into_iter()
+                .find_map(|entry| match entry.member {
+                    Queued::Vehicle(c) if !cars[&c].router.last_step() => Some(entry.front),
+                    _ => None,
+                });
+            let front = match leader {
+                Some(front) => front,
+                None => continue,
+            };
+            let speed_limit = map.get_parent(*l).speed_limit;
+            if (queue.geom_len - front) / speed_limit < CRITICAL_GAP_AT_ROUNDABOUT {
+                // The circulating vehicle finishing its turn will wake us up, but in case it's
+                // stuck, retry later.
+                scheduler.update(
+                    our_time + MAX_WAIT_TO_ENTER_ROUNDABOUT,
+                    Command::update_agent(req.agent),
+                );
+                return false;
+            }
+        }
         true
     }
 
+    /// Where several lanes of one road feed into the same lane, vehicles from each lane take turns.
+    /// If the last vehicle to merge came from our lane and somebody from another lane is waiting,
+    /// let them go first.
+    fn zipper_merge_policy(
+        &mut self,
+        req: &Request,
+        map: &Map,
+        now: Time,
+        scheduler: &mut Scheduler,
+    ) -> bool {
+        if self.last_merge.get(&req.turn.dst) != Some(&req.turn.src) {
+            return true;
+        }
+        let state = &self.state[&req.turn.parent];
+        let (our_time, _) = state.waiting[req];
+        if now >= our_time + MAX_WAIT_FOR_ZIPPER_MERGE {
+            return true;
+        }
+        let our_road = map.get_l(req.turn.src).parent;
+        let other_waiting = state.waiting.keys().any(|r| {
+            r.turn.dst == req.turn.dst
+                && r.turn.src != req.turn.src
+                && map.get_l(r.turn.src).parent == our_road
+        });
+        if !other_waiting {
+            return true;
+        }
+        // The other vehicle's turn finishing will wake us up, but in case they're stuck, retry
+        // later.
+        scheduler.update(
+            our_time + MAX_WAIT_FOR_ZIPPER_MERGE,
+            Command::update_agent(req.agent),
+        );
+        false
+    }
+
     fn traffic_signal_policy(
         &mut self,
         req: &Request,
@@ -864,6 +1074,26 @@ impl IntersectionSimState {
             return false;
         }
 
+        if let Some(ref timing) = stage.pedestrian_timing {
+            let elapsed = now - signal_state.stage_started_at;
+            if turn.turn_type == TurnType::Crosswalk {
+                // Pedestrians arriving during the flashing or steady "don't walk" wait for the next
+                // stage.
+                if elapsed >= timing.walk {
+                    return false;
+                }
+            } else if elapsed < timing.leading_interval {
+                // Vehicles wait for pedestrians to get a head start
+                if let Some(s) = scheduler {
+                    s.update(
+                        signal_state.stage_started_at + timing.leading_interval,
+                        Command::update_agent(req.agent),
+                    );
+                }
+                return false;
+            }
+        }
+
         if our_priority == TurnPriority::Yield
             && now < our_time + WAIT_BEFORE_YIELD_AT_TRAFFIC_SIGNAL
         {
@@ -1032,6 +1262,7 @@ impl SignalState {
     fn new(id: IntersectionID, now: Time, map: &Map, scheduler: &mut Scheduler) -> SignalState {
         let mut state = SignalState {
             current_stage: 0,
+            stage_started_at: now,
             stage_ends_at: now,
             extensions_count: 0,
         };
@@ -1051,6 +1282,11 @@ impl SignalState {
                 }
             } else {
                 state.stage_ends_at = now + dt - offset;
+                // At the very beginning of the simulation, the stage may have started before
+                // midnight. Just pretend it started now.
+                if now - Time::START_OF_DAY >= offset {
+                    state.stage_started_at = now - offset;
+                }
                 break;
             }
         }
@@ -1059,12 +1295,17 @@ impl SignalState {
     }
 }
 
-fn allow_block_the_box(i: &Intersection) -> bool {
+fn is_circulating(l: LaneID, map: &Map) -> bool {
+    map.get_parent(l).is_roundabout_ring()
+}
+
+fn allow_block_the_box(i: &Intersection, map: &Map) -> bool {
     // Degenerate intersections are often just artifacts of how roads are split up in OSM. Allow
     // vehicles to get stuck in them, since the only possible thing they could block is pedestrians
     // from using the crosswalk. Those crosswalks usually don't exist in reality, so this behavior
     // is more realistic.
-    if i.roads.len() == 2 {
+    // Mid-block crossings are the exception; the crosswalk is real there.
+    if i.roads.len() == 2 && !i.is_mid_block_crossing(map) {
         return true;
     }
 
@@ -1,3 +1,4 @@
+pub use self::car_following::CarFollowingModel;
 pub(crate) use self::driving::DrivingSimState;
 pub(crate) use self::intersection::IntersectionSimState;
 pub(crate) use self::parking::{ParkingSim, ParkingSimState};
@@ -5,6 +6,7 @@ pub(crate) use self::queue::Queue;
 pub(crate) use self::walking::WalkingSimState;
 
 mod car;
+mod car_following;
 mod driving;
 mod intersection;
 mod parking;
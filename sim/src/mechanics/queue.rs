@@ -7,7 +7,7 @@ use geom::{Distance, Time};
 use map_model::{Map, Position, Traversable};
 
 use crate::mechanics::car::{Car, CarState};
-use crate::{CarID, VehicleType, FOLLOWING_DISTANCE};
+use crate::{CarID, IncidentID, VehicleType, FOLLOWING_DISTANCE};
 
 /// A Queue of vehicles on a single lane or turn. This is where
 /// https://a-b-street.github.io/docs/tech/trafficsim/discrete_event.html#exact-positions is
@@ -25,6 +25,8 @@ use crate::{CarID, VehicleType, FOLLOWING_DISTANCE};
 ///   position of the blockage in this queue is unknown (it depends on the target queue). The
 ///   blockage just occupies the length of the vehicle and keeps following whatever's in front of
 ///   it.
+/// - an "incident blockage" is like a static blockage, but caused by a scheduled incident, like a
+///   crash, instead of a vehicle.
 /// - "active cars" are the main members of the queue -- everything except for laggy heads and
 ///   blockages.
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -62,6 +64,12 @@ pub enum Queued {
         cause: CarID,
         vehicle_len: Distance,
     },
+    /// An incident occupying a fixed interval of distance on the queue
+    Incident {
+        id: IncidentID,
+        front: Distance,
+        back: Distance,
+    },
 }
 
 /// The exact position of something in a `Queue` at some time
@@ -255,7 +263,8 @@ impl Queue {
                         back: front - car.vehicle.length,
                     }
                 }
-                Queued::StaticBlockage { front, back, .. } => QueueEntry {
+                Queued::StaticBlockage { front, back, .. }
+                | Queued::Incident { front, back, .. } => QueueEntry {
                     member: queued,
                     front,
                     back,
@@ -290,6 +299,7 @@ impl Queue {
             Queued::Vehicle(car) => Some((car, previous.front)),
             Queued::StaticBlockage { .. } => None,
             Queued::DynamicBlockage { .. } => None,
+            Queued::Incident { .. } => None,
         }
     }
 
@@ -452,7 +462,9 @@ impl Queue {
                     }
                     leader = Some(*car);
                 }
-                Queued::StaticBlockage { .. } | Queued::DynamicBlockage { .. } => {
+                Queued::StaticBlockage { .. }
+                | Queued::DynamicBlockage { .. }
+                | Queued::Incident { .. } => {
                     leader = None;
                 }
             }
@@ -460,6 +472,19 @@ impl Queue {
         None
     }
 
+    /// Find whatever is immediately in front of the specified vehicle, and how many members of the
+    /// queue are ahead of it. None if the vehicle isn't in the queue or is at the front.
+    pub fn get_member_ahead(&self, id: CarID) -> Option<(usize, Queued)> {
+        let idx = self
+            .members
+            .iter()
+            .position(|queued| *queued == Queued::Vehicle(id))?;
+        if idx == 0 {
+            return None;
+        }
+        Some((idx, self.members[idx - 1].clone()))
+    }
+
     /// Record that a car is blocking a static portion of the queue (from front to back). Must use
     /// the index from can_block_from_driveway.
     pub fn add_static_blockage(
@@ -515,6 +540,37 @@ impl Queue {
         }
     }
 
+    /// Record that an incident is blocking a static portion of the queue (from front to back). Must
+    /// use the index from get_idx_to_insert_car.
+    pub fn add_incident_blockage(
+        &mut self,
+        id: IncidentID,
+        front: Distance,
+        back: Distance,
+        idx: usize,
+    ) {
+        assert!(front > back);
+        assert!(back >= FOLLOWING_DISTANCE);
+        self.members
+            .insert(idx, Queued::Incident { id, front, back });
+        self.reserved_length += front - back + FOLLOWING_DISTANCE;
+    }
+
+    /// Record that an incident is no longer blocking part of the queue.
+    pub fn clear_incident_blockage(&mut self, id: IncidentID, idx: usize) {
+        match self.members.remove(idx).unwrap() {
+            Queued::Incident {
+                id: cause,
+                front,
+                back,
+            } => {
+                assert_eq!(id, cause);
+                self.reserved_length -= front - back + FOLLOWING_DISTANCE;
+            }
+            _ => unreachable!(),
+        }
+    }
+
     /// True if a static blockage can be inserted into the queue without anything already there
     /// intersecting it. Returns the index if so. The position represents the front of the
     /// blockage.
@@ -540,6 +596,7 @@ impl Queue {
                 Queued::Vehicle(c) => Some(*c),
                 Queued::StaticBlockage { .. } => None,
                 Queued::DynamicBlockage { .. } => None,
+                Queued::Incident { .. } => None,
             })
             .collect()
     }
@@ -621,6 +678,9 @@ fn dump_cars(dists: &[QueueEntry], cars: &FixedMap<CarID, Car>, id: Traversable,
             Queued::DynamicBlockage { cause, vehicle_len } => {
                 println!("  Dynamic blockage of length {} by {}", vehicle_len, cause);
             }
+            Queued::Incident { id, .. } => {
+                println!("  Blocked by {}", id);
+            }
         }
     }
     println!();
@@ -6,7 +6,7 @@ use abstutil::{deserialize_multimap, serialize_multimap, FixedMap, IndexableKey,
 use geom::{Distance, Duration, Line, PolyLine, Speed, Time};
 use map_model::{
     BuildingID, BusRouteID, DrivingSide, Map, ParkingLotID, Path, PathConstraints, PathStep,
-    Traversable, SIDEWALK_THICKNESS,
+    Traversable, TurnType, SIDEWALK_THICKNESS,
 };
 
 use crate::sim::Ctx;
@@ -14,15 +14,30 @@ use crate::{
     AgentID, AgentProperties, Command, CommutersVehiclesCounts, CreatePedestrian, DistanceInterval,
     DrawPedCrowdInput, DrawPedestrianInput, Event, Intent, IntersectionSimState, ParkedCar,
     ParkingSpot, PedCrowdLocation, PedestrianID, PersonID, Scheduler, SidewalkPOI, SidewalkSpot,
-    TimeInterval, TransitSimState, TripID, TripManager, UnzoomedAgent,
+    SimOptions, TimeInterval, TransitSimState, TripID, TripManager, UnzoomedAgent,
 };
 
 const TIME_TO_START_BIKING: Duration = Duration::const_seconds(30.0);
 const TIME_TO_FINISH_BIKING: Duration = Duration::const_seconds(45.0);
 
+/// In pedestrians per square meter. Nobody can move in a crowd this dense.
+const JAM_DENSITY: f64 = 5.4;
+/// In pedestrians per square meter. Sidewalks and crosswalks don't admit anybody else past this.
+const MAX_DENSITY: f64 = 4.0;
+/// Even in a very dense crowd, people shuffle forwards at least this fraction of their normal
+/// speed.
+const MIN_CROWDED_SPEED_FACTOR: f64 = 0.1;
+const BLIND_RETRY_TO_ENTER_CROWDED_AREA: Duration = Duration::const_seconds(2.0);
+
 /// Simulates pedestrians. Unlike vehicles, pedestrians can move bidirectionally on sidewalks and
-/// just "ghost" through each other. There's no queueing or slowdown when many people are
-/// overlapping. They're simply grouped together into a DrawPedCrowdInput for rendering.
+/// just "ghost" through each other. By default, there's no queueing or slowdown when many people
+/// are overlapping. They're simply grouped together into a DrawPedCrowdInput for rendering.
+///
+/// If crowding is enabled, people walk slower along crowded sidewalks and crosswalks, following
+/// Weidmann's fundamental diagram. The density is measured when somebody starts walking along
+/// something, and doesn't change until they finish. People won't start a turn if the crosswalk or
+/// the sidewalk after it is full, so crowds spill back. Everybody waiting for a bus counts against
+/// their sidewalk too, so an overcrowded bus stop platform blocks people from walking past it.
 #[derive(Serialize, Deserialize, Clone)]
 pub(crate) struct WalkingSimState {
     peds: FixedMap<PedestrianID, Pedestrian>,
@@ -32,14 +47,16 @@ pub(crate) struct WalkingSimState {
     )]
     peds_per_traversable: MultiMap<Traversable, PedestrianID>,
     events: Vec<Event>,
+    crowding: bool,
 }
 
 impl WalkingSimState {
-    pub fn new() -> WalkingSimState {
+    pub fn new(opts: &SimOptions) -> WalkingSimState {
         WalkingSimState {
             peds: FixedMap::new(),
             peds_per_traversable: MultiMap::new(),
             events: Vec::new(),
+            crowding: opts.pedestrian_crowding,
         }
     }
 
@@ -56,6 +73,8 @@ impl WalkingSimState {
             params.path.last_step().as_lane(),
             params.goal.sidewalk_pos.lane()
         );
+        self.peds_per_traversable
+            .insert(Traversable::Lane(start_lane), params.id);
 
         let mut ped = Pedestrian {
             id: params.id,
@@ -93,15 +112,25 @@ impl WalkingSimState {
                 Line::must_new(driving_pos.pt(map), params.start.sidewalk_pos.pt(map)),
                 TimeInterval::new(now, now + TIME_TO_FINISH_BIKING),
             ),
-            _ => ped.crossing_state(params.start.sidewalk_pos.dist_along(), now, map),
+            _ => {
+                let speed_factor = crowded_speed_factor(
+                    self.crowding,
+                    Traversable::Lane(start_lane),
+                    &self.peds_per_traversable,
+                    &mut self.events,
+                    map,
+                );
+                ped.crossing_state(
+                    params.start.sidewalk_pos.dist_along(),
+                    now,
+                    speed_factor,
+                    map,
+                )
+            }
         };
 
         scheduler.push(ped.state.get_end_time(), Command::UpdatePed(ped.id));
         self.peds.insert(ped.id, ped);
-        self.peds_per_traversable.insert(
-            Traversable::Lane(params.start.sidewalk_pos.lane()),
-            params.id,
-        );
     }
 
     pub fn get_draw_ped(
@@ -233,11 +262,13 @@ impl WalkingSimState {
                         &mut self.peds_per_traversable,
                         &mut self.events,
                         ctx.scheduler,
+                        self.crowding,
                     ) {
                         ctx.scheduler
                             .push(ped.state.get_end_time(), Command::UpdatePed(ped.id));
                     } else {
-                        // Must've failed because we can't turn yet. Don't schedule a retry here.
+                        // Must've failed because we can't turn yet. Don't schedule a retry here;
+                        // either the intersection or maybe_transition already did.
                         ped.state = PedState::WaitingToTurn(dist, now);
                     }
                 }
@@ -250,6 +281,7 @@ impl WalkingSimState {
                     &mut self.peds_per_traversable,
                     &mut self.events,
                     ctx.scheduler,
+                    self.crowding,
                 ) {
                     ctx.scheduler
                         .push(ped.state.get_end_time(), Command::UpdatePed(ped.id));
@@ -263,8 +295,19 @@ impl WalkingSimState {
                 }
             }
             PedState::LeavingBuilding(b, _) => {
-                ped.state =
-                    ped.crossing_state(ctx.map.get_b(b).sidewalk_pos.dist_along(), now, ctx.map);
+                let speed_factor = crowded_speed_factor(
+                    self.crowding,
+                    ped.path.current_step().as_traversable(),
+                    &self.peds_per_traversable,
+                    &mut self.events,
+                    ctx.map,
+                );
+                ped.state = ped.crossing_state(
+                    ctx.map.get_b(b).sidewalk_pos.dist_along(),
+                    now,
+                    speed_factor,
+                    ctx.map,
+                );
                 ctx.scheduler
                     .push(ped.state.get_end_time(), Command::UpdatePed(ped.id));
             }
@@ -282,8 +325,19 @@ impl WalkingSimState {
                 self.peds.remove(&id);
             }
             PedState::LeavingParkingLot(pl, _) => {
-                ped.state =
-                    ped.crossing_state(ctx.map.get_pl(pl).sidewalk_pos.dist_along(), now, ctx.map);
+                let speed_factor = crowded_speed_factor(
+                    self.crowding,
+                    ped.path.current_step().as_traversable(),
+                    &self.peds_per_traversable,
+                    &mut self.events,
+                    ctx.map,
+                );
+                ped.state = ped.crossing_state(
+                    ctx.map.get_pl(pl).sidewalk_pos.dist_along(),
+                    now,
+                    speed_factor,
+                    ctx.map,
+                );
                 ctx.scheduler
                     .push(ped.state.get_end_time(), Command::UpdatePed(ped.id));
             }
@@ -317,7 +371,15 @@ impl WalkingSimState {
                 self.peds.remove(&id);
             }
             PedState::FinishingBiking(ref spot, _, _) => {
-                ped.state = ped.crossing_state(spot.sidewalk_pos.dist_along(), now, ctx.map);
+                let speed_factor = crowded_speed_factor(
+                    self.crowding,
+                    ped.path.current_step().as_traversable(),
+                    &self.peds_per_traversable,
+                    &mut self.events,
+                    ctx.map,
+                );
+                ped.state =
+                    ped.crossing_state(spot.sidewalk_pos.dist_along(), now, speed_factor, ctx.map);
                 ctx.scheduler
                     .push(ped.state.get_end_time(), Command::UpdatePed(ped.id));
             }
@@ -619,7 +681,14 @@ struct Pedestrian {
 }
 
 impl Pedestrian {
-    fn crossing_state(&self, start_dist: Distance, start_time: Time, map: &Map) -> PedState {
+    /// The speed factor slows people down in crowds, and should come from crowded_speed_factor.
+    fn crossing_state(
+        &self,
+        start_dist: Distance,
+        start_time: Time,
+        speed_factor: f64,
+        map: &Map,
+    ) -> PedState {
         let end_dist = if self.path.is_last_step() {
             self.goal.sidewalk_pos.dist_along()
         } else {
@@ -636,7 +705,10 @@ impl Pedestrian {
             PathConstraints::Pedestrian,
             map,
         );
-        let time_int = TimeInterval::new(start_time, start_time + dist_int.length() / speed);
+        let time_int = TimeInterval::new(
+            start_time,
+            start_time + dist_int.length() / (speed * speed_factor),
+        );
         PedState::Crossing {
             dist_int,
             time_int,
@@ -797,8 +869,20 @@ impl Pedestrian {
         peds_per_traversable: &mut MultiMap<Traversable, PedestrianID>,
         events: &mut Vec<Event>,
         scheduler: &mut Scheduler,
+        crowding: bool,
     ) -> bool {
         if let PathStep::Turn(t) = self.path.next_step() {
+            if crowding
+                && !(has_room(Traversable::Turn(t), peds_per_traversable, map)
+                    && has_room(Traversable::Lane(t.dst), peds_per_traversable, map))
+            {
+                // The intersection won't wake us up when the crowd clears, so keep checking.
+                scheduler.update(
+                    now + BLIND_RETRY_TO_ENTER_CROWDED_AREA,
+                    Command::UpdatePed(self.id),
+                );
+                return false;
+            }
             if !intersections.maybe_start_turn(
                 AgentID::Pedestrian(self.id),
                 t,
@@ -823,8 +907,15 @@ impl Pedestrian {
             PathStep::ContraflowLane(l) => map.get_l(l).length(),
             PathStep::Turn(_) => Distance::ZERO,
         };
-        self.state = self.crossing_state(start_dist, now, map);
         peds_per_traversable.insert(self.path.current_step().as_traversable(), self.id);
+        let speed_factor = crowded_speed_factor(
+            crowding,
+            self.path.current_step().as_traversable(),
+            peds_per_traversable,
+            events,
+            map,
+        );
+        self.state = self.crossing_state(start_dist, now, speed_factor, map);
         events.push(Event::AgentEntersTraversable(
             AgentID::Pedestrian(self.id),
             Some(self.trip),
@@ -877,6 +968,85 @@ impl PedState {
     }
 }
 
+/// The area of a sidewalk or crosswalk in square meters, or None for anything else, which is never
+/// considered crowded.
+fn walkable_area(on: Traversable, map: &Map) -> Option<f64> {
+    let area = match on {
+        Traversable::Lane(l) => {
+            let lane = map.get_l(l);
+            if !lane.is_walkable() {
+                return None;
+            }
+            lane.length().inner_meters() * lane.width.inner_meters()
+        }
+        Traversable::Turn(t) => {
+            let turn = map.get_t(t);
+            if turn.turn_type != TurnType::Crosswalk {
+                return None;
+            }
+            turn.geom.length().inner_meters() * map.get_l(t.src).width.inner_meters()
+        }
+    };
+    // Very short crosswalks still fit somebody
+    Some(area.max(1.0))
+}
+
+/// In pedestrians per square meter, counting everybody on something.
+fn pedestrian_density(
+    on: Traversable,
+    peds_per_traversable: &MultiMap<Traversable, PedestrianID>,
+    map: &Map,
+) -> Option<f64> {
+    walkable_area(on, map).map(|area| (peds_per_traversable.get(on).len() as f64) / area)
+}
+
+/// Can somebody else start walking along a sidewalk or crosswalk?
+fn has_room(
+    on: Traversable,
+    peds_per_traversable: &MultiMap<Traversable, PedestrianID>,
+    map: &Map,
+) -> bool {
+    match walkable_area(on, map) {
+        Some(area) => {
+            let capacity = (MAX_DENSITY * area).floor().max(1.0) as usize;
+            peds_per_traversable.get(on).len() < capacity
+        }
+        None => true,
+    }
+}
+
+/// How much of their normal speed somebody starting to walk along something keeps in the crowd
+/// there, including themselves. Also records the density along sidewalks.
+fn crowded_speed_factor(
+    crowding: bool,
+    on: Traversable,
+    peds_per_traversable: &MultiMap<Traversable, PedestrianID>,
+    events: &mut Vec<Event>,
+    map: &Map,
+) -> f64 {
+    if !crowding {
+        return 1.0;
+    }
+    let density = match pedestrian_density(on, peds_per_traversable, map) {
+        Some(x) => x,
+        None => {
+            return 1.0;
+        }
+    };
+    if let Traversable::Lane(l) = on {
+        events.push(Event::PedestrianDensityMeasured(l, density));
+    }
+    weidmann_speed_factor(density)
+}
+
+/// Weidmann's fundamental diagram for pedestrians, relative to the speed in free flow.
+fn weidmann_speed_factor(density: f64) -> f64 {
+    if density <= 0.0 {
+        return 1.0;
+    }
+    (1.0 - (-1.913 * (1.0 / density - 1.0 / JAM_DENSITY)).exp()).max(MIN_CROWDED_SPEED_FACTOR)
+}
+
 // The crowds returned here may have low/high values extending up to radius past the real geometry.
 fn find_crowds(
     input: Vec<(PedestrianID, Distance)>,
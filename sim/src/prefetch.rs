@@ -0,0 +1,114 @@
+//! Most of the time spent simulating a large map goes to calculating paths when trips start.
+//! Events have to be processed one at a time, but the paths for trips about to start can be
+//! predicted ahead of time and calculated in parallel. The simulation still pathfinds in exactly
+//! the same order and gets exactly the same results; some of them are just ready early.
+//!
+//! Updating agents in parallel isn't supported. Each event can change the queues and intersections
+//! that the next one looks at, and splitting them between threads would make results depend on
+//! timing. So `--parallel` deliberately stops at pathfinding. Use `run_scenario --compare_parallel`
+//! to measure how much it helps on a particular scenario.
+
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+
+use abstutil::Timer;
+use geom::{Duration, Time};
+use map_model::{Map, Path, PathRequest};
+
+use crate::{ParkingSimState, Scheduler, TripManager};
+
+/// How far ahead to look for trips starting.
+const LOOKAHEAD: Duration = Duration::const_seconds(10.0 * 60.0);
+
+/// Holds paths calculated ahead of time, until the simulation asks for them.
+#[derive(Clone)]
+pub(crate) struct PathPrefetcher {
+    enabled: bool,
+    // Keyed by request, remembering roughly when the path is needed. Failures aren't kept; they
+    // just get calculated again. A BTreeMap keeps everything deterministic.
+    paths: BTreeMap<PathRequest, (Time, Path)>,
+    // Trips starting before this have already been considered.
+    prefetched_until: Time,
+}
+
+impl PathPrefetcher {
+    pub fn new(enabled: bool) -> PathPrefetcher {
+        PathPrefetcher {
+            enabled,
+            paths: BTreeMap::new(),
+            prefetched_until: Time::START_OF_DAY,
+        }
+    }
+
+    /// Uses a path calculated ahead of time, if there is one. Otherwise just calculates it now.
+    pub fn pathfind(&mut self, req: PathRequest, map: &Map) -> Result<Path> {
+        if let Some((_, path)) = self.paths.remove(&req) {
+            return Ok(path);
+        }
+        map.pathfind(req)
+    }
+
+    /// Calculates paths for trips starting soon that haven't already been handled.
+    pub fn maybe_prefetch(
+        &mut self,
+        now: Time,
+        scheduler: &Scheduler,
+        trips: &TripManager,
+        parking: &ParkingSimState,
+        map: &Map,
+    ) {
+        if !self.enabled {
+            return;
+        }
+        // Each batch is pretty large, so don't bother until the last one is mostly used up.
+        let end = now + LOOKAHEAD;
+        if self.prefetched_until > now + LOOKAHEAD / 2.0 {
+            return;
+        }
+
+        // Predictions that were never used -- maybe a parked car moved, or the trip was
+        // cancelled -- shouldn't pile up.
+        self.paths.retain(|_, (time, _)| *time + LOOKAHEAD >= now);
+
+        let start = self.prefetched_until.max(now);
+        let mut requests = Vec::new();
+        for (time, trip, args) in scheduler.get_trips_starting_between(start, end) {
+            for req in trips.predict_path_requests(trip, &args, parking, map) {
+                if !self.paths.contains_key(&req) {
+                    requests.push((time, req));
+                }
+            }
+        }
+        self.prefetched_until = end;
+        // Duplicate requests, like many people leaving the same building, only get calculated
+        // once.
+        requests.sort_by(|a, b| a.1.cmp(&b.1).then(a.0.cmp(&b.0)));
+        requests.dedup_by(|a, b| a.1 == b.1);
+        if requests.is_empty() {
+            return;
+        }
+
+        let results = Timer::throwaway().parallelize(
+            "prefetch paths for upcoming trips",
+            requests,
+            |(time, req)| {
+                let path = map.pathfind(req.clone()).ok()?;
+                Some((req, (time, path)))
+            },
+        );
+        self.paths.extend(results.into_iter().flatten());
+    }
+
+    /// Forgets everything calculated so far. Call this when the map changes.
+    pub fn clear(&mut self, now: Time) {
+        self.paths.clear();
+        self.prefetched_until = now;
+    }
+}
+
+impl std::default::Default for PathPrefetcher {
+    fn default() -> PathPrefetcher {
+        PathPrefetcher::new(false)
+    }
+}
@@ -4,8 +4,8 @@ use geom::Time;
 use map_model::{IntersectionID, Map, PathStep, Position, Traversable};
 
 use crate::{
-    AgentID, DrivingSimState, Event, IndividTrip, PersonSpec, Scenario, TripEndpoint, TripID,
-    TripMode, TripPurpose, VehicleType,
+    AgentID, CarID, DrivingSimState, Event, IndividTrip, PersonSpec, Scenario, TripEndpoint,
+    TripID, TripMode, TripPurpose, VehicleType,
 };
 
 /// Records trips beginning and ending at a specified set of intersections. This can be used to
@@ -61,11 +61,55 @@ impl TrafficRecorder {
         }
     }
 
+    /// Records a vehicle already somewhere inside the captured area, as if it suddenly appears at
+    /// the start of its current lane. It leaves through the last captured intersection along its
+    /// remaining path.
+    pub fn capture_existing(
+        &mut self,
+        time: Time,
+        car: CarID,
+        trip: TripID,
+        map: &Map,
+        driving: &DrivingSimState,
+    ) {
+        if self.seen_trips.contains(&trip) {
+            return;
+        }
+        let path = match driving.get_path(car) {
+            Some(path) => path,
+            None => {
+                return;
+            }
+        };
+        let start = match path.current_step() {
+            PathStep::Lane(l) | PathStep::ContraflowLane(l) => l,
+            PathStep::Turn(t) => t.dst,
+        };
+        let exit = path.get_steps().iter().rev().find_map(|step| match step {
+            PathStep::Turn(t) if self.capture_points.contains(&t.parent) => Some(t.parent),
+            _ => None,
+        });
+        if let Some(exit) = exit {
+            self.trips.push(IndividTrip::new(
+                time,
+                TripPurpose::Shopping,
+                TripEndpoint::SuddenlyAppear(Position::start(start)),
+                TripEndpoint::Border(exit),
+                if car.vehicle_type == VehicleType::Bike {
+                    TripMode::Bike
+                } else {
+                    TripMode::Drive
+                },
+            ));
+            self.seen_trips.insert(trip);
+        }
+    }
+
     pub fn num_recorded_trips(&self) -> usize {
         self.trips.len()
     }
 
-    pub fn save(mut self, map: &Map) {
+    pub fn into_scenario(mut self, name: String, map: &Map) -> Scenario {
         let mut people = Vec::new();
         for trip in self.trips.drain(..) {
             people.push(PersonSpec {
@@ -74,11 +118,14 @@ impl TrafficRecorder {
             });
         }
         Scenario {
-            scenario_name: "recorded".to_string(),
+            scenario_name: name,
             map_name: map.get_name().clone(),
             people,
             only_seed_buses: None,
         }
-        .save();
+    }
+
+    pub fn save(self, map: &Map) {
+        self.into_scenario("recorded".to_string(), map).save();
     }
 }
@@ -5,7 +5,7 @@ use std::collections::HashMap;
 
 use serde::{Deserialize, Serialize};
 
-use geom::Distance;
+use geom::{Distance, Duration};
 use map_model::{
     BuildingID, IntersectionID, LaneID, Map, Path, PathConstraints, PathRequest, PathStep,
     Position, Traversable, Turn, TurnID,
@@ -466,6 +466,103 @@ impl Router {
         }
     }
 
+    /// Called when the vehicle reaches the end of a lane and is about to pick a turn. If following
+    /// the rest of the path is expected to take at least `threshold` longer than an alternative,
+    /// given the current queues, switch to the alternative. Returns true if the path changed.
+    ///
+    /// The alternatives are the usual fastest path starting from each turn out of the current
+    /// lane. Pathfinding doesn't know about live traffic, so this only finds detours at each
+    /// intersection, not the globally best route.
+    pub fn maybe_reroute(
+        &mut self,
+        vehicle: &Vehicle,
+        queues: &HashMap<Traversable, Queue>,
+        map: &Map,
+        threshold: Duration,
+    ) -> bool {
+        match self.goal {
+            Goal::EndAtBorder { .. } => {}
+            // Once somebody starts looking for parking, the path no longer leads to the original
+            // destination.
+            Goal::ParkNearBuilding {
+                started_looking, ..
+            } => {
+                if started_looking {
+                    return false;
+                }
+            }
+            Goal::BikeThenStop { .. } | Goal::FollowBusRoute { .. } => {
+                return false;
+            }
+        }
+        // Don't abandon an uber-turn that's about to start
+        if self.path.currently_inside_ut().is_some() || self.path.about_to_start_ut().is_some() {
+            return false;
+        }
+        let steps = self.path.get_steps();
+        if steps.len() < 3 {
+            return false;
+        }
+        let current_lane = match steps[0] {
+            PathStep::Lane(l) => l,
+            _ => {
+                return false;
+            }
+        };
+        let end = self.path.get_req().end;
+        if steps.back() != Some(&PathStep::Lane(end.lane())) {
+            return false;
+        }
+
+        let (freeflow, delay) = match estimate_remaining(&self.path, 1, vehicle, queues, map) {
+            Some(pair) => pair,
+            // Part of the current path was closed, so anything is better
+            None => (Duration::ZERO, Duration::hours(24)),
+        };
+        // The original path was probably the fastest with no traffic, so if the queues along it
+        // don't add much delay, no alternative can win by the threshold.
+        if delay < threshold {
+            return false;
+        }
+        let current_cost = freeflow + delay;
+
+        let constraints = vehicle.vehicle_type.to_constraints();
+        let mut best: Option<(Duration, TurnID, Path)> = None;
+        for turn in map.get_turns_from_lane(current_lane) {
+            if !constraints.can_use(map.get_l(turn.id.dst), map) {
+                continue;
+            }
+            let req = PathRequest::vehicle(Position::start(turn.id.dst), end, constraints);
+            let rest = match map.pathfind(req) {
+                Ok(path) => path,
+                Err(_) => continue,
+            };
+            let turn_step = PathStep::Turn(turn.id);
+            let cost = match (
+                estimate_step(turn_step, turn.geom.length(), vehicle, queues, map),
+                estimate_remaining(&rest, 0, vehicle, queues, map),
+            ) {
+                (Some((ff1, delay1)), Some((ff2, delay2))) => ff1 + delay1 + ff2 + delay2,
+                _ => continue,
+            };
+            if best.as_ref().map(|(c, _, _)| cost < *c).unwrap_or(true) {
+                best = Some((cost, turn.id, rest));
+            }
+        }
+
+        if let Some((cost, turn, rest)) = best {
+            if cost + threshold <= current_cost {
+                debug!(
+                    "{} rerouting at the end of {}, expected time {} -> {}",
+                    self.owner, current_lane, current_cost, cost
+                );
+                self.path.replace_after_current_step(turn, rest, map);
+                return true;
+            }
+        }
+        false
+    }
+
     pub fn can_lanechange(&self, from: LaneID, to: LaneID, map: &Map) -> bool {
         let steps = self.path.get_steps();
         if steps.len() < 3 {
@@ -486,14 +583,73 @@ impl Router {
         .is_some()
     }
 
+    /// Like can_lanechange, but if the target lane can't reach the next lane in the path, also
+    /// allow using a different lane on the next road, as long as it still continues the rest of
+    /// the path. This is for vehicles that must change lanes, like when their lane is blocked.
+    pub fn must_lanechange(&self, from: LaneID, to: LaneID, map: &Map) -> bool {
+        self.can_lanechange(from, to, map) || self.lanechange_via_next_road(to, map).is_some()
+    }
+
+    /// After changing to a lane that can't make the current turn, what turn, lane on the next
+    /// road, and turn after that should be used instead?
+    fn lanechange_via_next_road(&self, to: LaneID, map: &Map) -> Option<(TurnID, LaneID, TurnID)> {
+        // Don't mess with uber-turns
+        if self.path.about_to_start_ut().is_some() {
+            return None;
+        }
+        let steps = self.path.get_steps();
+        if steps.len() < 5 {
+            return None;
+        }
+        let (current_turn, next_lane, next_turn) = match (steps[1], steps[2], steps[3]) {
+            (PathStep::Turn(t1), PathStep::Lane(l), PathStep::Turn(t2)) => (t1, l, t2),
+            _ => {
+                return None;
+            }
+        };
+        if self.path.is_upcoming_uber_turn_component(next_turn) {
+            return None;
+        }
+        map.get_l(next_lane)
+            .get_directed_parent()
+            .lanes(self.owner.vehicle_type.to_constraints(), map)
+            .into_iter()
+            .find_map(|l| {
+                let t1 = TurnID {
+                    parent: current_turn.parent,
+                    src: to,
+                    dst: l,
+                };
+                let t2 = TurnID {
+                    parent: next_turn.parent,
+                    src: l,
+                    dst: next_turn.dst,
+                };
+                if map.maybe_get_t(t1).is_some() && map.maybe_get_t(t2).is_some() {
+                    Some((t1, l, t2))
+                } else {
+                    None
+                }
+            })
+    }
+
     pub fn confirm_lanechange(&mut self, to: LaneID, map: &Map) {
         // No assertions, blind trust!
-        self.path.modify_step(0, PathStep::Lane(to), map);
         let mut turn = match self.path.get_steps()[1] {
             PathStep::Turn(t) => t,
             _ => unreachable!(),
         };
         turn.src = to;
+        if map.maybe_get_t(turn).is_none() {
+            // This must've been a mandatory lane-change, so use a different lane on the next road
+            let (turn1, next_lane, turn2) = self.lanechange_via_next_road(to, map).unwrap();
+            self.path.modify_step(0, PathStep::Lane(to), map);
+            self.path.modify_step(1, PathStep::Turn(turn1), map);
+            self.path.modify_step(2, PathStep::Lane(next_lane), map);
+            self.path.modify_step(3, PathStep::Turn(turn2), map);
+            return;
+        }
+        self.path.modify_step(0, PathStep::Lane(to), map);
         self.path.modify_step(1, PathStep::Turn(turn), map);
     }
 
@@ -513,3 +669,59 @@ impl Router {
         }
     }
 }
+
+/// Roughly how long each vehicle waiting in a queue delays the ones behind it, once they start
+/// moving. This corresponds to about 1800 vehicles per hour per lane.
+const QUEUE_DISCHARGE_HEADWAY: Duration = Duration::const_seconds(2.0);
+
+/// Estimates how long it'll take to follow a path, starting with some step, given the current
+/// queues. Returns (the time with no traffic, the extra delay from queues), or None if some step
+/// can't be used anymore, such as a closed lane.
+fn estimate_remaining(
+    path: &Path,
+    skip: usize,
+    vehicle: &Vehicle,
+    queues: &HashMap<Traversable, Queue>,
+    map: &Map,
+) -> Option<(Duration, Duration)> {
+    let mut freeflow = Duration::ZERO;
+    let mut delay = Duration::ZERO;
+    for step in path.get_steps().iter().skip(skip) {
+        let (ff, d) = estimate_step(
+            *step,
+            path.dist_crossed_from_step(map, step),
+            vehicle,
+            queues,
+            map,
+        )?;
+        freeflow += ff;
+        delay += d;
+    }
+    Some((freeflow, delay))
+}
+
+fn estimate_step(
+    step: PathStep,
+    dist: Distance,
+    vehicle: &Vehicle,
+    queues: &HashMap<Traversable, Queue>,
+    map: &Map,
+) -> Option<(Duration, Duration)> {
+    let constraints = vehicle.vehicle_type.to_constraints();
+    match step {
+        PathStep::Lane(l) | PathStep::ContraflowLane(l) => {
+            if !constraints.can_use(map.get_l(l), map) {
+                return None;
+            }
+        }
+        PathStep::Turn(t) => {
+            map.maybe_get_t(t)?;
+        }
+    }
+    let speed = step.max_speed_along(vehicle.max_speed, constraints, map);
+    let num_queued = queues
+        .get(&step.as_traversable())
+        .map(|q| q.target_lane_penalty().0)
+        .unwrap_or(0);
+    Some((dist / speed, QUEUE_DISCHARGE_HEADWAY * (num_queued as f64)))
+}
@@ -9,7 +9,8 @@ use geom::{Duration, Histogram, Time};
 use map_model::{BusRouteID, IntersectionID};
 
 use crate::{
-    pandemic, AgentID, CarID, CreateCar, CreatePedestrian, PedestrianID, StartTripArgs, TripID,
+    pandemic, AgentID, CarID, CreateCar, CreatePedestrian, IncidentID, PedestrianID, StartTripArgs,
+    TripID,
 };
 
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
@@ -27,6 +28,8 @@ pub(crate) enum Command {
     Pandemic(pandemic::Cmd),
     /// The Time is redundant, just used to dedupe commands
     StartBus(BusRouteID, Time),
+    StartIncident(IncidentID),
+    EndIncident(IncidentID),
 }
 
 impl Command {
@@ -50,6 +53,8 @@ impl Command {
             Command::Callback(_) => CommandType::Callback,
             Command::Pandemic(ref p) => CommandType::Pandemic(p.clone()),
             Command::StartBus(r, t) => CommandType::StartBus(*r, *t),
+            Command::StartIncident(id) => CommandType::StartIncident(*id),
+            Command::EndIncident(id) => CommandType::EndIncident(*id),
         }
     }
 
@@ -65,6 +70,7 @@ impl Command {
             Command::Callback(_) => SimpleCommandType::Callback,
             Command::Pandemic(_) => SimpleCommandType::Pandemic,
             Command::StartBus(_, _) => SimpleCommandType::StartBus,
+            Command::StartIncident(_) | Command::EndIncident(_) => SimpleCommandType::Incident,
         }
     }
 }
@@ -81,6 +87,8 @@ enum CommandType {
     Callback,
     Pandemic(pandemic::Cmd),
     StartBus(BusRouteID, Time),
+    StartIncident(IncidentID),
+    EndIncident(IncidentID),
 }
 
 /// A more compressed form of CommandType, just used for keeping stats on event processing.
@@ -94,6 +102,7 @@ enum SimpleCommandType {
     Callback,
     Pandemic,
     StartBus,
+    Incident,
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Clone)]
@@ -238,6 +247,24 @@ impl Scheduler {
         }
     }
 
+    /// Returns every trip scheduled to start in [start, end), sorted by time and then ID.
+    pub fn get_trips_starting_between(
+        &self,
+        start: Time,
+        end: Time,
+    ) -> Vec<(Time, TripID, StartTripArgs)> {
+        let mut trips = Vec::new();
+        for (cmd, time) in self.queued_commands.values() {
+            if let Command::StartTrip(trip, args) = cmd {
+                if *time >= start && *time < end {
+                    trips.push((*time, *trip, args.clone()));
+                }
+            }
+        }
+        trips.sort_by_key(|(time, trip, _)| (*time, *trip));
+        trips
+    }
+
     pub fn describe_stats(&self) -> Vec<String> {
         let mut stats = vec![
             format!("delta times for events: {}", self.delta_times.describe()),
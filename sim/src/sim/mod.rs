@@ -1,6 +1,6 @@
 // This file has a jumbled mess of queries, setup, and mutating methods.
 
-use std::collections::{BTreeSet, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 use std::panic;
 
 use anyhow::Result;
@@ -18,18 +18,22 @@ use map_model::{
 };
 
 pub use self::queries::{AgentProperties, DelayCause};
+use crate::gridlock::{find_affected, find_cycles};
 use crate::{
-    AgentID, AlertLocation, Analytics, CarID, Command, CreateCar, DrivingSimState, Event,
+    AgentID, AlertLocation, Analytics, CarFollowingModel, CarID, Command, CreateCar,
+    DrivingSimState, Event, GridlockCycle, GridlockReport, Incident, IncidentID, IncidentKind,
     IntersectionSimState, OrigPersonID, PandemicModel, ParkedCar, ParkingSim, ParkingSimState,
-    ParkingSpot, Person, PersonID, Router, Scheduler, SidewalkPOI, SidewalkSpot, StartTripArgs,
-    TrafficRecorder, TransitSimState, TripID, TripInfo, TripManager, TripPhaseType, Vehicle,
-    VehicleSpec, VehicleType, WalkingSimState, BUS_LENGTH, LIGHT_RAIL_LENGTH, MIN_CAR_LENGTH,
+    ParkingSpot, PathPrefetcher, Person, PersonID, Router, Scheduler, SidewalkPOI, SidewalkSpot,
+    StartTripArgs, TrafficRecorder, TransitSimState, TripID, TripInfo, TripManager, TripPhaseType,
+    Vehicle, VehicleSpec, VehicleType, WalkingSimState, BUS_LENGTH, LIGHT_RAIL_LENGTH,
+    MIN_CAR_LENGTH,
 };
 
 mod queries;
 
 // TODO Do something else.
 const BLIND_RETRY_TO_SPAWN: Duration = Duration::const_seconds(5.0);
+const BLIND_RETRY_TO_START_INCIDENT: Duration = Duration::const_seconds(5.0);
 
 /// The Sim ties together all the pieces of the simulation. Its main property is the current time.
 #[derive(Serialize, Deserialize, Clone)]
@@ -55,9 +59,15 @@ pub struct Sim {
     highlighted_people: Option<BTreeSet<PersonID>>,
 
     analytics: Analytics,
+    incidents: Vec<Incident>,
+    // Incidents currently affecting traffic, and when they actually started
+    active_incidents: BTreeMap<IncidentID, Time>,
     // This is created interactively, and there's no reason to preserve one for savestates.
     #[serde(skip_serializing, skip_deserializing)]
     recorder: Option<TrafficRecorder>,
+    // Only holds paths calculated ahead of time, which get recalculated if they're missing.
+    #[serde(skip_serializing, skip_deserializing)]
+    prefetcher: PathPrefetcher,
 
     #[serde(skip_serializing, skip_deserializing)]
     alerts: AlertHandler,
@@ -68,11 +78,27 @@ pub(crate) struct Ctx<'a> {
     pub intersections: &'a mut IntersectionSimState,
     pub scheduler: &'a mut Scheduler,
     pub map: &'a Map,
+    pub prefetcher: &'a mut PathPrefetcher,
     /// If present, live map edits are being processed, and the agents specified are in the process
     /// of being deleted. Some regular work should maybe be skipped.
     pub handling_live_edits: Option<BTreeSet<AgentID>>,
 }
 
+impl<'a> Ctx<'a> {
+    /// Calculates a path, possibly using one prefetched in parallel.
+    pub fn pathfind(&mut self, req: PathRequest) -> Result<Path> {
+        self.prefetcher.pathfind(req, self.map)
+    }
+
+    /// Like `pathfind`, but follows a fixed route first, if there is one.
+    pub fn pathfind_via(&mut self, req: PathRequest, route: Option<&Path>) -> Result<Path> {
+        match route {
+            Some(route) => self.map.pathfind_via(req, route),
+            None => self.pathfind(req),
+        }
+    }
+}
+
 /// Options controlling the traffic simulation.
 #[derive(Clone)]
 pub struct SimOptions {
@@ -88,6 +114,23 @@ pub struct SimOptions {
     /// As a vehicle follows a route, opportunistically make small changes to use a different lane,
     /// based on some score of "least-loaded" lane.
     pub recalc_lanechanging: bool,
+    /// The fraction of drivers, from 0 to 1, who react to live traffic. When they reach an
+    /// intersection and their remaining route is expected to take longer than an alternative by
+    /// `reroute_threshold`, they switch routes. Only cars reroute.
+    pub informed_drivers: f64,
+    /// How much faster an alternative route must be for an informed driver to switch.
+    pub reroute_threshold: Duration,
+    /// How vehicles speed up and slow down. The default discrete model is much faster to
+    /// simulate, but ignores the time lost accelerating and braking.
+    pub car_following: CarFollowingModel,
+    /// Let vehicles change lanes in the middle of a lane, not just to overtake slow vehicles: to
+    /// pass vehicles stopped at a bus stop or parking, to move into a much shorter queue, or to get
+    /// around a lane blocked by an incident. Where several lanes feed into one, vehicles from each
+    /// lane take turns merging, like a zipper.
+    pub mid_block_lanechanging: bool,
+    /// Make pedestrians walk slower along crowded sidewalks and crosswalks, and wait to enter them
+    /// when they're full. Sidewalk level of service is recorded in Analytics.
+    pub pedestrian_crowding: bool,
     /// If a cycle of vehicles depending on each other to turn is detected, temporarily allow
     /// "blocking the box" to try to break gridlock.
     pub break_turn_conflict_cycles: bool,
@@ -111,6 +154,10 @@ pub struct SimOptions {
     /// Don't collect any analytics. Only useful for benchmarking and debugging gridlock more
     /// quickly.
     pub skip_analytics: bool,
+    /// Calculate paths for trips that're about to start ahead of time, using all CPUs. This is the
+    /// only thing done in parallel; events are still processed one at a time in the same order, so
+    /// results are identical to running without this.
+    pub parallel_pathfinding: bool,
 }
 
 impl std::default::Default for SimOptions {
@@ -128,6 +175,22 @@ impl SimOptions {
             use_freeform_policy_everywhere: args.enabled("--freeform_policy"),
             dont_block_the_box: !args.enabled("--disable_block_the_box"),
             recalc_lanechanging: !args.enabled("--disable_recalc_lc"),
+            informed_drivers: args
+                .optional_parse("--informed_drivers", |s| s.parse::<f64>())
+                .unwrap_or(0.0),
+            reroute_threshold: args
+                .optional_parse("--reroute_threshold", Duration::parse)
+                .unwrap_or_else(|| Duration::minutes(1)),
+            car_following: args
+                .optional("--car_following")
+                .map(|x| {
+                    CarFollowingModel::parse(&x).unwrap_or_else(|| {
+                        panic!("Bad --car_following={}. Must be discrete|idm", x)
+                    })
+                })
+                .unwrap_or(CarFollowingModel::Discrete),
+            mid_block_lanechanging: args.enabled("--mid_block_lc"),
+            pedestrian_crowding: args.enabled("--ped_crowding"),
             break_turn_conflict_cycles: !args.enabled("--disable_break_turn_conflict_cycles"),
             handle_uber_turns: !args.enabled("--disable_handle_uber_turns"),
             enable_pandemic_model: if args.enabled("--pandemic") {
@@ -147,6 +210,7 @@ impl SimOptions {
             infinite_parking: args.enabled("--infinite_parking"),
             disable_turn_conflicts: args.enabled("--disable_turn_conflicts"),
             skip_analytics: args.enabled("--skip_analytics"),
+            parallel_pathfinding: args.enabled("--parallel"),
         }
     }
 }
@@ -174,6 +238,11 @@ impl SimOptions {
             use_freeform_policy_everywhere: false,
             dont_block_the_box: true,
             recalc_lanechanging: true,
+            informed_drivers: 0.0,
+            reroute_threshold: Duration::minutes(1),
+            car_following: CarFollowingModel::Discrete,
+            mid_block_lanechanging: false,
+            pedestrian_crowding: false,
             break_turn_conflict_cycles: true,
             handle_uber_turns: true,
             enable_pandemic_model: None,
@@ -181,6 +250,7 @@ impl SimOptions {
             infinite_parking: false,
             disable_turn_conflicts: false,
             skip_analytics: false,
+            parallel_pathfinding: false,
         }
     }
 }
@@ -206,7 +276,7 @@ impl Sim {
         Sim {
             driving: DrivingSimState::new(map, &opts),
             parking: ParkingSimState::new(map, opts.infinite_parking, &mut timer),
-            walking: WalkingSimState::new(),
+            walking: WalkingSimState::new(&opts),
             intersections: IntersectionSimState::new(map, &mut scheduler, &opts),
             transit: TransitSimState::new(map),
             trips: TripManager::new(),
@@ -222,7 +292,10 @@ impl Sim {
             alerts: opts.alerts,
 
             analytics: Analytics::new(!opts.skip_analytics),
+            incidents: Vec::new(),
+            active_incidents: BTreeMap::new(),
             recorder: None,
+            prefetcher: PathPrefetcher::new(opts.parallel_pathfinding),
         }
     }
 
@@ -284,6 +357,7 @@ impl Sim {
             vehicle_type: VehicleType::Car,
             length: MIN_CAR_LENGTH,
             max_speed: None,
+            accel_limits: VehicleType::Car.default_accel_limits(),
         };
         let driving_lane = map.find_driving_lane_near_building(b);
 
@@ -346,6 +420,7 @@ impl Sim {
             vehicle_type,
             length,
             max_speed: None,
+            accel_limits: vehicle_type.default_accel_limits(),
         }
         .make(
             CarID {
@@ -390,6 +465,8 @@ impl Sim {
         maybe_cb: &mut Option<Box<dyn SimCallback>>,
     ) -> bool {
         self.step_count += 1;
+        self.prefetcher
+            .maybe_prefetch(self.time, &self.scheduler, &self.trips, &self.parking, map);
 
         let max_time = if let Some(t) = self.scheduler.peek_next_time() {
             if t > self.time + max_dt {
@@ -437,6 +514,7 @@ impl Sim {
             intersections: &mut self.intersections,
             scheduler: &mut self.scheduler,
             map,
+            prefetcher: &mut self.prefetcher,
             handling_live_edits: None,
         };
 
@@ -631,6 +709,63 @@ impl Sim {
             Command::StartBus(r, _) => {
                 self.start_bus(map.get_br(r), map);
             }
+            Command::StartIncident(id) => {
+                let incident = self.incidents[id.0].clone();
+                let started = match incident.kind {
+                    IncidentKind::LaneBlockage(pos) => self
+                        .driving
+                        .start_lane_blockage(id, pos, self.time, &mut ctx),
+                    IncidentKind::CapacityReduction { road, factor } => {
+                        self.driving.slow_down_road(id, road, factor);
+                        true
+                    }
+                    IncidentKind::IntersectionClosure(i) => {
+                        self.intersections.close_intersection(id, i);
+                        true
+                    }
+                };
+                if started {
+                    self.active_incidents.insert(id, self.time);
+                    events.push(Event::IncidentStarted(id));
+                } else if self.time + BLIND_RETRY_TO_START_INCIDENT < incident.end {
+                    // Somebody's in the way; try again soon
+                    self.scheduler.push(
+                        self.time + BLIND_RETRY_TO_START_INCIDENT,
+                        Command::StartIncident(id),
+                    );
+                }
+            }
+            Command::EndIncident(id) => {
+                // If a lane blockage is still waiting for room, give up
+                self.scheduler.cancel(Command::StartIncident(id));
+                if let Some(started) = self.active_incidents.remove(&id) {
+                    match self.incidents[id.0].kind {
+                        IncidentKind::LaneBlockage(pos) => {
+                            self.driving.end_lane_blockage(
+                                id,
+                                pos.lane(),
+                                started,
+                                self.time,
+                                &mut ctx,
+                            );
+                        }
+                        IncidentKind::CapacityReduction { road, .. } => {
+                            self.driving.restore_road_speed(id, road);
+                        }
+                        IncidentKind::IntersectionClosure(i) => {
+                            self.intersections.reopen_intersection(
+                                id,
+                                i,
+                                started,
+                                self.time,
+                                &mut self.scheduler,
+                                map,
+                            );
+                        }
+                    }
+                    events.push(Event::IncidentEnded(id));
+                }
+            }
         }
 
         // Record events at precisely the time they occur.
@@ -821,8 +956,12 @@ impl Sim {
         abstio::find_next_file(self.save_path(base_time))
     }
 
-    pub fn load_savestate(path: String, timer: &mut Timer) -> Result<Sim> {
-        abstio::maybe_read_binary(path, timer)
+    /// Savestates don't remember options that only affect performance, like
+    /// `parallel_pathfinding`, so they're taken from `opts` instead.
+    pub fn load_savestate(path: String, opts: &SimOptions, timer: &mut Timer) -> Result<Sim> {
+        let mut sim: Sim = abstio::maybe_read_binary(path, timer)?;
+        sim.prefetcher = PathPrefetcher::new(opts.parallel_pathfinding);
+        Ok(sim)
     }
 }
 
@@ -837,6 +976,8 @@ impl Sim {
     /// (trips cancelled, parked cars displaced).
     pub fn handle_live_edits(&mut self, map: &Map, timer: &mut Timer) -> (usize, usize) {
         self.edits_name = map.get_edits().edits_name.clone();
+        // Anything calculated ahead of time used the old map
+        self.prefetcher.clear(self.time);
 
         let (affected, num_parked_cars) = self.find_trips_affected_by_live_edits(map, timer);
         let num_trips_cancelled = affected.len();
@@ -850,6 +991,7 @@ impl Sim {
             intersections: &mut self.intersections,
             scheduler: &mut self.scheduler,
             map,
+            prefetcher: &mut self.prefetcher,
             handling_live_edits: Some(affected_agents),
         };
         for (agent, trip) in affected {
@@ -965,6 +1107,7 @@ impl Sim {
                 intersections: &mut self.intersections,
                 scheduler: &mut self.scheduler,
                 map,
+                prefetcher: &mut self.prefetcher,
                 handling_live_edits: None,
             };
             let vehicle = self.driving.delete_car(id, self.time, &mut ctx);
@@ -1022,6 +1165,95 @@ impl Sim {
     }
 }
 
+// Gridlock
+impl Sim {
+    /// Looks for cycles of agents blocking each other, where everybody has been stuck for at least
+    /// `min_delay`. If there are any, explains why and captures the vehicles involved.
+    pub fn diagnose_gridlock(&self, map: &Map, min_delay: Duration) -> Option<GridlockReport> {
+        let graph = self.get_blocked_by_graph(map);
+        let cycles = find_cycles(&graph, min_delay);
+        if cycles.is_empty() {
+            return None;
+        }
+        let affected = find_affected(&graph, &cycles);
+        let agent_intersections = self.intersections.get_agent_intersections();
+
+        let mut results = Vec::new();
+        let mut all_intersections = BTreeSet::new();
+        for (agents, affected) in cycles.into_iter().zip(affected) {
+            let intersections: BTreeSet<IntersectionID> = agents
+                .iter()
+                .filter_map(|a| agent_intersections.get(a).cloned())
+                .collect();
+            all_intersections.extend(intersections.iter().cloned());
+            results.push(GridlockCycle {
+                stuck_for: agents.iter().map(|a| graph[a].0).min().unwrap(),
+                cause: self.driving.gridlock_cause(self.time, &agents),
+                intersections,
+                affected,
+                agents,
+            });
+        }
+
+        let mut recorder = TrafficRecorder::new(all_intersections);
+        for cycle in &results {
+            for a in cycle.agents.iter().chain(cycle.affected.iter()) {
+                if let (AgentID::Car(car), Some(trip)) = (a, self.trips.agent_to_trip(*a)) {
+                    recorder.capture_existing(self.time, *car, trip, map, &self.driving);
+                }
+            }
+        }
+
+        Some(GridlockReport {
+            map: map.get_name().clone(),
+            time: self.time,
+            capture: recorder.into_scenario(format!("gridlock_{}", self.time.as_filename()), map),
+            cycles: results,
+        })
+    }
+}
+
+// Incidents
+impl Sim {
+    /// Schedules an incident to disrupt traffic from its start to end time. It can't start in the
+    /// past.
+    pub fn schedule_incident(&mut self, incident: Incident, map: &Map) -> Result<IncidentID> {
+        incident.validate(map)?;
+        if incident.start < self.time {
+            bail!(
+                "{} starts at {}, but the simulation is already at {}",
+                incident.description,
+                incident.start,
+                self.time
+            );
+        }
+        let id = IncidentID(self.incidents.len());
+        self.scheduler
+            .push(incident.start, Command::StartIncident(id));
+        self.scheduler.push(incident.end, Command::EndIncident(id));
+        self.incidents.push(incident);
+        Ok(id)
+    }
+
+    pub fn get_incident(&self, id: IncidentID) -> &Incident {
+        &self.incidents[id.0]
+    }
+
+    /// All incidents ever scheduled, including ones that haven't started or have already ended
+    pub fn get_all_incidents(&self) -> Vec<(IncidentID, &Incident)> {
+        self.incidents
+            .iter()
+            .enumerate()
+            .map(|(idx, incident)| (IncidentID(idx), incident))
+            .collect()
+    }
+
+    /// Incidents currently affecting traffic
+    pub fn get_active_incidents(&self) -> Vec<IncidentID> {
+        self.active_incidents.keys().cloned().collect()
+    }
+}
+
 // Managing highlighted people
 impl Sim {
     pub fn set_highlighted_people(&mut self, people: BTreeSet<PersonID>) {
@@ -11,10 +11,10 @@ use map_model::{
 use crate::sim::Ctx;
 use crate::{
     AgentID, AgentType, AlertLocation, CarID, Command, CreateCar, CreatePedestrian, DrivingGoal,
-    Event, IndividTrip, OrigPersonID, ParkedCar, ParkingSim, ParkingSpot, PedestrianID, PersonID,
-    PersonSpec, Scenario, SidewalkPOI, SidewalkSpot, StartTripArgs, TransitSimState, TripEndpoint,
-    TripID, TripPhaseType, TripPurpose, TripSpec, Vehicle, VehicleSpec, VehicleType,
-    WalkingSimState,
+    Event, IndividTrip, OrigPersonID, ParkedCar, ParkingSim, ParkingSimState, ParkingSpot,
+    PedestrianID, PersonID, PersonSpec, Scenario, SidewalkPOI, SidewalkSpot, StartTripArgs,
+    TransitSimState, TripEndpoint, TripID, TripPhaseType, TripPurpose, TripSpec, Vehicle,
+    VehicleSpec, VehicleType, WalkingSimState,
 };
 
 /// Manages people, each of which executes some trips through the day. Each trip is further broken
@@ -205,7 +205,7 @@ impl TripManager {
                 );
                 let person = person.id;
 
-                match ctx.map.pathfind(req) {
+                match ctx.pathfind_via(req, args.route.as_ref()) {
                     Ok(path) => {
                         let router = goal.make_router(vehicle.id, path, ctx.map);
                         ctx.scheduler.push(
@@ -238,7 +238,7 @@ impl TripManager {
                     let walking_goal =
                         SidewalkSpot::parking_spot(parked_car.spot, ctx.map, ctx.parking);
                     let req = PathRequest::walking(start.sidewalk_pos, walking_goal.sidewalk_pos);
-                    match ctx.map.pathfind(req) {
+                    match ctx.pathfind(req) {
                         Ok(path) => {
                             ctx.scheduler.push(
                                 now,
@@ -306,7 +306,7 @@ impl TripManager {
                 person.state = PersonState::Trip(trip);
 
                 let req = PathRequest::walking(start.sidewalk_pos, goal.sidewalk_pos);
-                match ctx.map.pathfind(req) {
+                match ctx.pathfind_via(req, args.route.as_ref()) {
                     Ok(path) => {
                         ctx.scheduler.push(
                             now,
@@ -335,7 +335,7 @@ impl TripManager {
                         SidewalkSpot::building(start, ctx.map).sidewalk_pos,
                         walk_to.sidewalk_pos,
                     );
-                    match ctx.map.pathfind(req) {
+                    match ctx.pathfind(req) {
                         Ok(path) => {
                             // Where we start biking may have slightly changed due to live map
                             // edits!
@@ -409,7 +409,7 @@ impl TripManager {
 
                 let walk_to = SidewalkSpot::bus_stop(stop1, ctx.map);
                 let req = PathRequest::walking(start.sidewalk_pos, walk_to.sidewalk_pos);
-                match ctx.map.pathfind(req) {
+                match ctx.pathfind(req) {
                     Ok(path) => {
                         ctx.scheduler.push(
                             now,
@@ -432,6 +432,102 @@ impl TripManager {
         }
     }
 
+    /// Guesses the paths that'll be requested when this trip starts, mirroring start_trip. For
+    /// somebody walking to their parked car, also includes the drive afterwards. Guesses might be
+    /// wrong -- the car might move before the trip starts, or the person might still be busy with
+    /// a previous trip -- so nothing should depend on them.
+    pub fn predict_path_requests(
+        &self,
+        trip: TripID,
+        args: &StartTripArgs,
+        parking: &ParkingSimState,
+        map: &Map,
+    ) -> Vec<PathRequest> {
+        let info = &self.trips[trip.0].info;
+        // Replayed routes don't need the pathfinder much
+        if info.cancellation_reason.is_some() || args.route.is_some() {
+            return Vec::new();
+        }
+        let spec = match TripSpec::maybe_new(
+            info.start,
+            info.end,
+            info.mode,
+            args.use_vehicle,
+            args.retry_if_no_room,
+            map,
+        ) {
+            Ok(spec) => spec,
+            Err(_) => {
+                return Vec::new();
+            }
+        };
+
+        match spec.into_plan(map).0 {
+            TripSpec::VehicleAppearing {
+                start_pos,
+                goal,
+                use_vehicle,
+                ..
+            } => {
+                let constraints = if use_vehicle.vehicle_type == VehicleType::Bike {
+                    PathConstraints::Bike
+                } else {
+                    PathConstraints::Car
+                };
+                goal.goal_pos(constraints, map)
+                    .map(|end| PathRequest::vehicle(start_pos, end, constraints))
+                    .into_iter()
+                    .collect()
+            }
+            TripSpec::SpawningFailure { .. } => Vec::new(),
+            TripSpec::UsingParkedCar {
+                car,
+                start_bldg,
+                goal,
+            } => {
+                let parked_car = match parking.lookup_parked_car(car) {
+                    Some(p) => p,
+                    None => {
+                        return Vec::new();
+                    }
+                };
+                let mut requests = vec![PathRequest::walking(
+                    SidewalkSpot::building(start_bldg, map).sidewalk_pos,
+                    SidewalkSpot::parking_spot(parked_car.spot, map, parking).sidewalk_pos,
+                )];
+                if let Some(end) = goal.goal_pos(PathConstraints::Car, map) {
+                    let start =
+                        parking.spot_to_driving_pos(parked_car.spot, &parked_car.vehicle, map);
+                    requests.push(match parked_car.spot {
+                        ParkingSpot::Onstreet(_, _) => {
+                            PathRequest::vehicle(start, end, PathConstraints::Car)
+                        }
+                        ParkingSpot::Offstreet(_, _) | ParkingSpot::Lot(_, _) => {
+                            PathRequest::leave_from_driveway(start, end, PathConstraints::Car, map)
+                        }
+                    });
+                }
+                requests
+            }
+            TripSpec::JustWalking { start, goal } => {
+                vec![PathRequest::walking(start.sidewalk_pos, goal.sidewalk_pos)]
+            }
+            TripSpec::UsingBike { start, .. } => SidewalkSpot::bike_rack(start, map)
+                .map(|walk_to| {
+                    PathRequest::walking(
+                        SidewalkSpot::building(start, map).sidewalk_pos,
+                        walk_to.sidewalk_pos,
+                    )
+                })
+                .into_iter()
+                .collect(),
+            TripSpec::UsingTransit { start, stop1, .. } => vec![PathRequest::walking(
+                start.sidewalk_pos,
+                SidewalkSpot::bus_stop(stop1, map).sidewalk_pos,
+            )],
+        }
+    }
+
     pub fn collect_events(&mut self) -> Vec<Event> {
         std::mem::take(&mut self.events)
     }
@@ -544,7 +640,7 @@ impl TripManager {
 
         let person = trip.person;
         let trip = trip.id;
-        match ctx.map.pathfind(req) {
+        match ctx.pathfind(req) {
             Ok(path) => {
                 let router = drive_to.make_router(parked_car.vehicle.id, path, ctx.map);
                 ctx.scheduler.push(
@@ -612,8 +708,7 @@ impl TripManager {
                 req.start.lane()
             ))
         } else {
-            ctx.map
-                .pathfind(req)
+            ctx.pathfind(req)
                 .map(|path| drive_to.make_router(bike, path, ctx.map))
         };
         match maybe_router {
@@ -934,7 +1029,7 @@ impl TripManager {
         };
 
         let req = PathRequest::walking(start.sidewalk_pos, walk_to.sidewalk_pos);
-        match ctx.map.pathfind(req) {
+        match ctx.pathfind(req) {
             Ok(path) => {
                 let person = &self.people[trip.person.0];
                 ctx.scheduler.push(
@@ -0,0 +1,14 @@
+//! Converts an A/B Street map into a SUMO .net.xml.
+
+use abstutil::{CmdArgs, Timer};
+use map_model::Map;
+
+fn main() -> anyhow::Result<()> {
+    let mut timer = Timer::new("export SUMO network");
+    let mut args = CmdArgs::new();
+    let map = Map::load_synchronously(args.required("--map"), &mut timer);
+    let output = args.required("--output");
+    args.done();
+
+    sumo::export_network(&map, &output, &mut timer)
+}
@@ -0,0 +1,17 @@
+//! Converts an A/B Street scenario into a SUMO .rou.xml. The routes refer to the network produced
+//! by export_sumo_network for the same map.
+
+use abstutil::{CmdArgs, Timer};
+use map_model::Map;
+use sim::Scenario;
+
+fn main() -> anyhow::Result<()> {
+    let mut timer = Timer::new("export SUMO routes");
+    let mut args = CmdArgs::new();
+    let map = Map::load_synchronously(args.required("--map"), &mut timer);
+    let scenario: Scenario = abstio::read_binary(args.required("--scenario"), &mut timer);
+    let output = args.required("--output");
+    args.done();
+
+    sumo::export_scenario(&scenario, &map, &output, &mut timer)
+}
@@ -0,0 +1,23 @@
+//! Converts a SUMO route or trip file into an A/B Street scenario for a map. The SUMO network that
+//! the routes refer to must also be provided.
+
+use abstutil::{CmdArgs, Timer};
+use map_model::Map;
+use sumo::{Demand, Network};
+
+fn main() -> anyhow::Result<()> {
+    let mut timer = Timer::new("import SUMO routes");
+    let mut args = CmdArgs::new();
+    let map = Map::load_synchronously(args.required("--map"), &mut timer);
+    let network = Network::load(&args.required("--net"), &mut timer)?;
+    let routes = args.required("--routes");
+    let scenario_name = args
+        .optional("--scenario_name")
+        .unwrap_or_else(|| "sumo".to_string());
+    args.done();
+
+    let demand = Demand::load(&routes, &mut timer)?;
+    let scenario = sumo::import_scenario(&map, &network, demand, &scenario_name, &mut timer);
+    scenario.save();
+    Ok(())
+}
@@ -0,0 +1,349 @@
+//! Parse SUMO [demand](https://sumo.dlr.de/docs/Definition_of_Vehicles,_Vehicle_Types,_and_Routes.html)
+//! from route and trip files. Only what A/B Street can express is kept: every vehicle or person is
+//! reduced to a sequence of trips between two edges.
+
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+
+use abstutil::{prettyprint_usize, Timer};
+use geom::{Distance, Duration, Time};
+use sim::{TripMode, TripPurpose};
+
+use crate::{EdgeID, VehicleClass};
+
+pub struct Demand {
+    pub travelers: Vec<Traveler>,
+}
+
+/// A SUMO vehicle or person
+pub struct Traveler {
+    pub id: String,
+    pub trips: Vec<Trip>,
+}
+
+pub struct Trip {
+    pub depart: Time,
+    pub from: EdgeID,
+    pub to: EdgeID,
+    /// Measured from the start of the edge
+    pub depart_pos: Option<Distance>,
+    /// Measured from the start of the edge
+    pub arrival_pos: Option<Distance>,
+    pub mode: TripMode,
+    /// From the `actType` of the stop ending a person's trip, or a "purpose" parameter on the
+    /// vehicle or person. None if the input doesn't say.
+    pub purpose: Option<TripPurpose>,
+}
+
+impl Demand {
+    /// Reads vehicles, trips, and persons from a .rou.xml or .trips.xml file. Flows and anything
+    /// with a non-numeric departure time are skipped.
+    pub fn load(path: &str, timer: &mut Timer) -> Result<Demand> {
+        timer.start(format!("read {}", path));
+        let bytes = abstio::slurp_file(path)?;
+        let raw_string = std::str::from_utf8(&bytes)?;
+        let tree = roxmltree::Document::parse(raw_string)?;
+        timer.stop(format!("read {}", path));
+
+        let mut vehicle_types: BTreeMap<String, VehicleClass> = BTreeMap::new();
+        let mut routes: BTreeMap<String, Vec<EdgeID>> = BTreeMap::new();
+        for obj in tree.root_element().children() {
+            match obj.tag_name().name() {
+                "vType" => {
+                    vehicle_types.insert(
+                        attr(obj, "id")?.to_string(),
+                        obj.attribute("vClass")
+                            .map(VehicleClass::parse)
+                            .unwrap_or(VehicleClass::Passenger),
+                    );
+                }
+                "route" => {
+                    if let Some(id) = obj.attribute("id") {
+                        routes.insert(id.to_string(), parse_edges(attr(obj, "edges")?));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let mut travelers = Vec::new();
+        let mut skipped = 0;
+        for obj in tree.root_element().children() {
+            let result = match obj.tag_name().name() {
+                "vehicle" | "trip" => parse_vehicle(obj, &vehicle_types, &routes),
+                "person" => parse_person(obj),
+                "flow" | "personFlow" => {
+                    skipped += 1;
+                    continue;
+                }
+                _ => {
+                    continue;
+                }
+            };
+            match result {
+                Ok(traveler) => {
+                    if !traveler.trips.is_empty() {
+                        travelers.push(traveler);
+                    }
+                }
+                Err(err) => {
+                    warn!("Skipping {}: {}", obj.tag_name().name(), err);
+                    skipped += 1;
+                }
+            }
+        }
+        info!(
+            "Got {} travelers from {}, skipped {}",
+            prettyprint_usize(travelers.len()),
+            path,
+            prettyprint_usize(skipped)
+        );
+
+        Ok(Demand { travelers })
+    }
+}
+
+fn parse_vehicle(
+    obj: roxmltree::Node,
+    vehicle_types: &BTreeMap<String, VehicleClass>,
+    routes: &BTreeMap<String, Vec<EdgeID>>,
+) -> Result<Traveler> {
+    let id = attr(obj, "id")?.to_string();
+    let class = obj
+        .attribute("type")
+        .and_then(|t| vehicle_types.get(t))
+        .cloned()
+        .unwrap_or(VehicleClass::Passenger);
+    let mode = match class {
+        VehicleClass::Passenger => TripMode::Drive,
+        VehicleClass::Bicycle => TripMode::Bike,
+        // Buses are seeded separately from transit routes
+        other => bail!("unsupported vehicle class {}", other.as_str()),
+    };
+
+    let edges = if let (Some(from), Some(to)) = (obj.attribute("from"), obj.attribute("to")) {
+        vec![EdgeID(from.to_string()), EdgeID(to.to_string())]
+    } else if let Some(route) = obj.attribute("route") {
+        routes
+            .get(route)
+            .cloned()
+            .ok_or_else(|| anyhow!("unknown route {}", route))?
+    } else if let Some(route) = obj.children().find(|c| c.has_tag_name("route")) {
+        parse_edges(attr(route, "edges")?)
+    } else {
+        bail!("no route");
+    };
+    if edges.is_empty() {
+        bail!("empty route");
+    }
+
+    Ok(Traveler {
+        id,
+        trips: vec![Trip {
+            depart: parse_time(attr(obj, "depart")?)?,
+            from: edges[0].clone(),
+            to: edges.last().unwrap().clone(),
+            depart_pos: parse_pos(obj.attribute("departPos")),
+            arrival_pos: parse_pos(obj.attribute("arrivalPos")),
+            mode,
+            purpose: param(obj, "purpose").and_then(parse_purpose),
+        }],
+    })
+}
+
+/// Consecutive stages in a person's plan are merged into one trip, using the most "significant"
+/// mode. A stop ends the current trip, and its activity type is the purpose of that trip.
+fn parse_person(obj: roxmltree::Node) -> Result<Traveler> {
+    let id = attr(obj, "id")?.to_string();
+    let mut depart = parse_time(attr(obj, "depart")?)?;
+    let mut depart_pos = parse_pos(obj.attribute("departPos"));
+
+    let mut trips = Vec::new();
+    let mut current: Option<Trip> = None;
+    for stage in obj.children().filter(|c| c.is_element()) {
+        let mode = match stage.tag_name().name() {
+            "walk" => TripMode::Walk,
+            "ride" => TripMode::Transit,
+            "personTrip" => {
+                let modes = stage.attribute("modes").unwrap_or("");
+                if modes.contains("public") {
+                    TripMode::Transit
+                } else if modes.contains("car") {
+                    TripMode::Drive
+                } else if modes.contains("bicycle") {
+                    TripMode::Bike
+                } else {
+                    TripMode::Walk
+                }
+            }
+            "stop" => {
+                if let Some(mut trip) = current.take() {
+                    trip.purpose = stage.attribute("actType").and_then(parse_purpose);
+                    depart = if let Some(until) = stage.attribute("until") {
+                        parse_time(until)?
+                    } else {
+                        // We don't know when the previous trip finished, so this is a lower bound.
+                        // If the person is still busy then, the simulation delays the next trip.
+                        // TODO Estimate the travel time
+                        trip.depart
+                            + stage
+                                .attribute("duration")
+                                .map(|d| parse_time(d).map(|t| t - Time::START_OF_DAY))
+                                .transpose()?
+                                .unwrap_or(Duration::ZERO)
+                                .max(Duration::seconds(1.0))
+                    };
+                    depart_pos = trip.arrival_pos;
+                    trips.push(trip);
+                }
+                continue;
+            }
+            _ => {
+                continue;
+            }
+        };
+
+        let edges = if let Some(edges) = stage.attribute("edges") {
+            parse_edges(edges)
+        } else {
+            let mut edges = Vec::new();
+            if let Some(from) = stage.attribute("from") {
+                edges.push(EdgeID(from.to_string()));
+            }
+            edges.push(EdgeID(attr(stage, "to")?.to_string()));
+            edges
+        };
+        let arrival_pos = parse_pos(stage.attribute("arrivalPos"));
+
+        if let Some(ref mut trip) = current {
+            trip.to = edges.last().unwrap().clone();
+            trip.arrival_pos = arrival_pos;
+            trip.mode = most_significant(trip.mode, mode);
+        } else {
+            // Without an explicit start, a stage begins where the previous trip ended
+            let from = if stage.attribute("from").is_some() || stage.attribute("edges").is_some() {
+                edges[0].clone()
+            } else if let Some(prev) = trips.last() {
+                prev.to.clone()
+            } else {
+                bail!("first stage doesn't specify a start");
+            };
+            current = Some(Trip {
+                depart,
+                from,
+                to: edges.last().unwrap().clone(),
+                depart_pos,
+                arrival_pos,
+                mode,
+                purpose: None,
+            });
+        }
+    }
+    if let Some(trip) = current {
+        trips.push(trip);
+    }
+    if let Some(purpose) = param(obj, "purpose").and_then(parse_purpose) {
+        for trip in &mut trips {
+            if trip.purpose.is_none() {
+                trip.purpose = Some(purpose);
+            }
+        }
+    }
+
+    Ok(Traveler { id, trips })
+}
+
+fn most_significant(m1: TripMode, m2: TripMode) -> TripMode {
+    let rank = |m| match m {
+        TripMode::Walk => 0,
+        TripMode::Bike => 1,
+        TripMode::Transit => 2,
+        TripMode::Drive => 3,
+    };
+    if rank(m1) >= rank(m2) {
+        m1
+    } else {
+        m2
+    }
+}
+
+fn attr<'a>(obj: roxmltree::Node<'a, '_>, key: &str) -> Result<&'a str> {
+    obj.attribute(key)
+        .ok_or_else(|| anyhow!("{} missing {}", obj.tag_name().name(), key))
+}
+
+/// Finds a generic parameter, like `<param key="purpose" value="work"/>`
+fn param<'a>(obj: roxmltree::Node<'a, '_>, key: &str) -> Option<&'a str> {
+    obj.children()
+        .find(|c| c.has_tag_name("param") && c.attribute("key") == Some(key))
+        .and_then(|c| c.attribute("value"))
+}
+
+fn parse_edges(raw: &str) -> Vec<EdgeID> {
+    raw.split_whitespace()
+        .map(|e| EdgeID(e.to_string()))
+        .collect()
+}
+
+/// SUMO expresses times as seconds, or in newer versions, as "hh:mm:ss"
+fn parse_time(raw: &str) -> Result<Time> {
+    if raw == "triggered" || raw == "containerTriggered" || raw == "begin" {
+        bail!("unsupported departure time {}", raw);
+    }
+    Time::parse(raw)
+}
+
+/// Special values like "random" or "free" and negative positions (measured from the end of the
+/// edge) aren't supported
+fn parse_pos(raw: Option<&str>) -> Option<Distance> {
+    raw.and_then(|x| x.parse::<f64>().ok())
+        .filter(|x| *x >= 0.0)
+        .map(Distance::meters)
+}
+
+/// Activity types are free-form, so just look at the beginning. Understands everything that
+/// `purpose_name` produces.
+fn parse_purpose(raw: &str) -> Option<TripPurpose> {
+    let raw = raw.to_lowercase();
+    for (prefix, purpose) in vec![
+        ("home", TripPurpose::Home),
+        ("work", TripPurpose::Work),
+        ("school", TripPurpose::School),
+        ("education", TripPurpose::School),
+        ("university", TripPurpose::School),
+        ("escort", TripPurpose::Escort),
+        ("personal", TripPurpose::PersonalBusiness),
+        ("shop", TripPurpose::Shopping),
+        ("meal", TripPurpose::Meal),
+        ("eat", TripPurpose::Meal),
+        ("social", TripPurpose::Social),
+        ("visit", TripPurpose::Social),
+        ("recreation", TripPurpose::Recreation),
+        ("leisure", TripPurpose::Recreation),
+        ("medical", TripPurpose::Medical),
+        ("park_and_ride", TripPurpose::ParkAndRideTransfer),
+    ] {
+        if raw.starts_with(prefix) {
+            return Some(purpose);
+        }
+    }
+    None
+}
+
+/// How a trip purpose is written to SUMO demand
+pub(crate) fn purpose_name(purpose: TripPurpose) -> &'static str {
+    match purpose {
+        TripPurpose::Home => "home",
+        TripPurpose::Work => "work",
+        TripPurpose::School => "school",
+        TripPurpose::Escort => "escort",
+        TripPurpose::PersonalBusiness => "personal_business",
+        TripPurpose::Shopping => "shopping",
+        TripPurpose::Meal => "meal",
+        TripPurpose::Social => "social",
+        TripPurpose::Recreation => "recreation",
+        TripPurpose::Medical => "medical",
+        TripPurpose::ParkAndRideTransfer => "park_and_ride",
+    }
+}
@@ -0,0 +1,546 @@
+//! Writes an A/B Street map as a SUMO .net.xml file, so that the same network can be simulated in
+//! both. Some simplifications:
+//!
+//! - Each road becomes one edge per direction. The forwards edge uses the road's ID, and the
+//!   backwards edge is the same ID prefixed by `-`, following netconvert's convention for OSM.
+//! - Parking, buffers, shared left turn lanes, and construction lanes aren't exported.
+//! - Crosswalks and walking areas aren't expressed yet, so pedestrians in SUMO will jump across
+//!   junctions.
+//! - Every vehicle turn becomes a connection with one internal lane, using the turn's geometry.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use anyhow::Result;
+
+use abstutil::Timer;
+use geom::{PolyLine, Polygon, Pt2D};
+use map_model::{
+    osm, DirectedRoadID, Direction, DrivingSide, Intersection, IntersectionID, IntersectionType,
+    LaneID, LaneType, Map, StageType, Turn, TurnID, TurnPriority, TurnType,
+};
+
+use crate::{EdgeID, NodeID, VehicleClass};
+
+/// The SUMO edge representing one direction of a road.
+pub fn edge_id(dr: DirectedRoadID) -> EdgeID {
+    match dr.dir {
+        Direction::Fwd => EdgeID(dr.id.0.to_string()),
+        Direction::Back => EdgeID(format!("-{}", dr.id.0)),
+    }
+}
+
+/// The SUMO junction representing an intersection.
+pub fn junction_id(i: IntersectionID) -> NodeID {
+    NodeID(i.0.to_string())
+}
+
+/// The lanes leading into an intersection that're exported, in the order of the junction's
+/// incLanes.
+fn exported_incoming_lanes(
+    i: &Intersection,
+    lanes: &BTreeMap<LaneID, (EdgeID, usize)>,
+) -> Vec<LaneID> {
+    i.incoming_lanes
+        .iter()
+        .filter(|l| lanes.contains_key(l))
+        .cloned()
+        .collect()
+}
+
+/// Write a map to a SUMO network file.
+pub fn export_network(map: &Map, path: &str, timer: &mut Timer) -> Result<()> {
+    timer.start(format!("export {}", path));
+    let mut f = BufWriter::new(File::create(path)?);
+    let exporter = Exporter::new(map);
+    exporter.write(&mut f)?;
+    f.flush()?;
+    timer.stop(format!("export {}", path));
+    Ok(())
+}
+
+struct Exporter<'a> {
+    map: &'a Map,
+    /// SUMO's Y axis increases northbound, the opposite of map-space.
+    max_y: f64,
+    /// Every exported lane, mapped to its edge and index. Index 0 is the lane closest to the curb.
+    lanes: BTreeMap<LaneID, (EdgeID, usize)>,
+    /// Per intersection, the vehicle turns in the order of their SUMO link index.
+    links: BTreeMap<IntersectionID, Vec<TurnID>>,
+}
+
+impl<'a> Exporter<'a> {
+    fn new(map: &'a Map) -> Exporter<'a> {
+        let mut lanes = BTreeMap::new();
+        for r in map.all_roads() {
+            for dr in r.id.both_directions() {
+                for (idx, l) in sumo_lane_order(map, dr).into_iter().enumerate() {
+                    lanes.insert(l, (edge_id(dr), idx));
+                }
+            }
+        }
+
+        let mut links = BTreeMap::new();
+        for i in map.all_intersections() {
+            let mut turns: Vec<TurnID> = i
+                .turns
+                .iter()
+                .filter(|t| {
+                    !t.between_sidewalks()
+                        && lanes.contains_key(&t.id.src)
+                        && lanes.contains_key(&t.id.dst)
+                })
+                .map(|t| t.id)
+                .collect();
+            // SUMO expects link indices to follow the order of incLanes.
+            let incoming: BTreeMap<LaneID, usize> = exported_incoming_lanes(i, &lanes)
+                .into_iter()
+                .enumerate()
+                .map(|(idx, l)| (l, idx))
+                .collect();
+            turns.sort_by_key(|t| (incoming[&t.src], lanes[&t.dst].clone()));
+            links.insert(i.id, turns);
+        }
+
+        Exporter {
+            map,
+            max_y: map.get_bounds().max_y,
+            lanes,
+            links,
+        }
+    }
+
+    fn write<W: Write>(&self, f: &mut W) -> Result<()> {
+        let map = self.map;
+        let bounds = map.get_bounds();
+        let gps = map.get_gps_bounds();
+
+        writeln!(f, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            f,
+            r#"<!-- Exported from A/B Street map {} -->"#,
+            escape(&map.get_name().describe())
+        )?;
+        if map.get_config().driving_side == DrivingSide::Left {
+            writeln!(f, r#"<net version="1.9" lefthand="true">"#)?;
+        } else {
+            writeln!(f, r#"<net version="1.9">"#)?;
+        }
+        // SUMO positions are the projected coordinates plus netOffset. Without a real projection,
+        // tools can only use origBoundary.
+        let (net_offset, proj) = match (gps.projected_origin(), gps.projection.to_proj4(gps)) {
+            (Some((x, y)), Some(proj)) => ((-x, self.max_y - y), proj),
+            _ => ((0.0, 0.0), "!".to_string()),
+        };
+        writeln!(
+            f,
+            r#"    <location netOffset="{:.2},{:.2}" convBoundary="{:.2},{:.2},{:.2},{:.2}" origBoundary="{},{},{},{}" projParameter="{}"/>"#,
+            net_offset.0,
+            net_offset.1,
+            bounds.min_x,
+            self.max_y - bounds.max_y,
+            bounds.max_x,
+            self.max_y - bounds.min_y,
+            gps.min_lon,
+            gps.min_lat,
+            gps.max_lon,
+            gps.max_lat,
+            proj
+        )?;
+
+        self.write_types(f)?;
+        self.write_internal_edges(f)?;
+        self.write_edges(f)?;
+        self.write_traffic_signals(f)?;
+        for i in map.all_intersections() {
+            self.write_junction(f, i)?;
+        }
+        self.write_connections(f)?;
+
+        writeln!(f, "</net>")?;
+        Ok(())
+    }
+
+    fn write_types<W: Write>(&self, f: &mut W) -> Result<()> {
+        let mut types = BTreeMap::new();
+        for r in self.map.all_roads() {
+            types
+                .entry(edge_type(r.osm_tags.get(osm::HIGHWAY)))
+                .or_insert_with(|| (r.get_detailed_rank(), r.speed_limit));
+        }
+        for (id, (priority, speed)) in types {
+            writeln!(
+                f,
+                r#"    <type id="{}" priority="{}" speed="{:.2}"/>"#,
+                escape(&id),
+                priority,
+                speed.inner_meters_per_second()
+            )?;
+        }
+        Ok(())
+    }
+
+    fn write_internal_edges<W: Write>(&self, f: &mut W) -> Result<()> {
+        for (i, turns) in &self.links {
+            for (idx, t) in turns.iter().enumerate() {
+                let turn = self.map.get_t(*t);
+                let speed = self
+                    .map
+                    .get_parent(t.src)
+                    .speed_limit
+                    .min(self.map.get_parent(t.dst).speed_limit);
+                writeln!(
+                    f,
+                    r#"    <edge id="{}" function="internal">"#,
+                    internal_edge_id(*i, idx)
+                )?;
+                writeln!(
+                    f,
+                    r#"        <lane id="{}" index="0" speed="{:.2}" length="{:.2}" shape="{}"/>"#,
+                    internal_lane_id(*i, idx),
+                    speed.inner_meters_per_second(),
+                    turn.geom.length().inner_meters(),
+                    self.fmt_pl(&turn.geom)
+                )?;
+                writeln!(f, "    </edge>")?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_edges<W: Write>(&self, f: &mut W) -> Result<()> {
+        let map = self.map;
+        let cfg = map.get_config();
+        for r in map.all_roads() {
+            for dr in r.id.both_directions() {
+                let lanes = sumo_lane_order(map, dr);
+                if lanes.is_empty() {
+                    continue;
+                }
+                let name = r
+                    .osm_tags
+                    .get(osm::NAME)
+                    .map(|n| format!(r#" name="{}""#, escape(n)))
+                    .unwrap_or_else(String::new);
+                writeln!(
+                    f,
+                    r#"    <edge id="{}" from="{}" to="{}"{} priority="{}" type="{}">"#,
+                    escape(&edge_id(dr).0),
+                    junction_id(dr.src_i(map)).0,
+                    junction_id(dr.dst_i(map)).0,
+                    name,
+                    r.get_detailed_rank(),
+                    escape(&edge_type(r.osm_tags.get(osm::HIGHWAY)))
+                )?;
+                for (idx, l) in lanes.into_iter().enumerate() {
+                    let lane = map.get_l(l);
+                    let allow: Vec<&str> =
+                        allowed_classes(lane.lane_type, cfg.bikes_can_use_bus_lanes)
+                            .iter()
+                            .map(|c| c.as_str())
+                            .collect();
+                    writeln!(
+                        f,
+                        r#"        <lane id="{}_{}" index="{}" allow="{}" speed="{:.2}" length="{:.2}" width="{:.2}" shape="{}"/>"#,
+                        escape(&edge_id(dr).0),
+                        idx,
+                        idx,
+                        allow.join(" "),
+                        r.speed_limit.inner_meters_per_second(),
+                        lane.length().inner_meters(),
+                        lane.width.inner_meters(),
+                        self.fmt_pl(&lane.lane_center_pts)
+                    )?;
+                }
+                writeln!(f, "    </edge>")?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_traffic_signals<W: Write>(&self, f: &mut W) -> Result<()> {
+        for (i, turns) in &self.links {
+            let signal = if let Some(ts) = self.map.maybe_get_traffic_signal(*i) {
+                ts
+            } else {
+                continue;
+            };
+            if turns.is_empty() {
+                continue;
+            }
+            let actuated = signal
+                .stages
+                .iter()
+                .any(|s| matches!(s.stage_type, StageType::Variable(_, _, _)));
+            writeln!(
+                f,
+                r#"    <tlLogic id="{}" type="{}" programID="0" offset="{}">"#,
+                junction_id(*i).0,
+                if actuated { "actuated" } else { "static" },
+                signal.offset.inner_seconds()
+            )?;
+            for stage in &signal.stages {
+                let state: String = turns
+                    .iter()
+                    .map(|t| match stage.get_priority_of_turn(*t, signal) {
+                        TurnPriority::Protected => 'G',
+                        TurnPriority::Yield => 'g',
+                        TurnPriority::Banned => 'r',
+                    })
+                    .collect();
+                match stage.stage_type {
+                    StageType::Fixed(d) => {
+                        writeln!(
+                            f,
+                            r#"        <phase duration="{}" state="{}"/>"#,
+                            d.inner_seconds(),
+                            state
+                        )?;
+                    }
+                    StageType::Variable(min, _, additional) => {
+                        writeln!(
+                            f,
+                            r#"        <phase duration="{}" minDur="{}" maxDur="{}" state="{}"/>"#,
+                            min.inner_seconds(),
+                            min.inner_seconds(),
+                            (min + additional).inner_seconds(),
+                            state
+                        )?;
+                    }
+                }
+            }
+            writeln!(f, "    </tlLogic>")?;
+        }
+        Ok(())
+    }
+
+    fn write_junction<W: Write>(&self, f: &mut W, i: &Intersection) -> Result<()> {
+        let links = &self.links[&i.id];
+        let junction_type = match i.intersection_type {
+            IntersectionType::TrafficSignal if !links.is_empty() => "traffic_light",
+            IntersectionType::Border => "dead_end",
+            _ => {
+                if self
+                    .map
+                    .maybe_get_stop_sign(i.id)
+                    .map(|ss| !ss.roads.is_empty() && ss.roads.values().all(|r| r.must_stop))
+                    .unwrap_or(false)
+                {
+                    "allway_stop"
+                } else {
+                    "priority"
+                }
+            }
+        };
+        let incoming: Vec<String> = exported_incoming_lanes(i, &self.lanes)
+            .into_iter()
+            .map(|l| {
+                let (e, idx) = &self.lanes[&l];
+                format!("{}_{}", escape(&e.0), idx)
+            })
+            .collect();
+        let internal: Vec<String> = (0..links.len())
+            .map(|idx| internal_lane_id(i.id, idx))
+            .collect();
+        let center = i.polygon.center();
+        writeln!(
+            f,
+            r#"    <junction id="{}" type="{}" x="{:.2}" y="{:.2}" incLanes="{}" intLanes="{}" shape="{}">"#,
+            junction_id(i.id).0,
+            junction_type,
+            center.x(),
+            self.max_y - center.y(),
+            incoming.join(" "),
+            internal.join(" "),
+            self.fmt_polygon(&i.polygon)
+        )?;
+
+        let turns: Vec<&Turn> = links.iter().map(|t| self.map.get_t(*t)).collect();
+        for (idx1, t1) in turns.iter().enumerate() {
+            // In SUMO's bitstrings, the rightmost character corresponds to link 0.
+            let mut response = String::new();
+            let mut foes = String::new();
+            for t2 in turns.iter().rev() {
+                let conflict = t1.id != t2.id && t1.conflicts_with(t2);
+                foes.push(if conflict { '1' } else { '0' });
+                response.push(if conflict && self.must_yield(t1, t2) {
+                    '1'
+                } else {
+                    '0'
+                });
+            }
+            writeln!(
+                f,
+                r#"        <request index="{}" response="{}" foes="{}" cont="0"/>"#,
+                idx1, response, foes
+            )?;
+        }
+        writeln!(f, "    </junction>")?;
+        Ok(())
+    }
+
+    fn write_connections<W: Write>(&self, f: &mut W) -> Result<()> {
+        for (i, turns) in &self.links {
+            let tl = self.map.maybe_get_traffic_signal(*i).is_some();
+            for (idx, t) in turns.iter().enumerate() {
+                let turn = self.map.get_t(*t);
+                let (from_edge, from_lane) = &self.lanes[&t.src];
+                let (to_edge, to_lane) = &self.lanes[&t.dst];
+                let dir = direction(turn.turn_type);
+                let tl_attrs = if tl {
+                    format!(r#" tl="{}" linkIndex="{}""#, junction_id(*i).0, idx)
+                } else {
+                    String::new()
+                };
+                writeln!(
+                    f,
+                    r#"    <connection from="{}" to="{}" fromLane="{}" toLane="{}" via="{}"{} dir="{}" state="{}"/>"#,
+                    escape(&from_edge.0),
+                    escape(&to_edge.0),
+                    from_lane,
+                    to_lane,
+                    internal_lane_id(*i, idx),
+                    tl_attrs,
+                    dir,
+                    if tl { "O" } else { "M" }
+                )?;
+                writeln!(
+                    f,
+                    r#"    <connection from="{}" to="{}" fromLane="0" toLane="{}" dir="{}" state="M"/>"#,
+                    internal_edge_id(*i, idx),
+                    escape(&to_edge.0),
+                    to_lane,
+                    dir
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Does the first turn have to yield to the second? Both turns conflict.
+    fn must_yield(&self, t1: &Turn, t2: &Turn) -> bool {
+        let rank = |t: &Turn| {
+            let priority = match self.map.maybe_get_stop_sign(t.id.parent) {
+                Some(ss) if ss.roads.contains_key(&self.map.get_l(t.id.src).parent) => {
+                    ss.get_priority(t.id, self.map)
+                }
+                _ => TurnPriority::Protected,
+            };
+            let movement = match (t.turn_type, self.map.get_config().driving_side) {
+                (TurnType::Straight, _) => 3,
+                (TurnType::Right, DrivingSide::Right) | (TurnType::Left, DrivingSide::Left) => 2,
+                (TurnType::Left, DrivingSide::Right) | (TurnType::Right, DrivingSide::Left) => 1,
+                _ => 0,
+            };
+            (priority, movement)
+        };
+        let (pri1, mvmnt1) = rank(t1);
+        let (pri2, mvmnt2) = rank(t2);
+        pri1 < pri2 || (pri1 == pri2 && mvmnt1 < mvmnt2)
+    }
+
+    fn fmt_pt(&self, pt: Pt2D) -> String {
+        format!("{:.2},{:.2}", pt.x(), self.max_y - pt.y())
+    }
+
+    fn fmt_pl(&self, pl: &PolyLine) -> String {
+        pl.points()
+            .iter()
+            .map(|pt| self.fmt_pt(*pt))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn fmt_polygon(&self, polygon: &Polygon) -> String {
+        let mut pts = polygon.points().clone();
+        pts.dedup();
+        pts.iter()
+            .map(|pt| self.fmt_pt(*pt))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// The lanes of one direction of a road that're exported, in SUMO's order: index 0 is closest to
+/// the curb.
+fn sumo_lane_order(map: &Map, dr: DirectedRoadID) -> Vec<LaneID> {
+    let mut lanes: Vec<LaneID> = map
+        .get_r(dr.id)
+        .lanes_ltr()
+        .into_iter()
+        .filter(|(_, dir, lt)| *dir == dr.dir && exported(*lt))
+        .map(|(l, _, _)| l)
+        .collect();
+    if (map.get_config().driving_side == DrivingSide::Right) == (dr.dir == Direction::Fwd) {
+        lanes.reverse();
+    }
+    lanes
+}
+
+fn exported(lt: LaneType) -> bool {
+    matches!(
+        lt,
+        LaneType::Driving
+            | LaneType::Bus
+            | LaneType::Biking
+            | LaneType::Sidewalk
+            | LaneType::Shoulder
+            | LaneType::LightRail
+    )
+}
+
+/// The vehicle classes that A/B Street allows to use a lane.
+pub fn allowed_classes(lt: LaneType, bikes_can_use_bus_lanes: bool) -> Vec<VehicleClass> {
+    match lt {
+        LaneType::Driving => vec![
+            VehicleClass::Passenger,
+            VehicleClass::Bus,
+            VehicleClass::Bicycle,
+        ],
+        LaneType::Bus => {
+            if bikes_can_use_bus_lanes {
+                vec![VehicleClass::Bus, VehicleClass::Bicycle]
+            } else {
+                vec![VehicleClass::Bus]
+            }
+        }
+        LaneType::Biking => vec![VehicleClass::Bicycle],
+        LaneType::Sidewalk | LaneType::Shoulder => vec![VehicleClass::Pedestrian],
+        LaneType::LightRail => vec![VehicleClass::RailUrban],
+        _ => Vec::new(),
+    }
+}
+
+/// The importer expects types of the form "highway.residential"
+fn edge_type(highway: Option<&String>) -> String {
+    format!(
+        "highway.{}",
+        highway.map(|x| x.as_str()).unwrap_or("unclassified")
+    )
+}
+
+fn direction(turn_type: TurnType) -> &'static str {
+    match turn_type {
+        TurnType::Left => "l",
+        TurnType::Right => "r",
+        TurnType::UTurn => "t",
+        // Crosswalks and sidewalk corners aren't exported
+        TurnType::Straight | TurnType::Crosswalk | TurnType::SharedSidewalkCorner => "s",
+    }
+}
+
+fn internal_edge_id(i: IntersectionID, idx: usize) -> String {
+    format!(":{}_{}", i.0, idx)
+}
+
+fn internal_lane_id(i: IntersectionID, idx: usize) -> String {
+    format!(":{}_{}_0", i.0, idx)
+}
+
+fn escape(x: &str) -> String {
+    x.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
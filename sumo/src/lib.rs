@@ -2,15 +2,23 @@
 
 #[macro_use]
 extern crate anyhow;
+#[macro_use]
+extern crate log;
 
 use std::collections::BTreeMap;
 
 use geom::{Distance, PolyLine, Polygon, Pt2D, Speed};
 
+pub use self::demand::{Demand, Traveler, Trip};
+pub use self::export::{allowed_classes, edge_id, export_network, junction_id};
 pub use self::raw::{Connection, Direction, EdgeID, InternalLaneID, LaneID, NodeID};
+pub use self::scenario::{export_scenario, import_scenario, match_edges};
 
+mod demand;
+mod export;
 mod normalize;
 mod raw;
+mod scenario;
 
 /// A normalized form of a SUMO
 /// [network](https://sumo.dlr.de/docs/Networks/SUMO_Road_Networks.html). A `raw::Network` is a direct representation of a .net.xml file. That's further simplified to produce this structure, which should be easier to work with. The
@@ -74,12 +82,38 @@ pub struct Junction {
     pub shape: Polygon,
 }
 
-#[derive(PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum VehicleClass {
     Pedestrian,
+    Passenger,
+    Bus,
     Bicycle,
     RailUrban,
     // TODO Use all values from
     // https://sumo.dlr.de/docs/Definition_of_Vehicles,_Vehicle_Types,_and_Routes.html#abstract_vehicle_class
     Other(String),
 }
+
+impl VehicleClass {
+    pub fn parse(x: &str) -> VehicleClass {
+        match x {
+            "pedestrian" => VehicleClass::Pedestrian,
+            "passenger" => VehicleClass::Passenger,
+            "bus" => VehicleClass::Bus,
+            "bicycle" => VehicleClass::Bicycle,
+            "rail_urban" => VehicleClass::RailUrban,
+            other => VehicleClass::Other(other.to_string()),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            VehicleClass::Pedestrian => "pedestrian",
+            VehicleClass::Passenger => "passenger",
+            VehicleClass::Bus => "bus",
+            VehicleClass::Bicycle => "bicycle",
+            VehicleClass::RailUrban => "rail_urban",
+            VehicleClass::Other(x) => x,
+        }
+    }
+}
@@ -67,6 +67,10 @@ fn convert(orig_path: &str, network: Network) -> Result<Map> {
             ids_lanes.insert(lane.id.clone(), lane_id);
             let lane_type = if lane.allow == vec![VehicleClass::Pedestrian] {
                 LaneType::Sidewalk
+            } else if lane.allow == vec![VehicleClass::Bus]
+                || lane.allow == vec![VehicleClass::Bus, VehicleClass::Bicycle]
+            {
+                LaneType::Bus
             } else if lane.allow == vec![VehicleClass::Bicycle] {
                 LaneType::Biking
             } else if lane.allow == vec![VehicleClass::RailUrban] {
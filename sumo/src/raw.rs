@@ -5,7 +5,7 @@
 use abstutil::Timer;
 use serde::Deserialize;
 
-use geom::{Bounds, Distance, GPSBounds, PolyLine, Polygon, Pt2D, Ring, Speed};
+use geom::{Bounds, Distance, GPSBounds, PolyLine, Polygon, Projection, Pt2D, Ring, Speed};
 
 use crate::VehicleClass;
 
@@ -44,7 +44,7 @@ impl Network {
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
 pub struct EdgeID(pub String);
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
-pub struct NodeID(String);
+pub struct NodeID(pub String);
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
 pub struct LaneID(pub String);
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
@@ -216,28 +216,22 @@ fn parse_gps_bounds<'de, D: serde::Deserializer<'de>>(d: D) -> Result<GPSBounds,
     if nums.len() != 4 {
         return Err(serde::de::Error::custom("not 4 parts".to_string()));
     }
-    Ok(GPSBounds {
-        min_lon: nums[0],
-        min_lat: nums[1],
-        max_lon: nums[2],
-        max_lat: nums[3],
-    })
+    Ok(GPSBounds::from_limits(
+        nums[0],
+        nums[1],
+        nums[2],
+        nums[3],
+        // SUMO's convBoundary is treated as origBoundary stretched linearly. Even when
+        // projParameter names a real projection, netOffset rarely lines up with our origin.
+        Projection::Linear,
+    ))
 }
 
 fn parse_list_vehicles<'de, D: serde::Deserializer<'de>>(
     d: D,
 ) -> Result<Vec<VehicleClass>, D::Error> {
     let raw = <String>::deserialize(d)?;
-    let mut vehicles = Vec::new();
-    for x in raw.split(' ') {
-        vehicles.push(match x {
-            "pedestrian" => VehicleClass::Pedestrian,
-            "bicycle" => VehicleClass::Bicycle,
-            "rail_urban" => VehicleClass::RailUrban,
-            other => VehicleClass::Other(other.to_string()),
-        });
-    }
-    Ok(vehicles)
+    Ok(raw.split(' ').map(VehicleClass::parse).collect())
 }
 
 fn parse_list_lanes<'de, D: serde::Deserializer<'de>>(d: D) -> Result<Vec<LaneID>, D::Error> {
@@ -0,0 +1,266 @@
+//! Exchange demand between A/B Street scenarios and SUMO route files.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use anyhow::Result;
+
+use abstutil::{prettyprint_usize, Timer};
+use geom::{Distance, FindClosest, Pt2D, Time};
+use map_model::{DirectedRoadID, Direction, IntersectionID, Map};
+use sim::{IndividTrip, PersonSpec, Scenario, TripEndpoint, TripMode, TripPurpose};
+
+use crate::demand::{purpose_name, Demand, Trip};
+use crate::{edge_id, EdgeID, Network};
+
+/// Write every trip in a scenario to a SUMO .rou.xml file, referring to edges produced by
+/// `export_network`. Driving and biking trips become vehicle trips, and walking and transit trips
+/// become persons. SUMO will compute the routes. The trips of one person aren't linked together.
+/// Each trip's purpose is kept as a "purpose" parameter.
+pub fn export_scenario(
+    scenario: &Scenario,
+    map: &Map,
+    path: &str,
+    timer: &mut Timer,
+) -> Result<()> {
+    timer.start(format!("export {}", path));
+    // SUMO expects everything sorted by departure time
+    let mut entries: Vec<(Time, String)> = Vec::new();
+    let mut skipped = 0;
+    for (person_idx, person) in scenario.people.iter().enumerate() {
+        for (trip_idx, trip) in person.trips.iter().enumerate() {
+            let id = format!("{}_{}", person_idx, trip_idx);
+            match export_trip(&id, trip, map) {
+                Some(entry) => {
+                    entries.push((trip.depart, entry));
+                }
+                None => {
+                    skipped += 1;
+                }
+            }
+        }
+    }
+    entries.sort_by_key(|(t, _)| *t);
+    if skipped > 0 {
+        warn!(
+            "Skipped {} trips with endpoints that can't be expressed as edges",
+            prettyprint_usize(skipped)
+        );
+    }
+
+    let mut f = BufWriter::new(File::create(path)?);
+    writeln!(f, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        f,
+        r#"<!-- Exported from A/B Street scenario {} on {} -->"#,
+        scenario.scenario_name,
+        scenario.map_name.describe()
+    )?;
+    writeln!(f, "<routes>")?;
+    writeln!(f, r#"    <vType id="car" vClass="passenger"/>"#)?;
+    writeln!(f, r#"    <vType id="bike" vClass="bicycle"/>"#)?;
+    for (_, entry) in entries {
+        writeln!(f, "{}", entry)?;
+    }
+    writeln!(f, "</routes>")?;
+    f.flush()?;
+    timer.stop(format!("export {}", path));
+    Ok(())
+}
+
+fn export_trip(id: &str, trip: &IndividTrip, map: &Map) -> Option<String> {
+    if trip.cancelled {
+        return None;
+    }
+    let start = trip.origin.pos(trip.mode, true, map)?;
+    let end = trip.destination.pos(trip.mode, false, map)?;
+    let from = edge_id(map.get_l(start.lane()).get_directed_parent());
+    let to = edge_id(map.get_l(end.lane()).get_directed_parent());
+    let depart = trip.depart.inner_seconds();
+    let depart_pos = start.dist_along().inner_meters();
+    let arrival_pos = end.dist_along().inner_meters();
+    let purpose = purpose_name(trip.purpose);
+
+    Some(match trip.mode {
+        TripMode::Drive | TripMode::Bike => format!(
+            "    <trip id=\"{}\" type=\"{}\" depart=\"{:.1}\" from=\"{}\" to=\"{}\" \
+             departPos=\"{:.1}\" arrivalPos=\"{:.1}\">\n        <param key=\"purpose\" \
+             value=\"{}\"/>\n    </trip>",
+            id,
+            if trip.mode == TripMode::Drive {
+                "car"
+            } else {
+                "bike"
+            },
+            depart,
+            from.0,
+            to.0,
+            depart_pos,
+            arrival_pos,
+            purpose
+        ),
+        TripMode::Walk => format!(
+            "    <person id=\"{}\" depart=\"{:.1}\" departPos=\"{:.1}\">\n        <param \
+             key=\"purpose\" value=\"{}\"/>\n        <walk from=\"{}\" to=\"{}\" \
+             arrivalPos=\"{:.1}\"/>\n    </person>",
+            id, depart, depart_pos, purpose, from.0, to.0, arrival_pos
+        ),
+        TripMode::Transit => format!(
+            "    <person id=\"{}\" depart=\"{:.1}\" departPos=\"{:.1}\">\n        <param \
+             key=\"purpose\" value=\"{}\"/>\n        <personTrip from=\"{}\" to=\"{}\" \
+             arrivalPos=\"{:.1}\" modes=\"public\"/>\n    </person>",
+            id, depart, depart_pos, purpose, from.0, to.0, arrival_pos
+        ),
+    })
+}
+
+/// Turn SUMO demand into a scenario. `network` must be the SUMO network that the demand refers to,
+/// and it has to cover the same area as `map`. Trip endpoints along an edge are snapped to the
+/// nearest building on that road, or to a border if the edge starts or ends at one. SUMO doesn't
+/// require trips to have a purpose; when the input doesn't give one, the trip is treated as
+/// personal business, a neutral catch-all.
+pub fn import_scenario(
+    map: &Map,
+    network: &Network,
+    demand: Demand,
+    scenario_name: &str,
+    timer: &mut Timer,
+) -> Scenario {
+    let edges = match_edges(map, network);
+    let mut closest: FindClosest<TripEndpoint> = FindClosest::new(map.get_bounds());
+    for b in map.all_buildings() {
+        closest.add(TripEndpoint::Bldg(b.id), b.polygon.points());
+    }
+
+    let mut people = Vec::new();
+    let mut skipped = 0;
+    timer.start_iter("convert SUMO travelers", demand.travelers.len());
+    for traveler in demand.travelers {
+        timer.next();
+        let mut spec = PersonSpec {
+            orig_id: None,
+            trips: Vec::new(),
+        };
+        for trip in traveler.trips {
+            // Keep continuity between trips
+            let origin = if let Some(prev) = spec.trips.last() {
+                Some(prev.destination)
+            } else {
+                endpoint(map, network, &edges, &closest, &trip, true)
+            };
+            let destination = endpoint(map, network, &edges, &closest, &trip, false);
+            if let (Some(origin), Some(destination)) = (origin, destination) {
+                if origin != destination {
+                    spec.trips.push(IndividTrip::new(
+                        trip.depart,
+                        trip.purpose.unwrap_or(TripPurpose::PersonalBusiness),
+                        origin,
+                        destination,
+                        trip.mode,
+                    ));
+                    continue;
+                }
+            }
+            warn!(
+                "Skipping the rest of {}; can't match {} to {}",
+                traveler.id, trip.from.0, trip.to.0
+            );
+            skipped += 1;
+            break;
+        }
+        if !spec.trips.is_empty() {
+            people.push(spec);
+        }
+    }
+    info!(
+        "Imported {} people, skipped {} trips",
+        prettyprint_usize(people.len()),
+        prettyprint_usize(skipped)
+    );
+
+    Scenario {
+        scenario_name: scenario_name.to_string(),
+        map_name: map.get_name().clone(),
+        people,
+        only_seed_buses: None,
+    }
+    .remove_weird_schedules()
+}
+
+/// Match every normal SUMO edge to a directed road in the map, by snapping the endpoints of the
+/// edge to intersections. This works for maps converted from the network and networks exported
+/// from the map, since both share map-space coordinates.
+pub fn match_edges(map: &Map, network: &Network) -> BTreeMap<EdgeID, DirectedRoadID> {
+    let mut closest: FindClosest<IntersectionID> = FindClosest::new(map.get_bounds());
+    for i in map.all_intersections() {
+        closest.add(i.id, i.polygon.points());
+    }
+    let snap = |pt: Pt2D| {
+        closest
+            .closest_pt(pt, Distance::meters(50.0))
+            .map(|(i, _)| i)
+    };
+
+    let mut results = BTreeMap::new();
+    for edge in network.normal_edges.values() {
+        let i1 = snap(network.junctions[&edge.from].pt);
+        let i2 = snap(network.junctions[&edge.to].pt);
+        if let (Some(i1), Some(i2)) = (i1, i2) {
+            if i1 == i2 {
+                continue;
+            }
+            if let Some(r) = map.find_road_between(i1, i2) {
+                let dir = if map.get_r(r).src_i == i1 {
+                    Direction::Fwd
+                } else {
+                    Direction::Back
+                };
+                results.insert(edge.id.clone(), DirectedRoadID { id: r, dir });
+            }
+        }
+    }
+    results
+}
+
+fn endpoint(
+    map: &Map,
+    network: &Network,
+    edges: &BTreeMap<EdgeID, DirectedRoadID>,
+    closest: &FindClosest<TripEndpoint>,
+    trip: &Trip,
+    is_origin: bool,
+) -> Option<TripEndpoint> {
+    let (edge, pos) = if is_origin {
+        (&trip.from, trip.depart_pos)
+    } else {
+        (&trip.to, trip.arrival_pos)
+    };
+    let dr = *edges.get(edge)?;
+
+    let i = if is_origin {
+        map.get_i(dr.src_i(map))
+    } else {
+        map.get_i(dr.dst_i(map))
+    };
+    if (is_origin && i.is_incoming_border()) || (!is_origin && i.is_outgoing_border()) {
+        return Some(TripEndpoint::Border(i.id));
+    }
+
+    // Find the point along the edge
+    let pl = &network.normal_edges[edge].lanes[0].center_line;
+    let pt = pl
+        .dist_along(pos.unwrap_or(pl.length() / 2.0).min(pl.length()))
+        .ok()?
+        .0;
+    // Prefer buildings along the road, but fall back to anything nearby
+    map.road_to_buildings(dr.id)
+        .iter()
+        .min_by_key(|b| map.get_b(**b).polygon.center().dist_to(pt))
+        .map(|b| TripEndpoint::Bldg(*b))
+        .or_else(|| {
+            closest
+                .closest_pt(pt, Distance::meters(100.0))
+                .map(|(x, _)| x)
+        })
+}
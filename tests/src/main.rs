@@ -1,5 +1,6 @@
 //! Integration tests
 
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs::File;
 use std::io::Write;
 
@@ -8,14 +9,43 @@ use rand::seq::SliceRandom;
 
 use abstio::{CityName, MapName};
 use abstutil::Timer;
-use geom::{Distance, Duration, Time};
-use map_model::{IntersectionID, Map};
-use sim::{IndividTrip, PersonSpec, Scenario, TripEndpoint, TripMode, TripPurpose};
+use geom::{Distance, Duration, LonLat, Projection, Pt2D, Time};
+use map_model::{
+    map_matching, Direction, EditCmd, EditIntersection, IntersectionID, LaneID, LaneType, Map,
+    PathConstraints, PathRequest, PathStep, PedestrianTiming, Position,
+};
+use sim::{
+    Incident, IncidentKind, IndividTrip, LevelOfService, PersonSpec, Scenario, TripEndpoint,
+    TripMode, TripPurpose,
+};
 
 fn main() -> Result<()> {
     test_lane_changing(&import_map(abstio::path(
         "../tests/input/lane_selection.osm",
     )))?;
+    {
+        let map = import_map(abstio::path("../tests/input/roundabout.osm"));
+        test_roundabout_entry(&map)?;
+        test_map_matching(&map)?;
+        test_parallel_pathfinding(&map)?;
+    }
+    test_mid_block_crossings()?;
+    {
+        let map = import_map(abstio::path("../tests/input/mid_block_crossings.osm"));
+        test_incidents(&map)?;
+        test_pedestrian_crowding(&map)?;
+    }
+    {
+        let map = import_map(abstio::path("../tests/input/lane_selection.osm"));
+        test_sumo_export(&map)?;
+        test_sumo_demand(&map)?;
+        test_gmns_round_trip(&map)?;
+    }
+    test_pedestrian_timing(&import_map(abstio::path(
+        "../tests/input/lane_selection.osm",
+    )))?;
+    test_rerouting(&import_map(abstio::path("../tests/input/detour.osm")))?;
+    test_mid_block_lanechanging(&import_map(abstio::path("../tests/input/lane_drop.osm")))?;
     test_map_importer()?;
     check_proposals()?;
     smoke_test()?;
@@ -46,28 +76,337 @@ fn test_map_importer() -> Result<()> {
 
 /// Run the contents of a .osm through the full map importer with default options.
 fn import_map(path: String) -> Map {
+    import_map_with(path, |_| {})
+}
+
+/// Like `import_map`, but first adjust some of the options.
+fn import_map_with<F: Fn(&mut convert_osm::Options)>(path: String, adjust: F) -> Map {
     let mut timer = Timer::new("convert synthetic map");
-    let raw = convert_osm::convert(
-        convert_osm::Options {
-            name: MapName::new("zz", "oneshot", &abstutil::basename(&path)),
-            osm_input: path,
-            clip: None,
-            map_config: map_model::MapConfig {
-                driving_side: map_model::DrivingSide::Right,
-                bikes_can_use_bus_lanes: true,
-                inferred_sidewalks: true,
-                street_parking_spot_length: Distance::meters(8.0),
-            },
-            onstreet_parking: convert_osm::OnstreetParking::JustOSM,
-            public_offstreet_parking: convert_osm::PublicOffstreetParking::None,
-            private_offstreet_parking: convert_osm::PrivateOffstreetParking::FixedPerBldg(0),
-            include_railroads: true,
-            extra_buildings: None,
-            skip_local_roads: false,
+    let mut opts = convert_osm::Options {
+        name: MapName::new("zz", "oneshot", &abstutil::basename(&path)),
+        osm_input: path,
+        clip: None,
+        map_config: map_model::MapConfig {
+            driving_side: map_model::DrivingSide::Right,
+            bikes_can_use_bus_lanes: true,
+            inferred_sidewalks: true,
+            street_parking_spot_length: Distance::meters(8.0),
+            projection: Projection::default(),
         },
+        onstreet_parking: convert_osm::OnstreetParking::JustOSM,
+        public_offstreet_parking: convert_osm::PublicOffstreetParking::None,
+        private_offstreet_parking: convert_osm::PrivateOffstreetParking::FixedPerBldg(0),
+        include_railroads: true,
+        extra_buildings: None,
+        skip_local_roads: false,
+        mid_block_crossings: false,
+        informal_crossings: None,
+    };
+    adjust(&mut opts);
+    let raw = convert_osm::convert(opts, &mut timer);
+    Map::create_from_raw(raw, map_model::RawToMapOptions::default(), &mut timer)
+}
+
+/// Verify which crossings split roads, depending on the import options.
+fn test_mid_block_crossings() -> Result<()> {
+    let path = abstio::path("../tests/input/mid_block_crossings.osm");
+    let mid_block = |map: &Map| -> Vec<Pt2D> {
+        map.all_intersections()
+            .iter()
+            .filter(|i| i.is_mid_block_crossing(map))
+            .map(|i| i.polygon.center())
+            .collect()
+    };
+
+    // By default, nothing changes
+    let map = import_map(path.clone());
+    assert!(mid_block(&map).is_empty());
+    let junctions: Vec<_> = map
+        .all_intersections()
+        .iter()
+        .filter(|i| !i.is_border())
+        .collect();
+    assert_eq!(junctions.len(), 1);
+    let junction = junctions[0].polygon.center();
+
+    // The crossing mapped at the junction of three roads isn't mid-block
+    let map = import_map_with(path.clone(), |opts| {
+        opts.mid_block_crossings = true;
+    });
+    assert_eq!(mid_block(&map).len(), 1);
+
+    // Informal crossings only go along the slow road, east of the junction
+    let map = import_map_with(path, |opts| {
+        opts.mid_block_crossings = true;
+        opts.informal_crossings = Some(Distance::meters(50.0));
+    });
+    let crossings = mid_block(&map);
+    assert!(crossings.len() > 1);
+    assert!(crossings.iter().all(|pt| pt.x() > junction.x()));
+
+    Ok(())
+}
+
+/// Export a map to SUMO, read it back, and check that connections line up with junctions.
+fn test_sumo_export(map: &Map) -> Result<()> {
+    let path = abstio::path(format!(
+        "../tests/goldenfiles/{}.net.xml",
+        map.get_name().map
+    ));
+    sumo::export_network(map, &path, &mut Timer::throwaway())?;
+    let network = sumo::Network::load(&path, &mut Timer::throwaway())?;
+    std::fs::remove_file(&path)?;
+
+    assert_eq!(network.junctions.len(), map.all_intersections().len());
+    for r in map.all_roads() {
+        for dr in r.id.both_directions() {
+            let driving = r
+                .lanes_ltr()
+                .into_iter()
+                .filter(|(_, dir, lt)| *dir == dr.dir && *lt == LaneType::Driving)
+                .count();
+            if driving > 0 {
+                assert!(network.normal_edges.contains_key(&sumo::edge_id(dr)));
+            }
+        }
+    }
+
+    // Connections through each junction are listed in the order of their link index, which has
+    // to follow the junction's incoming lanes. Each one uses the internal lane at its index.
+    let mut links: BTreeMap<sumo::NodeID, Vec<&sumo::Connection>> = BTreeMap::new();
+    for c in &network.connections {
+        if c.via.is_some() {
+            links
+                .entry(network.normal_edges[&c.from].to.clone())
+                .or_default()
+                .push(c);
+        }
+    }
+    let mut num_links = 0;
+    for (id, junction) in &network.junctions {
+        let connections = links.remove(id).unwrap_or_else(Vec::new);
+        assert_eq!(connections.len(), junction.internal_lanes.len());
+        let mut last = 0;
+        for (idx, c) in connections.into_iter().enumerate() {
+            let position = junction
+                .incoming_lanes
+                .iter()
+                .position(|l| *l == c.from_lane())
+                .unwrap_or_else(|| panic!("{} doesn't lead into {}", c.from_lane().0, id.0));
+            assert!(position >= last, "Link {} at {} is out of order", idx, id.0);
+            last = position;
+            assert!(c.via.as_ref() == Some(&junction.internal_lanes[idx]));
+            num_links += 1;
+        }
+    }
+    assert!(links.is_empty());
+    assert!(num_links > 0);
+    Ok(())
+}
+
+/// Export trips to SUMO and import them again. The mode, departure time, endpoints, and purpose
+/// should all survive. When SUMO demand doesn't say why someone travels, a neutral purpose is used.
+fn test_sumo_demand(map: &Map) -> Result<()> {
+    let mut timer = Timer::throwaway();
+    let net_path = abstio::path(format!(
+        "../tests/goldenfiles/{}_demand.net.xml",
+        map.get_name().map
+    ));
+    sumo::export_network(map, &net_path, &mut timer)?;
+    let network = sumo::Network::load(&net_path, &mut timer)?;
+    std::fs::remove_file(&net_path)?;
+
+    // Only trips starting and ending on an exported edge can be expressed in SUMO
+    let edge = |endpoint: TripEndpoint, mode: TripMode, from: bool| {
+        endpoint
+            .pos(mode, from, map)
+            .map(|pos| sumo::edge_id(map.get_l(pos.lane()).get_directed_parent()))
+            .filter(|e| network.normal_edges.contains_key(e))
+    };
+
+    // Trips between every pair of borders, cycling through some purposes
+    let purposes = [
+        TripPurpose::Work,
+        TripPurpose::Shopping,
+        TripPurpose::Recreation,
+        TripPurpose::Home,
+    ];
+    let mut people = Vec::new();
+    let mut edges = None;
+    for origin in map.all_incoming_borders() {
+        for destination in map.all_outgoing_borders() {
+            if origin.id == destination.id {
+                continue;
+            }
+            for mode in [TripMode::Drive, TripMode::Bike] {
+                let origin = TripEndpoint::Border(origin.id);
+                let destination = TripEndpoint::Border(destination.id);
+                if let (Some(from), Some(to)) =
+                    (edge(origin, mode, true), edge(destination, mode, false))
+                {
+                    edges = Some((from, to));
+                    people.push(PersonSpec {
+                        orig_id: None,
+                        trips: vec![IndividTrip::new(
+                            Time::START_OF_DAY + Duration::seconds((people.len() + 1) as f64),
+                            purposes[people.len() % purposes.len()],
+                            origin,
+                            destination,
+                            mode,
+                        )],
+                    });
+                }
+            }
+        }
+    }
+    assert!(!people.is_empty());
+    let scenario = Scenario {
+        scenario_name: "sumo_demand".to_string(),
+        map_name: map.get_name().clone(),
+        people,
+        only_seed_buses: None,
+    };
+
+    let summarize = |scenario: &Scenario| {
+        scenario
+            .people
+            .iter()
+            .flat_map(|person| {
+                person
+                    .trips
+                    .iter()
+                    .map(|t| (t.depart, t.mode, t.purpose, t.origin, t.destination))
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let rou_path = abstio::path(format!(
+        "../tests/goldenfiles/{}.rou.xml",
+        map.get_name().map
+    ));
+    sumo::export_scenario(&scenario, map, &rou_path, &mut timer)?;
+    let demand = sumo::Demand::load(&rou_path, &mut timer)?;
+    let imported = sumo::import_scenario(map, &network, demand, "sumo_demand", &mut timer);
+    assert_eq!(summarize(&imported), summarize(&scenario));
+
+    // A person whose stop says what they're doing there, and a vehicle that doesn't say anything
+    let (from, to) = edges.unwrap();
+    let mut f = File::create(&rou_path)?;
+    writeln!(f, "<routes>")?;
+    writeln!(f, r#"    <person id="worker" depart="10">"#)?;
+    writeln!(f, r#"        <walk from="{}" to="{}"/>"#, from.0, to.0)?;
+    writeln!(
+        f,
+        r#"        <stop lane="{}_0" duration="3600" actType="work"/>"#,
+        to.0
+    )?;
+    writeln!(f, "    </person>")?;
+    writeln!(
+        f,
+        r#"    <trip id="unknown" depart="20" from="{}" to="{}"/>"#,
+        from.0, to.0
+    )?;
+    writeln!(f, "</routes>")?;
+    drop(f);
+    let demand = sumo::Demand::load(&rou_path, &mut timer)?;
+    std::fs::remove_file(&rou_path)?;
+    let imported = summarize(&sumo::import_scenario(
+        map,
+        &network,
+        demand,
+        "sumo_demand",
         &mut timer,
+    ));
+    assert_eq!(
+        imported
+            .into_iter()
+            .map(|(_, mode, purpose, _, _)| (mode, purpose))
+            .collect::<Vec<_>>(),
+        vec![
+            (TripMode::Walk, TripPurpose::Work),
+            (TripMode::Drive, TripPurpose::PersonalBusiness)
+        ]
     );
-    Map::create_from_raw(raw, map_model::RawToMapOptions::default(), &mut timer)
+
+    Ok(())
+}
+
+/// Export a map to GMNS, then import the signal timing again. Movements are matched both through
+/// movement.csv and through the geometry and direction code in timing.csv.
+fn test_gmns_round_trip(map: &Map) -> Result<()> {
+    let dir = abstio::path(format!("../tests/goldenfiles/{}_gmns", map.get_name().map));
+    map_model::gmns::export(map, &dir, &mut Timer::throwaway())?;
+
+    let read_rows = |table: &str| -> Result<Vec<String>> {
+        // Skip the header
+        Ok(std::fs::read_to_string(format!("{}/{}", dir, table))?
+            .lines()
+            .skip(1)
+            .map(|line| line.to_string())
+            .collect())
+    };
+    assert_eq!(read_rows("node.csv")?.len(), map.all_intersections().len());
+    // Every vehicle movement through a signal is in the movement table. node_id is the second
+    // column.
+    let movement_rows = read_rows("movement.csv")?;
+    for i in map.all_intersections() {
+        if let Some(signal) = map.maybe_get_traffic_signal(i.id) {
+            let node_id = i.id.0.to_string();
+            assert_eq!(
+                movement_rows
+                    .iter()
+                    .filter(|row| row.split(',').nth(1) == Some(node_id.as_str()))
+                    .count(),
+                signal.movements.keys().filter(|id| !id.crosswalk).count()
+            );
+        }
+    }
+
+    // Crosswalks aren't exported
+    let vehicle_movements = |stage: &map_model::Stage| -> BTreeSet<map_model::MovementID> {
+        stage
+            .protected_movements
+            .iter()
+            .chain(stage.yield_movements.iter())
+            .filter(|id| !id.crosswalk)
+            .cloned()
+            .collect()
+    };
+    let timing_path = format!("{}/timing.csv", dir);
+    let movement_path = format!("{}/movement.csv", dir);
+    let mut num_signals = 0;
+    for movement_table in vec![Some(movement_path.as_str()), None] {
+        let timing = map_model::gmns::SignalTiming::load(&timing_path, movement_table)?;
+        for i in map.all_intersections() {
+            let orig = if let Some(signal) = map.maybe_get_traffic_signal(i.id) {
+                signal
+            } else {
+                continue;
+            };
+            num_signals += 1;
+            let imported = timing.import(map, i.id)?;
+            assert_eq!(orig.stages.len(), imported.stages.len());
+
+            for (stage1, stage2) in orig.stages.iter().zip(imported.stages.iter()) {
+                assert_eq!(
+                    stage1.stage_type.simple_duration().inner_seconds().round(),
+                    stage2.stage_type.simple_duration().inner_seconds()
+                );
+                // Crosswalks are added back wherever they fit. That may downgrade some protected
+                // turns, but never upgrade anything.
+                assert_eq!(vehicle_movements(stage1), vehicle_movements(stage2));
+                assert!(stage2
+                    .protected_movements
+                    .iter()
+                    .filter(|id| !id.crosswalk)
+                    .all(|id| stage1.protected_movements.contains(id)));
+            }
+        }
+    }
+    assert!(num_signals > 0);
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
 }
 
 /// Verify what turns are generated by writing (from lane, to lane, turn type).
@@ -237,3 +576,737 @@ fn test_lane_changing(map: &Map) -> Result<()> {
 
     Ok(())
 }
+
+/// Verify vehicles entering a roundabout yield to traffic already circulating.
+fn test_roundabout_entry(map: &Map) -> Result<()> {
+    assert!(map.all_intersections().iter().any(|i| i.is_roundabout()));
+
+    // The border at the far end of each approach
+    let north = find_border(map, |pt| pt.y());
+    let south = find_border(map, |pt| -pt.y());
+    let west = find_border(map, |pt| pt.x());
+    let east = find_border(map, |pt| -pt.x());
+
+    let drive = |depart: Duration, from: IntersectionID, to: IntersectionID| PersonSpec {
+        orig_id: None,
+        trips: vec![IndividTrip::new(
+            Time::START_OF_DAY + depart,
+            TripPurpose::Shopping,
+            TripEndpoint::Border(from),
+            TripEndpoint::Border(to),
+            TripMode::Drive,
+        )],
+    };
+
+    // One car enters from the south and goes north. Traffic from the west to the east circulates
+    // past the south entrance.
+    let mut alone = Scenario::empty(map, "roundabout_alone");
+    alone.people.push(drive(Duration::minutes(1), south, north));
+    let mut busy = Scenario::empty(map, "roundabout_busy");
+    busy.people.push(drive(Duration::minutes(1), south, north));
+    for idx in 0..60 {
+        busy.people
+            .push(drive(Duration::seconds(2.0 * idx as f64), west, east));
+    }
+
+    let entering_time = |scenario: &Scenario| {
+        let mut sim = setup_scenario(map, scenario, sim::SimOptions::new("test_roundabout_entry"));
+        run_until_done(map, &mut sim);
+        let (trip, _) = sim
+            .all_trip_info()
+            .into_iter()
+            .find(|(_, info)| info.start == TripEndpoint::Border(south))
+            .unwrap();
+        sim.get_analytics().finished_trip_time(trip).unwrap()
+    };
+    let alone = entering_time(&alone);
+    let busy = entering_time(&busy);
+    // With a steady stream of circulating traffic, there's never a big enough gap, so the
+    // entering car should wait a while.
+    if busy < alone + Duration::seconds(15.0) {
+        panic!(
+            "Entering the roundabout took {} alone and {} with circulating traffic; it should \
+             have yielded",
+            alone, busy
+        );
+    }
+
+    Ok(())
+}
+
+/// Match a noisy GPS trace of a car going around the roundabout one more time than it needs to,
+/// then replay it.
+fn test_map_matching(map: &Map) -> Result<()> {
+    let south = find_border(map, |pt| -pt.y());
+    let west = find_border(map, |pt| pt.x());
+    let driving =
+        |lanes: &Vec<LaneID>| *lanes.iter().find(|l| map.get_l(**l).is_driving()).unwrap();
+    let entering = driving(&map.get_i(south).outgoing_lanes);
+    let exiting = driving(&map.get_i(west).incoming_lanes);
+    // The part of the ring just past the west exit
+    let past_exit = *map
+        .get_i(map.get_l(exiting).src_i)
+        .outgoing_lanes
+        .iter()
+        .find(|l| map.get_parent(**l).is_roundabout_ring())
+        .unwrap();
+    let halfway = |l: LaneID| Position::new(l, map.get_l(l).length() / 2.0);
+    let legs = vec![
+        map.pathfind(PathRequest::vehicle(
+            Position::new(entering, Distance::meters(20.0)),
+            halfway(past_exit),
+            PathConstraints::Car,
+        ))?,
+        map.pathfind(PathRequest::vehicle(
+            halfway(past_exit),
+            halfway(exiting),
+            PathConstraints::Car,
+        ))?,
+    ];
+
+    // A fix every 10 meters, wobbling from side to side
+    let mut trace = Vec::new();
+    for leg in &legs {
+        let pl = leg.trace(map).unwrap();
+        let mut dist = Distance::ZERO;
+        while dist < pl.length() {
+            let (pt, angle) = pl.must_dist_along(dist);
+            let wobble = if trace.len() % 2 == 0 { 1.5 } else { -1.5 };
+            let pt = pt.project_away(Distance::meters(wobble), angle.rotate_degs(90.0));
+            trace.push((
+                Time::START_OF_DAY + Duration::seconds(2.0 * trace.len() as f64),
+                pt.to_gps(map.get_gps_bounds()),
+            ));
+            dist += Distance::meters(10.0);
+        }
+    }
+    let matched = map_matching::match_trace(
+        map,
+        &trace,
+        PathConstraints::Car,
+        &map_matching::MatchingOptions::default(),
+        &mut Timer::throwaway(),
+    )?;
+    let roads = |steps: Vec<PathStep>| {
+        let mut roads = Vec::new();
+        for step in steps {
+            if let PathStep::Lane(l) = step {
+                let r = map.get_l(l).parent;
+                if roads.last() != Some(&r) {
+                    roads.push(r);
+                }
+            }
+        }
+        roads
+    };
+    assert_eq!(
+        roads(matched.path.get_steps().iter().cloned().collect()),
+        roads(
+            legs.iter()
+                .flat_map(|leg| leg.get_steps().iter().cloned())
+                .collect()
+        )
+    );
+
+    // The simulation would normally go straight to the exit
+    let mut scenario = Scenario::empty(map, "map_matching");
+    let mut trip = IndividTrip::new(
+        matched.start_time(),
+        TripPurpose::Shopping,
+        TripEndpoint::SuddenlyAppear(matched.positions[0].1),
+        TripEndpoint::Border(west),
+        TripMode::Drive,
+    );
+    let mut duration = |trip: IndividTrip| {
+        scenario.people = vec![PersonSpec {
+            orig_id: None,
+            trips: vec![trip],
+        }];
+        let mut sim = setup_scenario(map, &scenario, sim::SimOptions::new("test_map_matching"));
+        run_until_done(map, &mut sim);
+        let (id, _) = sim.all_trip_info().pop().unwrap();
+        sim.get_analytics().finished_trip_time(id).unwrap()
+    };
+    let direct = duration(trip.clone());
+    trip.route = Some(matched.path.clone());
+    let replayed = duration(trip);
+    // Going around the ring once more is about 250m
+    if replayed < direct + Duration::seconds(15.0) {
+        panic!(
+            "The direct trip took {}, and replaying the extra loop took {}",
+            direct, replayed
+        );
+    }
+
+    // The matched route only exists in memory. Saving and loading the scenario keeps the trip,
+    // but the simulation picks its own route again.
+    assert!(scenario.people[0].trips[0].route.is_some());
+    for loaded in vec![
+        abstutil::from_binary::<Scenario>(&abstutil::to_binary(&scenario))?,
+        abstutil::from_json::<Scenario>(abstutil::to_json(&scenario).as_bytes())?,
+    ] {
+        let trip = &loaded.people[0].trips[0];
+        assert!(trip.route.is_none());
+        assert_eq!(trip.depart, matched.start_time());
+        assert_eq!(trip.destination, TripEndpoint::Border(west));
+    }
+
+    // Somebody walks along a sidewalk, then turns around partway. A path can't turn around in
+    // the middle of a lane, so that part should be skipped.
+    let sidewalk = *map
+        .get_i(west)
+        .outgoing_lanes
+        .iter()
+        .find(|l| map.get_l(**l).is_walkable())
+        .unwrap();
+    let length = map.get_l(sidewalk).length();
+    let mut dists = Vec::new();
+    let mut dist = length * 0.15;
+    while dist < length * 0.85 {
+        dists.push(dist);
+        dist += Distance::meters(10.0);
+    }
+    while dist > length * 0.4 {
+        dist -= Distance::meters(10.0);
+        dists.push(dist);
+    }
+    let trace: Vec<(Time, LonLat)> = dists
+        .into_iter()
+        .enumerate()
+        .map(|(idx, dist)| {
+            (
+                Time::START_OF_DAY + Duration::seconds(10.0 * idx as f64),
+                map.get_l(sidewalk)
+                    .lane_center_pts
+                    .must_dist_along(dist)
+                    .0
+                    .to_gps(map.get_gps_bounds()),
+            )
+        })
+        .collect();
+    let matched = map_matching::match_trace(
+        map,
+        &trace,
+        PathConstraints::Pedestrian,
+        &map_matching::MatchingOptions::default(),
+        &mut Timer::throwaway(),
+    )?;
+    assert!(matched.positions.len() < trace.len());
+    let steps: Vec<PathStep> = matched.path.get_steps().iter().cloned().collect();
+    for pair in steps.windows(2) {
+        assert!(pair[0].connects_to(pair[1], map));
+    }
+    assert_eq!(matched.positions.last().unwrap().0, trace.last().unwrap().0);
+
+    Ok(())
+}
+
+/// Calculating paths ahead of time in parallel must not change anything about the simulation.
+fn test_parallel_pathfinding(map: &Map) -> Result<()> {
+    let borders: Vec<IntersectionID> = map
+        .all_intersections()
+        .iter()
+        .filter(|i| i.is_border())
+        .map(|i| i.id)
+        .collect();
+    // Trips between every pair of borders, spread out over longer than the prefetcher looks ahead
+    let mut scenario = Scenario::empty(map, "parallel_pathfinding");
+    let mut depart = Duration::ZERO;
+    for from in &borders {
+        for to in &borders {
+            if from == to {
+                continue;
+            }
+            for mode in vec![TripMode::Drive, TripMode::Walk, TripMode::Bike] {
+                scenario.people.push(PersonSpec {
+                    orig_id: None,
+                    trips: vec![IndividTrip::new(
+                        Time::START_OF_DAY + depart,
+                        TripPurpose::Shopping,
+                        TripEndpoint::Border(*from),
+                        TripEndpoint::Border(*to),
+                        mode,
+                    )],
+                });
+                depart += Duration::seconds(30.0);
+            }
+        }
+    }
+
+    let run = |parallel_pathfinding: bool| {
+        let mut opts = sim::SimOptions::new("test_parallel_pathfinding");
+        opts.parallel_pathfinding = parallel_pathfinding;
+        let mut sim = setup_scenario(map, &scenario, opts);
+        run_until_done(map, &mut sim);
+        sim
+    };
+    let serial = run(false);
+    let parallel = run(true);
+    assert_eq!(
+        serial.get_analytics().finished_trips.len(),
+        scenario.people.len()
+    );
+    assert_eq!(
+        serial.get_analytics().finished_trips,
+        parallel.get_analytics().finished_trips
+    );
+    assert_eq!(
+        abstutil::to_json(serial.get_analytics()),
+        abstutil::to_json(parallel.get_analytics())
+    );
+    Ok(())
+}
+
+/// Verify incidents are checked when they're scheduled, and that blocking a lane holds up traffic
+/// until the incident ends.
+fn test_incidents(map: &Map) -> Result<()> {
+    let road = map
+        .all_roads()
+        .iter()
+        .find(|r| r.osm_tags.is("name", "Fast Street"))
+        .unwrap();
+    let (lane, _, _) = road
+        .lanes_ltr()
+        .into_iter()
+        .find(|(_, dir, lt)| *dir == Direction::Fwd && *lt == LaneType::Driving)
+        .unwrap();
+    let (sidewalk, _, _) = road
+        .lanes_ltr()
+        .into_iter()
+        .find(|(_, _, lt)| *lt == LaneType::Sidewalk)
+        .unwrap();
+    let incident = |kind: IncidentKind, start: Duration, end: Duration| Incident {
+        kind,
+        start: Time::START_OF_DAY + start,
+        end: Time::START_OF_DAY + end,
+        description: "test".to_string(),
+    };
+    let blockage = IncidentKind::LaneBlockage(Position::new(lane, map.get_l(lane).length() / 2.0));
+    let five_mins = Duration::minutes(5);
+
+    // Nonsense is rejected
+    let mut sim = sim::Sim::new(map, sim::SimOptions::new("test_incidents"));
+    for bad in vec![
+        incident(blockage.clone(), five_mins, Duration::ZERO),
+        incident(
+            IncidentKind::LaneBlockage(Position::new(sidewalk, Distance::ZERO)),
+            Duration::ZERO,
+            five_mins,
+        ),
+        incident(
+            IncidentKind::CapacityReduction {
+                road: road.id,
+                factor: 0.0,
+            },
+            Duration::ZERO,
+            five_mins,
+        ),
+    ] {
+        assert!(sim.schedule_incident(bad, map).is_err());
+    }
+    sim.timed_step(
+        map,
+        Duration::minutes(1),
+        &mut None,
+        &mut Timer::throwaway(),
+    );
+    // Too late to start this
+    assert!(sim
+        .schedule_incident(incident(blockage.clone(), Duration::ZERO, five_mins), map)
+        .is_err());
+
+    // One car drives along the blocked lane
+    let mut scenario = Scenario::empty(map, "incidents");
+    scenario.people.push(PersonSpec {
+        orig_id: None,
+        trips: vec![IndividTrip::new(
+            Time::START_OF_DAY,
+            TripPurpose::Shopping,
+            TripEndpoint::Border(find_border(map, |pt| pt.x())),
+            TripEndpoint::Border(find_border(map, |pt| -pt.x())),
+            TripMode::Drive,
+        )],
+    });
+
+    let mut sim = setup_scenario(map, &scenario, sim::SimOptions::new("test_incidents"));
+    run_until_done(map, &mut sim);
+    let unblocked = sim.time();
+    assert!(unblocked < Time::START_OF_DAY + five_mins);
+
+    let mut sim = setup_scenario(map, &scenario, sim::SimOptions::new("test_incidents"));
+    let id = sim.schedule_incident(incident(blockage, Duration::ZERO, five_mins), map)?;
+    run_until_done(map, &mut sim);
+    // The car can't get past until the blockage is cleared
+    assert!(sim.time() >= Time::START_OF_DAY + five_mins);
+    assert_eq!(sim.get_analytics().total_incident_delay(id).0, 1);
+
+    // Incidents can overlap on the same road or intersection. A short one ending in the middle of
+    // a longer one mustn't lift the longer one early, so the outcome matches the longer one alone.
+    // The car only starts along the slow road after the short incidents are over.
+    let slow_road = map
+        .all_roads()
+        .iter()
+        .find(|r| r.osm_tags.is("name", "Slow Street"))
+        .unwrap()
+        .id;
+    let junction = vec![road.src_i, road.dst_i]
+        .into_iter()
+        .find(|i| !map.get_i(*i).is_border())
+        .unwrap();
+    let ten_mins = Duration::minutes(10);
+    for (long, short) in vec![
+        (
+            IncidentKind::CapacityReduction {
+                road: slow_road,
+                factor: 0.2,
+            },
+            IncidentKind::CapacityReduction {
+                road: slow_road,
+                factor: 0.5,
+            },
+        ),
+        (
+            IncidentKind::IntersectionClosure(junction),
+            IncidentKind::IntersectionClosure(junction),
+        ),
+    ] {
+        let finished = |overlap: bool| -> Result<Time> {
+            let mut sim = setup_scenario(map, &scenario, sim::SimOptions::new("test_incidents"));
+            sim.schedule_incident(incident(long.clone(), Duration::ZERO, ten_mins), map)?;
+            if overlap {
+                sim.schedule_incident(
+                    incident(
+                        short.clone(),
+                        Duration::seconds(1.0),
+                        Duration::seconds(2.0),
+                    ),
+                    map,
+                )?;
+            }
+            run_until_done(map, &mut sim);
+            Ok(sim.time())
+        };
+        let alone = finished(false)?;
+        assert!(alone > unblocked);
+        assert_eq!(finished(true)?, alone);
+    }
+
+    Ok(())
+}
+
+/// Block the main street for a while, and make sure informed drivers take the detour instead of
+/// waiting.
+fn test_rerouting(map: &Map) -> Result<()> {
+    let main_street = map
+        .all_roads()
+        .iter()
+        .find(|r| r.osm_tags.is("name", "Main Street"))
+        .unwrap();
+    let (lane, _, _) = main_street
+        .lanes_ltr()
+        .into_iter()
+        .find(|(_, dir, lt)| *dir == Direction::Fwd && *lt == LaneType::Driving)
+        .unwrap();
+    let blocked_until = Time::START_OF_DAY + Duration::minutes(15);
+    let incident = Incident {
+        kind: IncidentKind::LaneBlockage(Position::new(lane, map.get_l(lane).length() / 2.0)),
+        start: Time::START_OF_DAY,
+        end: blocked_until,
+        description: "test".to_string(),
+    };
+
+    let mut scenario = Scenario::empty(map, "rerouting");
+    for idx in 0..40 {
+        scenario.people.push(PersonSpec {
+            orig_id: None,
+            trips: vec![IndividTrip::new(
+                Time::START_OF_DAY + Duration::seconds(3.0 * idx as f64),
+                TripPurpose::Shopping,
+                TripEndpoint::Border(find_border(map, |pt| pt.x())),
+                TripEndpoint::Border(find_border(map, |pt| -pt.x())),
+                TripMode::Drive,
+            )],
+        });
+    }
+
+    // How many trips finish while the main street is still blocked?
+    let finished_early = |informed_drivers: f64| -> Result<usize> {
+        let mut opts = sim::SimOptions::new("test_rerouting");
+        opts.informed_drivers = informed_drivers;
+        opts.reroute_threshold = Duration::seconds(10.0);
+        let mut sim = setup_scenario(map, &scenario, opts);
+        sim.schedule_incident(incident.clone(), map)?;
+        run_until_done(map, &mut sim);
+        Ok(sim
+            .get_analytics()
+            .finished_trips
+            .iter()
+            .filter(|(t, _, _, _)| *t < blocked_until)
+            .count())
+    };
+    // Nobody knows to avoid the blockage, so everybody queues behind it
+    assert_eq!(finished_early(0.0)?, 0);
+    // Once the queue behind the blockage is long enough, everybody else takes the detour
+    let informed = finished_early(1.0)?;
+    if informed < 20 {
+        panic!(
+            "Only {} of 40 informed drivers got around the blocked main street",
+            informed
+        );
+    }
+
+    Ok(())
+}
+
+/// Block the lane where vehicles enter a two-lane street. With mid-block lane-changing, they go
+/// around the blockage, then merge where the street narrows.
+fn test_mid_block_lanechanging(map: &Map) -> Result<()> {
+    let west = find_border(map, |pt| pt.x());
+    let east = find_border(map, |pt| -pt.x());
+    // Vehicles appearing at a border always start in this lane
+    let lane = map
+        .get_i(west)
+        .some_outgoing_road(map)
+        .and_then(|dr| dr.lanes(PathConstraints::Car, map).pop())
+        .unwrap();
+    assert_eq!(
+        map.get_l(lane)
+            .get_directed_parent()
+            .lanes(PathConstraints::Car, map)
+            .len(),
+        2
+    );
+    let blocked_until = Time::START_OF_DAY + Duration::minutes(10);
+    let incident = Incident {
+        kind: IncidentKind::LaneBlockage(Position::new(lane, map.get_l(lane).length() / 2.0)),
+        start: Time::START_OF_DAY,
+        end: blocked_until,
+        description: "test".to_string(),
+    };
+
+    let mut scenario = Scenario::empty(map, "mid_block_lanechanging");
+    for idx in 0..10 {
+        scenario.people.push(PersonSpec {
+            orig_id: None,
+            trips: vec![IndividTrip::new(
+                Time::START_OF_DAY + Duration::seconds(3.0 * idx as f64),
+                TripPurpose::Shopping,
+                TripEndpoint::Border(west),
+                TripEndpoint::Border(east),
+                TripMode::Drive,
+            )],
+        });
+    }
+
+    let done_at = |mid_block_lanechanging: bool| -> Result<Time> {
+        let mut opts = sim::SimOptions::new("test_mid_block_lanechanging");
+        opts.mid_block_lanechanging = mid_block_lanechanging;
+        let mut sim = setup_scenario(map, &scenario, opts);
+        sim.schedule_incident(incident.clone(), map)?;
+        run_until_done(map, &mut sim);
+        Ok(sim.time())
+    };
+    // Lanes are only chosen at the end of the previous one, so everybody waits for the blockage
+    // to clear
+    assert!(done_at(false)? >= blocked_until);
+    let time = done_at(true)?;
+    if time >= blocked_until {
+        panic!(
+            "With mid-block lane-changing, the last vehicle finished at {}; it should've gone \
+             around the blockage",
+            time
+        );
+    }
+
+    Ok(())
+}
+
+/// A big crowd sets off along the same sidewalk at once. With crowding simulated, they should slow
+/// each other down.
+fn test_pedestrian_crowding(map: &Map) -> Result<()> {
+    let mut scenario = Scenario::empty(map, "pedestrian_crowding");
+    for idx in 0..400 {
+        scenario.people.push(PersonSpec {
+            orig_id: None,
+            trips: vec![IndividTrip::new(
+                Time::START_OF_DAY + Duration::seconds(0.05 * idx as f64),
+                TripPurpose::Recreation,
+                TripEndpoint::Border(find_border(map, |pt| pt.x())),
+                TripEndpoint::Border(find_border(map, |pt| -pt.x())),
+                TripMode::Walk,
+            )],
+        });
+    }
+
+    let run = |crowding: bool| {
+        let mut opts = sim::SimOptions::new("test_pedestrian_crowding");
+        opts.pedestrian_crowding = crowding;
+        let mut sim = setup_scenario(map, &scenario, opts);
+        run_until_done(map, &mut sim);
+        let worst = sim
+            .get_analytics()
+            .worst_sidewalk_los(sim.time())
+            .values()
+            .max()
+            .cloned();
+        (sim.time(), worst)
+    };
+    let (uncrowded_time, uncrowded_los) = run(false);
+    let (crowded_time, crowded_los) = run(true);
+    // Level of service is only measured when crowding is simulated
+    assert_eq!(uncrowded_los, None);
+    if crowded_los < Some(LevelOfService::D) {
+        panic!(
+            "400 people on one sidewalk only reached level of service {:?}",
+            crowded_los
+        );
+    }
+    if crowded_time <= uncrowded_time {
+        panic!(
+            "The crowd finished walking at {} without crowding and {} with it; it should be slower",
+            uncrowded_time, crowded_time
+        );
+    }
+
+    Ok(())
+}
+
+/// Only let pedestrians start crossing at the very beginning of each stage. Anybody arriving later
+/// has to wait for the next cycle.
+fn test_pedestrian_timing(map: &Map) -> Result<()> {
+    let i = map
+        .all_intersections()
+        .iter()
+        .find(|i| i.is_traffic_signal())
+        .unwrap()
+        .id;
+    let mut short_walk = map.clone();
+    let mut ts = map.get_traffic_signal(i).clone();
+    let mut num_timed = 0;
+    for idx in 0..ts.stages.len() {
+        let walk = Duration::seconds(3.0);
+        let duration = ts.stages[idx].stage_type.simple_duration();
+        let min_crossing_time = ts.get_min_crossing_time(idx);
+        if min_crossing_time == Duration::ZERO || duration - walk < min_crossing_time {
+            continue;
+        }
+        ts.stages[idx].pedestrian_timing = Some(PedestrianTiming {
+            leading_interval: Duration::ZERO,
+            walk,
+            flashing_dont_walk: duration - walk,
+        });
+        num_timed += 1;
+    }
+    assert!(num_timed > 0);
+    ts.validate()?;
+    let mut edits = short_walk.get_edits().clone();
+    edits.commands.push(EditCmd::ChangeIntersection {
+        i,
+        old: short_walk.get_i_edit(i),
+        new: EditIntersection::TrafficSignal(ts.export(&short_walk)),
+    });
+    short_walk.must_apply_edits(edits);
+    // The timing survives the round-trip through the edit format
+    assert_eq!(
+        short_walk
+            .get_traffic_signal(i)
+            .stages
+            .iter()
+            .filter(|s| s.pedestrian_timing.is_some())
+            .count(),
+        num_timed
+    );
+    // But it isn't stored in map files, so the format doesn't change
+    assert_eq!(
+        abstutil::serialized_size_bytes(short_walk.get_traffic_signal(i)),
+        abstutil::serialized_size_bytes(map.get_traffic_signal(i))
+    );
+
+    let north = find_border(map, |pt| pt.y());
+    let south = find_border(map, |pt| -pt.y());
+    let west = find_border(map, |pt| pt.x());
+    let east = find_border(map, |pt| -pt.x());
+    let mut scenario = Scenario::empty(map, "pedestrian_timing");
+    for round in 0..10 {
+        for (idx, (from, to)) in vec![(north, south), (south, north), (west, east), (east, west)]
+            .into_iter()
+            .enumerate()
+        {
+            scenario.people.push(PersonSpec {
+                orig_id: None,
+                trips: vec![IndividTrip::new(
+                    Time::START_OF_DAY + Duration::seconds((28 * round + 7 * idx) as f64),
+                    TripPurpose::Shopping,
+                    TripEndpoint::Border(from),
+                    TripEndpoint::Border(to),
+                    TripMode::Walk,
+                )],
+            });
+        }
+    }
+
+    let crosswalk_delay = |map: &Map| {
+        let mut sim = setup_scenario(
+            map,
+            &scenario,
+            sim::SimOptions::new("test_pedestrian_timing"),
+        );
+        run_until_done(map, &mut sim);
+        let mut count = 0;
+        let mut total = Duration::ZERO;
+        for t in &map.get_i(i).turns {
+            let (n, dt) = sim.get_analytics().total_crosswalk_delay(t.id);
+            count += n;
+            total += dt;
+        }
+        (count, total)
+    };
+    let (count1, delay1) = crosswalk_delay(map);
+    let (count2, delay2) = crosswalk_delay(&short_walk);
+    // Everybody crosses at the signal either way
+    assert!(count1 > 0);
+    assert_eq!(count1, count2);
+    if delay2 <= delay1 {
+        panic!(
+            "Pedestrians waited {} in total at crosswalks normally, and {} with a short walk \
+             interval; it should be longer",
+            delay1, delay2
+        );
+    }
+
+    Ok(())
+}
+
+/// Finds the border intersection with the smallest value of some function of its position.
+fn find_border<F: Fn(Pt2D) -> f64>(map: &Map, key: F) -> IntersectionID {
+    map.all_intersections()
+        .iter()
+        .filter(|i| i.is_border())
+        .min_by(|a, b| {
+            key(a.polygon.center())
+                .partial_cmp(&key(b.polygon.center()))
+                .unwrap()
+        })
+        .unwrap()
+        .id
+}
+
+/// Set up a scenario using a fixed RNG seed, without printing alerts.
+fn setup_scenario(map: &Map, scenario: &Scenario, mut opts: sim::SimOptions) -> sim::Sim {
+    opts.alerts = sim::AlertHandler::Silence;
+    let mut sim = sim::Sim::new(map, opts);
+    let mut rng = sim::SimFlags::for_test(&scenario.scenario_name).make_rng();
+    scenario.instantiate(&mut sim, map, &mut rng, &mut Timer::throwaway());
+    sim
+}
+
+/// Run until every trip is done. Panics if that takes longer than an hour.
+fn run_until_done(map: &Map, sim: &mut sim::Sim) {
+    let deadline = sim.time() + Duration::hours(1);
+    while !sim.is_done() {
+        if sim.time() >= deadline {
+            panic!("Trips still aren't done at {}", sim.time());
+        }
+        sim.tiny_step(map, &mut None);
+    }
+}
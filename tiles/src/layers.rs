@@ -0,0 +1,321 @@
+use geom::{GPSBounds, Pt2D};
+use map_model::{osm, BuildingType, Direction, IntersectionType, Map};
+
+use crate::RoadAttributes;
+
+/// One layer of every tile
+pub struct Layer {
+    pub name: &'static str,
+    pub features: Vec<Feature>,
+}
+
+pub struct Feature {
+    pub id: u64,
+    /// Only include this feature in tiles at this zoom level or higher
+    pub min_zoom: u32,
+    pub geometry: Geometry,
+    pub properties: Vec<(String, Value)>,
+    /// In world coordinates, (min, max)
+    pub bbox: (WorldPt, WorldPt),
+}
+
+/// Web Mercator coordinates, with x and y ranging from 0 to 1. y increases southwards.
+#[derive(Clone, Copy)]
+pub struct WorldPt {
+    pub x: f64,
+    pub y: f64,
+}
+
+pub enum Geometry {
+    Point(WorldPt),
+    LineString(Vec<WorldPt>),
+    /// Just the outer ring, without repeating the first point
+    Polygon(Vec<WorldPt>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    String(String),
+    Int(i64),
+    Double(f64),
+    Bool(bool),
+}
+
+impl Value {
+    /// The type of the value, as described in the TileJSON `vector_layers` metadata
+    pub fn describe_type(&self) -> &'static str {
+        match self {
+            Value::String(_) => "String",
+            Value::Int(_) | Value::Double(_) => "Number",
+            Value::Bool(_) => "Boolean",
+        }
+    }
+}
+
+impl Feature {
+    fn new(
+        id: usize,
+        min_zoom: u32,
+        geometry: Geometry,
+        properties: Vec<(&str, Value)>,
+    ) -> Feature {
+        let pts = match geometry {
+            Geometry::Point(ref pt) => std::slice::from_ref(pt),
+            Geometry::LineString(ref pts) | Geometry::Polygon(ref pts) => pts.as_slice(),
+        };
+        let mut min = WorldPt {
+            x: f64::MAX,
+            y: f64::MAX,
+        };
+        let mut max = WorldPt {
+            x: f64::MIN,
+            y: f64::MIN,
+        };
+        for pt in pts {
+            min.x = min.x.min(pt.x);
+            min.y = min.y.min(pt.y);
+            max.x = max.x.max(pt.x);
+            max.y = max.y.max(pt.y);
+        }
+        Feature {
+            id: id as u64,
+            min_zoom,
+            geometry,
+            properties: properties
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v))
+                .collect(),
+            bbox: (min, max),
+        }
+    }
+}
+
+/// Express everything in the map as features, in Web Mercator.
+pub fn make_layers(map: &Map, road_attributes: &RoadAttributes) -> Vec<Layer> {
+    let gps_bounds = map.get_gps_bounds();
+    let project = |pts: &Vec<Pt2D>| -> Vec<WorldPt> {
+        pts.iter().map(|pt| to_world(*pt, gps_bounds)).collect()
+    };
+    let project_polygon = |pts: &Vec<Pt2D>| -> Vec<WorldPt> {
+        let mut pts = project(pts);
+        if pts.len() > 1 && pts[0].x == pts.last().unwrap().x && pts[0].y == pts.last().unwrap().y {
+            pts.pop();
+        }
+        pts
+    };
+
+    let mut roads = Vec::new();
+    let mut lanes = Vec::new();
+    for r in map.all_roads() {
+        let min_zoom = match r.get_rank() {
+            osm::RoadRank::Highway => 0,
+            osm::RoadRank::Arterial => 12,
+            osm::RoadRank::Local => 14,
+        };
+        let lanes_ltr = r.lanes_ltr();
+        let props = vec![
+            ("osm_way_id", Value::Int(r.orig_id.osm_way_id.0)),
+            ("name", Value::String(r.get_name(None))),
+            (
+                "highway",
+                Value::String(
+                    r.osm_tags
+                        .get(osm::HIGHWAY)
+                        .cloned()
+                        .unwrap_or_else(String::new),
+                ),
+            ),
+            (
+                "speed_limit_kmph",
+                Value::Double(r.speed_limit.inner_meters_per_second() * 3.6),
+            ),
+            ("width_m", Value::Double(r.get_width(map).inner_meters())),
+            (
+                "lane_types",
+                Value::String(lanes_ltr.iter().map(|(_, _, lt)| lt.to_char()).collect()),
+            ),
+            (
+                "lane_directions",
+                Value::String(
+                    lanes_ltr
+                        .iter()
+                        .map(|(_, dir, _)| if *dir == Direction::Fwd { 'F' } else { 'B' })
+                        .collect(),
+                ),
+            ),
+            (
+                "driving_lanes",
+                Value::Int(
+                    lanes_ltr
+                        .iter()
+                        .filter(|(_, _, lt)| lt.is_for_moving_vehicles())
+                        .count() as i64,
+                ),
+            ),
+            ("incline_pct", Value::Double(r.percent_incline * 100.0)),
+        ];
+        let mut feature = Feature::new(
+            r.id.0,
+            min_zoom,
+            Geometry::LineString(project(r.center_pts.points())),
+            props,
+        );
+        for (name, values) in road_attributes {
+            if let Some(value) = values.get(&r.id) {
+                feature
+                    .properties
+                    .push((name.clone(), Value::Double(*value)));
+            }
+        }
+        roads.push(feature);
+
+        for (l, dir, lt) in lanes_ltr {
+            let lane = map.get_l(l);
+            lanes.push(Feature::new(
+                l.0,
+                16,
+                Geometry::LineString(project(lane.lane_center_pts.points())),
+                vec![
+                    ("road", Value::Int(r.id.0 as i64)),
+                    ("type", Value::String(lt.short_name().to_string())),
+                    (
+                        "direction",
+                        Value::String(
+                            if dir == Direction::Fwd {
+                                "forwards"
+                            } else {
+                                "backwards"
+                            }
+                            .to_string(),
+                        ),
+                    ),
+                    ("width_m", Value::Double(lane.width.inner_meters())),
+                ],
+            ));
+        }
+    }
+
+    let mut intersections = Vec::new();
+    for i in map.all_intersections() {
+        intersections.push(Feature::new(
+            i.id.0,
+            15,
+            Geometry::Polygon(project_polygon(i.polygon.points())),
+            vec![
+                ("osm_node_id", Value::Int(i.orig_id.0)),
+                (
+                    "control",
+                    Value::String(
+                        match i.intersection_type {
+                            IntersectionType::StopSign => "stop_sign",
+                            IntersectionType::TrafficSignal => "traffic_signal",
+                            IntersectionType::Border => "border",
+                            IntersectionType::Construction => "construction",
+                            IntersectionType::Roundabout => "roundabout",
+                        }
+                        .to_string(),
+                    ),
+                ),
+            ],
+        ));
+    }
+
+    let mut buildings = Vec::new();
+    for b in map.all_buildings() {
+        let (bldg_type, residents, workers) = match b.bldg_type {
+            BuildingType::Residential { num_residents, .. } => ("residential", num_residents, 0),
+            BuildingType::ResidentialCommercial(residents, workers) => {
+                ("residential_commercial", residents, workers)
+            }
+            BuildingType::Commercial(workers) => ("commercial", 0, workers),
+            BuildingType::Empty => ("empty", 0, 0),
+        };
+        let mut props = vec![
+            ("osm_id", Value::String(b.orig_id.to_string())),
+            ("type", Value::String(bldg_type.to_string())),
+            ("residents", Value::Int(residents as i64)),
+            ("workers", Value::Int(workers as i64)),
+            ("address", Value::String(b.address.clone())),
+            ("levels", Value::Double(b.levels)),
+        ];
+        if let Some(ref name) = b.name {
+            props.push(("name", Value::String(name.get(None).to_string())));
+        }
+        if !b.amenities.is_empty() {
+            let amenities: Vec<&str> = b
+                .amenities
+                .iter()
+                .map(|a| a.amenity_type.as_str())
+                .collect();
+            props.push(("amenities", Value::String(amenities.join(","))));
+        }
+        buildings.push(Feature::new(
+            b.id.0,
+            14,
+            Geometry::Polygon(project_polygon(b.polygon.points())),
+            props,
+        ));
+    }
+
+    let mut parking_lots = Vec::new();
+    for pl in map.all_parking_lots() {
+        parking_lots.push(Feature::new(
+            pl.id.0,
+            15,
+            Geometry::Polygon(project_polygon(pl.polygon.points())),
+            vec![(
+                "capacity",
+                Value::Int((pl.spots.len() + pl.extra_spots) as i64),
+            )],
+        ));
+    }
+
+    let mut bus_stops = Vec::new();
+    for (idx, bs) in map.all_bus_stops().values().enumerate() {
+        bus_stops.push(Feature::new(
+            idx,
+            15,
+            Geometry::Point(to_world(bs.sidewalk_pos.pt(map), gps_bounds)),
+            vec![
+                ("name", Value::String(bs.name.clone())),
+                ("is_train_stop", Value::Bool(bs.is_train_stop)),
+            ],
+        ));
+    }
+
+    vec![
+        Layer {
+            name: "roads",
+            features: roads,
+        },
+        Layer {
+            name: "lanes",
+            features: lanes,
+        },
+        Layer {
+            name: "intersections",
+            features: intersections,
+        },
+        Layer {
+            name: "buildings",
+            features: buildings,
+        },
+        Layer {
+            name: "parking_lots",
+            features: parking_lots,
+        },
+        Layer {
+            name: "bus_stops",
+            features: bus_stops,
+        },
+    ]
+}
+
+fn to_world(pt: Pt2D, gps_bounds: &GPSBounds) -> WorldPt {
+    let gps = pt.to_gps(gps_bounds);
+    let lat = gps.y().to_radians();
+    WorldPt {
+        x: (gps.x() + 180.0) / 360.0,
+        y: (1.0 - (lat.tan() + 1.0 / lat.cos()).ln() / std::f64::consts::PI) / 2.0,
+    }
+}
@@ -0,0 +1,85 @@
+//! Cuts a map into [Mapbox vector tiles](https://github.com/mapbox/vector-tile-spec) across a
+//! range of zoom levels, storing them in an [MBTiles](https://github.com/mapbox/mbtiles-spec) file.
+//! This is much smaller than the full GeoJSON for city-scale maps, and any vector tile client can
+//! render it. An MBTiles file can be converted to PMTiles with the `pmtiles convert` tool.
+//!
+//! Layers:
+//! - roads, with lane details and any extra attributes, like simulation results
+//! - lanes, only at high zoom levels
+//! - intersections
+//! - buildings, with their type and amenities
+//! - parking_lots
+//! - bus_stops
+
+#[macro_use]
+extern crate anyhow;
+#[macro_use]
+extern crate log;
+
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+
+use abstutil::{prettyprint_usize, Timer};
+use map_model::{Map, RoadID};
+
+pub use self::layers::make_layers;
+
+mod layers;
+mod mbtiles;
+mod mvt;
+
+/// Extra numeric attributes to attach to roads, usually produced by a simulation, like throughput
+/// or delay. The outer key is the attribute name.
+pub type RoadAttributes = BTreeMap<String, BTreeMap<RoadID, f64>>;
+
+pub struct Options {
+    pub min_zoom: u32,
+    pub max_zoom: u32,
+}
+
+impl Options {
+    pub fn default() -> Options {
+        Options {
+            min_zoom: 12,
+            max_zoom: 16,
+        }
+    }
+}
+
+/// Generates all tiles for a map and writes them to an MBTiles file, overwriting anything there.
+pub fn write_mbtiles(
+    map: &Map,
+    road_attributes: &RoadAttributes,
+    opts: &Options,
+    path: &str,
+    timer: &mut Timer,
+) -> Result<()> {
+    if opts.min_zoom > opts.max_zoom || opts.max_zoom > 22 {
+        bail!("Bad zoom range {} to {}", opts.min_zoom, opts.max_zoom);
+    }
+
+    timer.start("make layers");
+    let layers = make_layers(map, road_attributes);
+    timer.stop("make layers");
+
+    let mut out = mbtiles::MBTiles::create(path)?;
+    out.write_metadata(map, &layers, opts)?;
+
+    let mut total = 0;
+    for zoom in opts.min_zoom..=opts.max_zoom {
+        let tiles = mvt::assign_tiles(&layers, zoom);
+        timer.start_iter(format!("encode tiles for zoom {}", zoom), tiles.len());
+        out.begin()?;
+        for ((x, y), features) in tiles {
+            timer.next();
+            if let Some(bytes) = mvt::encode_tile(&layers, &features, zoom, x, y) {
+                out.insert(zoom, x, y, &bytes)?;
+                total += 1;
+            }
+        }
+        out.commit()?;
+    }
+    info!("Wrote {} tiles to {}", prettyprint_usize(total), path);
+    Ok(())
+}
@@ -0,0 +1,75 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+
+use abstutil::{CmdArgs, Timer};
+use map_model::Map;
+use sim::Analytics;
+use tiles::{Options, RoadAttributes};
+
+/// Cut a map into vector tiles, stored in an MBTiles file. If a scenario is specified, attach
+/// throughput and delay from its prebaked results to roads.
+fn main() -> Result<()> {
+    let mut args = CmdArgs::new();
+    let map = args.required("--map");
+    let output = args.required("--output");
+    let mut opts = Options::default();
+    if let Some(x) = args.optional_parse("--min_zoom", |s| s.parse::<u32>()) {
+        opts.min_zoom = x;
+    }
+    if let Some(x) = args.optional_parse("--max_zoom", |s| s.parse::<u32>()) {
+        opts.max_zoom = x;
+    }
+    let prebaked = args.optional("--prebaked");
+    args.done();
+
+    let mut timer = Timer::new("generate vector tiles");
+    let map = Map::load_synchronously(map, &mut timer);
+    let road_attributes = if let Some(scenario_name) = prebaked {
+        let analytics: Analytics = abstio::read_binary(
+            abstio::path_prebaked_results(map.get_name(), &scenario_name),
+            &mut timer,
+        );
+        from_analytics(&map, &analytics)
+    } else {
+        RoadAttributes::new()
+    };
+
+    tiles::write_mbtiles(&map, &road_attributes, &opts, &output, &mut timer)
+}
+
+fn from_analytics(map: &Map, analytics: &Analytics) -> RoadAttributes {
+    let mut throughput = BTreeMap::new();
+    for ((r, _, _), count) in &analytics.road_thruput.counts {
+        *throughput.entry(*r).or_insert(0.0) += *count as f64;
+    }
+
+    // Delay is only recorded at traffic signals, per movement. Attribute it to the incoming road.
+    let mut delays = BTreeMap::new();
+    for (i, entries) in &analytics.intersection_delays {
+        let signal = if let Some(ts) = map.maybe_get_traffic_signal(*i) {
+            ts
+        } else {
+            continue;
+        };
+        let movements: Vec<_> = signal.movements.keys().collect();
+        for (idx, _, delay, _) in entries {
+            if let Some(m) = movements.get(*idx as usize) {
+                let (sum, count) = delays.entry(m.from.id).or_insert((0.0, 0));
+                *sum += delay.inner_seconds();
+                *count += 1;
+            }
+        }
+    }
+
+    let mut attributes = RoadAttributes::new();
+    attributes.insert("throughput".to_string(), throughput);
+    attributes.insert(
+        "avg_signal_delay_s".to_string(),
+        delays
+            .into_iter()
+            .map(|(r, (sum, count))| (r, sum / (count as f64)))
+            .collect(),
+    );
+    attributes
+}
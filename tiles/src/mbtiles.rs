@@ -0,0 +1,111 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use anyhow::Result;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use rusqlite::{params, Connection};
+
+use map_model::Map;
+
+use crate::layers::Layer;
+use crate::Options;
+
+/// An SQLite database following https://github.com/mapbox/mbtiles-spec/blob/master/1.3/spec.md
+pub struct MBTiles {
+    conn: Connection,
+}
+
+impl MBTiles {
+    pub fn create(path: &str) -> Result<MBTiles> {
+        if abstio::file_exists(path) {
+            std::fs::remove_file(path)?;
+        }
+        let conn = Connection::open(path)?;
+        conn.execute_batch(
+            "CREATE TABLE metadata (name TEXT, value TEXT);
+             CREATE TABLE tiles (zoom_level INTEGER, tile_column INTEGER, tile_row INTEGER, \
+             tile_data BLOB);
+             CREATE UNIQUE INDEX tile_index ON tiles (zoom_level, tile_column, tile_row);",
+        )?;
+        Ok(MBTiles { conn })
+    }
+
+    pub fn write_metadata(&self, map: &Map, layers: &[Layer], opts: &Options) -> Result<()> {
+        let b = map.get_gps_bounds();
+        let center = map.get_bounds().center().to_gps(b);
+
+        // Describe the layers and their attributes, for clients like Mapbox GL
+        let mut vector_layers = Vec::new();
+        for layer in layers {
+            let mut fields = BTreeMap::new();
+            let mut min_zoom = opts.max_zoom;
+            for feature in &layer.features {
+                min_zoom = min_zoom.min(feature.min_zoom);
+                for (key, value) in &feature.properties {
+                    fields
+                        .entry(key.clone())
+                        .or_insert_with(|| value.describe_type());
+                }
+            }
+            vector_layers.push(serde_json::json!({
+                "id": layer.name,
+                "fields": fields,
+                "minzoom": min_zoom.max(opts.min_zoom),
+                "maxzoom": opts.max_zoom,
+            }));
+        }
+
+        for (name, value) in vec![
+            ("name", map.get_name().as_filename()),
+            ("format", "pbf".to_string()),
+            ("type", "overlay".to_string()),
+            ("minzoom", opts.min_zoom.to_string()),
+            ("maxzoom", opts.max_zoom.to_string()),
+            (
+                "bounds",
+                format!("{},{},{},{}", b.min_lon, b.min_lat, b.max_lon, b.max_lat),
+            ),
+            (
+                "center",
+                format!("{},{},{}", center.x(), center.y(), opts.min_zoom),
+            ),
+            (
+                "json",
+                serde_json::json!({ "vector_layers": vector_layers }).to_string(),
+            ),
+        ] {
+            self.conn.execute(
+                "INSERT INTO metadata (name, value) VALUES (?1, ?2)",
+                params![name, value],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Inserts are much faster inside a transaction
+    pub fn begin(&self) -> Result<()> {
+        self.conn.execute_batch("BEGIN")?;
+        Ok(())
+    }
+
+    pub fn commit(&self) -> Result<()> {
+        self.conn.execute_batch("COMMIT")?;
+        Ok(())
+    }
+
+    /// Takes XYZ tile coordinates and an uncompressed tile
+    pub fn insert(&self, zoom: u32, x: u32, y: u32, tile: &[u8]) -> Result<()> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(tile)?;
+        let compressed = encoder.finish()?;
+        // MBTiles uses the TMS scheme, with Y flipped
+        let row = 2_u32.pow(zoom) - 1 - y;
+        self.conn.execute(
+            "INSERT INTO tiles (zoom_level, tile_column, tile_row, tile_data) VALUES (?1, ?2, ?3, \
+             ?4)",
+            params![zoom, x, row, compressed],
+        )?;
+        Ok(())
+    }
+}
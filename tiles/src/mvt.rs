@@ -0,0 +1,564 @@
+//! Encodes features as Mapbox vector tiles. The protobuf encoding is simple enough to write by
+//! hand; see https://github.com/mapbox/vector-tile-spec/blob/master/2.1/vector_tile.proto.
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::layers::{Feature, Geometry, Layer, Value, WorldPt};
+
+/// Tile coordinates range from 0 to this
+const EXTENT: u32 = 4096;
+/// Geometry is clipped this far outside each tile, so that lines and outlines join seamlessly
+const BUFFER: f64 = 64.0;
+
+/// For every tile at one zoom level, find the (layer, feature) indices overlapping it.
+pub fn assign_tiles(layers: &[Layer], zoom: u32) -> BTreeMap<(u32, u32), Vec<(usize, usize)>> {
+    let n = 2_u32.pow(zoom);
+    let buffer = BUFFER / (EXTENT as f64) / (n as f64);
+    let to_tile = |x: f64| ((x * n as f64).floor().max(0.0) as u32).min(n - 1);
+
+    let mut tiles: BTreeMap<(u32, u32), Vec<(usize, usize)>> = BTreeMap::new();
+    for (layer_idx, layer) in layers.iter().enumerate() {
+        for (feature_idx, feature) in layer.features.iter().enumerate() {
+            if feature.min_zoom > zoom {
+                continue;
+            }
+            let (min, max) = feature.bbox;
+            for x in to_tile(min.x - buffer)..=to_tile(max.x + buffer) {
+                for y in to_tile(min.y - buffer)..=to_tile(max.y + buffer) {
+                    tiles
+                        .entry((x, y))
+                        .or_insert_with(Vec::new)
+                        .push((layer_idx, feature_idx));
+                }
+            }
+        }
+    }
+    tiles
+}
+
+/// Returns the encoded tile, or None if no features actually wind up in it after clipping.
+pub fn encode_tile(
+    layers: &[Layer],
+    features: &[(usize, usize)],
+    zoom: u32,
+    x: u32,
+    y: u32,
+) -> Option<Vec<u8>> {
+    let mut per_layer: BTreeMap<usize, Vec<&Feature>> = BTreeMap::new();
+    for (layer_idx, feature_idx) in features {
+        per_layer
+            .entry(*layer_idx)
+            .or_insert_with(Vec::new)
+            .push(&layers[*layer_idx].features[*feature_idx]);
+    }
+
+    let mut tile = Vec::new();
+    for (layer_idx, features) in per_layer {
+        if let Some(layer) = encode_layer(layers[layer_idx].name, features, zoom, x, y) {
+            write_bytes(&mut tile, 3, &layer);
+        }
+    }
+    if tile.is_empty() {
+        None
+    } else {
+        Some(tile)
+    }
+}
+
+fn encode_layer(name: &str, features: Vec<&Feature>, zoom: u32, x: u32, y: u32) -> Option<Vec<u8>> {
+    let mut keys: Vec<&str> = Vec::new();
+    let mut key_indices: HashMap<&str, u32> = HashMap::new();
+    let mut values: Vec<&Value> = Vec::new();
+    // f64 isn't hashable, so key by the debug representation
+    let mut value_indices: HashMap<String, u32> = HashMap::new();
+
+    let mut encoded_features = Vec::new();
+    for feature in features {
+        let (geom_type, geometry) = if let Some(result) = encode_geometry(feature, zoom, x, y) {
+            result
+        } else {
+            continue;
+        };
+
+        let mut tags = Vec::new();
+        for (key, value) in &feature.properties {
+            let key_idx = *key_indices.entry(key.as_str()).or_insert_with(|| {
+                keys.push(key.as_str());
+                (keys.len() - 1) as u32
+            });
+            let value_idx = *value_indices
+                .entry(format!("{:?}", value))
+                .or_insert_with(|| {
+                    values.push(value);
+                    (values.len() - 1) as u32
+                });
+            tags.push(key_idx);
+            tags.push(value_idx);
+        }
+
+        let mut buf = Vec::new();
+        write_key(&mut buf, 1, 0);
+        write_varint(&mut buf, feature.id);
+        write_packed(&mut buf, 2, &tags);
+        write_key(&mut buf, 3, 0);
+        write_varint(&mut buf, geom_type);
+        write_packed(&mut buf, 4, &geometry);
+        encoded_features.push(buf);
+    }
+    if encoded_features.is_empty() {
+        return None;
+    }
+
+    let mut layer = Vec::new();
+    write_key(&mut layer, 15, 0);
+    write_varint(&mut layer, 2);
+    write_bytes(&mut layer, 1, name.as_bytes());
+    for feature in encoded_features {
+        write_bytes(&mut layer, 2, &feature);
+    }
+    for key in keys {
+        write_bytes(&mut layer, 3, key.as_bytes());
+    }
+    for value in values {
+        write_bytes(&mut layer, 4, &encode_value(value));
+    }
+    write_key(&mut layer, 5, 0);
+    write_varint(&mut layer, EXTENT as u64);
+    Some(layer)
+}
+
+fn encode_value(value: &Value) -> Vec<u8> {
+    let mut buf = Vec::new();
+    match value {
+        Value::String(x) => {
+            write_bytes(&mut buf, 1, x.as_bytes());
+        }
+        Value::Double(x) => {
+            write_key(&mut buf, 3, 1);
+            buf.extend_from_slice(&x.to_le_bytes());
+        }
+        Value::Int(x) => {
+            // sint64 uses zigzag encoding
+            let x = *x;
+            write_key(&mut buf, 6, 0);
+            write_varint(&mut buf, ((x << 1) ^ (x >> 63)) as u64);
+        }
+        Value::Bool(x) => {
+            write_key(&mut buf, 7, 0);
+            write_varint(&mut buf, *x as u64);
+        }
+    }
+    buf
+}
+
+/// Clips the feature to the tile and returns the geometry type and commands, or None if nothing
+/// is left.
+fn encode_geometry(feature: &Feature, zoom: u32, x: u32, y: u32) -> Option<(u64, Vec<u32>)> {
+    let n = 2_u32.pow(zoom) as f64;
+    let to_tile = |pt: &WorldPt| {
+        (
+            (pt.x * n - x as f64) * EXTENT as f64,
+            (pt.y * n - y as f64) * EXTENT as f64,
+        )
+    };
+    let min = -BUFFER;
+    let max = EXTENT as f64 + BUFFER;
+
+    let mut commands = Vec::new();
+    let mut cursor = (0, 0);
+    match feature.geometry {
+        Geometry::Point(ref pt) => {
+            let (px, py) = to_tile(pt);
+            if px < 0.0 || px >= EXTENT as f64 || py < 0.0 || py >= EXTENT as f64 {
+                return None;
+            }
+            encode_path(
+                &mut commands,
+                &mut cursor,
+                &[(px.round() as i32, py.round() as i32)],
+                false,
+            );
+            Some((1, commands))
+        }
+        Geometry::LineString(ref pts) => {
+            let pts: Vec<(f64, f64)> = pts.iter().map(to_tile).collect();
+            for line in clip_line(&pts, min, max) {
+                let line = quantize(&line);
+                if line.len() >= 2 {
+                    encode_path(&mut commands, &mut cursor, &line, false);
+                }
+            }
+            if commands.is_empty() {
+                None
+            } else {
+                Some((2, commands))
+            }
+        }
+        Geometry::Polygon(ref pts) => {
+            let pts: Vec<(f64, f64)> = pts.iter().map(to_tile).collect();
+            let mut ring = quantize(&clip_polygon(pts, min, max));
+            if ring.len() > 1 && ring[0] == *ring.last().unwrap() {
+                ring.pop();
+            }
+            if ring.len() < 3 {
+                return None;
+            }
+            // Exterior rings must have a positive area in tile coordinates, where Y points down
+            let area: i64 = (0..ring.len())
+                .map(|i| {
+                    let (x1, y1) = ring[i];
+                    let (x2, y2) = ring[(i + 1) % ring.len()];
+                    (x1 as i64) * (y2 as i64) - (x2 as i64) * (y1 as i64)
+                })
+                .sum();
+            if area == 0 {
+                return None;
+            }
+            if area < 0 {
+                ring.reverse();
+            }
+            encode_path(&mut commands, &mut cursor, &ring, true);
+            Some((3, commands))
+        }
+    }
+}
+
+fn quantize(pts: &[(f64, f64)]) -> Vec<(i32, i32)> {
+    let mut result: Vec<(i32, i32)> = Vec::new();
+    for (x, y) in pts {
+        let pt = (x.round() as i32, y.round() as i32);
+        if result.last() != Some(&pt) {
+            result.push(pt);
+        }
+    }
+    result
+}
+
+fn encode_path(commands: &mut Vec<u32>, cursor: &mut (i32, i32), pts: &[(i32, i32)], close: bool) {
+    commands.push(command(1, 1));
+    push_delta(commands, cursor, pts[0]);
+    if pts.len() > 1 {
+        commands.push(command(2, pts.len() - 1));
+        for pt in &pts[1..] {
+            push_delta(commands, cursor, *pt);
+        }
+    }
+    if close {
+        commands.push(command(7, 1));
+    }
+}
+
+fn command(id: u32, count: usize) -> u32 {
+    (id & 0x7) | ((count as u32) << 3)
+}
+
+fn push_delta(commands: &mut Vec<u32>, cursor: &mut (i32, i32), pt: (i32, i32)) {
+    commands.push(zigzag(pt.0 - cursor.0));
+    commands.push(zigzag(pt.1 - cursor.1));
+    *cursor = pt;
+}
+
+fn zigzag(n: i32) -> u32 {
+    ((n << 1) ^ (n >> 31)) as u32
+}
+
+/// Sutherland-Hodgman against a square clipping region
+fn clip_polygon(mut pts: Vec<(f64, f64)>, min: f64, max: f64) -> Vec<(f64, f64)> {
+    // Clip against the left, right, top, and bottom edges in turn
+    for edge in 0..4 {
+        if pts.is_empty() {
+            break;
+        }
+        let inside = |p: (f64, f64)| match edge {
+            0 => p.0 >= min,
+            1 => p.0 <= max,
+            2 => p.1 >= min,
+            _ => p.1 <= max,
+        };
+        let intersect = |a, b| match edge {
+            0 => intersect_x(a, b, min),
+            1 => intersect_x(a, b, max),
+            2 => intersect_y(a, b, min),
+            _ => intersect_y(a, b, max),
+        };
+
+        let mut output = Vec::new();
+        let mut prev = *pts.last().unwrap();
+        for pt in pts {
+            if inside(pt) {
+                if !inside(prev) {
+                    output.push(intersect(prev, pt));
+                }
+                output.push(pt);
+            } else if inside(prev) {
+                output.push(intersect(prev, pt));
+            }
+            prev = pt;
+        }
+        pts = output;
+    }
+    pts
+}
+
+fn intersect_x(a: (f64, f64), b: (f64, f64), x: f64) -> (f64, f64) {
+    let t = (x - a.0) / (b.0 - a.0);
+    (x, a.1 + t * (b.1 - a.1))
+}
+
+fn intersect_y(a: (f64, f64), b: (f64, f64), y: f64) -> (f64, f64) {
+    let t = (y - a.1) / (b.1 - a.1);
+    (a.0 + t * (b.0 - a.0), y)
+}
+
+/// Liang-Barsky on every segment, splitting the line into pieces wherever it leaves the clipping
+/// region
+fn clip_line(pts: &[(f64, f64)], min: f64, max: f64) -> Vec<Vec<(f64, f64)>> {
+    let mut results = Vec::new();
+    let mut current: Vec<(f64, f64)> = Vec::new();
+    for pair in pts.windows(2) {
+        let (a, b) = (pair[0], pair[1]);
+        let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+        let mut t0: f64 = 0.0;
+        let mut t1: f64 = 1.0;
+        let mut visible = true;
+        for (p, q) in vec![
+            (-dx, a.0 - min),
+            (dx, max - a.0),
+            (-dy, a.1 - min),
+            (dy, max - a.1),
+        ] {
+            if p == 0.0 {
+                if q < 0.0 {
+                    visible = false;
+                    break;
+                }
+            } else {
+                let r = q / p;
+                if p < 0.0 {
+                    t0 = t0.max(r);
+                } else {
+                    t1 = t1.min(r);
+                }
+            }
+        }
+        if !visible || t0 > t1 {
+            if current.len() >= 2 {
+                results.push(std::mem::take(&mut current));
+            }
+            current.clear();
+            continue;
+        }
+
+        let start = (a.0 + t0 * dx, a.1 + t0 * dy);
+        let end = (a.0 + t1 * dx, a.1 + t1 * dy);
+        if current.is_empty() {
+            current.push(start);
+        }
+        current.push(end);
+        if t1 < 1.0 {
+            // The line leaves the region
+            results.push(std::mem::take(&mut current));
+        }
+    }
+    if current.len() >= 2 {
+        results.push(current);
+    }
+    results
+}
+
+fn write_varint(buf: &mut Vec<u8>, mut x: u64) {
+    while x >= 0x80 {
+        buf.push((x as u8) | 0x80);
+        x >>= 7;
+    }
+    buf.push(x as u8);
+}
+
+fn write_key(buf: &mut Vec<u8>, field: u32, wire_type: u32) {
+    write_varint(buf, ((field << 3) | wire_type) as u64);
+}
+
+fn write_bytes(buf: &mut Vec<u8>, field: u32, bytes: &[u8]) {
+    write_key(buf, field, 2);
+    write_varint(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+fn write_packed(buf: &mut Vec<u8>, field: u32, values: &[u32]) {
+    let mut inner = Vec::new();
+    for x in values {
+        write_varint(&mut inner, *x as u64);
+    }
+    write_bytes(buf, field, &inner);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feature(geometry: Geometry) -> Feature {
+        Feature {
+            id: 1,
+            min_zoom: 0,
+            geometry,
+            properties: Vec::new(),
+            bbox: (WorldPt { x: 0.0, y: 0.0 }, WorldPt { x: 1.0, y: 1.0 }),
+        }
+    }
+
+    fn pts(raw: &[(f64, f64)]) -> Vec<WorldPt> {
+        raw.iter().map(|(x, y)| WorldPt { x: *x, y: *y }).collect()
+    }
+
+    #[test]
+    fn varint() {
+        for (x, expected) in vec![
+            (0, vec![0x00]),
+            (1, vec![0x01]),
+            (127, vec![0x7f]),
+            (128, vec![0x80, 0x01]),
+            (300, vec![0xac, 0x02]),
+            (16384, vec![0x80, 0x80, 0x01]),
+        ] {
+            let mut buf = Vec::new();
+            write_varint(&mut buf, x);
+            assert_eq!(buf, expected, "encoding {}", x);
+        }
+    }
+
+    #[test]
+    fn zigzag_values() {
+        for (x, expected) in vec![
+            (0, 0),
+            (-1, 1),
+            (1, 2),
+            (-2, 3),
+            (2, 4),
+            (i32::MAX, 0xffff_fffe),
+            (i32::MIN, 0xffff_ffff),
+        ] {
+            assert_eq!(zigzag(x), expected, "encoding {}", x);
+        }
+    }
+
+    #[test]
+    fn encode_point() {
+        // At zoom 0, the single tile covers the whole world
+        let (geom_type, commands) = encode_geometry(
+            &feature(Geometry::Point(WorldPt { x: 0.25, y: 0.5 })),
+            0,
+            0,
+            0,
+        )
+        .unwrap();
+        assert_eq!(geom_type, 1);
+        // MoveTo(1), then (1024, 2048)
+        assert_eq!(commands, vec![9, 2048, 4096]);
+
+        // Points in other tiles are skipped
+        assert!(encode_geometry(
+            &feature(Geometry::Point(WorldPt { x: 0.25, y: 0.5 })),
+            1,
+            1,
+            0
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn encode_line() {
+        let (geom_type, commands) = encode_geometry(
+            &feature(Geometry::LineString(pts(&[
+                (0.0, 0.0),
+                (0.5, 0.0),
+                (0.5, 0.5),
+            ]))),
+            0,
+            0,
+            0,
+        )
+        .unwrap();
+        assert_eq!(geom_type, 2);
+        // MoveTo(1) to (0, 0), then LineTo(2) by (+2048, 0) and (0, +2048)
+        assert_eq!(commands, vec![9, 0, 0, 18, 4096, 0, 0, 4096]);
+
+        // Crossing the east edge gets clipped at the buffer
+        let (_, commands) = encode_geometry(
+            &feature(Geometry::LineString(pts(&[(0.5, 0.5), (1.5, 0.5)]))),
+            0,
+            0,
+            0,
+        )
+        .unwrap();
+        // MoveTo(1) to (2048, 2048), then LineTo(1) by (+2112, 0)
+        assert_eq!(commands, vec![9, 4096, 4096, 10, 4224, 0]);
+    }
+
+    #[test]
+    fn encode_polygon() {
+        let square = pts(&[(0.0, 0.0), (0.5, 0.0), (0.5, 0.5), (0.0, 0.5)]);
+        let (geom_type, commands) =
+            encode_geometry(&feature(Geometry::Polygon(square.clone())), 0, 0, 0).unwrap();
+        assert_eq!(geom_type, 3);
+        // MoveTo(1) to (0, 0), LineTo(3) by (+2048, 0), (0, +2048), (-2048, 0), then ClosePath(1)
+        assert_eq!(commands, vec![9, 0, 0, 26, 4096, 0, 0, 4096, 4095, 0, 15]);
+
+        // The opposite winding order gets fixed, so the exterior ring has positive area
+        let mut reversed = square;
+        reversed.reverse();
+        assert_eq!(
+            encode_geometry(&feature(Geometry::Polygon(reversed)), 0, 0, 0),
+            Some((geom_type, commands))
+        );
+
+        // Entirely in a different tile
+        assert!(encode_geometry(
+            &feature(Geometry::Polygon(pts(&[
+                (0.6, 0.1),
+                (0.9, 0.1),
+                (0.9, 0.4)
+            ]))),
+            1,
+            0,
+            0
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn clip_polygons() {
+        let inside = vec![(1.0, 1.0), (5.0, 1.0), (5.0, 5.0), (1.0, 5.0)];
+        assert_eq!(clip_polygon(inside.clone(), 0.0, 10.0), inside);
+
+        let outside = vec![(20.0, 20.0), (30.0, 20.0), (30.0, 30.0)];
+        assert!(clip_polygon(outside, 0.0, 10.0).is_empty());
+
+        let crossing = vec![(5.0, 5.0), (15.0, 5.0), (15.0, 15.0), (5.0, 15.0)];
+        assert_eq!(
+            clip_polygon(crossing, 0.0, 10.0),
+            vec![(5.0, 10.0), (5.0, 5.0), (10.0, 5.0), (10.0, 10.0)]
+        );
+    }
+
+    #[test]
+    fn clip_lines() {
+        let inside = vec![(1.0, 1.0), (5.0, 5.0), (9.0, 1.0)];
+        assert_eq!(clip_line(&inside, 0.0, 10.0), vec![inside]);
+
+        let outside = vec![(20.0, 20.0), (30.0, 30.0)];
+        assert!(clip_line(&outside, 0.0, 10.0).is_empty());
+
+        let crossing = vec![(5.0, 5.0), (15.0, 5.0)];
+        assert_eq!(
+            clip_line(&crossing, 0.0, 10.0),
+            vec![vec![(5.0, 5.0), (10.0, 5.0)]]
+        );
+
+        // Leaving and coming back splits the line into two pieces
+        let leave_and_return = vec![(5.0, 5.0), (15.0, 5.0), (15.0, 8.0), (5.0, 8.0)];
+        assert_eq!(
+            clip_line(&leave_and_return, 0.0, 10.0),
+            vec![vec![(5.0, 5.0), (10.0, 5.0)], vec![(10.0, 8.0), (5.0, 8.0)]]
+        );
+    }
+}
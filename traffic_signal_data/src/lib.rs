@@ -46,6 +46,23 @@ pub struct Stage {
     pub permitted_turns: BTreeSet<Turn>,
     /// The stage lasts this long before moving to the next one.
     pub stage_type: StageType,
+    /// When pedestrians may start crossing during this stage. If this is missing, they may start
+    /// any time the crosswalk is protected.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pedestrian_timing: Option<PedestrianTiming>,
+}
+
+/// The timing of pedestrian signals during a stage, measured from the start of the stage.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PedestrianTiming {
+    /// Vehicle movements in the stage wait this many seconds, giving pedestrians a head start. This
+    /// is a leading pedestrian interval; 0 disables it.
+    pub leading_interval_seconds: usize,
+    /// Pedestrians may start crossing for this many seconds after the stage begins.
+    pub walk_seconds: usize,
+    /// After the walk interval, the signal flashes "don't walk" for this many seconds. Nobody may
+    /// start crossing, but there's enough time for anybody who's already crossing to finish.
+    pub flashing_dont_walk_seconds: usize,
 }
 
 /// How long a stage lasts before moving to the next one.
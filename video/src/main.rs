@@ -0,0 +1,401 @@
+//! Renders a simulation run to an image sequence or video, without opening a window. This is
+//! useful for sharing the effects of some edits, or for comparing runs side-by-side.
+
+#[macro_use]
+extern crate anyhow;
+#[macro_use]
+extern crate log;
+
+use std::process::Command;
+
+use anyhow::Result;
+use rand::SeedableRng;
+use rand_xorshift::XorShiftRng;
+
+use abstutil::{CmdArgs, Timer};
+use geom::{Bounds, Circle, Distance, Duration, LonLat, Polygon, Pt2D, Time};
+use map_gui::colors::{ColorScheme, ColorSchemeChoice};
+use map_gui::render::{unzoomed_agent_radius, DrawMap};
+use map_model::{Map, MapEdits, SIDEWALK_THICKNESS};
+use sim::{CarStatus, Scenario, Sim, SimFlags, SimOptions, VehicleType};
+use widgetry::{
+    Assets, Color, GeomBatch, Line, OffscreenCanvas, ScreenDims, ScreenPt, Style, Text,
+};
+
+struct Options {
+    start: Time,
+    end: Time,
+    frame_step: Duration,
+    zoomed: bool,
+    dims: ScreenDims,
+    center: Option<LonLat>,
+    zoom: Option<f64>,
+    output: String,
+    fps: usize,
+    label: Option<String>,
+}
+
+impl Options {
+    fn from_args(args: &mut CmdArgs) -> Result<Options> {
+        let opts = Options {
+            start: args
+                .optional_parse("--start", Time::parse)
+                .unwrap_or_else(|| Time::START_OF_DAY + Duration::hours(7)),
+            end: args
+                .optional_parse("--end", Time::parse)
+                .unwrap_or_else(|| Time::START_OF_DAY + Duration::hours(8)),
+            frame_step: args
+                .optional_parse("--frame_step", Duration::parse)
+                .unwrap_or_else(|| Duration::seconds(10.0)),
+            zoomed: args.enabled("--zoomed"),
+            dims: ScreenDims::new(
+                args.optional_parse("--width", |s| s.parse::<f64>())
+                    .unwrap_or(1920.0),
+                args.optional_parse("--height", |s| s.parse::<f64>())
+                    .unwrap_or(1080.0),
+            ),
+            center: args.optional_parse("--center", parse_lon_lat),
+            zoom: args.optional_parse("--zoom", |s| s.parse::<f64>()),
+            output: args.required("--output"),
+            fps: args
+                .optional_parse("--fps", |s| s.parse::<usize>())
+                .unwrap_or(30),
+            label: args.optional("--label"),
+        };
+        if opts.end <= opts.start {
+            bail!("--end must be after --start");
+        }
+        if opts.frame_step <= Duration::ZERO {
+            bail!("--frame_step must be positive");
+        }
+        Ok(opts)
+    }
+
+    /// The simulation time of every frame, from the start until the end (if it lands on a step)
+    fn frame_times(&self) -> Vec<Time> {
+        let num_frames = ((self.end - self.start) / self.frame_step).floor() as usize + 1;
+        (0..num_frames)
+            .map(|frame| self.start + self.frame_step * (frame as f64))
+            .collect()
+    }
+}
+
+fn main() -> Result<()> {
+    let mut args = CmdArgs::new();
+    let scenario_path = args.required("--scenario");
+    let edits_name = args.optional("--edits");
+    let opts = Options::from_args(&mut args)?;
+    let rng_seed = args
+        .optional_parse("--rng_seed", |s| s.parse())
+        .unwrap_or(SimFlags::RNG_SEED);
+    let sim_opts = SimOptions::from_args(&mut args, rng_seed);
+    args.done();
+
+    let mut timer = Timer::new("render video");
+    let scenario: Scenario = abstio::read_binary(scenario_path, &mut timer);
+    let mut map = Map::load_synchronously(scenario.map_name.path(), &mut timer);
+    if let Some(name) = edits_name {
+        let edits =
+            MapEdits::load_from_file(&map, abstio::path_edits(map.get_name(), &name), &mut timer)?;
+        map.must_apply_edits(edits);
+        map.recalculate_pathfinding_after_edits(&mut timer);
+    }
+    let mut sim = Sim::new(&map, sim_opts);
+    let mut rng = XorShiftRng::seed_from_u64(rng_seed);
+    scenario.instantiate(&mut sim, &map, &mut rng, &mut timer);
+
+    render(&map, &mut sim, &opts, &mut timer)
+}
+
+fn render(map: &Map, sim: &mut Sim, opts: &Options, timer: &mut Timer) -> Result<()> {
+    // Videos are assembled from a temporary directory of frames
+    let video = is_video(&opts.output);
+    let frames_dir = if video {
+        format!("{}_frames", opts.output)
+    } else {
+        opts.output.clone()
+    };
+    std::fs::create_dir_all(&frames_dir)?;
+
+    let cs = ColorScheme::headless(ColorSchemeChoice::DayMode);
+    let mut canvas = OffscreenCanvas::new(opts.dims)?;
+    match (opts.center, opts.zoom) {
+        (None, None) => {
+            canvas.fit_bounds(map.get_bounds());
+        }
+        (center, zoom) => {
+            canvas.cam_zoom = zoom.unwrap_or(if opts.zoomed { 4.0 } else { 1.0 });
+            let pt = center
+                .map(|gps| gps.to_pt(map.get_gps_bounds()))
+                .unwrap_or_else(|| map.get_bounds().center());
+            canvas.cam_x = pt.x() * canvas.cam_zoom - opts.dims.width / 2.0;
+            canvas.cam_y = pt.y() * canvas.cam_zoom - opts.dims.height / 2.0;
+        }
+    }
+    let view = Bounds::from(&[
+        Pt2D::new(
+            canvas.cam_x / canvas.cam_zoom,
+            canvas.cam_y / canvas.cam_zoom,
+        ),
+        Pt2D::new(
+            (canvas.cam_x + opts.dims.width) / canvas.cam_zoom,
+            (canvas.cam_y + opts.dims.height) / canvas.cam_zoom,
+        ),
+    ]);
+
+    // Every frame draws the whole batch, so only keep what might be visible
+    timer.start("draw the map");
+    let background = DrawMap::static_batch(map, &cs, opts.zoomed).crop(&view);
+    timer.stop("draw the map");
+    let assets = Assets::new(
+        Style::light_bg(),
+        None,
+        false,
+        Box::new(|path| abstio::slurp_file(path).unwrap_or_else(|_| Vec::new())),
+    );
+
+    if sim.time() < opts.start {
+        sim.timed_step(map, opts.start - sim.time(), &mut None, timer);
+    }
+    let frame_times = opts.frame_times();
+    let num_frames = frame_times.len();
+    timer.start_iter("render frames", num_frames);
+    for (frame, time) in frame_times.into_iter().enumerate() {
+        timer.next();
+        if sim.time() < time {
+            sim.timed_step(map, time - sim.time(), &mut None, &mut Timer::throwaway());
+        }
+
+        canvas.clear(cs.flat_map_background());
+        canvas.draw(&background);
+        canvas.draw(&draw_agents(map, sim, &cs, opts.zoomed).crop(&view));
+        draw_overlay(&mut canvas, &assets, sim.time(), opts.label.as_ref());
+        canvas.save_png(&format!("{}/{:05}.png", frames_dir, frame))?;
+    }
+
+    if video {
+        assemble_video(&frames_dir, &opts.output, opts.fps)?;
+        std::fs::remove_dir_all(&frames_dir)?;
+        info!("Wrote {}", opts.output);
+    } else {
+        info!("Wrote {} frames to {}", num_frames, frames_dir);
+    }
+    Ok(())
+}
+
+fn draw_agents(map: &Map, sim: &Sim, cs: &ColorScheme, zoomed: bool) -> GeomBatch {
+    let mut batch = GeomBatch::new();
+    if zoomed {
+        for car in sim.get_all_draw_cars(map) {
+            let color = if car.status == CarStatus::Parked {
+                cs.parked_car
+            } else {
+                vehicle_color(cs, Some(car.id.vehicle_type))
+            };
+            let width = if car.id.vehicle_type == VehicleType::Bike {
+                Distance::meters(0.8)
+            } else {
+                Distance::meters(1.75)
+            };
+            batch.push(color, car.body.make_polygons(width));
+        }
+        for ped in sim.get_all_draw_peds(map) {
+            batch.push(
+                cs.unzoomed_pedestrian,
+                Circle::new(ped.pos, SIDEWALK_THICKNESS / 4.0).to_polygon(),
+            );
+        }
+    } else {
+        for agent in sim.get_unzoomed_agents(map) {
+            let vt = agent.id.to_vehicle_type();
+            batch.push(
+                vehicle_color(cs, vt),
+                Circle::new(agent.pos, unzoomed_agent_radius(vt)).to_polygon(),
+            );
+        }
+    }
+    batch
+}
+
+fn vehicle_color(cs: &ColorScheme, vt: Option<VehicleType>) -> Color {
+    match vt {
+        Some(VehicleType::Car) => cs.unzoomed_car,
+        Some(VehicleType::Bike) => cs.unzoomed_bike,
+        Some(VehicleType::Bus) | Some(VehicleType::Train) => cs.unzoomed_bus,
+        None => cs.unzoomed_pedestrian,
+    }
+}
+
+/// Shows the current time, and optionally a label, in the top-left corner.
+fn draw_overlay(canvas: &mut OffscreenCanvas, assets: &Assets, time: Time, label: Option<&String>) {
+    canvas.draw_screenspace(ScreenPt::new(20.0, 20.0), &overlay(assets, time, label));
+}
+
+fn overlay(assets: &Assets, time: Time, label: Option<&String>) -> GeomBatch {
+    let mut txt = Text::from(Line(time.ampm_tostring()).fg(Color::WHITE).big_monospaced());
+    if let Some(label) = label {
+        txt.add_line(Line(label).fg(Color::WHITE));
+    }
+    let txt = txt.render_autocropped(assets);
+    let dims = txt.get_dims();
+    let padding = 10.0;
+    let mut batch = GeomBatch::new();
+    batch.push(
+        Color::BLACK.alpha(0.7),
+        Polygon::rounded_rectangle(dims.width + 2.0 * padding, dims.height + 2.0 * padding, 5.0),
+    );
+    batch.append(txt.translate(padding, padding));
+    batch
+}
+
+fn is_video(path: &str) -> bool {
+    path.ends_with(".mp4") || path.ends_with(".webm") || path.ends_with(".gif")
+}
+
+fn assemble_video(frames_dir: &str, output: &str, fps: usize) -> Result<()> {
+    let mut cmd = Command::new("ffmpeg");
+    cmd.arg("-y")
+        .arg("-framerate")
+        .arg(fps.to_string())
+        .arg("-i")
+        .arg(format!("{}/%05d.png", frames_dir));
+    if !output.ends_with(".gif") {
+        // Most players can't handle the default pixel format for PNG input
+        cmd.arg("-pix_fmt").arg("yuv420p");
+    }
+    cmd.arg(output);
+    info!("Running {:?}", cmd);
+    let status = cmd
+        .status()
+        .map_err(|err| anyhow!("Couldn't run ffmpeg (is it installed?): {}", err))?;
+    if !status.success() {
+        bail!("ffmpeg failed: {}", status);
+    }
+    Ok(())
+}
+
+fn parse_lon_lat(x: &str) -> Result<LonLat> {
+    let parts: Vec<&str> = x.split(',').collect();
+    if parts.len() != 2 {
+        bail!("--center should be lon,lat, not {}", x);
+    }
+    Ok(LonLat::new(parts[0].parse()?, parts[1].parse()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: Vec<&str>) -> Result<Options> {
+        let mut args = CmdArgs::from_args(args.into_iter().map(|x| x.to_string()).collect());
+        let opts = Options::from_args(&mut args)?;
+        args.done();
+        Ok(opts)
+    }
+
+    fn test_assets() -> Assets {
+        Assets::new(Style::light_bg(), None, false, Box::new(|_| Vec::new()))
+    }
+
+    #[test]
+    fn frame_window() {
+        let hms = |h: usize, m: usize, s: f64| {
+            Time::START_OF_DAY + Duration::hours(h) + Duration::minutes(m) + Duration::seconds(s)
+        };
+
+        let opts = parse(vec!["--output=out"]).unwrap();
+        assert_eq!(opts.start, hms(7, 0, 0.0));
+        assert_eq!(opts.end, hms(8, 0, 0.0));
+        assert_eq!(opts.frame_step, Duration::seconds(10.0));
+        assert_eq!(opts.frame_times().len(), 361);
+
+        let opts = parse(vec![
+            "--output=out",
+            "--start=17:30:00",
+            "--end=17:31:00",
+            "--frame_step=25",
+        ])
+        .unwrap();
+        // The end doesn't land on a step, so the last frame is before it
+        assert_eq!(
+            opts.frame_times(),
+            vec![hms(17, 30, 0.0), hms(17, 30, 25.0), hms(17, 30, 50.0)]
+        );
+
+        // The end lands exactly on a step
+        let opts = parse(vec![
+            "--output=out",
+            "--start=08:00:00",
+            "--end=08:00:30",
+            "--frame_step=10",
+        ])
+        .unwrap();
+        assert_eq!(opts.frame_times().len(), 4);
+        assert_eq!(*opts.frame_times().last().unwrap(), opts.end);
+
+        assert!(parse(vec!["--output=out", "--start=9:00:00", "--end=8:00:00"]).is_err());
+        assert!(parse(vec!["--output=out", "--start=9:00:00", "--end=9:00:00"]).is_err());
+        assert!(parse(vec!["--output=out", "--frame_step=0"]).is_err());
+    }
+
+    #[test]
+    fn overlay_options() {
+        let opts = parse(vec!["--output=run.mp4"]).unwrap();
+        assert_eq!(opts.label, None);
+        assert!(is_video(&opts.output));
+        assert!(!is_video("frames"));
+
+        let opts = parse(vec![
+            "--output=frames",
+            "--label=Before",
+            "--center=-122.3,47.6",
+            "--zoom=2.5",
+            "--width=640",
+            "--height=480",
+            "--zoomed",
+        ])
+        .unwrap();
+        assert_eq!(opts.label, Some("Before".to_string()));
+        assert_eq!(opts.center, Some(LonLat::new(-122.3, 47.6)));
+        assert_eq!(opts.zoom, Some(2.5));
+        assert_eq!(opts.dims, ScreenDims::new(640.0, 480.0));
+        assert!(opts.zoomed);
+
+        assert!(parse_lon_lat("-122.3").is_err());
+        assert!(parse_lon_lat("a,b").is_err());
+
+        // A label adds a line under the time
+        let assets = test_assets();
+        let time = Time::START_OF_DAY + Duration::hours(7);
+        let without_label = overlay(&assets, time, None).get_dims();
+        let with_label = overlay(&assets, time, Some(&"Before".to_string())).get_dims();
+        assert!(without_label.width > 0.0);
+        assert!(with_label.height > without_label.height);
+    }
+
+    #[test]
+    fn render_overlay() {
+        let assets = test_assets();
+        let mut canvas = OffscreenCanvas::new(ScreenDims::new(300.0, 100.0)).unwrap();
+        canvas.clear(Color::WHITE);
+        let time = Time::START_OF_DAY + Duration::hours(7);
+        draw_overlay(&mut canvas, &assets, time, None);
+
+        let dims = overlay(&assets, time, None).get_dims();
+        let rgba = canvas.to_rgba();
+        let pixel = |x: f64, y: f64| {
+            let idx = 4 * (y as usize * 300 + x as usize);
+            [rgba[idx], rgba[idx + 1], rgba[idx + 2], rgba[idx + 3]]
+        };
+        // The translucent black box darkens the background along its left edge
+        let [r, g, b, a] = pixel(22.0, 20.0 + dims.height / 2.0);
+        assert_eq!(a, 255);
+        assert!(r < 100 && r == g && g == b, "{:?}", (r, g, b));
+        // Nothing is drawn outside the corner
+        assert_eq!(pixel(10.0, 10.0), [255, 255, 255, 255]);
+        assert_eq!(
+            pixel(20.0 + dims.width + 10.0, 20.0 + dims.height + 10.0),
+            [255, 255, 255, 255]
+        );
+    }
+}
@@ -6,6 +6,7 @@ use crate::{
 };
 
 pub mod geom_batch_stack;
+pub mod vector;
 
 /// A mutable builder for a group of colored polygons.
 #[derive(Clone)]
@@ -143,6 +144,23 @@ impl GeomBatch {
         ScreenDims::new(bounds.width(), bounds.height())
     }
 
+    /// Only keeps polygons whose bounding box overlaps the bounds. Polygons aren't clipped, so
+    /// some will extend past the bounds.
+    pub fn crop(self, bounds: &Bounds) -> GeomBatch {
+        let mut result = GeomBatch::new();
+        for (fill, polygon, z) in self.list {
+            let b = polygon.get_bounds();
+            if b.max_x >= bounds.min_x
+                && b.min_x <= bounds.max_x
+                && b.max_y >= bounds.min_y
+                && b.min_y <= bounds.max_y
+            {
+                result.list.push((fill, polygon, z));
+            }
+        }
+        result
+    }
+
     /// Returns a batch containing an SVG from a file.
     pub fn load_svg<P: AsRef<Prerender>, I: AsRef<str>>(prerender: &P, filename: I) -> GeomBatch {
         svg::load_svg(prerender.as_ref(), filename.as_ref()).0
@@ -0,0 +1,525 @@
+//! Writes `GeomBatch`es as vector graphics, for output that stays sharp when printed or zoomed.
+//! Text is already rendered to polygons, so it survives too.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use anyhow::Result;
+
+use geom::{Bounds, Polygon};
+
+use crate::{Color, Fill, GeomBatch, LinearGradient};
+
+/// A single page of vector graphics, built up from named groups of polygons. Coordinates are in
+/// points (1/72 of an inch), with the origin at the top-left and Y pointing down.
+pub struct VectorDocument {
+    width: f64,
+    height: f64,
+    groups: Vec<Group>,
+}
+
+struct Group {
+    name: String,
+    batch: GeomBatch,
+    clip: Option<Bounds>,
+}
+
+impl VectorDocument {
+    pub fn new(width: f64, height: f64) -> VectorDocument {
+        VectorDocument {
+            width,
+            height,
+            groups: Vec::new(),
+        }
+    }
+
+    /// Adds a group of polygons, already in page coordinates, above everything so far.
+    pub fn push<I: Into<String>>(&mut self, name: I, batch: GeomBatch) {
+        self.groups.push(Group {
+            name: name.into(),
+            batch,
+            clip: None,
+        });
+    }
+
+    /// Like `push`, but only draws what's inside a rectangle on the page.
+    pub fn push_clipped<I: Into<String>>(&mut self, name: I, batch: GeomBatch, clip: Bounds) {
+        self.groups.push(Group {
+            name: name.into(),
+            batch,
+            clip: Some(clip),
+        });
+    }
+
+    /// Writes SVG or PDF, depending on the file extension.
+    pub fn save(&self, path: &str) -> Result<()> {
+        let lower = path.to_lowercase();
+        if lower.ends_with(".svg") {
+            std::fs::write(path, self.to_svg())?;
+        } else if lower.ends_with(".pdf") {
+            std::fs::write(path, self.to_pdf())?;
+        } else {
+            bail!("Don't know how to write {}; use .svg or .pdf", path);
+        }
+        Ok(())
+    }
+
+    pub fn to_svg(&self) -> String {
+        let mut textures = TextureColors::new();
+        let mut defs = String::new();
+        let mut body = String::new();
+        let mut num_gradients = 0;
+        for (idx, group) in self.groups.iter().enumerate() {
+            if let Some(ref clip) = group.clip {
+                writeln!(
+                    defs,
+                    r#"<clipPath id="clip{}"><rect x="{}" y="{}" width="{}" height="{}"/></clipPath>"#,
+                    idx,
+                    num(clip.min_x),
+                    num(clip.min_y),
+                    num(clip.width()),
+                    num(clip.height())
+                )
+                .unwrap();
+                writeln!(
+                    body,
+                    r#"<g id="{}" clip-path="url(#clip{})">"#,
+                    escape(&group.name),
+                    idx
+                )
+                .unwrap();
+            } else {
+                writeln!(body, r#"<g id="{}">"#, escape(&group.name)).unwrap();
+            }
+
+            for (fill, polygon) in back_to_front(&group.batch) {
+                let paint = match fill {
+                    Fill::Color(color) => color_attrs(*color),
+                    Fill::LinearGradient(ref lg) => {
+                        num_gradients += 1;
+                        write_svg_gradient(&mut defs, num_gradients, lg);
+                        format!(r#"fill="url(#gradient{})""#, num_gradients)
+                    }
+                    Fill::Texture(texture) => color_attrs(textures.get(texture.0)),
+                    Fill::ColoredTexture(color, texture) => {
+                        color_attrs(multiply(*color, textures.get(texture.0)))
+                    }
+                };
+                let mut d = String::new();
+                for pts in polygon.fill_loops() {
+                    for (i, pt) in pts.iter().enumerate() {
+                        let cmd = if i == 0 { 'M' } else { 'L' };
+                        write!(d, "{}{} {}", cmd, num(pt.x()), num(pt.y())).unwrap();
+                    }
+                    d.push('Z');
+                }
+                writeln!(body, r#"<path fill-rule="evenodd" {} d="{}"/>"#, paint, d).unwrap();
+            }
+            body.push_str("</g>\n");
+        }
+
+        let mut out = String::new();
+        writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#).unwrap();
+        writeln!(
+            out,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}pt" height="{}pt" viewBox="0 0 {} {}">"#,
+            num(self.width),
+            num(self.height),
+            num(self.width),
+            num(self.height)
+        )
+        .unwrap();
+        if !defs.is_empty() {
+            writeln!(out, "<defs>\n{}</defs>", defs).unwrap();
+        }
+        out.push_str(&body);
+        out.push_str("</svg>\n");
+        out
+    }
+
+    /// A minimal PDF 1.4 file with one page. Gradients ignore the alpha of their stops, except
+    /// for the first.
+    pub fn to_pdf(&self) -> Vec<u8> {
+        let mut textures = TextureColors::new();
+        // Opacity values are rounded, so there aren't too many graphics states
+        let mut alphas: BTreeMap<u8, usize> = BTreeMap::new();
+        let mut gradients: Vec<String> = Vec::new();
+
+        let mut content = String::new();
+        // Flip the Y axis, so the rest of the content can use page coordinates directly
+        writeln!(content, "1 0 0 -1 0 {} cm", num(self.height)).unwrap();
+        for group in &self.groups {
+            content.push_str("q\n");
+            if let Some(ref clip) = group.clip {
+                writeln!(
+                    content,
+                    "{} {} {} {} re W n",
+                    num(clip.min_x),
+                    num(clip.min_y),
+                    num(clip.width()),
+                    num(clip.height())
+                )
+                .unwrap();
+            }
+            for (fill, polygon) in back_to_front(&group.batch) {
+                let color = match fill {
+                    Fill::Color(color) => Some(*color),
+                    Fill::LinearGradient(_) => None,
+                    Fill::Texture(texture) => Some(textures.get(texture.0)),
+                    Fill::ColoredTexture(color, texture) => {
+                        Some(multiply(*color, textures.get(texture.0)))
+                    }
+                };
+                let alpha = match fill {
+                    Fill::LinearGradient(ref lg) => lg.stops[0].1.a,
+                    _ => color.unwrap().a,
+                };
+                let alpha = (alpha.max(0.0).min(1.0) * 255.0).round() as u8;
+                if alpha == 0 {
+                    continue;
+                }
+                let num_alphas = alphas.len();
+                let gs = *alphas.entry(alpha).or_insert(num_alphas);
+                write!(content, "/GS{} gs ", gs).unwrap();
+                if let Some(color) = color {
+                    writeln!(
+                        content,
+                        "{} {} {} rg",
+                        num(color.r as f64),
+                        num(color.g as f64),
+                        num(color.b as f64)
+                    )
+                    .unwrap();
+                } else if let Fill::LinearGradient(ref lg) = fill {
+                    gradients.push(pdf_gradient(lg, self.height));
+                    writeln!(content, "/Pattern cs /P{} scn", gradients.len() - 1).unwrap();
+                }
+                for pts in polygon.fill_loops() {
+                    for (i, pt) in pts.iter().enumerate() {
+                        let op = if i == 0 { "m" } else { "l" };
+                        write!(content, "{} {} {} ", num(pt.x()), num(pt.y()), op).unwrap();
+                    }
+                    content.push_str("h\n");
+                }
+                content.push_str("f*\n");
+            }
+            content.push_str("Q\n");
+        }
+
+        let mut resources = String::from("<< /ExtGState <<");
+        for (alpha, idx) in &alphas {
+            let alpha = num((*alpha as f64) / 255.0);
+            write!(resources, " /GS{} << /ca {} /CA {} >>", idx, alpha, alpha).unwrap();
+        }
+        resources.push_str(" >> /Pattern <<");
+        for (idx, gradient) in gradients.iter().enumerate() {
+            write!(resources, " /P{} {}", idx, gradient).unwrap();
+        }
+        resources.push_str(" >> >>");
+
+        let objects = vec![
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Resources {} /Contents 4 0 R \
+                 /Group << /S /Transparency /CS /DeviceRGB >> >>",
+                num(self.width),
+                num(self.height),
+                resources
+            ),
+            format!(
+                "<< /Length {} >>\nstream\n{}endstream",
+                content.len(),
+                content
+            ),
+        ];
+
+        let mut out = String::from("%PDF-1.4\n");
+        let mut offsets = Vec::new();
+        for (idx, obj) in objects.iter().enumerate() {
+            offsets.push(out.len());
+            writeln!(out, "{} 0 obj\n{}\nendobj", idx + 1, obj).unwrap();
+        }
+        let xref = out.len();
+        writeln!(out, "xref\n0 {}", objects.len() + 1).unwrap();
+        out.push_str("0000000000 65535 f \n");
+        for offset in offsets {
+            writeln!(out, "{:010} 00000 n ", offset).unwrap();
+        }
+        writeln!(
+            out,
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF",
+            objects.len() + 1,
+            xref
+        )
+        .unwrap();
+        out.into_bytes()
+    }
+}
+
+/// Like the depth buffer in the OpenGL backend, values closer to -1.0 are on top, and with equal
+/// z, things drawn later win.
+fn back_to_front(batch: &GeomBatch) -> Vec<(&Fill, &Polygon)> {
+    let mut list: Vec<&(Fill, Polygon, f64)> = batch.list.iter().collect();
+    list.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap());
+    list.into_iter().map(|(fill, p, _)| (fill, p)).collect()
+}
+
+/// Keeps files small without losing visible precision.
+fn num(x: f64) -> String {
+    let x = (x * 1000.0).round() / 1000.0;
+    if x == 0.0 {
+        // Avoid "-0"
+        "0".to_string()
+    } else {
+        x.to_string()
+    }
+}
+
+fn escape(x: &str) -> String {
+    x.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn color_attrs(color: Color) -> String {
+    if color.a < 1.0 {
+        format!(
+            r#"fill="{}" fill-opacity="{}""#,
+            color.as_hex(),
+            num(color.a as f64)
+        )
+    } else {
+        format!(r#"fill="{}""#, color.as_hex())
+    }
+}
+
+fn multiply(c1: Color, c2: Color) -> Color {
+    Color::rgba_f(c1.r * c2.r, c1.g * c2.g, c1.b * c2.b, c1.a * c2.a)
+}
+
+fn write_svg_gradient(defs: &mut String, id: usize, lg: &LinearGradient) {
+    let pt1 = lg.line.pt1();
+    let pt2 = lg.line.pt2();
+    writeln!(
+        defs,
+        r#"<linearGradient id="gradient{}" gradientUnits="userSpaceOnUse" x1="{}" y1="{}" x2="{}" y2="{}">"#,
+        id,
+        num(pt1.x()),
+        num(pt1.y()),
+        num(pt2.x()),
+        num(pt2.y())
+    )
+    .unwrap();
+    for (pct, color) in &lg.stops {
+        writeln!(
+            defs,
+            r#"<stop offset="{}" stop-color="{}" stop-opacity="{}"/>"#,
+            num(*pct),
+            color.as_hex(),
+            num(color.a as f64)
+        )
+        .unwrap();
+    }
+    defs.push_str("</linearGradient>\n");
+}
+
+/// An axial shading pattern, stitching together linear interpolations between each pair of
+/// stops. Patterns ignore the current transformation, so this repeats the Y flip.
+fn pdf_gradient(lg: &LinearGradient, page_height: f64) -> String {
+    let mut stops = lg.stops.clone();
+    if stops[0].0 > 0.0 {
+        stops.insert(0, (0.0, stops[0].1));
+    }
+    if stops.last().unwrap().0 < 1.0 {
+        stops.push((1.0, stops.last().unwrap().1));
+    }
+    let rgb = |c: Color| {
+        format!(
+            "[{} {} {}]",
+            num(c.r as f64),
+            num(c.g as f64),
+            num(c.b as f64)
+        )
+    };
+    let mut functions = String::new();
+    let mut bounds = Vec::new();
+    let mut encode = Vec::new();
+    for (idx, pair) in stops.windows(2).enumerate() {
+        write!(
+            functions,
+            "<< /FunctionType 2 /Domain [0 1] /C0 {} /C1 {} /N 1 >> ",
+            rgb(pair[0].1),
+            rgb(pair[1].1)
+        )
+        .unwrap();
+        if idx > 0 {
+            bounds.push(num(pair[0].0));
+        }
+        encode.push("0 1");
+    }
+
+    let pt1 = lg.line.pt1();
+    let pt2 = lg.line.pt2();
+    format!(
+        "<< /PatternType 2 /Matrix [1 0 0 -1 0 {}] /Shading << /ShadingType 2 /ColorSpace \
+         /DeviceRGB /Coords [{} {} {} {}] /Extend [true true] /Function << /FunctionType 3 \
+         /Domain [0 1] /Functions [{}] /Bounds [{}] /Encode [{}] >> >> >>",
+        num(page_height),
+        num(pt1.x()),
+        num(pt1.y()),
+        num(pt2.x()),
+        num(pt2.y()),
+        functions,
+        bounds.join(" "),
+        encode.join(" ")
+    )
+}
+
+/// Vector output can't easily tile the textures, so each one is drawn as its average color.
+struct TextureColors {
+    // Index 0 is Texture::NOOP
+    colors: Option<Vec<Color>>,
+}
+
+impl TextureColors {
+    fn new() -> TextureColors {
+        TextureColors { colors: None }
+    }
+
+    fn get(&mut self, texture_id: u32) -> Color {
+        let colors = self.colors.get_or_insert_with(|| {
+            let sprite_size = 64;
+            let img = image::load_from_memory(include_bytes!("../../textures/spritesheet.png"))
+                .expect("failed to load texture sprite sheet")
+                .to_rgba8();
+            let (width, height) = img.dimensions();
+            let mut colors = vec![Color::WHITE];
+            for sprite_y in 0..height / sprite_size {
+                for sprite_x in 0..width / sprite_size {
+                    // Weight by alpha, so transparent pixels don't darken the average
+                    let mut sum = [0.0; 4];
+                    for y in 0..sprite_size {
+                        for x in 0..sprite_size {
+                            let [r, g, b, a] = img
+                                .get_pixel(sprite_x * sprite_size + x, sprite_y * sprite_size + y)
+                                .0;
+                            let a = a as f64 / 255.0;
+                            sum[0] += r as f64 * a;
+                            sum[1] += g as f64 * a;
+                            sum[2] += b as f64 * a;
+                            sum[3] += a;
+                        }
+                    }
+                    colors.push(if sum[3] == 0.0 {
+                        Color::CLEAR
+                    } else {
+                        Color::rgba(
+                            (sum[0] / sum[3]) as usize,
+                            (sum[1] / sum[3]) as usize,
+                            (sum[2] / sum[3]) as usize,
+                            (sum[3] / (sprite_size * sprite_size) as f64) as f32,
+                        )
+                    });
+                }
+            }
+            colors
+        });
+        colors
+            .get(texture_id as usize)
+            .cloned()
+            .unwrap_or(Color::WHITE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use geom::Pt2D;
+
+    use super::*;
+
+    fn document() -> VectorDocument {
+        let mut doc = VectorDocument::new(100.0, 50.0);
+        let mut below = GeomBatch::new();
+        below.push(Color::RED.alpha(0.5), Polygon::rectangle(10.0, 20.0));
+        doc.push("map & roads", below);
+        let mut above = GeomBatch::new();
+        above.push(
+            Color::BLUE,
+            Polygon::rectangle(10.0, 20.0).translate(5.0, 5.0),
+        );
+        doc.push_clipped(
+            "legend",
+            above,
+            Bounds::from(&[Pt2D::new(0.0, 0.0), Pt2D::new(50.0, 25.0)]),
+        );
+        doc
+    }
+
+    #[test]
+    fn svg() {
+        let svg = document().to_svg();
+        assert!(svg.contains(r#"viewBox="0 0 100 50""#));
+        assert!(svg.contains(r#"<g id="map &amp; roads">"#));
+        assert!(svg.contains(r#"<g id="legend" clip-path="url(#clip1)">"#));
+        assert!(svg.contains(
+            r#"<clipPath id="clip1"><rect x="0" y="0" width="50" height="25"/></clipPath>"#
+        ));
+        assert!(svg.contains(
+            r##"<path fill-rule="evenodd" fill="#FF0000" fill-opacity="0.5" d="M0 0L10 0L10 20L0 20L0 0Z"/>"##
+        ));
+        assert!(svg.contains(r##"fill="#0000FF" d="M5 5L15 5L15 25L5 25L5 5Z""##));
+        // Groups are drawn in the order they were pushed
+        assert!(svg.find("map &amp; roads").unwrap() < svg.find("legend").unwrap());
+        assert!(svg.ends_with("</svg>\n"));
+    }
+
+    #[test]
+    fn pdf() {
+        let pdf = String::from_utf8(document().to_pdf()).unwrap();
+        assert!(pdf.starts_with("%PDF-1.4\n"));
+        assert!(pdf.ends_with("%%EOF\n"));
+        assert!(pdf.contains("/MediaBox [0 0 100 50]"));
+        assert!(pdf.contains("0 0 50 25 re W n"));
+        assert!(pdf.contains("1 0 0 rg"));
+        // The half-transparent and opaque fills each get a graphics state
+        assert!(pdf.contains("/ca 0.502"));
+        assert!(pdf.contains("/ca 1"));
+
+        // The cross-reference table has to point at each object
+        let xref: usize = pdf
+            .lines()
+            .skip_while(|line| *line != "startxref")
+            .nth(1)
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!(pdf[xref..].starts_with("xref\n"));
+        for line in pdf[xref..].lines().skip(3).take(4) {
+            let offset: usize = line[0..10].parse().unwrap();
+            assert!(pdf[offset..]
+                .split('\n')
+                .next()
+                .unwrap()
+                .ends_with(" 0 obj"));
+        }
+    }
+
+    #[test]
+    fn z_order() {
+        let mut batch = GeomBatch::new();
+        batch.push_with_z(Color::RED, Polygon::rectangle(1.0, 1.0), -0.5);
+        batch.push_with_z(Color::BLUE, Polygon::rectangle(1.0, 1.0), 0.0);
+        let mut doc = VectorDocument::new(1.0, 1.0);
+        doc.push("batch", batch);
+        let svg = doc.to_svg();
+        // Red is closer to -1.0, so it's on top and written last
+        assert!(svg.find("#0000FF").unwrap() < svg.find("#FF0000").unwrap());
+    }
+
+    #[test]
+    fn unknown_extension() {
+        assert!(document().save("map.png").is_err());
+    }
+}
@@ -33,6 +33,7 @@ extern crate anyhow;
 extern crate log;
 
 pub use crate::app_state::{DrawBaselayer, SharedAppState, SimpleState, State, Transition};
+pub use crate::assets::Assets;
 pub use crate::backend::Drawable;
 pub use crate::canvas::{Canvas, CanvasSettings, HorizontalAlignment, VerticalAlignment};
 pub use crate::color::{Color, Fill, LinearGradient, Texture};
@@ -42,8 +43,11 @@ pub use crate::event_ctx::{EventCtx, UpdateType};
 pub use crate::geom::geom_batch_stack::{
     Alignment as StackAlignment, Axis as StackAxis, GeomBatchStack,
 };
+pub use crate::geom::vector::VectorDocument;
 pub use crate::geom::{GeomBatch, RewriteColor};
 pub use crate::input::UserInput;
+#[cfg(feature = "offscreen")]
+pub use crate::offscreen::{render_tiles, OffscreenCanvas};
 pub use crate::runner::{run, Settings};
 pub use crate::screen_geom::{ScreenDims, ScreenPt, ScreenRectangle};
 pub use crate::style::{ButtonStyle, OutlineStyle, Style};
@@ -92,6 +96,8 @@ mod event;
 mod event_ctx;
 mod geom;
 mod input;
+#[cfg(feature = "offscreen")]
+mod offscreen;
 mod runner;
 mod screen_geom;
 mod style;
@@ -0,0 +1,413 @@
+//! Renders `GeomBatch`es and `Text` on the CPU into an in-memory image, without a window or GPU.
+//! This lets servers and command-line tools produce PNGs of maps and dashboards. The output should
+//! match what the OpenGL backend draws, apart from anti-aliasing.
+
+use anyhow::Result;
+use tiny_skia::{
+    FillRule, FilterQuality, GradientStop, Paint, PathBuilder, Pattern, Pixmap, Point, Shader,
+    SpreadMode, Transform,
+};
+
+use abstutil::Timer;
+use geom::{Bounds, Polygon};
+
+use crate::{Assets, Color, Fill, GeomBatch, ScreenDims, ScreenPt, Text};
+
+/// The size of each texture in the spritesheet, in pixels
+const SPRITE_SIZE: u32 = 64;
+/// How many map-space units one texture covers. This matches `texture_scale` in the vertex
+/// shaders.
+const TEXTURE_SCALE: f32 = 16.0;
+
+/// An offscreen image to draw into. The camera works like `Canvas`: a point in map-space is drawn
+/// at `pt * zoom - (cam_x, cam_y)`.
+pub struct OffscreenCanvas {
+    pixmap: Pixmap,
+    pub cam_x: f64,
+    pub cam_y: f64,
+    pub cam_zoom: f64,
+    // Index 0 is Texture::NOOP. Only loaded the first time a texture is drawn.
+    sprites: Option<Vec<Pixmap>>,
+}
+
+impl OffscreenCanvas {
+    /// Creates a transparent image.
+    pub fn new(dims: ScreenDims) -> Result<OffscreenCanvas> {
+        let width = dims.width.ceil() as u32;
+        let height = dims.height.ceil() as u32;
+        let pixmap = Pixmap::new(width, height)
+            .ok_or_else(|| anyhow!("Can't render an image of size {:?}", dims))?;
+        Ok(OffscreenCanvas {
+            pixmap,
+            cam_x: 0.0,
+            cam_y: 0.0,
+            cam_zoom: 1.0,
+            sprites: None,
+        })
+    }
+
+    pub fn get_dims(&self) -> ScreenDims {
+        ScreenDims::new(self.pixmap.width() as f64, self.pixmap.height() as f64)
+    }
+
+    /// Fills the entire image with one color.
+    pub fn clear(&mut self, color: Color) {
+        self.pixmap.fill(to_color(color));
+    }
+
+    /// Zooms and pans so that the bounds fill the image, keeping the aspect ratio.
+    pub fn fit_bounds(&mut self, bounds: &Bounds) {
+        let dims = self.get_dims();
+        self.cam_zoom = (dims.width / bounds.width()).min(dims.height / bounds.height());
+        let center = bounds.center();
+        self.cam_x = center.x() * self.cam_zoom - dims.width / 2.0;
+        self.cam_y = center.y() * self.cam_zoom - dims.height / 2.0;
+    }
+
+    /// Draws something in map-space, using the current camera.
+    pub fn draw(&mut self, batch: &GeomBatch) {
+        let transform = Transform::from_row(
+            self.cam_zoom as f32,
+            0.0,
+            0.0,
+            self.cam_zoom as f32,
+            -self.cam_x as f32,
+            -self.cam_y as f32,
+        )
+        .unwrap();
+        self.draw_with_transform(batch, transform);
+    }
+
+    /// Draws something in screen-space, like a panel or tooltip, ignoring the camera.
+    pub fn draw_screenspace(&mut self, top_left: ScreenPt, batch: &GeomBatch) {
+        let transform = Transform::from_translate(top_left.x as f32, top_left.y as f32).unwrap();
+        self.draw_with_transform(batch, transform);
+    }
+
+    /// Draws text in screen-space.
+    pub fn draw_text<A: AsRef<Assets>>(&mut self, top_left: ScreenPt, txt: Text, assets: &A) {
+        let batch = txt.render(assets);
+        self.draw_screenspace(top_left, &batch);
+    }
+
+    /// Returns every pixel as (non-premultiplied) RGBA, row by row from the top-left.
+    pub fn to_rgba(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.pixmap.data().len());
+        for pixel in self.pixmap.pixels() {
+            let c = pixel.demultiply();
+            bytes.extend_from_slice(&[c.red(), c.green(), c.blue(), c.alpha()]);
+        }
+        bytes
+    }
+
+    pub fn save_png(&self, path: &str) -> Result<()> {
+        let img =
+            image::RgbaImage::from_raw(self.pixmap.width(), self.pixmap.height(), self.to_rgba())
+                .unwrap();
+        img.save(path)?;
+        Ok(())
+    }
+
+    fn draw_with_transform(&mut self, batch: &GeomBatch, transform: Transform) {
+        // The OpenGL backend relies on the depth buffer. Values closer to -1.0 are on top, and
+        // with equal z, things drawn later win.
+        let mut list: Vec<&(Fill, Polygon, f64)> = batch.list.iter().collect();
+        list.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap());
+
+        if self.sprites.is_none()
+            && list
+                .iter()
+                .any(|(fill, _, _)| matches!(fill, Fill::Texture(_) | Fill::ColoredTexture(_, _)))
+        {
+            self.sprites = Some(load_sprites());
+        }
+        let sprites = self.sprites.as_deref().unwrap_or(&[]);
+
+        for (fill, polygon, _) in list {
+            // All triangles of one polygon go in one path, so anti-aliasing doesn't leave seams
+            // between them.
+            let (pts, indices) = polygon.raw_for_rendering();
+            let mut pb = PathBuilder::new();
+            for tri in indices.chunks(3) {
+                let p1 = pts[tri[0] as usize];
+                let p2 = pts[tri[1] as usize];
+                let p3 = pts[tri[2] as usize];
+                pb.move_to(p1.x() as f32, p1.y() as f32);
+                pb.line_to(p2.x() as f32, p2.y() as f32);
+                pb.line_to(p3.x() as f32, p3.y() as f32);
+                pb.close();
+            }
+            let path = match pb.finish() {
+                Some(path) => path,
+                // Degenerate polygons draw nothing
+                None => continue,
+            };
+
+            let mut tinted = None;
+            let shader = match fill {
+                Fill::Color(color) => Shader::SolidColor(to_color(*color)),
+                Fill::LinearGradient(ref lg) => {
+                    let pt1 = lg.line.pt1();
+                    let pt2 = lg.line.pt2();
+                    let stops = lg
+                        .stops
+                        .iter()
+                        .map(|(pct, color)| GradientStop::new(*pct as f32, to_color(*color)))
+                        .collect();
+                    match tiny_skia::LinearGradient::new(
+                        Point::from_xy(pt1.x() as f32, pt1.y() as f32),
+                        Point::from_xy(pt2.x() as f32, pt2.y() as f32),
+                        stops,
+                        SpreadMode::Pad,
+                        Transform::identity(),
+                    ) {
+                        Some(shader) => shader,
+                        None => continue,
+                    }
+                }
+                Fill::Texture(texture) => {
+                    texture_pattern(sprite(sprites, texture.0), Color::WHITE, &mut tinted)
+                }
+                Fill::ColoredTexture(color, texture) => {
+                    texture_pattern(sprite(sprites, texture.0), *color, &mut tinted)
+                }
+            };
+            fill_path(&mut self.pixmap, &path, shader, transform);
+        }
+    }
+}
+
+fn fill_path(pixmap: &mut Pixmap, path: &tiny_skia::Path, shader: Shader, transform: Transform) {
+    let paint = Paint {
+        shader,
+        anti_alias: true,
+        ..Default::default()
+    };
+    pixmap.fill_path(path, &paint, FillRule::Winding, transform, None);
+}
+
+fn to_color(c: Color) -> tiny_skia::Color {
+    tiny_skia::Color::from_rgba(
+        c.r.max(0.0).min(1.0),
+        c.g.max(0.0).min(1.0),
+        c.b.max(0.0).min(1.0),
+        c.a.max(0.0).min(1.0),
+    )
+    .unwrap()
+}
+
+/// An unknown texture draws like the no-op one, rather than crashing.
+fn sprite(sprites: &[Pixmap], texture_id: u32) -> &Pixmap {
+    &sprites[if (texture_id as usize) < sprites.len() {
+        texture_id as usize
+    } else {
+        0
+    }]
+}
+
+/// Tiles a texture across map-space, like the fragment shaders, multiplying it by a color.
+fn texture_pattern<'a>(
+    sprite: &'a Pixmap,
+    color: Color,
+    storage: &'a mut Option<Pixmap>,
+) -> Shader<'a> {
+    let pixmap = if color == Color::WHITE {
+        sprite
+    } else {
+        let mut tinted = sprite.clone();
+        for pixel in tinted.data_mut().chunks_mut(4) {
+            // Pixels are premultiplied, so scaling every channel is the same as multiplying the
+            // colors and then premultiplying.
+            pixel[0] = (pixel[0] as f32 * color.r * color.a).round() as u8;
+            pixel[1] = (pixel[1] as f32 * color.g * color.a).round() as u8;
+            pixel[2] = (pixel[2] as f32 * color.b * color.a).round() as u8;
+            pixel[3] = (pixel[3] as f32 * color.a).round() as u8;
+        }
+        storage.get_or_insert(tinted)
+    };
+    let scale = TEXTURE_SCALE / SPRITE_SIZE as f32;
+    Pattern::new(
+        pixmap.as_ref(),
+        SpreadMode::Repeat,
+        FilterQuality::Nearest,
+        1.0,
+        Transform::from_scale(scale, scale).unwrap(),
+    )
+}
+
+/// Splits the spritesheet into textures, numbered the same way as `Fill::Texture`.
+fn load_sprites() -> Vec<Pixmap> {
+    let img = image::load_from_memory(include_bytes!("../textures/spritesheet.png"))
+        .expect("failed to load texture sprite sheet")
+        .to_rgba8();
+    let (width, height) = img.dimensions();
+
+    let mut noop = Pixmap::new(1, 1).unwrap();
+    noop.fill(tiny_skia::Color::WHITE);
+    let mut sprites = vec![noop];
+    for sprite_y in 0..height / SPRITE_SIZE {
+        for sprite_x in 0..width / SPRITE_SIZE {
+            let mut sprite = Pixmap::new(SPRITE_SIZE, SPRITE_SIZE).unwrap();
+            for (idx, pixel) in sprite.data_mut().chunks_mut(4).enumerate() {
+                let x = sprite_x * SPRITE_SIZE + (idx as u32) % SPRITE_SIZE;
+                let y = sprite_y * SPRITE_SIZE + (idx as u32) / SPRITE_SIZE;
+                let [r, g, b, a] = img.get_pixel(x, y).0;
+                let premultiply = |c: u8| ((c as u32 * a as u32 + 127) / 255) as u8;
+                pixel.copy_from_slice(&[premultiply(r), premultiply(g), premultiply(b), a]);
+            }
+            sprites.push(sprite);
+        }
+    }
+    sprites
+}
+
+/// Renders map-space geometry as a grid of PNG tiles, like the screenshot tool in the GUI, but
+/// without a window. `map_dims` is the size of the map in map-space.
+pub fn render_tiles(
+    batch: &GeomBatch,
+    background: Color,
+    map_dims: (f64, f64),
+    zoom: f64,
+    dims: ScreenDims,
+    dir_path: &str,
+    leaflet_naming: bool,
+) -> Result<()> {
+    let mut timer = Timer::new("rendering tiles");
+    let num_tiles_x = (map_dims.0 * zoom / dims.width).ceil() as usize;
+    let num_tiles_y = (map_dims.1 * zoom / dims.height).ceil() as usize;
+    std::fs::create_dir_all(dir_path)?;
+
+    let mut canvas = OffscreenCanvas::new(dims)?;
+    canvas.cam_zoom = zoom;
+    timer.start_iter("rendering tiles", num_tiles_x * num_tiles_y);
+    for tile_y in 0..num_tiles_y {
+        for tile_x in 0..num_tiles_x {
+            timer.next();
+            canvas.cam_x = (tile_x as f64) * dims.width;
+            canvas.cam_y = (tile_y as f64) * dims.height;
+            canvas.clear(background);
+            canvas.draw(batch);
+
+            let filename = if leaflet_naming {
+                format!("{}/{}_{}.png", dir_path, tile_x, tile_y)
+            } else {
+                format!("{}/{:02}x{:02}.png", dir_path, tile_x + 1, tile_y + 1)
+            };
+            canvas.save_png(&filename)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use geom::Pt2D;
+
+    use super::*;
+
+    fn pixel(canvas: &OffscreenCanvas, x: usize, y: usize) -> [u8; 4] {
+        let idx = 4 * (y * canvas.pixmap.width() as usize + x);
+        let rgba = canvas.to_rgba();
+        [rgba[idx], rgba[idx + 1], rgba[idx + 2], rgba[idx + 3]]
+    }
+
+    #[test]
+    fn camera() {
+        let mut canvas = OffscreenCanvas::new(ScreenDims::new(20.0, 10.0)).unwrap();
+        canvas.clear(Color::WHITE);
+        // The bounds are twice as big as the image and square, so they'll be centered
+        // horizontally
+        canvas.fit_bounds(&Bounds::from(&[Pt2D::new(0.0, 0.0), Pt2D::new(20.0, 20.0)]));
+        assert_eq!(canvas.cam_zoom, 0.5);
+        let mut batch = GeomBatch::new();
+        batch.push(Color::RED, Polygon::rectangle(10.0, 20.0));
+        canvas.draw(&batch);
+
+        assert_eq!(pixel(&canvas, 7, 5), [255, 0, 0, 255]);
+        assert_eq!(pixel(&canvas, 12, 5), [255, 255, 255, 255]);
+        // Outside the bounds entirely
+        assert_eq!(pixel(&canvas, 2, 5), [255, 255, 255, 255]);
+
+        // Screen-space ignores the camera
+        let mut batch = GeomBatch::new();
+        batch.push(Color::BLUE, Polygon::rectangle(2.0, 2.0));
+        canvas.draw_screenspace(ScreenPt::new(15.0, 0.0), &batch);
+        assert_eq!(pixel(&canvas, 16, 1), [0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn z_order_and_alpha() {
+        let mut canvas = OffscreenCanvas::new(ScreenDims::new(4.0, 4.0)).unwrap();
+        let mut batch = GeomBatch::new();
+        batch.push_with_z(Color::RED, Polygon::rectangle(4.0, 4.0), -0.5);
+        batch.push_with_z(Color::BLUE, Polygon::rectangle(4.0, 4.0), 0.0);
+        batch.push(Color::GREEN.alpha(0.0), Polygon::rectangle(4.0, 4.0));
+        canvas.draw(&batch);
+        // Red is closer to -1.0, so it's on top, and the transparent green changes nothing
+        assert_eq!(pixel(&canvas, 1, 1), [255, 0, 0, 255]);
+
+        let mut canvas = OffscreenCanvas::new(ScreenDims::new(4.0, 4.0)).unwrap();
+        let mut batch = GeomBatch::new();
+        batch.push(Color::BLUE.alpha(0.5), Polygon::rectangle(4.0, 4.0));
+        canvas.draw(&batch);
+        let [r, g, b, a] = pixel(&canvas, 1, 1);
+        assert_eq!((r, g), (0, 0));
+        assert!(b >= 254);
+        assert!((127..=128).contains(&a));
+    }
+
+    #[test]
+    fn tiles() {
+        let dir = std::env::temp_dir().join(format!("widgetry_tiles_{}", std::process::id()));
+        let dir = dir.to_str().unwrap().to_string();
+        // The map is 2.5 tiles wide and one tall, and the right half of it is red
+        let mut batch = GeomBatch::new();
+        batch.push(
+            Color::RED,
+            Polygon::rectangle(25.0, 20.0).translate(25.0, 0.0),
+        );
+        render_tiles(
+            &batch,
+            Color::WHITE,
+            (50.0, 20.0),
+            1.0,
+            ScreenDims::new(20.0, 20.0),
+            &dir,
+            false,
+        )
+        .unwrap();
+
+        let mut files: Vec<String> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        files.sort();
+        assert_eq!(files, vec!["01x01.png", "02x01.png", "03x01.png"]);
+        let middle = image::open(format!("{}/02x01.png", dir))
+            .unwrap()
+            .to_rgba8();
+        assert_eq!(middle.dimensions(), (20, 20));
+        assert_eq!(middle.get_pixel(2, 10).0, [255, 255, 255, 255]);
+        assert_eq!(middle.get_pixel(8, 10).0, [255, 0, 0, 255]);
+
+        render_tiles(
+            &batch,
+            Color::WHITE,
+            (50.0, 20.0),
+            2.0,
+            ScreenDims::new(20.0, 20.0),
+            &format!("{}/leaflet", dir),
+            true,
+        )
+        .unwrap();
+        assert_eq!(
+            std::fs::read_dir(format!("{}/leaflet", dir))
+                .unwrap()
+                .count(),
+            10
+        );
+        assert!(std::path::Path::new(&format!("{}/leaflet/4_1.png", dir)).exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}